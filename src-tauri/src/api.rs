@@ -0,0 +1,675 @@
+// HTTP and WebSocket control API for scripts, dashboards and remote UIs. Every
+// command runs the same code as the matching Tauri command, so the mixer
+// behaves identically whether it is driven from the window or from outside.
+// Only loopback connections are accepted unless remote access is switched on,
+// which also requires a bearer token. Requests sent by web pages (carrying a
+// foreign `Origin`) are refused, and commands must be posted as JSON, so a
+// page the user visits can't drive the mixer with a simple cross-site request.
+//
+// REST routes map onto commands; the WebSocket at `/api/ws` (token as `?token=`,
+// since browsers can't set headers on it) takes commands as
+// `{"id": 1, "command": "set_device_volume", "args": {...}}` and pushes
+// `{"event": ..., "data": ...}` messages: `state` whenever the engine state
+// changes, `meters` with peak levels, and the app's audio and device events.
+//
+// Routes under `/api/actions` are one-shot button actions for Stream Deck
+// plugins and similar controllers: toggles and volume nudges that answer with
+// the resulting state, so the button can show it.
+
+use crate::audio::{AudioCommand, AudioStateSnapshot, MeterSnapshot};
+use crate::streaming::{self, read_request, write_response, HttpRequest};
+use crate::AppState;
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+const LOCALHOST: &str = "127.0.0.1";
+
+/// How often meters are pushed to WebSocket clients.
+const METER_INTERVAL: Duration = Duration::from_millis(100);
+/// Meter ticks between checks of the engine state.
+const STATE_POLL_TICKS: u32 = 5;
+/// How long a WebSocket read waits before queued events are sent.
+const WS_POLL: Duration = Duration::from_millis(20);
+/// Events queued per WebSocket client before it is considered stalled.
+const CLIENT_QUEUE_EVENTS: usize = 256;
+/// Default volume change of a nudge action, as a fader position.
+const NUDGE_STEP: f32 = 0.05;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Listen on every interface instead of loopback only. Needs `token`.
+    pub allow_remote: bool,
+    /// When set, requests must send `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: 8095, allow_remote: false, token: String::new() }
+    }
+}
+
+/// Fans event messages out to the connected WebSocket clients.
+#[derive(Default)]
+pub struct EventHub {
+    clients: Mutex<Vec<Sender<String>>>,
+}
+
+impl EventHub {
+    fn subscribe(&self) -> Receiver<String> {
+        let (tx, rx) = crossbeam_channel::bounded(CLIENT_QUEUE_EVENTS);
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.push(tx);
+        }
+        rx
+    }
+
+    fn has_clients(&self) -> bool {
+        self.clients.lock().is_ok_and(|c| !c.is_empty())
+    }
+
+    pub fn broadcast<T: Serialize>(&self, event: &str, data: &T) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        if clients.is_empty() {
+            return;
+        }
+        let message = json!({ "event": event, "data": data }).to_string();
+        // Drop clients that went away or stopped reading
+        clients.retain(|tx| tx.try_send(message.clone()).is_ok());
+    }
+}
+
+pub struct ApiServer {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ApiServer {
+    pub fn start(settings: &ApiSettings, app: AppHandle, hub: Arc<EventHub>) -> Result<Self, String> {
+        if settings.allow_remote && settings.token.is_empty() {
+            return Err("Remote access to the control API needs a token".to_string());
+        }
+        let host = if settings.allow_remote { streaming::ANY_ADDRESS } else { LOCALHOST };
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept = {
+            let stop_flag = stop.clone();
+            let hub = hub.clone();
+            let app = app.clone();
+            let token = settings.token.clone();
+            streaming::spawn_tcp_listener_on(host, settings.port, stop.clone(), move |stream| {
+                if let Err(e) = serve_request(stream, &token, &app, &hub, &stop_flag) {
+                    println!("API request failed: {}", e);
+                }
+            })?
+        };
+        println!("Control API on http://{}:{}/api", host, settings.port);
+
+        let poller = {
+            let stop = stop.clone();
+            let tx = app.state::<AppState>().tx.clone();
+            thread::spawn(move || poll_engine(&tx, &hub, &stop))
+        };
+
+        Ok(Self { stop, threads: vec![accept, poller] })
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Running server and event hub, managed as app state so settings changes can
+/// restart the server and the rest of the app can publish events.
+#[derive(Default)]
+pub struct ApiService {
+    server: Mutex<Option<ApiServer>>,
+    hub: Arc<EventHub>,
+}
+
+impl ApiService {
+    /// Stops the current server and starts a new one if `settings` enable it.
+    pub fn apply(&self, app: &AppHandle, settings: &ApiSettings) -> Result<(), String> {
+        let mut server = self.server.lock().map_err(|e| e.to_string())?;
+        *server = None;
+        if settings.enabled {
+            *server = Some(ApiServer::start(settings, app.clone(), self.hub.clone())?);
+        }
+        Ok(())
+    }
+
+    pub fn broadcast<T: Serialize>(&self, event: &str, data: &T) {
+        self.hub.broadcast(event, data);
+    }
+}
+
+/// Pushes meters, and the engine state whenever it changes, while anyone is listening.
+fn poll_engine(tx: &Sender<AudioCommand>, hub: &EventHub, stop: &AtomicBool) {
+    let mut last_state = Value::Null;
+    let mut tick = 0u32;
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(METER_INTERVAL);
+        if !hub.has_clients() {
+            last_state = Value::Null;
+            continue;
+        }
+        let (reply_tx, reply_rx) = crossbeam_channel::bounded::<MeterSnapshot>(1);
+        if tx.send(AudioCommand::GetMeters(reply_tx)).is_ok() {
+            if let Ok(meters) = reply_rx.recv_timeout(METER_INTERVAL) {
+                hub.broadcast("meters", &meters);
+            }
+        }
+        tick += 1;
+        if tick % STATE_POLL_TICKS == 0 {
+            let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+            if tx.send(AudioCommand::GetState(reply_tx)).is_ok() {
+                if let Ok(state) = reply_rx.recv_timeout(METER_INTERVAL * STATE_POLL_TICKS) {
+                    let state = json!(state);
+                    if state != last_state {
+                        hub.broadcast("state", &state);
+                        last_state = state;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Why a request was refused, sent back as the response status.
+enum ApiError {
+    NotFound,
+    BadRequest(String),
+    Unauthorized,
+    Forbidden(String),
+    NotJson,
+}
+
+impl<E: std::fmt::Display> From<E> for ApiError {
+    fn from(e: E) -> Self {
+        ApiError::BadRequest(e.to_string())
+    }
+}
+
+impl ApiError {
+    fn message(&self) -> String {
+        match self {
+            ApiError::NotFound => "Not found".to_string(),
+            ApiError::BadRequest(message) => message.clone(),
+            ApiError::Unauthorized => "Missing or wrong API token".to_string(),
+            ApiError::Forbidden(message) => message.clone(),
+            ApiError::NotJson => "Commands must be sent as application/json".to_string(),
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self {
+            ApiError::NotFound => "404 Not Found",
+            ApiError::BadRequest(_) => "400 Bad Request",
+            ApiError::Unauthorized => "401 Unauthorized",
+            ApiError::Forbidden(_) => "403 Forbidden",
+            ApiError::NotJson => "415 Unsupported Media Type",
+        }
+    }
+}
+
+/// Refuses requests made by web pages on other origins and, when a token is
+/// set, requests that don't carry it. WebSockets aren't covered by CORS, so
+/// upgrades go through here too.
+fn authorize(request: &HttpRequest, token: &str) -> Result<(), ApiError> {
+    if let Some(origin) = request.header("origin") {
+        if !is_local_origin(origin) {
+            return Err(ApiError::Forbidden(format!("Requests from {} are not allowed", origin)));
+        }
+    }
+    let sent = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_string())
+        .or_else(|| query_param(&request.query, "token"));
+    if !token.is_empty() && sent.as_deref() != Some(token) {
+        return Err(ApiError::Unauthorized);
+    }
+    Ok(())
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Whether a browser `Origin` is this app's own window or a page served from
+/// this machine.
+fn is_local_origin(origin: &str) -> bool {
+    let host = origin.split_once("://").map_or("", |(_, rest)| rest);
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(host, "localhost" | "tauri.localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// Commands that change something must be JSON, which browsers can't send
+/// cross-site without a preflight.
+fn require_json(request: &HttpRequest) -> Result<(), ApiError> {
+    if request.method == "GET" {
+        return Ok(());
+    }
+    let media_type = request.header("content-type").and_then(|value| value.split(';').next());
+    match media_type {
+        Some(media_type) if media_type.trim().eq_ignore_ascii_case("application/json") => Ok(()),
+        _ => Err(ApiError::NotJson),
+    }
+}
+
+fn serve_request(
+    mut stream: TcpStream,
+    token: &str,
+    app: &AppHandle,
+    hub: &EventHub,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+    let request = read_request(&mut stream)?;
+    let segments: Vec<String> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    if let Err(e) = authorize(&request, token) {
+        let body = json!({ "error": e.message() }).to_string();
+        return write_response(&mut stream, e.status(), "application/json", body.as_bytes());
+    }
+    if request.method == "GET" && segments == ["api", "ws"] {
+        return serve_websocket(stream, &request, app, hub, stop);
+    }
+    let result = require_json(&request)
+        .and_then(|()| route(&request.method, &segments, &request.body))
+        .and_then(|(command, args)| run_command(app, command, &args));
+    let status = match &result {
+        Ok(_) => "200 OK",
+        Err(e) => e.status(),
+    };
+    let body = match result {
+        Ok(value) => value,
+        Err(e) => json!({ "error": e.message() }),
+    };
+    write_response(&mut stream, status, "application/json", body.to_string().as_bytes())
+}
+
+/// Maps a REST route to a command and its arguments. Path parameters are
+/// added to the JSON body.
+fn route(method: &str, path: &[&str], body: &[u8]) -> Result<(&'static str, Value), ApiError> {
+    let mut args: Value = if body.is_empty() { json!({}) } else { serde_json::from_slice(body)? };
+    if !args.is_object() {
+        return Err(ApiError::BadRequest("Request body must be a JSON object".to_string()));
+    }
+    let command = match (method, path) {
+        ("GET", ["api", "state"]) => "get_audio_state",
+        ("GET", ["api", "devices"]) => "get_audio_devices",
+        ("GET", ["api", "input-devices"]) => "get_input_devices",
+        ("GET", ["api", "network-devices"]) => "get_network_devices",
+        ("POST", ["api", "capture", "start"]) => "start_capture",
+        ("POST", ["api", "capture", "stop"]) => "stop_capture",
+        ("POST", ["api", "outputs"]) => "add_device_to_mix",
+        ("DELETE", ["api", "outputs", name]) => {
+            args["device_name"] = json!(name);
+            "remove_device_from_mix"
+        },
+        ("PUT", ["api", "outputs", name, setting]) => {
+            args["device_name"] = json!(name);
+            match *setting {
+                "volume" => "set_device_volume",
+                "mute" => "set_device_mute",
+                "solo" => "set_device_solo",
+                _ => return Err(ApiError::NotFound),
+            }
+        },
+        ("PUT", ["api", "input", "volume"]) => "set_input_volume",
+        ("PUT", ["api", "input", "mute"]) => "set_input_mute",
+        ("PUT", ["api", "master", "volume"]) => "set_master_volume",
+        ("PUT", ["api", "master", "mute"]) => "set_master_mute",
+        ("GET", ["api", "outputs", name]) => {
+            args["device_name"] = json!(name);
+            "get_output_state"
+        },
+        ("GET", ["api", "routes"]) => "get_routes",
+        ("PUT", ["api", "routes"]) => "set_route",
+        ("GET", ["api", "profiles"]) => "list_profiles",
+        ("POST", ["api", "profiles", name, "apply"]) => {
+            args["name"] = json!(name);
+            "apply_profile"
+        },
+        ("POST", ["api", "actions", "capture", "toggle"]) => "toggle_capture",
+        ("POST", ["api", "actions", "outputs", name, action]) => {
+            args["device_name"] = json!(name);
+            match *action {
+                "toggle-mute" => "toggle_device_mute",
+                "volume-up" => nudge(&mut args, 1.0, "nudge_device_volume"),
+                "volume-down" => nudge(&mut args, -1.0, "nudge_device_volume"),
+                _ => return Err(ApiError::NotFound),
+            }
+        },
+        ("POST", ["api", "actions", stage @ ("input" | "master"), action]) => match (*stage, *action) {
+            ("input", "toggle-mute") => "toggle_input_mute",
+            ("input", "volume-up") => nudge(&mut args, 1.0, "nudge_input_volume"),
+            ("input", "volume-down") => nudge(&mut args, -1.0, "nudge_input_volume"),
+            ("master", "toggle-mute") => "toggle_master_mute",
+            ("master", "volume-up") => nudge(&mut args, 1.0, "nudge_master_volume"),
+            ("master", "volume-down") => nudge(&mut args, -1.0, "nudge_master_volume"),
+            _ => return Err(ApiError::NotFound),
+        },
+        _ => return Err(ApiError::NotFound),
+    };
+    Ok((command, args))
+}
+
+/// Sets the signed `step` of a nudge action, defaulting to `NUDGE_STEP`.
+fn nudge(args: &mut Value, direction: f32, command: &'static str) -> &'static str {
+    let step = args.get("step").and_then(Value::as_f64).map(|s| s.abs() as f32).unwrap_or(NUDGE_STEP);
+    args["step"] = json!(step * direction);
+    command
+}
+
+/// Runs a control API command for another part of the app, e.g. a hotkey.
+pub fn execute(app: &AppHandle, command: &str, args: &Value) -> Result<Value, String> {
+    run_command(app, command, args).map_err(|e| e.message())
+}
+
+/// Runs a command by its Tauri command name, with the same arguments.
+fn run_command(app: &AppHandle, command: &str, args: &Value) -> Result<Value, ApiError> {
+    let state = || app.state::<AppState>();
+    let app = app.clone();
+    match command {
+        "get_audio_state" => Ok(json!(crate::get_audio_state(state())?)),
+        "get_audio_devices" => Ok(json!(crate::get_audio_devices(state(), arg(args, "favorites_first")?))),
+        "get_input_devices" => Ok(json!(crate::get_input_devices(state()))),
+        "get_network_devices" => Ok(json!(crate::get_network_devices())),
+        "start_capture" => ok(crate::start_capture(state())),
+        "stop_capture" => ok(crate::stop_capture(state())),
+        "add_device_to_mix" => ok(crate::add_device_to_mix(app, state(), arg(args, "device_name")?)),
+        "remove_device_from_mix" => ok(crate::remove_device_from_mix(state(), arg(args, "device_name")?)),
+        "set_device_volume" => ok(crate::set_device_volume(state(), arg(args, "device_name")?, arg(args, "volume")?)),
+        "set_device_mute" => ok(crate::set_device_mute(state(), arg(args, "device_name")?, arg(args, "muted")?)),
+        "set_device_solo" => ok(crate::set_device_solo(state(), arg(args, "device_name")?, arg(args, "solo")?)),
+        "set_input_volume" => ok(crate::set_input_volume(state(), arg(args, "volume")?)),
+        "set_input_mute" => ok(crate::set_input_mute(state(), arg(args, "muted")?)),
+        "set_master_volume" => ok(crate::set_master_volume(app, state(), arg(args, "volume")?)),
+        "set_master_mute" => ok(crate::set_master_mute(app, state(), arg(args, "muted")?)),
+        "get_routes" => Ok(json!(crate::get_routes(app))),
+        "set_route" => ok(crate::set_route(app, state(), arg(args, "source")?, arg(args, "output")?, arg(args, "gain")?)),
+        "list_profiles" => Ok(json!(crate::list_profiles(app))),
+        "apply_profile" => ok(crate::apply_profile(app, state(), arg(args, "name")?)),
+        "get_output_state" => {
+            let name = state().devices.resolve(&arg::<String>(args, "device_name")?);
+            Ok(output_state(&crate::get_audio_state(state())?, &name))
+        },
+        "toggle_capture" => {
+            let capturing = !crate::get_audio_state(state())?.capturing;
+            if capturing { crate::start_capture(state())? } else { crate::stop_capture(state())? }
+            Ok(json!({ "capturing": capturing }))
+        },
+        "toggle_device_mute" => {
+            let name = state().devices.resolve(&arg::<String>(args, "device_name")?);
+            let current = active_output_state(&crate::get_audio_state(state())?, &name)?;
+            let muted = current["muted"] != json!(true);
+            crate::set_device_mute(state(), name.clone(), muted)?;
+            Ok(json!({ "device_name": name, "muted": muted }))
+        },
+        "nudge_device_volume" => {
+            let name = state().devices.resolve(&arg::<String>(args, "device_name")?);
+            let current = active_output_state(&crate::get_audio_state(state())?, &name)?;
+            let volume = nudged(current["volume"].as_f64().unwrap_or(1.0) as f32, args)?;
+            crate::set_device_volume(state(), name.clone(), volume)?;
+            Ok(json!({ "device_name": name, "volume": volume }))
+        },
+        "toggle_input_mute" => {
+            let muted = !crate::get_audio_state(state())?.input_muted;
+            crate::set_input_mute(state(), muted)?;
+            Ok(json!({ "muted": muted }))
+        },
+        "nudge_input_volume" => {
+            let volume = nudged(crate::get_audio_state(state())?.input_volume, args)?;
+            crate::set_input_volume(state(), volume)?;
+            Ok(json!({ "volume": volume }))
+        },
+        "toggle_master_mute" => {
+            let muted = !crate::get_audio_state(state())?.master_muted;
+            crate::set_master_mute(app, state(), muted)?;
+            Ok(json!({ "muted": muted }))
+        },
+        "nudge_master_volume" => {
+            let volume = nudged(crate::get_audio_state(state())?.master_volume, args)?;
+            crate::set_master_volume(app, state(), volume)?;
+            Ok(json!({ "volume": volume }))
+        },
+        "toggle_mini_window" => Ok(json!({ "visible": crate::window_state::toggle_mini(&app)? })),
+        "activate" => {
+            crate::activate(&app, arg(args, "args")?);
+            Ok(json!({ "ok": true }))
+        },
+        _ => Err(ApiError::BadRequest(format!("Unknown command: {}", command))),
+    }
+}
+
+/// Button feedback for one output; inactive outputs report no volume.
+fn output_state(state: &AudioStateSnapshot, name: &str) -> Value {
+    json!({
+        "device_name": name,
+        "active": state.outputs.iter().any(|o| o == name),
+        "volume": state.volumes.get(name),
+        "muted": state.muted_outputs.iter().any(|o| o == name),
+    })
+}
+
+fn active_output_state(state: &AudioStateSnapshot, name: &str) -> Result<Value, ApiError> {
+    let output = output_state(state, name);
+    if output["active"] != json!(true) {
+        return Err(ApiError::BadRequest(format!("{} is not in the mix", name)));
+    }
+    Ok(output)
+}
+
+fn nudged(current: f32, args: &Value) -> Result<f32, ApiError> {
+    let step: Option<f32> = arg(args, "step")?;
+    Ok((current + step.unwrap_or(NUDGE_STEP)).clamp(0.0, 1.0))
+}
+
+fn arg<T: DeserializeOwned>(args: &Value, key: &str) -> Result<T, ApiError> {
+    let value = args.get(key).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(|e| ApiError::BadRequest(format!("{}: {}", key, e)))
+}
+
+fn ok<E: std::fmt::Display>(result: Result<(), E>) -> Result<Value, ApiError> {
+    result.map(|_| json!({ "ok": true })).map_err(ApiError::from)
+}
+
+#[derive(Deserialize)]
+struct CommandMessage {
+    #[serde(default)]
+    id: Value,
+    command: String,
+    #[serde(default)]
+    args: Value,
+}
+
+/// Completes the WebSocket upgrade, then answers commands and forwards hub
+/// events until the client leaves or the server stops.
+fn serve_websocket(
+    mut stream: TcpStream,
+    request: &HttpRequest,
+    app: &AppHandle,
+    hub: &EventHub,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    let key = request
+        .header("sec-websocket-key")
+        .ok_or_else(|| "Missing Sec-WebSocket-Key".to_string())?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    stream.write_all(response.as_bytes()).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(WS_POLL)).map_err(|e| e.to_string())?;
+
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let events = hub.subscribe();
+    // Start the client off with the full state; changes follow as events
+    if let Ok(state) = run_command(app, "get_audio_state", &Value::Null) {
+        let message = json!({ "event": "state", "data": state }).to_string();
+        socket.send(Message::Text(message)).map_err(|e| e.to_string())?;
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = handle_command_message(app, &text);
+                socket.send(Message::Text(reply)).map_err(|e| e.to_string())?;
+            },
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Ok(_) => {},
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {},
+            Err(e) => return Err(e.to_string()),
+        }
+        loop {
+            match events.try_recv() {
+                Ok(message) => socket.send(Message::Text(message)).map_err(|e| e.to_string())?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("Client fell behind".to_string()),
+            }
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+    Ok(())
+}
+
+/// Answers one JSON command message, as sent over the WebSocket or the IPC
+/// socket, with its JSON reply.
+pub fn handle_command_message(app: &AppHandle, text: &str) -> String {
+    let reply = match serde_json::from_str::<CommandMessage>(text) {
+        Ok(request) => match run_command(app, &request.command, &request.args) {
+            Ok(result) => json!({ "id": request.id, "result": result }),
+            Err(e) => json!({ "id": request.id, "error": e.message() }),
+        },
+        Err(e) => json!({ "id": Value::Null, "error": format!("Bad command: {}", e) }),
+    };
+    reply.to_string()
+}
+
+/// Decodes `%XX` escapes in a path segment, so device names can contain spaces.
+pub(crate) fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            },
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("Speakers%20(Realtek%C2%AE%20Audio)"), "Speakers (Realtek® Audio)");
+        assert_eq!(percent_decode("vban%3A%2F%2Fhost"), "vban://host");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_routes_carry_path_parameters() {
+        let (command, args) = route("PUT", &["api", "outputs", "Speakers", "volume"], br#"{"volume":0.5}"#).ok().unwrap();
+        assert_eq!(command, "set_device_volume");
+        assert_eq!(args, json!({ "device_name": "Speakers", "volume": 0.5 }));
+
+        let (command, args) = route("DELETE", &["api", "outputs", "Speakers"], b"").ok().unwrap();
+        assert_eq!(command, "remove_device_from_mix");
+        assert_eq!(args, json!({ "device_name": "Speakers" }));
+
+        let (command, args) = route("POST", &["api", "actions", "master", "volume-down"], b"").ok().unwrap();
+        assert_eq!(command, "nudge_master_volume");
+        assert_eq!(args, json!({ "step": -NUDGE_STEP }));
+
+        assert!(matches!(route("PUT", &["api", "outputs", "Speakers", "pan"], b""), Err(ApiError::NotFound)));
+        assert!(matches!(route("POST", &["api", "outputs"], b"[1]"), Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_web_pages_and_missing_tokens_are_refused() {
+        let request = |headers: &[(&str, &str)]| HttpRequest {
+            method: "POST".to_string(),
+            path: "/api/outputs".to_string(),
+            query: String::new(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            body: Vec::new(),
+        };
+        let json = ("Content-Type", "application/json; charset=utf-8");
+        assert!(authorize(&request(&[json]), "").is_ok());
+        assert!(require_json(&request(&[json])).is_ok());
+        assert!(matches!(require_json(&request(&[("Content-Type", "text/plain")])), Err(ApiError::NotJson)));
+        assert!(matches!(require_json(&request(&[])), Err(ApiError::NotJson)));
+
+        assert!(authorize(&request(&[("Origin", "http://localhost:1420")]), "").is_ok());
+        assert!(authorize(&request(&[("Origin", "tauri://localhost")]), "").is_ok());
+        assert!(matches!(authorize(&request(&[("Origin", "https://evil.example")]), ""), Err(ApiError::Forbidden(_))));
+        assert!(matches!(authorize(&request(&[("Origin", "http://localhost.evil.example")]), ""), Err(ApiError::Forbidden(_))));
+        assert!(matches!(authorize(&request(&[("Origin", "null")]), ""), Err(ApiError::Forbidden(_))));
+
+        assert!(matches!(authorize(&request(&[]), "secret"), Err(ApiError::Unauthorized)));
+        assert!(matches!(authorize(&request(&[("Authorization", "Bearer wrong")]), "secret"), Err(ApiError::Unauthorized)));
+        assert!(authorize(&request(&[("Authorization", "Bearer secret")]), "secret").is_ok());
+
+        let mut upgrade = request(&[("Origin", "https://evil.example")]);
+        upgrade.query = "token=secret".to_string();
+        assert!(matches!(authorize(&upgrade, "secret"), Err(ApiError::Forbidden(_))));
+        upgrade.headers.clear();
+        assert!(authorize(&upgrade, "secret").is_ok());
+    }
+}
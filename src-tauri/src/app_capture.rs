@@ -0,0 +1,148 @@
+// Per-application capture through the Windows 10+ process loopback API
+// (ActivateAudioInterfaceAsync with AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK).
+// cpal has no notion of it, so it runs on its own capture thread.
+
+use crate::priority;
+use crossbeam_channel::{bounded, Sender};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use sysinfo::System;
+use wasapi::{AudioClient, Direction, SampleType, StreamMode, WaveFormat};
+
+/// Process loopback has no mix format of its own; WASAPI converts to this.
+pub const APP_CAPTURE_SAMPLE_RATE: u32 = 48000;
+pub const APP_CAPTURE_CHANNELS: usize = 2;
+
+/// How long to wait for audio before re-checking the stop flag. The event only
+/// fires while the target process is actually playing something.
+const EVENT_TIMEOUT_MS: u32 = 100;
+
+/// A running process loopback capture. Dropping it stops the capture thread.
+pub struct AppCapture {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AppCapture {
+    /// Starts capturing `process_id`. With `include_tree` the process and its
+    /// children are captured; without it, everything *except* that tree is.
+    pub fn start<F>(process_id: u32, include_tree: bool, mut on_data: F) -> Result<Self, String>
+    where
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let (ready_tx, ready_rx) = bounded(1);
+
+        let thread = thread::spawn(move || {
+            let _priority = priority::promote_current_thread("Application capture", 0, APP_CAPTURE_SAMPLE_RATE);
+            if let Err(e) = capture_loop(process_id, include_tree, &stop_flag, &ready_tx, &mut on_data) {
+                eprintln!("Application capture error: {}", e);
+                let _ = ready_tx.send(Err(e));
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { stop, thread: Some(thread) }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Application capture thread exited".to_string()),
+        }
+    }
+}
+
+impl Drop for AppCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_loop(
+    process_id: u32,
+    include_tree: bool,
+    stop: &AtomicBool,
+    ready: &Sender<Result<(), String>>,
+    on_data: &mut dyn FnMut(&[f32]),
+) -> Result<(), String> {
+    let _ = wasapi::initialize_mta();
+
+    let format = WaveFormat::new(
+        32,
+        32,
+        &SampleType::Float,
+        APP_CAPTURE_SAMPLE_RATE as usize,
+        APP_CAPTURE_CHANNELS,
+        None,
+    );
+    let mut client = AudioClient::new_application_loopback_client(process_id, include_tree)
+        .map_err(|e| e.to_string())?;
+    let mode = StreamMode::EventsShared { autoconvert: true, buffer_duration_hns: 0 };
+    client
+        .initialize_client(&format, &Direction::Capture, &mode)
+        .map_err(|e| e.to_string())?;
+    let event = client.set_get_eventhandle().map_err(|e| e.to_string())?;
+    let capture = client.get_audiocaptureclient().map_err(|e| e.to_string())?;
+    client.start_stream().map_err(|e| e.to_string())?;
+    let _ = ready.send(Ok(()));
+
+    let mut bytes: VecDeque<u8> = VecDeque::new();
+    let mut samples: Vec<f32> = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        capture
+            .read_from_device_to_deque(&mut bytes)
+            .map_err(|e| e.to_string())?;
+
+        samples.clear();
+        while bytes.len() >= 4 {
+            let raw = [
+                bytes.pop_front().unwrap_or(0),
+                bytes.pop_front().unwrap_or(0),
+                bytes.pop_front().unwrap_or(0),
+                bytes.pop_front().unwrap_or(0),
+            ];
+            samples.push(f32::from_le_bytes(raw));
+        }
+        if !samples.is_empty() {
+            on_data(&samples);
+        }
+
+        let _ = event.wait_for_event(EVENT_TIMEOUT_MS);
+    }
+
+    client.stop_stream().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Finds the root process of a running application by executable name
+/// (e.g. "Spotify.exe"), so its whole process tree can be captured.
+pub fn find_process_id(name: &str) -> Option<u32> {
+    let sys = System::new_all();
+    let matches: Vec<_> = sys
+        .processes()
+        .values()
+        .filter(|p| p.name().eq_ignore_ascii_case(name))
+        .collect();
+
+    let root = matches.iter().find(|p| {
+        let parent_is_same_app = p
+            .parent()
+            .and_then(|parent| sys.process(parent))
+            .is_some_and(|parent| parent.name().eq_ignore_ascii_case(name));
+        !parent_is_same_app
+    });
+
+    root.or(matches.first()).map(|p| p.pid().as_u32())
+}
+
+/// Executable names of running processes, for the capture source picker.
+pub fn list_applications() -> Vec<String> {
+    let sys = System::new_all();
+    let mut names: Vec<String> = sys.processes().values().map(|p| p.name().to_string()).collect();
+    names.sort_by_key(|n| n.to_lowercase());
+    names.dedup();
+    names
+}
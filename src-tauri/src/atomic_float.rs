@@ -0,0 +1,49 @@
+// An f32 that the actor and the audio callbacks share without a lock, stored
+// as its bit pattern in an AtomicU32. The callbacks only need the latest
+// value, so every access is relaxed.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Stores `value`, returning the previous value.
+    pub fn swap(&self, value: f32) -> f32 {
+        f32::from_bits(self.0.swap(value.to_bits(), Ordering::Relaxed))
+    }
+
+    /// Raises the value to `value` if it is higher, as peak meters do.
+    pub fn fetch_max(&self, value: f32) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            (value > f32::from_bits(bits)).then_some(value.to_bits())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_is_held_until_taken() {
+        let meter = AtomicF32::new(0.0);
+        meter.fetch_max(0.5);
+        meter.fetch_max(0.25);
+        assert_eq!(meter.load(), 0.5);
+        assert_eq!(meter.swap(0.0), 0.5);
+        assert_eq!(meter.load(), 0.0);
+    }
+}
@@ -1,115 +1,761 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use rtrb::{RingBuffer, Producer};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use crossbeam_channel::{unbounded, Sender};
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::atomic_float::AtomicF32;
+use crate::config::LinkGroup;
+use crate::feeds::{FeedControl, Feeds};
+use crate::handoff::{Handoff, HandoffControl};
+use crate::dsp::{self, Dither, Ducker, DuckingSettings, GainRamp, NoiseGate, NoiseGateSettings, VolumeTaper};
+use crate::echo::{self, EchoRender};
+use crate::host;
+use crate::recovery::{StreamFailure, StreamRecovery, CAPTURE_STREAM};
+use crate::watchdog::{self, Watchdog};
+use crate::sample_format;
+use crate::mic::{self, MicControls, MicSource};
+use crate::hls::{HlsServer, HlsSettings};
+use crate::now_playing;
+use crate::streaming::{HttpStreamServer, HttpStreamSettings, IcecastClient, IcecastSettings};
+use crate::sync::{SyncClient, SyncClientSettings, SyncServer, SyncServerSettings};
+use crate::tap;
+use crate::vban::{self, VbanReceiver, VbanReceiverSettings, VbanSender};
+use crate::cast::{self, CastSender};
+use crate::discovery::{self, NetworkDevice, NetworkDeviceKind};
+use crate::link::{self, LinkReceiver, LinkSender};
+use crate::metrics::{EngineMetrics, StreamStats};
+use crate::raop::{self, RaopSender};
+use crate::webrtc_out::{WebRtcServer, WebRtcSettings};
+use crate::rtp::{RtpSender, RtpSettings};
+use crate::recording::{self, Recorder, RecordingMetadata, RecordingSettings, ReplayBuffer, ReplaySettings, SplitSettings, TagOptions};
+#[cfg(windows)]
+use crate::app_capture;
+use crate::bus::{self, Converter};
+use crate::routing::{self, Route, RouteSource, RoutingMatrix, MIX_OUTPUT};
+use crate::inserts::{InsertConfig, InsertControl, InsertParams};
+#[cfg(windows)]
+use crate::exclusive::ExclusiveOutput;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+use crate::pipewire_audio::{self, PwStream};
 // use tauri::State; // Not used in the provided code, so omitting for now
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct AudioDeviceInfo {
     pub name: String,
     pub index: usize,
+    /// The user's name for the device if they gave it one, else the name the
+    /// system shows for a PipeWire sink.
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// The device is one of the user's favorites.
+    #[serde(default)]
+    pub pinned: bool,
+    /// The host API the device is opened through (e.g. "WASAPI"), or the
+    /// protocol of a network speaker.
+    #[serde(default)]
+    pub host_api: String,
+    /// Channel counts the device can be opened with.
+    #[serde(default)]
+    pub channels: Vec<u16>,
+    /// Supported sample-rate range, if the device reports one.
+    #[serde(default)]
+    pub min_sample_rate: Option<u32>,
+    #[serde(default)]
+    pub max_sample_rate: Option<u32>,
+    /// The system's default device.
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// Errors returned to the UI when a request is refused.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum AudioError {
+    /// The output is the device being captured and would feed back into itself
+    FeedbackLoop(String),
+    /// The request could not be delivered to the audio engine
+    Engine(String),
+    /// More applications excluded from the capture than can be, with the number given
+    TooManyExclusions(usize),
+    /// Excluding applications from the capture isn't available on this system
+    ExclusionUnsupported,
+}
+
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::FeedbackLoop(device) => write!(f, "'{}' is the capture source, adding it as an output would create a feedback loop", device),
+            AudioError::Engine(msg) => write!(f, "{}", msg),
+            AudioError::TooManyExclusions(given) => write!(
+                f,
+                "Only {} application can be excluded from the capture at a time, {} were given",
+                MAX_CAPTURE_EXCLUSIONS, given
+            ),
+            AudioError::ExclusionUnsupported => write!(f, "Excluding applications from the capture requires Windows 10 or later"),
+        }
+    }
+}
+
+impl From<String> for AudioError {
+    fn from(msg: String) -> Self {
+        AudioError::Engine(msg)
+    }
+}
+
+impl<T> From<crossbeam_channel::SendError<T>> for AudioError {
+    fn from(e: crossbeam_channel::SendError<T>) -> Self {
+        AudioError::Engine(e.to_string())
+    }
+}
+
+/// What the capture stage records from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureSource {
+    /// Everything playing on the default output device
+    #[default]
+    SystemLoopback,
+    /// A single application by executable name (Windows 10+ process loopback)
+    Application { name: String },
+    /// Another instance's mix, sent to this port by its link output
+    Network { port: u16 },
+    /// An input device, e.g. the inputs of an ASIO interface
+    Device { name: String },
+    /// The monitor of a PipeWire sink, by node name (Linux)
+    Monitor { sink: String },
 }
 
 // Commands sent from Main Thread (UI) to Audio Thread
 pub enum AudioCommand {
     StartLoopback,
     StopLoopback,
+    SetCaptureSource(CaptureSource),
+    SetCaptureExclusions(Vec<String>), // executable names
+    SetCaptureSampleRate(Option<u32>), // Hz, None for the device default
     AddOutput(String), // device name
     RemoveOutput(String),
-    SetVolume(String, f32),
+    SwapOutput(String, String), // old device, new device
+    SetCrossfadeDuration(u32), // ms
+    SetVolume(String, f32), // slider position 0.0-1.0, mapped through the taper
+    SetVolumeDb(String, f32),
     SetMute(String, bool),
+    SetWidth(String, f32),
+    SetBoost(String, f32), // dB above unity, 0 to +12
+    SetSoftClip(String, bool),
+    SetOutputBuffer(String, OutputBuffer), // reopens the output if it is open
+    SetExclusive(String, bool), // WASAPI exclusive mode, reopens the output if it is open
+    SetDither(String, Dither), // for 16-bit devices, reopens the output if it is open
+    SetInserts(String, Vec<InsertConfig>), // effect plugins, in order
+    GetInsertParams(String, usize, Sender<Result<InsertParams, String>>),
+    SetSolo(String, bool),
     SetInputVolume(f32),
+    SetInputVolumeDb(f32),
+    SetVolumeTaper(VolumeTaper),
+    SetMasterVolume(f32),
+    SetMasterMute(bool),
+    SetVolumeCap(Option<f32>), // dB ceiling on the master gain, None to lift it
     SetInputMute(bool),
+    SetNoiseGate(NoiseGateSettings),
+    SetDucking(DuckingSettings),
+    SetCaptureFades(u32, u32), // fade-in ms, fade-out ms
+    SetLinkGroups(Vec<LinkGroup>),
+    StartMic(String), // input device name
+    StopMic,
+    SetMicVolume(f32),
+    SetMicMute(bool),
+    SetMicNoiseSuppression(bool),
+    SetEchoCancellation(bool),
+    SetRoute(RouteSource, String, f32), // source, output (or "mix"), linear gain
+    SetRoutes(Vec<Route>),
+    SetBitPerfect(bool),
+    StartRecording(PathBuf, SplitSettings, TagOptions), // output directory, rollover limits, tags
+    StopRecording,
+    SetRecordingSettings(RecordingSettings),
+    SaveReplay(PathBuf), // output directory
+    SetReplaySettings(ReplaySettings),
+    PauseRecording,
+    ResumeRecording,
+    GetState(Sender<AudioStateSnapshot>),
+    GetMeters(Sender<MeterSnapshot>),
+    GetMetrics(Sender<EngineMetrics>),
+    /// Reopen every device stream, keeping the mix as it is.
+    RebuildStreams,
+    StartIcecast(IcecastSettings),
+    StopIcecast,
+    SetHttpStreamSettings(HttpStreamSettings),
+    SetHlsSettings(HlsSettings),
+    SetWebRtcSettings(WebRtcSettings),
+    SetVbanReceiver(VbanReceiverSettings),
+    SetRtpSettings(RtpSettings),
+    SetSyncServer(SyncServerSettings),
+    SetSyncClient(SyncClientSettings),
+}
+
+// Notifications sent from the Audio Thread back to the UI
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AudioEvent {
+    // A linked output followed another fader
+    VolumeChanged { device: String, volume: f32 },
+    RecordingStarted { path: String },
+    RecordingStopped { path: String },
+    RecordingFailed { error: String },
+    ReplaySaved { path: String },
+    RecordingPaused,
+    RecordingResumed,
+    CaptureStarted,
+    CaptureStopped,
+    OutputAdded { device: String },
+    OutputRemoved { device: String },
+    MasterMuteChanged { muted: bool },
+    OutputMuteChanged { device: String, muted: bool },
+    /// A stream failed to open or reported an error; `stream` is the output
+    /// name, or "capture".
+    StreamError { stream: String, error: String },
+    /// An output device went away, e.g. unplugged headphones.
+    OutputDisconnected { device: String, error: String },
+    /// A stream's callbacks stopped firing; it was closed and will be rebuilt.
+    StreamStalled { stream: String },
+    /// The audio thread panicked. Unless `restarted` is false a fresh engine
+    /// took over, without any of the old one's state.
+    EnginePanicked { reason: String, restarted: bool, was_capturing: bool },
+    /// The capture exclusions couldn't be applied; the capture ignores them.
+    CaptureExclusionsRejected { error: AudioError },
+    /// A stream kept failing and is no longer being rebuilt.
+    StreamRecoveryFailed { stream: String, error: String },
+    /// The mix reached full scale; sent at most every few seconds.
+    ClippingDetected { peak: f32 },
+}
+
+/// Point-in-time view of the engine, answered by the audio thread.
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioStateSnapshot {
+    pub capturing: bool,
+    pub capture_source: CaptureSource,
+    pub outputs: Vec<String>,
+    /// Fader position per output.
+    pub volumes: BTreeMap<String, f32>,
+    pub muted_outputs: Vec<String>,
+    /// Fader positions of the input and master stages.
+    pub input_volume: f32,
+    pub input_muted: bool,
+    pub master_volume: f32,
+    pub master_muted: bool,
+    pub mic_device: Option<String>,
+    pub recording: Option<RecordingState>,
+    pub replay_enabled: bool,
+    pub icecast_streaming: bool,
+    pub http_streaming: bool,
+    pub hls_streaming: bool,
+    pub webrtc_monitoring: bool,
+    /// Session description of the running RTP stream.
+    pub rtp_sdp: Option<String>,
+    pub sync_serving: bool,
+    pub sync_client: bool,
+    /// Samples are copied to the only output untouched.
+    pub bit_perfect: bool,
+}
+
+/// Peak levels (linear) since the previous reading.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct MeterSnapshot {
+    /// The processed capture mix.
+    pub mix: f32,
+    pub outputs: BTreeMap<String, f32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RecordingState {
+    pub path: String,
+    pub paused: bool,
 }
 
 struct AudioActor {
+    capture_source: CaptureSource,
+    capture_exclusions: Vec<String>,
     capture_stream: Option<cpal::Stream>,
+    #[cfg(windows)]
+    app_capture: Option<app_capture::AppCapture>,
     capture_sample_rate: Option<cpal::SampleRate>, // Store input rate
-    producers: Arc<Mutex<Vec<(String, Producer<f32>)>>>,
-    output_streams: HashMap<String, cpal::Stream>,
-    volumes: HashMap<String, Arc<Mutex<f32>>>,
-    mutes: HashMap<String, Arc<Mutex<bool>>>,
+    capture_sample_format: Option<cpal::SampleFormat>,
+    capture_rate_override: Option<u32>,
+    feeds: FeedControl,
+    output_streams: HashMap<String, OutputStream>,
+    volumes: HashMap<String, Arc<AtomicF32>>, // linear gain
+    positions: HashMap<String, f32>, // last slider position per output
+    link_groups: Vec<LinkGroup>,
+    mutes: HashMap<String, Arc<AtomicBool>>,
+    widths: HashMap<String, Arc<AtomicF32>>,
+    boosts: HashMap<String, Arc<AtomicF32>>, // linear boost gain
+    clippers: HashMap<String, Arc<AtomicBool>>,
+    fades: HashMap<String, Arc<AtomicF32>>, // crossfade target per output
+    /// Outputs swapped away and fading out, with when to tear them down.
+    retiring_outputs: HashMap<String, Instant>,
+    soloed: HashSet<String>,
+    solo_mutes: HashMap<String, Arc<AtomicBool>>, // silenced by another output's solo
+    crossfade_ms: Arc<AtomicU32>,
+    meters: HashMap<String, Arc<AtomicF32>>, // output peak since last read
+    mix_meter: Arc<AtomicF32>,
+    stats: HashMap<String, Arc<StreamStats>>, // per-output counters for /metrics
+    capture_stats: Arc<StreamStats>,
     
     // Input state
-    input_volume: Arc<Mutex<f32>>,
-    input_muted: Arc<Mutex<bool>>,
+    input_position: f32,
+    input_volume: Arc<AtomicF32>,
+    input_muted: Arc<AtomicBool>,
+    noise_gate: HandoffControl<NoiseGateSettings>,
+    ducking: HandoffControl<DuckingSettings>,
+    taper: VolumeTaper,
+
+    // Master stage applied to the whole mix
+    master_position: f32,
+    master_volume: Arc<AtomicF32>,
+    master_muted: Arc<AtomicBool>,
+    /// Quiet-hours ceiling on the master gain; the fader position is kept.
+    volume_cap: Option<f32>,
+
+    // Microphone mixed into the capture fan-out
+    mic_stream: Option<cpal::Stream>,
+    mic_device: Option<String>,
+    mic_source: HandoffControl<Option<MicSource>>,
+    mic_controls: MicControls,
+    mic_format: Option<(usize, u32)>, // channels, sample rate
+
+    // VBAN stream from another machine, mixed in like the mic
+    network_source: HandoffControl<Option<MicSource>>,
+    vban_receiver: Option<VbanReceiver>,
+    link_receiver: Option<LinkReceiver>,
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    pipewire_capture: Option<PwStream>,
+    vban_receiver_settings: VbanReceiverSettings,
+
+    // Echo cancellation: the loopback is the far-end reference for the mic
+    echo_cancellation: bool,
+    echo_render: HandoffControl<Option<EchoRender>>,
+    echo_errors: Option<echo::EchoErrors>,
+    capture_channels: Option<usize>,
+
+    // Recording of the processed capture mix
+    recorder: Option<Recorder>,
+    record_tap: HandoffControl<Option<Producer<f32>>>,
+    recording_settings: RecordingSettings,
+    recording_split: SplitSettings,
+    recording_metadata: Option<RecordingMetadata>,
+    // The mix tap stays with the capture callback while paused, which skips
+    // it; stem taps are parked here. Either way the files stay open.
+    recording_paused: bool,
+    record_paused: Arc<AtomicBool>,
+    paused_stem_taps: HashMap<String, Producer<f32>>,
+    // Per-output stems, fed from each output callback
+    stem_recorders: HashMap<String, Recorder>,
+    stem_taps: HashMap<String, Arc<Mutex<Option<Producer<f32>>>>>,
+    output_formats: HashMap<String, (usize, u32)>, // channels, sample rate
+    output_sample_formats: HashMap<String, cpal::SampleFormat>, // device outputs only
+    // Bit-perfect mode: requested by the user, active while the mix allows it
+    bit_perfect: bool,
+    passthrough: Arc<AtomicBool>,
+    // Send level of each source into each output, handed to the capture callback
+    routes: RoutingMatrix,
+    route_control: HandoffControl<RoutingMatrix>,
+    // Buffer sizes by output, kept while an output is out of the mix
+    output_buffers: HashMap<String, OutputBuffer>,
+    // Outputs to open in exclusive mode where the platform has one
+    exclusive_outputs: HashSet<String>,
+    output_dither: HashMap<String, Dither>,
+    // Effect plugins by output, and the insert chains of open outputs
+    output_inserts: HashMap<String, Vec<InsertConfig>>,
+    insert_controls: HashMap<String, InsertControl>,
+    // Rolling history of the mix for "save replay"
+    replay: Option<ReplayBuffer>,
+    replay_tap: HandoffControl<Option<Producer<f32>>>,
+    replay_settings: ReplaySettings,
+
+    // Network streams of the mix, each fed from its own named tap
+    mix_taps: HandoffControl<Vec<(String, Producer<f32>)>>,
+    icecast: Option<IcecastClient>,
+    icecast_settings: Option<IcecastSettings>,
+    http_stream: Option<HttpStreamServer>,
+    http_stream_settings: HttpStreamSettings,
+    hls: Option<HlsServer>,
+    hls_settings: HlsSettings,
+    webrtc: Option<WebRtcServer>,
+    webrtc_settings: WebRtcSettings,
+    rtp: Option<RtpSender>,
+    rtp_settings: RtpSettings,
+    sync_server: Option<SyncServer>,
+    sync_server_settings: SyncServerSettings,
+    // Plays another instance's synced mix; independent of local capture
+    sync_client: Option<SyncClient>,
+
+    // Capture start/stop fades
+    capture_fade_in_ms: u32,
+    capture_fade_out_ms: u32,
+    capture_stopping: Arc<AtomicBool>,
+    /// When a capture that is fading out is torn down.
+    capture_teardown_at: Option<Instant>,
+
+    // Errors from stream callbacks, and the rebuilds they schedule
+    failures: Sender<StreamFailure>,
+    recovery: StreamRecovery,
+    // Settings of outputs that failed to reopen during recovery
+    recovering_outputs: HashMap<String, OutputControls>,
+    watchdog: Watchdog,
+
+    events: Sender<AudioEvent>,
+}
+
+/// The user's settings of an open output, carried across a reopen.
+#[derive(Default)]
+struct OutputControls {
+    gain: Option<f32>,
+    position: Option<f32>,
+    muted: Option<bool>,
+    width: Option<f32>,
+    boost: Option<f32>,
+    clip: Option<bool>,
+    solo: bool,
 }
 
 impl AudioActor {
-    fn new() -> Self {
+    fn new(events: Sender<AudioEvent>, failures: Sender<StreamFailure>) -> Self {
         Self {
+            capture_source: CaptureSource::default(),
+            capture_exclusions: Vec::new(),
             capture_stream: None,
+            #[cfg(windows)]
+            app_capture: None,
             capture_sample_rate: None,
-            producers: Arc::new(Mutex::new(Vec::new())),
+            capture_sample_format: None,
+            capture_rate_override: None,
+            feeds: FeedControl::new(),
             output_streams: HashMap::new(),
             volumes: HashMap::new(),
+            positions: HashMap::new(),
+            link_groups: Vec::new(),
             mutes: HashMap::new(),
-            input_volume: Arc::new(Mutex::new(1.0)),
-            input_muted: Arc::new(Mutex::new(false)),
+            widths: HashMap::new(),
+            boosts: HashMap::new(),
+            clippers: HashMap::new(),
+            fades: HashMap::new(),
+            retiring_outputs: HashMap::new(),
+            soloed: HashSet::new(),
+            solo_mutes: HashMap::new(),
+            crossfade_ms: Arc::new(AtomicU32::new(DEFAULT_CROSSFADE_MS)),
+            meters: HashMap::new(),
+            mix_meter: Arc::new(AtomicF32::new(0.0)),
+            stats: HashMap::new(),
+            capture_stats: Arc::new(StreamStats::default()),
+            input_position: 1.0,
+            input_volume: Arc::new(AtomicF32::new(1.0)),
+            input_muted: Arc::new(AtomicBool::new(false)),
+            noise_gate: HandoffControl::new(),
+            ducking: HandoffControl::new(),
+            taper: VolumeTaper::default(),
+            master_position: 1.0,
+            volume_cap: None,
+            master_volume: Arc::new(AtomicF32::new(1.0)),
+            master_muted: Arc::new(AtomicBool::new(false)),
+            mic_stream: None,
+            mic_device: None,
+            mic_source: HandoffControl::new(),
+            mic_controls: MicControls::default(),
+            mic_format: None,
+            network_source: HandoffControl::new(),
+            vban_receiver: None,
+            link_receiver: None,
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            pipewire_capture: None,
+            vban_receiver_settings: VbanReceiverSettings::default(),
+            echo_cancellation: false,
+            echo_render: HandoffControl::new(),
+            echo_errors: None,
+            capture_channels: None,
+            recorder: None,
+            record_tap: HandoffControl::new(),
+            recording_settings: RecordingSettings::default(),
+            recording_split: SplitSettings::default(),
+            recording_metadata: None,
+            recording_paused: false,
+            record_paused: Arc::new(AtomicBool::new(false)),
+            paused_stem_taps: HashMap::new(),
+            stem_recorders: HashMap::new(),
+            stem_taps: HashMap::new(),
+            output_formats: HashMap::new(),
+            output_sample_formats: HashMap::new(),
+            bit_perfect: false,
+            passthrough: Arc::new(AtomicBool::new(false)),
+            routes: RoutingMatrix::default(),
+            route_control: HandoffControl::new(),
+            output_buffers: HashMap::new(),
+            exclusive_outputs: HashSet::new(),
+            output_dither: HashMap::new(),
+            output_inserts: HashMap::new(),
+            insert_controls: HashMap::new(),
+            replay: None,
+            replay_tap: HandoffControl::new(),
+            replay_settings: ReplaySettings::default(),
+            mix_taps: HandoffControl::new(),
+            icecast: None,
+            icecast_settings: None,
+            http_stream: None,
+            http_stream_settings: HttpStreamSettings::default(),
+            hls: None,
+            hls_settings: HlsSettings::default(),
+            webrtc: None,
+            webrtc_settings: WebRtcSettings::default(),
+            rtp: None,
+            rtp_settings: RtpSettings::default(),
+            sync_server: None,
+            sync_server_settings: SyncServerSettings::default(),
+            sync_client: None,
+            capture_fade_in_ms: DEFAULT_CAPTURE_FADE_MS,
+            capture_fade_out_ms: DEFAULT_CAPTURE_FADE_MS,
+            capture_stopping: Arc::new(AtomicBool::new(false)),
+            capture_teardown_at: None,
+            failures,
+            recovery: StreamRecovery::default(),
+            recovering_outputs: HashMap::new(),
+            watchdog: Watchdog::new(),
+            events,
+        }
+    }
+
+    /// Whether a capture is running and not on its way out.
+    fn is_capturing(&self) -> bool {
+        self.capture_open() && self.capture_teardown_at.is_none()
+    }
+
+    /// Whether a capture stream exists, including one that is fading out.
+    fn capture_open(&self) -> bool {
+        #[cfg(windows)]
+        if self.app_capture.is_some() {
+            return true;
         }
+        #[cfg(all(target_os = "linux", feature = "pipewire"))]
+        if self.pipewire_capture.is_some() {
+            return true;
+        }
+        self.capture_stream.is_some() || self.link_receiver.is_some()
     }
 
     fn start_loopback(&mut self) {
-        if self.capture_stream.is_some() {
+        // Started again while the last capture was still fading out
+        if self.capture_teardown_at.is_some() {
+            self.close_capture();
+        }
+        if self.is_capturing() {
             println!("Capture already running");
             return;
         }
 
-        let host = cpal::default_host();
-        let device = match host.default_output_device() {
+        match self.capture_source.clone() {
+            CaptureSource::SystemLoopback => {
+                if !self.start_excluding_capture() && !self.start_monitor_capture(None) {
+                    self.start_system_capture();
+                }
+            },
+            CaptureSource::Device { name } => self.start_device_capture(Some(&name)),
+            CaptureSource::Monitor { sink } => {
+                if !self.start_monitor_capture(Some(sink.clone())) {
+                    eprintln!("Monitor capture of {} is not available", sink);
+                }
+            },
+            CaptureSource::Application { name } => self.start_application_capture(&name),
+            CaptureSource::Network { port } => self.start_network_capture(port),
+        }
+
+        // Reopen the mic so it follows the capture sample rate
+        if let Some(mic_name) = self.mic_device.clone() {
+            self.start_mic(mic_name);
+        }
+        self.update_replay_buffer();
+        self.restart_streams();
+        self.update_passthrough();
+        if self.is_capturing() {
+            let _ = self.events.send(AudioEvent::CaptureStarted);
+        }
+    }
+
+    /// Builds the gain/fan-out stage for a new capture stream running at
+    /// `channels` and `sample_rate`; it converts the stream into the bus.
+    fn capture_processor(&mut self, channels: usize, sample_rate: u32) -> CaptureProcessor {
+        self.capture_channels = Some(channels);
+        // Sources other than devices deliver f32; device capture overrides this
+        self.capture_sample_format = Some(cpal::SampleFormat::F32);
+        let bus = self.bus_format();
+        let mut input = Converter::into_bus(bus::Format::new(channels, sample_rate), bus);
+        input.reserve(MAX_CAPTURE_PERIOD_FRAMES);
+        let frames = input.output_frames(MAX_CAPTURE_PERIOD_FRAMES);
+        let (channels, sample_rate) = (bus.channels, bus.sample_rate);
+        let samples = frames * channels;
+        // Fade in from silence; stop_loopback flips `stopping` to fade back out
+        self.capture_stopping.store(false, Ordering::Relaxed);
+        let mut fade = GainRamp::new(0.0, sample_rate, self.capture_fade_in_ms as f32);
+        fade.set_target(1.0);
+
+        CaptureProcessor {
+            feeds: self.feeds.feeds(),
+            input_volume: self.input_volume.clone(),
+            input_muted: self.input_muted.clone(),
+            gate_settings: self.noise_gate.handoff(),
+            ducking_settings: self.ducking.handoff(),
+            master_volume: self.master_volume.clone(),
+            master_muted: self.master_muted.clone(),
+            mic_source: self.mic_source.handoff(),
+            network_source: self.network_source.handoff(),
+            echo_render: self.echo_render.handoff(),
+            record_tap: self.record_tap.handoff(),
+            record_paused: self.record_paused.clone(),
+            replay_tap: self.replay_tap.handoff(),
+            mix_taps: self.mix_taps.handoff(),
+            meter: self.mix_meter.clone(),
+            stats: self.capture_stats.clone(),
+            events: self.events.clone(),
+            last_clip: None,
+            stopping: self.capture_stopping.clone(),
+            channels,
+            sample_rate,
+            fade_out_ms: self.capture_fade_out_ms as f32,
+            ramp: GainRamp::new(0.0, sample_rate, dsp::VOLUME_RAMP_MS),
+            mute_fade: GainRamp::new(1.0, sample_rate, dsp::MUTE_FADE_MS),
+            gate: NoiseGate::new(NoiseGateSettings::default(), sample_rate),
+            ducker: Ducker::new(DuckingSettings::default(), sample_rate),
+            master_ramp: GainRamp::new(0.0, sample_rate, dsp::VOLUME_RAMP_MS),
+            master_mute_fade: GainRamp::new(1.0, sample_rate, dsp::MUTE_FADE_MS),
+            fade,
+            passthrough: self.passthrough.clone(),
+            input,
+            converted: Vec::with_capacity(samples),
+            routes: self.route_control.handoff(),
+            capture_stem: Vec::with_capacity(samples),
+            mic_stem: Vec::with_capacity(samples),
+            network_stem: Vec::with_capacity(samples),
+            master_gains: Vec::with_capacity(frames),
+            scratch: Vec::with_capacity(samples),
+            routed: Vec::with_capacity(samples),
+            mic_frame: Vec::with_capacity(channels),
+            network_frame: Vec::with_capacity(channels),
+        }
+    }
+
+    /// Records a sink's monitor through PipeWire, the default sink's for
+    /// None. Returns false when PipeWire isn't available.
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    fn start_monitor_capture(&mut self, sink: Option<String>) -> bool {
+        let (channels, sample_rate) = (2, self.capture_rate_override.unwrap_or(PIPEWIRE_SAMPLE_RATE));
+        self.capture_sample_rate = Some(cpal::SampleRate(sample_rate));
+        let mut processor = self.capture_processor(channels, sample_rate);
+        match PwStream::capture(sink, channels, sample_rate, move |data| processor.process(data)) {
+            Ok(stream) => {
+                self.pipewire_capture = Some(stream);
+                true
+            },
+            Err(e) => {
+                eprintln!("Failed to start PipeWire capture: {}", e);
+                false
+            },
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "pipewire")))]
+    fn start_monitor_capture(&mut self, _sink: Option<String>) -> bool {
+        false
+    }
+
+    /// Records what plays on the default output. CoreAudio can't record an
+    /// output device, so macOS records the input of a loopback driver instead.
+    fn start_system_capture(&mut self) {
+        if !cfg!(target_os = "macos") {
+            self.start_device_capture(None);
+            return;
+        }
+        match loopback_input() {
+            Some(name) => self.start_device_capture(Some(&name)),
+            None => {
+                let error = "No loopback device found. Install BlackHole and add it to a Multi-Output Device used as the system output.";
+                eprintln!("{}", error);
+                let _ = self.events.send(AudioEvent::StreamError { stream: CAPTURE_STREAM.to_string(), error: error.to_string() });
+            },
+        }
+    }
+
+    /// Records an input device, or the default output through loopback.
+    fn start_device_capture(&mut self, input: Option<&str>) {
+        let host = host::current();
+        let device = match input {
+            Some(name) => host.input_devices().ok().and_then(|mut d| d.find(|d| d.name().unwrap_or_default() == name)),
+            None => host.default_output_device(),
+        };
+        let device = match device {
             Some(d) => d,
             None => {
-                eprintln!("No default output device found");
+                eprintln!("Capture device not found: {}", input.unwrap_or("default output"));
                 return;
             }
         };
 
         println!("Starting capture on: {}", device.name().unwrap_or_default());
 
-        let config = match device.default_output_config() {
+        let config = match input {
+            Some(_) => device.default_input_config(),
+            None => device.default_output_config(),
+        };
+        let config = match config {
             Ok(c) => c,
             Err(e) => {
                 eprintln!("Failed to get config: {}", e);
                 return;
             }
         };
+        let config = match self.capture_rate_override {
+            Some(rate) => {
+                let configs = match input {
+                    Some(_) => device.supported_input_configs().map(|c| c.collect::<Vec<_>>()),
+                    None => device.supported_output_configs().map(|c| c.collect::<Vec<_>>()),
+                };
+                match configs.ok().and_then(|c| sample_format::best_config(c.into_iter(), cpal::SampleRate(rate))) {
+                    Some(c) => c,
+                    None => {
+                        let error = format!("Capture device does not support {} Hz, using {} Hz", rate, config.sample_rate().0);
+                        eprintln!("{}", error);
+                        let _ = self.events.send(AudioEvent::StreamError { stream: CAPTURE_STREAM.to_string(), error });
+                        config
+                    },
+                }
+            },
+            None => config,
+        };
 
         // Save Sample Rate!
         self.capture_sample_rate = Some(config.sample_rate());
         println!("Capture Sample Rate: {}", config.sample_rate().0);
 
+        let sample_format = config.sample_format();
         let stream_config: cpal::StreamConfig = config.into();
-        let producers_handle = self.producers.clone();
-        let in_vol_handle = self.input_volume.clone();
-        let in_mute_handle = self.input_muted.clone();
+        let mut processor = self.capture_processor(stream_config.channels as usize, stream_config.sample_rate.0);
+        self.capture_sample_format = Some(sample_format);
+        let error_events = self.events.clone();
+        let failures = self.failures.clone();
 
-        let stream_res = device.build_input_stream(
+        let stream_res = sample_format::build_input_stream(
+            &device,
             &stream_config,
+            sample_format,
             move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                // Check Input Mute/Vol
-                let vol = if let Ok(m) = in_mute_handle.lock() {
-                    if *m { 0.0 } else { 
-                        if let Ok(v) = in_vol_handle.lock() { *v } else { 1.0 }
-                    }
-                } else { 0.0 };
-                
-                if let Ok(mut producers) = producers_handle.lock() {
-                    for (_name, producer) in producers.iter_mut() {
-                        for &sample in data {
-                            if !producer.is_full() {
-                                let _ = producer.push(sample * vol);
-                            }
-                        }
-                    }
-                }
+                processor.process(data);
             },
             move |err| {
                 eprintln!("Capture error: {}", err);
+                let _ = error_events.send(AudioEvent::StreamError { stream: CAPTURE_STREAM.to_string(), error: err.to_string() });
+                let _ = failures.send(StreamFailure { stream: CAPTURE_STREAM.to_string(), error: err.to_string() });
             },
-            None
         );
 
         match stream_res {
@@ -121,19 +767,231 @@ impl AudioActor {
         }
     }
 
+    /// Receives a linked instance's mix. The link runs in the bus format so
+    /// nothing converts on this side; senders resample.
+    fn start_network_capture(&mut self, port: u16) {
+        let bus = self.bus_format();
+        let (channels, sample_rate) = (bus.channels, cpal::SampleRate(bus.sample_rate));
+        self.capture_sample_rate = Some(sample_rate);
+        let mut processor = self.capture_processor(channels, sample_rate.0);
+
+        match LinkReceiver::start(port, channels, sample_rate.0, move |data| processor.process(data)) {
+            Ok(receiver) => self.link_receiver = Some(receiver),
+            Err(e) => eprintln!("Failed to start link receiver: {}", e),
+        }
+    }
+
+    #[cfg(windows)]
+    fn start_application_capture(&mut self, app_name: &str) {
+        let pid = match app_capture::find_process_id(app_name) {
+            Some(pid) => pid,
+            None => {
+                eprintln!("Application not running: {}", app_name);
+                return;
+            }
+        };
+
+        println!("Starting application capture on: {} (pid {})", app_name, pid);
+        self.start_process_capture(pid, true);
+    }
+
+    #[cfg(not(windows))]
+    fn start_application_capture(&mut self, app_name: &str) {
+        eprintln!("Per-application capture of '{}' requires Windows 10 or later", app_name);
+    }
+
+    /// Captures the whole system except an excluded application. Returns false
+    /// when no exclusion applies and the plain device loopback should be used.
+    #[cfg(windows)]
+    fn start_excluding_capture(&mut self) -> bool {
+        let running = self.capture_exclusions.iter()
+            .find_map(|name| app_capture::find_process_id(name).map(|pid| (name.clone(), pid)));
+        let Some((name, pid)) = running else {
+            return false;
+        };
+
+        println!("Starting capture excluding: {} (pid {})", name, pid);
+        self.start_process_capture(pid, false)
+    }
+
+    #[cfg(not(windows))]
+    fn start_excluding_capture(&mut self) -> bool {
+        false
+    }
+
+    #[cfg(windows)]
+    fn start_process_capture(&mut self, pid: u32, include_tree: bool) -> bool {
+        self.capture_sample_rate = Some(cpal::SampleRate(app_capture::APP_CAPTURE_SAMPLE_RATE));
+        let mut processor = self.capture_processor(app_capture::APP_CAPTURE_CHANNELS, app_capture::APP_CAPTURE_SAMPLE_RATE);
+
+        match app_capture::AppCapture::start(pid, include_tree, move |data| processor.process(data)) {
+            Ok(capture) => {
+                self.app_capture = Some(capture);
+                true
+            },
+            Err(e) => {
+                eprintln!("Failed to start application capture: {}", e);
+                false
+            }
+        }
+    }
+
+    fn set_capture_exclusions(&mut self, exclusions: Vec<String>) {
+        if exclusions == self.capture_exclusions {
+            return;
+        }
+        println!("Setting capture exclusions: {:?}", exclusions);
+        // Refused lists (e.g. from an older config) leave the capture unfiltered
+        if let Err(error) = check_exclusions(&exclusions) {
+            eprintln!("{}", error);
+            let _ = self.events.send(AudioEvent::CaptureExclusionsRejected { error });
+            if self.capture_exclusions.is_empty() {
+                return;
+            }
+            self.capture_exclusions.clear();
+        } else {
+            self.capture_exclusions = exclusions;
+        }
+
+        if self.is_capturing() && self.capture_source == CaptureSource::SystemLoopback {
+            self.close_capture();
+            self.start_loopback();
+        }
+    }
+
+    /// Forces the capture, and the bus with it, to `rate`, or back to the
+    /// device default and a 48 kHz bus. Everything that runs at the bus rate
+    /// (outputs, the mic, a running capture) reopens at the new one.
+    fn set_capture_sample_rate(&mut self, rate: Option<u32>) {
+        if rate == self.capture_rate_override {
+            return;
+        }
+        println!("Setting capture sample rate: {:?}", rate);
+        let capturing = self.is_capturing();
+        if capturing {
+            self.close_capture();
+        }
+        self.capture_rate_override = rate;
+        let outputs: Vec<String> = self.output_streams.keys().cloned().collect();
+        for name in outputs {
+            self.reopen_output(name);
+        }
+        if capturing {
+            self.start_loopback();
+        } else if let Some(mic_name) = self.mic_device.clone() {
+            self.start_mic(mic_name);
+        }
+    }
+
+    fn set_capture_source(&mut self, source: CaptureSource) {
+        if source == self.capture_source {
+            return;
+        }
+        println!("Setting capture source: {:?}", source);
+        self.capture_source = source;
+
+        // Restart a running capture on the new source
+        if self.is_capturing() {
+            self.close_capture();
+            self.start_loopback();
+        }
+    }
+
+    /// Stops the capture after letting the callback fade it out; the stream is
+    /// torn down by `finish_teardowns` once the fade is over.
     fn stop_loopback(&mut self) {
+        if self.capture_teardown_at.is_some() {
+            return;
+        }
+        if self.is_capturing() && self.capture_fade_out_ms > 0 {
+            self.capture_stopping.store(true, Ordering::Relaxed);
+            self.capture_teardown_at = Some(Instant::now() + Duration::from_millis(self.capture_fade_out_ms as u64));
+            return;
+        }
+        self.close_capture();
+    }
+
+    /// Drops the capture at once. Restarts call this directly, since the
+    /// stream is either broken or replaced straight away.
+    fn close_capture(&mut self) {
+        self.capture_teardown_at = None;
+        let was_capturing = self.capture_open();
+
         // Drop the stream to stop it
         self.capture_stream = None;
+        self.link_receiver = None;
+        #[cfg(all(target_os = "linux", feature = "pipewire"))]
+        {
+            self.pipewire_capture = None;
+        }
+        #[cfg(windows)]
+        {
+            self.app_capture = None;
+        }
         println!("Capture stopped");
+        if was_capturing {
+            let _ = self.events.send(AudioEvent::CaptureStopped);
+        }
     }
 
     fn set_volume(&mut self, device_name: String, volume: f32) {
-        println!("Setting volume for '{}': {}", device_name, volume);
+        let previous = self.positions.get(&device_name).copied();
+        self.apply_position(&device_name, volume);
+        if let Some(previous) = previous {
+            self.follow_link_groups(&device_name, previous, volume);
+        }
+    }
+
+    fn apply_position(&mut self, device_name: &str, volume: f32) {
+        if self.volumes.contains_key(device_name) {
+            self.positions.insert(device_name.to_string(), volume);
+        }
+        let gain = self.taper.position_to_gain(volume);
+        self.set_gain(device_name.to_string(), gain);
+    }
+
+    /// Moves every output linked to `leader` by the same relative amount.
+    fn follow_link_groups(&mut self, leader: &str, previous: f32, volume: f32) {
+        let mut followers: Vec<String> = self.link_groups.iter()
+            .filter(|g| g.members.iter().any(|m| m == leader))
+            .flat_map(|g| g.members.iter())
+            .filter(|m| *m != leader)
+            .cloned()
+            .collect();
+        followers.sort();
+        followers.dedup();
+
+        for follower in followers {
+            let current = match self.positions.get(&follower) {
+                Some(&p) => p,
+                None => continue,
+            };
+            let linked = linked_position(current, previous, volume);
+            self.apply_position(&follower, linked);
+            let _ = self.events.send(AudioEvent::VolumeChanged { device: follower, volume: linked });
+        }
+    }
+
+    fn set_link_groups(&mut self, groups: Vec<LinkGroup>) {
+        println!("Setting {} link group(s)", groups.len());
+        self.link_groups = groups;
+    }
+
+    /// Sets the fader to the position giving `db`; levels above unity need
+    /// the boost instead.
+    fn set_volume_db(&mut self, device_name: String, db: f32) {
+        let position = self.taper.gain_to_position(dsp::db_to_gain(db.clamp(dsp::MIN_DB, 0.0)));
+        self.set_volume(device_name.clone(), position);
+        if self.volumes.contains_key(&device_name) {
+            let _ = self.events.send(AudioEvent::VolumeChanged { device: device_name, volume: position });
+        }
+    }
+
+    fn set_gain(&mut self, device_name: String, gain: f32) {
+        println!("Setting gain for '{}': {}", device_name, gain);
         if let Some(vol) = self.volumes.get(&device_name) {
-             if let Ok(mut v) = vol.lock() {
-                 *v = volume;
-                 println!("Volume key found and updated.");
-             }
+             vol.store(gain);
+             println!("Volume key found and updated.");
         } else {
             println!("Device '{}' not found in volumes map. Available keys: {:?}", device_name, self.volumes.keys());
         }
@@ -142,188 +1000,2105 @@ impl AudioActor {
     fn set_mute(&mut self, device_name: String, muted: bool) {
         println!("Setting mute for '{}': {}", device_name, muted);
         if let Some(m) = self.mutes.get(&device_name) {
-             if let Ok(mut v) = m.lock() { *v = muted; }
+             let changed = m.swap(muted, Ordering::Relaxed) != muted;
+             if changed {
+                 let _ = self.events.send(AudioEvent::OutputMuteChanged { device: device_name, muted });
+             }
         } else {
              println!("Device '{}' not found in mutes map.", device_name);
         }
     }
 
-    fn set_input_volume(&mut self, volume: f32) {
-        println!("Setting input volume: {}", volume);
-        if let Ok(mut v) = self.input_volume.lock() { *v = volume; }
+    fn set_width(&mut self, device_name: String, width: f32) {
+        println!("Setting stereo width for '{}': {}", device_name, width);
+        if let Some(w) = self.widths.get(&device_name) {
+             w.store(width.clamp(0.0, dsp::MAX_STEREO_WIDTH));
+        } else {
+             println!("Device '{}' not found in widths map.", device_name);
+        }
     }
 
-    fn set_input_mute(&mut self, muted: bool) {
-         println!("Setting input mute: {}", muted);
-         if let Ok(mut v) = self.input_muted.lock() { *v = muted; }
+    fn set_boost(&mut self, device_name: String, boost_db: f32) {
+        println!("Setting boost for '{}': {} dB", device_name, boost_db);
+        if let Some(b) = self.boosts.get(&device_name) {
+             b.store(dsp::db_to_gain(boost_db.clamp(0.0, dsp::MAX_BOOST_DB)));
+        } else {
+             println!("Device '{}' not found in boosts map.", device_name);
+        }
     }
 
-    fn add_output(&mut self, device_name: String) {
-        if self.output_streams.contains_key(&device_name) {
-            println!("Device exists: {}", device_name);
+    /// Stores the buffer sizes of an output; an open output is reopened with them.
+    fn set_output_buffer(&mut self, device_name: String, buffer: OutputBuffer) {
+        if self.output_buffers.get(&device_name).copied().unwrap_or_default() == buffer {
             return;
         }
+        println!("Setting buffer for '{}': {:?}", device_name, buffer);
+        self.output_buffers.insert(device_name.clone(), buffer);
+        if self.output_streams.contains_key(&device_name) {
+            self.reopen_output(device_name);
+        }
+    }
 
-        let host = cpal::default_host();
-        let device = match host.output_devices() {
-            Ok(mut devices) => devices.find(|d| d.name().unwrap_or_default() == device_name),
-            Err(_) => None,
+    /// Marks an output for exclusive mode; an open output is reopened.
+    fn set_exclusive(&mut self, device_name: String, exclusive: bool) {
+        let changed = if exclusive {
+            self.exclusive_outputs.insert(device_name.clone())
+        } else {
+            self.exclusive_outputs.remove(&device_name)
         };
+        if changed && self.output_streams.contains_key(&device_name) {
+            println!("Setting exclusive mode for '{}': {}", device_name, exclusive);
+            self.reopen_output(device_name);
+        }
+    }
 
-        let device = match device {
-            Some(d) => d,
-            None => {
-                eprintln!("Device not found: {}", device_name);
-                return;
-            }
-        };
+    /// Sets the dither used when an output converts to 16 bits or fewer; an
+    /// open output is reopened.
+    fn set_dither(&mut self, device_name: String, dither: Dither) {
+        let previous = self.output_dither.insert(device_name.clone(), dither).unwrap_or_default();
+        if previous != dither && self.output_streams.contains_key(&device_name) {
+            println!("Setting dither for '{}': {:?}", device_name, dither);
+            self.reopen_output(device_name);
+        }
+    }
 
-        // Try to find matching config
-        let target_rate = self.capture_sample_rate.unwrap_or(cpal::SampleRate(48000));
-        
-        let mut best_config = None;
-        if let Ok(configs) = device.supported_output_configs() {
-            for config in configs {
-                if config.min_sample_rate() <= target_rate && config.max_sample_rate() >= target_rate {
-                     // Found range containing our target
-                     best_config = Some(config.with_sample_rate(target_rate));
-                     break;
-                }
+    /// Sets the effect plugins of an output. Bypass and parameter changes
+    /// apply live; adding, removing or reordering plugins reloads the chain.
+    fn set_inserts(&mut self, device_name: String, inserts: Vec<InsertConfig>) {
+        if let Some(control) = self.insert_controls.get(&device_name) {
+            control.collect_retired();
+            let current = self.output_inserts.get(&device_name).map(Vec::as_slice).unwrap_or_default();
+            if !control.update(current, &inserts) {
+                println!("Loading {} insert(s) on '{}'", inserts.len(), device_name);
+                control.load(inserts.clone());
             }
         }
+        self.output_inserts.insert(device_name, inserts);
+    }
 
-        let config: cpal::StreamConfig = match best_config {
-            Some(c) => c.into(),
-            None => {
-                 println!("Warning: Could not match sample rate {}. Using default.", target_rate.0);
-                 device.default_output_config().map(|c| c.into()).unwrap_or_else(|_| cpal::StreamConfig { 
-                    channels: 2, sample_rate: cpal::SampleRate(44100), buffer_size: cpal::BufferSize::Default 
-                })
-            }
-        };
-        
-        println!("Output {} configured at: {}", device_name, config.sample_rate.0);
+    /// The insert at `index` on an output, for reading its parameters on the
+    /// main thread.
+    fn insert_params(&self, device_name: &str, index: usize) -> Result<InsertParams, String> {
+        let control = self.insert_controls.get(device_name).ok_or_else(|| format!("Output '{}' is not open", device_name))?;
+        control.params(index)
+    }
 
-        let (producer, mut consumer) = RingBuffer::<f32>::new(16384); // Increased buffer size
-        
-        if let Ok(mut lock) = self.producers.lock() {
-            lock.push((device_name.clone(), producer));
+    fn set_soft_clip(&mut self, device_name: String, enabled: bool) {
+        println!("Setting soft clip for '{}': {}", device_name, enabled);
+        if let Some(c) = self.clippers.get(&device_name) {
+             c.store(enabled, Ordering::Relaxed);
+        } else {
+             println!("Device '{}' not found in clippers map.", device_name);
         }
+    }
 
-        // Volume handle
-        let volume_handle = Arc::new(Mutex::new(1.0));
-        self.volumes.insert(device_name.clone(), volume_handle.clone());
-        
-        // Mute handle
-        let mute_handle = Arc::new(Mutex::new(false));
-        self.mutes.insert(device_name.clone(), mute_handle.clone());
+    fn set_solo(&mut self, device_name: String, solo: bool) {
+        println!("Setting solo for '{}': {}", device_name, solo);
+        if !self.output_streams.contains_key(&device_name) {
+            println!("Device '{}' not active, ignoring solo.", device_name);
+            return;
+        }
+        if solo {
+            self.soloed.insert(device_name);
+        } else {
+            self.soloed.remove(&device_name);
+        }
+        self.update_solo_mutes();
+    }
 
-        let vol_clone = volume_handle.clone();
-        let mute_clone = mute_handle.clone();
+    /// Silences every output that isn't soloed while at least one solo is active.
+    /// The user's own mute state is left untouched.
+    fn update_solo_mutes(&self) {
+        for (name, handle) in &self.solo_mutes {
+            let silenced = !self.soloed.is_empty() && !self.soloed.contains(name);
+            handle.store(silenced, Ordering::Relaxed);
+        }
+    }
 
-        let stream_res = device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let current_vol = if let Ok(m) = mute_clone.lock() {
-                    if *m { 0.0 } else {
-                         if let Ok(g) = vol_clone.lock() { *g } else { 1.0 }
-                    }
-                } else { 0.0 };
-                
-                for sample in data.iter_mut() {
-                     let val = consumer.pop().unwrap_or(0.0);
-                     *sample = val * current_vol;
-                }
-            },
-            move |err| eprintln!("Output error: {}", err),
-            None
-        );
+    fn set_input_volume(&mut self, volume: f32) {
+        self.input_position = volume;
+        let gain = self.taper.position_to_gain(volume);
+        self.set_input_gain(gain);
+    }
 
-        match stream_res {
-            Ok(stream) => {
-                let _ = stream.play();
-                self.output_streams.insert(device_name.clone(), stream);
-                println!("Added output with volume control: {}", device_name);
+    fn set_input_volume_db(&mut self, db: f32) {
+        let position = self.taper.gain_to_position(dsp::db_to_gain(db.clamp(dsp::MIN_DB, 0.0)));
+        self.set_input_volume(position);
+    }
+
+    fn set_input_gain(&mut self, gain: f32) {
+        println!("Setting input gain: {}", gain);
+        self.input_volume.store(gain);
+    }
+
+    fn start_mic(&mut self, device_name: String) {
+        self.stop_mic();
+        let target_rate = cpal::SampleRate(self.bus_format().sample_rate);
+        match mic::open_mic(&device_name, target_rate, self.mic_controls.clone()) {
+            Ok((stream, source)) => {
+                self.mic_format = Some((source.channels(), source.device_sample_rate()));
+                self.mic_source.set(Some(source));
+                self.mic_stream = Some(stream);
+                self.mic_device = Some(device_name.clone());
+                println!("Mic added to mix: {}", device_name);
             },
-            Err(e) => eprintln!("Failed to build output stream: {}", e),
+            Err(e) => eprintln!("Failed to open mic: {}", e),
         }
+        self.update_echo_canceller();
+        self.update_passthrough();
     }
 
-    fn remove_output(&mut self, device_name: String) {
-        // Drop the stream first to stop playback
-        if self.output_streams.remove(&device_name).is_some() {
-             println!("Stopped output stream: {}", device_name);
+    fn stop_mic(&mut self) {
+        if self.mic_stream.take().is_some() {
+            println!("Mic removed from mix");
         }
+        self.mic_device = None;
+        self.mic_format = None;
+        self.mic_source.set(None);
+        self.update_echo_canceller();
+        self.update_passthrough();
+    }
 
-        // Remove from producers list to stop feeding it data
-        if let Ok(mut lock) = self.producers.lock() {
-            lock.retain(|(name, _)| name != &device_name);
-        }
+    fn set_mic_volume(&mut self, volume: f32) {
+        let gain = self.taper.position_to_gain(volume);
+        println!("Setting mic gain: {}", gain);
+        self.mic_controls.volume.store(gain);
+    }
 
-        // Remove volume control
-        self.volumes.remove(&device_name);
-        // Remove mute control
-        self.mutes.remove(&device_name);
+    fn set_mic_mute(&mut self, muted: bool) {
+        println!("Setting mic mute: {}", muted);
+        self.mic_controls.muted.store(muted, Ordering::Relaxed);
     }
-}
 
-pub fn spawn_audio_thread() -> Sender<AudioCommand> {
-    let (tx, rx) = unbounded();
-    thread::spawn(move || {
-        let mut actor = AudioActor::new();
-        while let Ok(cmd) = rx.recv() {
-            match cmd {
-                AudioCommand::StartLoopback => actor.start_loopback(),
-                AudioCommand::StopLoopback => actor.stop_loopback(),
-                AudioCommand::AddOutput(name) => actor.add_output(name),
-                AudioCommand::RemoveOutput(name) => actor.remove_output(name),
-                AudioCommand::SetVolume(name, vol) => actor.set_volume(name, vol),
-                AudioCommand::SetMute(name, mute) => actor.set_mute(name, mute),
-                AudioCommand::SetInputVolume(vol) => actor.set_input_volume(vol),
-                AudioCommand::SetInputMute(mute) => actor.set_input_mute(mute),
-            }
-        }
-    });
-    tx
-}
+    fn set_mic_noise_suppression(&mut self, enabled: bool) {
+        println!("Setting mic noise suppression: {}", enabled);
+        self.mic_controls.noise_suppression.store(enabled, Ordering::Relaxed);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rtrb::RingBuffer;
+    fn set_bit_perfect(&mut self, enabled: bool) {
+        println!("Setting bit-perfect mode: {}", enabled);
+        self.bit_perfect = enabled;
+        self.update_passthrough();
+    }
 
-    #[test]
-    fn test_ring_buffer_transfer() {
-        let (mut producer, mut consumer) = RingBuffer::<f32>::new(100);
-        let _ = producer.push(0.5);
-        assert_eq!(consumer.pop().unwrap(), 0.5);
+    /// Bit-perfect passthrough applies while exactly one output is open, the
+    /// capture and that output (a device stream) both run in the bus format
+    /// so neither converts, their sample formats match, and nothing else
+    /// (mic, network input) is mixed in. Otherwise the normal gain and DSP
+    /// stages run.
+    fn update_passthrough(&mut self) {
+        let mut outputs = self.output_streams.keys();
+        let only_output = match (outputs.next(), outputs.next()) {
+            (Some(name), None) => Some(name),
+            _ => None,
+        };
+        let bus = self.capture_format();
+        let capture = self.capture_channels.zip(self.capture_sample_rate.map(|r| r.0));
+        let formats_match = bus.is_some() && capture == bus && only_output.is_some_and(|name| {
+            self.output_formats.get(name).copied() == bus
+                && self.output_sample_formats.get(name).copied() == self.capture_sample_format
+        });
+        let mixing = self.mic_stream.is_some() || self.vban_receiver.is_some();
+        let active = self.bit_perfect && formats_match && !mixing;
+        if self.passthrough.swap(active, Ordering::Relaxed) != active {
+            println!("Bit-perfect passthrough {}", if active { "active" } else { "inactive" });
+        }
     }
 
-    #[test]
-    fn test_volume_application_logic() {
-        let volume = 0.5;
-        let input_sample = 1.0;
-        let output_sample = input_sample * volume;
-        assert_eq!(output_sample, 0.5);
+    fn set_route(&mut self, source: RouteSource, output: String, gain: f32) {
+        println!("Setting route {:?} -> '{}': {}", source, output, gain);
+        self.routes.set(source, &output, gain);
+        self.route_control.set(self.routes.clone());
+    }
+
+    fn set_routes(&mut self, routes: Vec<Route>) {
+        self.routes = RoutingMatrix::from_routes(&routes);
+        self.route_control.set(self.routes.clone());
+    }
+
+    fn set_echo_cancellation(&mut self, enabled: bool) {
+        println!("Setting echo cancellation: {}", enabled);
+        self.echo_cancellation = enabled;
+        self.update_echo_canceller();
+    }
+
+    /// Rebuilds (or removes) the echo canceller for the current mic and bus
+    /// formats. Both sides must run at 48 kHz for WebRTC APM.
+    fn update_echo_canceller(&mut self) {
+        let mut pair = None;
+        if let (true, Some((mic_channels, mic_rate)), Some((capture_channels, capture_rate))) =
+            (self.echo_cancellation, self.mic_format, self.capture_format())
+        {
+            if mic_rate != echo::ECHO_SAMPLE_RATE || capture_rate != echo::ECHO_SAMPLE_RATE {
+                println!("Echo cancellation unavailable at {} Hz / {} Hz", mic_rate, capture_rate);
+            } else {
+                match echo::new_pair(mic_channels, capture_channels) {
+                    Ok(p) => pair = Some(p),
+                    Err(e) => eprintln!("Failed to create echo canceller: {}", e),
+                }
+            }
+        }
+
+        self.report_echo_errors();
+        let (capture, render, errors) = match pair {
+            Some((capture, render, errors)) => (Some(capture), Some(render), Some(errors)),
+            None => (None, None, None),
+        };
+        self.echo_render.set(render);
+        self.mic_controls.echo.set(capture);
+        self.echo_errors = errors;
+    }
+
+    /// Logs APM failures counted by the echo canceller's callbacks.
+    fn report_echo_errors(&mut self) {
+        let errors = self.echo_errors.as_ref().map(|e| e.take()).unwrap_or(0);
+        if errors > 0 {
+            eprintln!("Echo cancellation failed on {} frames", errors);
+        }
+    }
+
+    fn start_recording(&mut self, directory: PathBuf, split: SplitSettings, tags: TagOptions) {
+        if self.recorder.is_some() {
+            println!("Recording already running");
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => {
+                self.recording_failed("Capture must be running to record".to_string());
+                return;
+            }
+        };
+
+        let path = recording::recording_path(&directory, self.recording_settings.format);
+        self.recording_split = split;
+        let now = chrono::Local::now();
+        let mut devices: Vec<String> = self.output_streams.keys().cloned().collect();
+        devices.sort();
+        let metadata = RecordingMetadata {
+            title: tags.title.unwrap_or_else(|| format!("Audio Merge {}", now.format("%Y-%m-%d %H:%M"))),
+            date: now.format("%Y-%m-%d").to_string(),
+            devices,
+            now_playing: if tags.now_playing { now_playing::current() } else { None },
+        };
+        self.recording_metadata = Some(metadata.clone());
+        match Recorder::start(path, &self.recording_settings, split, metadata, channels, sample_rate) {
+            Ok((recorder, producer)) => {
+                self.record_paused.store(false, Ordering::Relaxed);
+                self.record_tap.set(Some(producer));
+                let path = recorder.path().to_path_buf();
+                self.recorder = Some(recorder);
+                println!("Recording to {}", path.display());
+                let _ = self.events.send(AudioEvent::RecordingStarted { path: path.display().to_string() });
+                let outputs: Vec<String> = self.output_streams.keys().cloned().collect();
+                for name in outputs {
+                    self.start_stem(&name);
+                }
+            },
+            Err(e) => self.recording_failed(e),
+        }
+    }
+
+    /// Starts recording an output's stem if a recording with stems is running.
+    fn start_stem(&mut self, device_name: &str) {
+        let mix_path = match &self.recorder {
+            Some(recorder) if self.recording_settings.stems => recorder.base_path().to_path_buf(),
+            _ => return,
+        };
+        let (channels, sample_rate) = match self.output_formats.get(device_name) {
+            Some(&format) => format,
+            None => return,
+        };
+
+        let path = recording::stem_path(&mix_path, device_name);
+        let mut metadata = match &self.recording_metadata {
+            Some(m) => m.clone(),
+            None => return,
+        };
+        metadata.title = format!("{} ({})", metadata.title, device_name);
+        metadata.devices = vec![device_name.to_string()];
+        match Recorder::start(path, &self.recording_settings, self.recording_split, metadata, channels, sample_rate) {
+            Ok((recorder, producer)) => {
+                let path = recorder.path().to_path_buf();
+                if self.recording_paused {
+                    self.paused_stem_taps.insert(device_name.to_string(), producer);
+                } else if let Some(tap) = self.stem_taps.get(device_name) {
+                    if let Ok(mut t) = tap.lock() { *t = Some(producer); }
+                }
+                self.stem_recorders.insert(device_name.to_string(), recorder);
+                println!("Recording stem to {}", path.display());
+            },
+            Err(e) => self.recording_failed(format!("Stem for {}: {}", device_name, e)),
+        }
+    }
+
+    fn stop_stem(&mut self, device_name: &str) {
+        self.paused_stem_taps.remove(device_name);
+        if let Some(tap) = self.stem_taps.get(device_name) {
+            if let Ok(mut t) = tap.lock() { *t = None; }
+        }
+        if let Some(recorder) = self.stem_recorders.remove(device_name) {
+            match recorder.finish() {
+                Ok(path) => println!("Stem saved: {}", path.display()),
+                Err(e) => self.recording_failed(format!("Stem for {}: {}", device_name, e)),
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        let stems: Vec<String> = self.stem_recorders.keys().cloned().collect();
+        for name in stems {
+            self.stop_stem(&name);
+        }
+        self.record_tap.set(None);
+        self.record_paused.store(false, Ordering::Relaxed);
+        self.recording_paused = false;
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(path) => {
+                    println!("Recording saved: {}", path.display());
+                    let _ = self.events.send(AudioEvent::RecordingStopped { path: path.display().to_string() });
+                },
+                Err(e) => self.recording_failed(e),
+            }
+        }
+    }
+
+    /// Stops feeding the recording (and its stems) without closing the files.
+    fn pause_recording(&mut self) {
+        if self.recorder.is_none() || self.recording_paused {
+            return;
+        }
+        self.record_paused.store(true, Ordering::Relaxed);
+        for (name, tap) in &self.stem_taps {
+            if let Some(producer) = tap.lock().ok().and_then(|mut t| t.take()) {
+                self.paused_stem_taps.insert(name.clone(), producer);
+            }
+        }
+        self.recording_paused = true;
+        println!("Recording paused");
+        let _ = self.events.send(AudioEvent::RecordingPaused);
+    }
+
+    fn resume_recording(&mut self) {
+        if !self.recording_paused {
+            return;
+        }
+        self.record_paused.store(false, Ordering::Relaxed);
+        for (name, producer) in self.paused_stem_taps.drain() {
+            if let Some(tap) = self.stem_taps.get(&name) {
+                if let Ok(mut t) = tap.lock() { *t = Some(producer); }
+            }
+        }
+        self.recording_paused = false;
+        println!("Recording resumed");
+        let _ = self.events.send(AudioEvent::RecordingResumed);
+    }
+
+    fn snapshot(&self) -> AudioStateSnapshot {
+        let mut outputs: Vec<String> = self.output_streams.keys()
+            .filter(|name| !self.retiring_outputs.contains_key(*name))
+            .cloned()
+            .collect();
+        outputs.sort();
+        let muted_outputs = outputs
+            .iter()
+            .filter(|name| self.mutes.get(*name).is_some_and(|m| m.load(Ordering::Relaxed)))
+            .cloned()
+            .collect();
+        AudioStateSnapshot {
+            capturing: self.is_capturing(),
+            capture_source: self.capture_source.clone(),
+            volumes: self.positions.iter().map(|(name, &p)| (name.clone(), p)).collect(),
+            muted_outputs,
+            outputs,
+            input_volume: self.input_position,
+            input_muted: self.input_muted.load(Ordering::Relaxed),
+            master_volume: self.master_position,
+            master_muted: self.master_muted.load(Ordering::Relaxed),
+            mic_device: self.mic_device.clone(),
+            recording: self.recorder.as_ref().map(|r| RecordingState {
+                path: r.path().display().to_string(),
+                paused: self.recording_paused,
+            }),
+            replay_enabled: self.replay.is_some(),
+            icecast_streaming: self.icecast.is_some(),
+            http_streaming: self.http_stream.is_some(),
+            hls_streaming: self.hls.is_some(),
+            webrtc_monitoring: self.webrtc.is_some(),
+            rtp_sdp: self.rtp.as_ref().map(|r| r.sdp().to_string()),
+            sync_serving: self.sync_server.is_some(),
+            sync_client: self.sync_client.is_some(),
+            bit_perfect: self.passthrough.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Peak levels since the last call, resetting the meters.
+    fn meters(&self) -> MeterSnapshot {
+        let take = |meter: &Arc<AtomicF32>| meter.swap(0.0);
+        MeterSnapshot {
+            mix: take(&self.mix_meter),
+            outputs: self.meters.iter().map(|(name, meter)| (name.clone(), take(meter))).collect(),
+        }
+    }
+
+    fn metrics(&self) -> EngineMetrics {
+        EngineMetrics {
+            capturing: self.is_capturing(),
+            capture: self.capture_stats.snapshot(),
+            outputs: self.stats.iter().map(|(name, stats)| (name.clone(), stats.snapshot())).collect(),
+        }
+    }
+
+    fn recording_failed(&self, error: String) {
+        eprintln!("Recording failed: {}", error);
+        let _ = self.events.send(AudioEvent::RecordingFailed { error });
+    }
+
+    fn set_recording_settings(&mut self, settings: RecordingSettings) {
+        println!("Setting recording settings: {:?}", settings);
+        // Applies to the next recording; the running one keeps its encoder
+        self.recording_settings = settings;
+    }
+
+    fn set_replay_settings(&mut self, settings: ReplaySettings) {
+        println!("Setting replay buffer: {:?}", settings);
+        let changed = settings.enabled != self.replay_settings.enabled || settings.seconds != self.replay_settings.seconds;
+        self.replay_settings = settings;
+        if changed {
+            self.update_replay_buffer();
+        }
+    }
+
+    /// (Re)creates the replay buffer for the current capture format. Any
+    /// buffered history is lost.
+    fn update_replay_buffer(&mut self) {
+        self.replay_tap.set(None);
+        self.replay = None;
+
+        if !self.replay_settings.enabled || !self.is_capturing() {
+            return;
+        }
+        if let Some((channels, rate)) = self.capture_format() {
+            let (replay, producer) = ReplayBuffer::start(self.replay_settings.seconds, channels, rate);
+            self.replay_tap.set(Some(producer));
+            self.replay = Some(replay);
+        }
+    }
+
+    fn save_replay(&mut self, directory: PathBuf) {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => {
+                self.recording_failed("Replay buffer is not running".to_string());
+                return;
+            }
+        };
+
+        let path = recording::replay_path(&directory, self.recording_settings.format);
+        let events = self.events.clone();
+        replay.save(path, &self.recording_settings, move |result| {
+            let event = match result {
+                Ok(path) => {
+                    println!("Replay saved: {}", path.display());
+                    AudioEvent::ReplaySaved { path: path.display().to_string() }
+                },
+                Err(error) => {
+                    eprintln!("Failed to save replay: {}", error);
+                    AudioEvent::RecordingFailed { error }
+                },
+            };
+            let _ = events.send(event);
+        });
+    }
+
+    fn add_mix_tap(&mut self, name: &str, producer: Producer<f32>) {
+        let name = name.to_string();
+        self.mix_taps.update(move |taps| {
+            taps.retain(|(n, _)| n != &name);
+            taps.push((name, producer));
+        });
+    }
+
+    fn remove_mix_tap(&mut self, name: &str) {
+        let name = name.to_string();
+        self.mix_taps.update(move |taps| taps.retain(|(n, _)| n != &name));
+    }
+
+    /// Format of the mix bus, whether or not anything is captured.
+    fn bus_format(&self) -> bus::Format {
+        bus::format(self.capture_rate_override)
+    }
+
+    /// Format of the captured mix (the bus), if capture is running.
+    fn capture_format(&self) -> Option<(usize, u32)> {
+        let bus = self.bus_format();
+        self.is_capturing().then_some((bus.channels, bus.sample_rate))
+    }
+
+    /// Network streams follow the capture format, so they restart with it.
+    fn restart_streams(&mut self) {
+        if let Some(settings) = self.icecast_settings.clone() {
+            self.start_icecast(settings);
+        }
+        self.update_http_stream();
+        self.update_hls();
+        self.update_webrtc();
+        self.update_vban_receiver();
+        self.update_rtp();
+        self.update_sync_server();
+    }
+
+    fn set_vban_receiver(&mut self, settings: VbanReceiverSettings) {
+        self.vban_receiver_settings = settings;
+        self.update_vban_receiver();
+    }
+
+    fn update_vban_receiver(&mut self) {
+        self.network_source.set(None);
+        self.vban_receiver = None;
+        if !self.vban_receiver_settings.enabled {
+            self.update_passthrough();
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match VbanReceiver::start(self.vban_receiver_settings.clone(), channels, sample_rate) {
+            Ok((receiver, source)) => {
+                self.network_source.set(Some(source));
+                self.vban_receiver = Some(receiver);
+            },
+            Err(e) => eprintln!("Failed to start VBAN receiver: {}", e),
+        }
+        self.update_passthrough();
+    }
+
+    fn set_rtp_settings(&mut self, settings: RtpSettings) {
+        self.rtp_settings = settings;
+        self.update_rtp();
+    }
+
+    fn update_rtp(&mut self) {
+        self.remove_mix_tap("rtp");
+        self.rtp = None;
+        if !self.rtp_settings.enabled {
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match RtpSender::start(self.rtp_settings.clone(), channels, sample_rate) {
+            Ok((sender, producer)) => {
+                self.add_mix_tap("rtp", producer);
+                self.rtp = Some(sender);
+            },
+            Err(e) => eprintln!("Failed to start RTP stream: {}", e),
+        }
+    }
+
+    fn set_sync_server(&mut self, settings: SyncServerSettings) {
+        self.sync_server_settings = settings;
+        self.update_sync_server();
+    }
+
+    fn update_sync_server(&mut self) {
+        self.remove_mix_tap("sync");
+        self.sync_server = None;
+        if !self.sync_server_settings.enabled {
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match SyncServer::start(self.sync_server_settings.clone(), channels, sample_rate) {
+            Ok((server, producer)) => {
+                self.add_mix_tap("sync", producer);
+                self.sync_server = Some(server);
+            },
+            Err(e) => eprintln!("Failed to start sync server: {}", e),
+        }
+    }
+
+    fn set_sync_client(&mut self, settings: SyncClientSettings) {
+        self.sync_client = None;
+        if settings.enabled {
+            println!("Following sync server {}:{} on {}", settings.server, settings.port, settings.device);
+            self.sync_client = Some(SyncClient::start(settings));
+        }
+    }
+
+    fn set_http_stream_settings(&mut self, settings: HttpStreamSettings) {
+        self.http_stream_settings = settings;
+        self.update_http_stream();
+    }
+
+    /// (Re)starts the HTTP server to match its settings and the capture format.
+    fn update_http_stream(&mut self) {
+        self.remove_mix_tap("http");
+        self.http_stream = None;
+        if !self.http_stream_settings.enabled {
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match HttpStreamServer::start(self.http_stream_settings.clone(), channels, sample_rate) {
+            Ok((server, producer)) => {
+                self.add_mix_tap("http", producer);
+                self.http_stream = Some(server);
+            },
+            Err(e) => eprintln!("Failed to start HTTP stream: {}", e),
+        }
+    }
+
+    fn set_hls_settings(&mut self, settings: HlsSettings) {
+        self.hls_settings = settings;
+        self.update_hls();
+    }
+
+    fn update_hls(&mut self) {
+        self.remove_mix_tap("hls");
+        self.hls = None;
+        if !self.hls_settings.enabled {
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match HlsServer::start(self.hls_settings.clone(), channels, sample_rate) {
+            Ok((server, producer)) => {
+                self.add_mix_tap("hls", producer);
+                self.hls = Some(server);
+            },
+            Err(e) => eprintln!("Failed to start HLS output: {}", e),
+        }
+    }
+
+    fn set_webrtc_settings(&mut self, settings: WebRtcSettings) {
+        self.webrtc_settings = settings;
+        self.update_webrtc();
+    }
+
+    fn update_webrtc(&mut self) {
+        self.remove_mix_tap("webrtc");
+        self.webrtc = None;
+        if !self.webrtc_settings.enabled {
+            return;
+        }
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => return,
+        };
+        match WebRtcServer::start(self.webrtc_settings.clone(), channels, sample_rate) {
+            Ok((server, producer)) => {
+                self.add_mix_tap("webrtc", producer);
+                self.webrtc = Some(server);
+            },
+            Err(e) => eprintln!("Failed to start WebRTC monitor: {}", e),
+        }
+    }
+
+    fn start_icecast(&mut self, settings: IcecastSettings) {
+        self.stop_icecast();
+        let (channels, sample_rate) = match self.capture_format() {
+            Some(format) => format,
+            None => {
+                eprintln!("Capture must be running to stream to Icecast");
+                return;
+            }
+        };
+        let (client, producer) = IcecastClient::start(settings.clone(), channels, sample_rate);
+        self.add_mix_tap("icecast", producer);
+        self.icecast = Some(client);
+        self.icecast_settings = Some(settings);
+    }
+
+    fn stop_icecast(&mut self) {
+        self.remove_mix_tap("icecast");
+        if self.icecast.take().is_some() {
+            println!("Icecast stream stopped");
+        }
+        self.icecast_settings = None;
+    }
+
+    fn set_noise_gate(&mut self, settings: NoiseGateSettings) {
+        println!("Setting noise gate: {:?}", settings);
+        self.noise_gate.set(settings);
+    }
+
+    fn set_ducking(&mut self, settings: DuckingSettings) {
+        println!("Setting ducking: {:?}", settings);
+        self.ducking.set(settings);
+    }
+
+    fn set_master_volume(&mut self, volume: f32) {
+        self.master_position = volume;
+        let mut gain = self.taper.position_to_gain(volume);
+        if let Some(cap) = self.volume_cap {
+            gain = gain.min(cap);
+        }
+        println!("Setting master gain: {}", gain);
+        self.master_volume.store(gain);
+    }
+
+    fn set_volume_cap(&mut self, cap_db: Option<f32>) {
+        println!("Setting volume cap: {:?} dB", cap_db);
+        self.volume_cap = cap_db.map(dsp::db_to_gain);
+        self.set_master_volume(self.master_position);
+    }
+
+    fn set_master_mute(&mut self, muted: bool) {
+        println!("Setting master mute: {}", muted);
+        let changed = self.master_muted.swap(muted, Ordering::Relaxed) != muted;
+        if changed {
+            let _ = self.events.send(AudioEvent::MasterMuteChanged { muted });
+        }
+    }
+
+    fn set_volume_taper(&mut self, taper: VolumeTaper) {
+        println!("Setting volume taper: {:?}", taper);
+        self.taper = taper;
+        // Faders keep their positions, which now map to other gains
+        let positions: Vec<(String, f32)> = self.positions.iter().map(|(name, &p)| (name.clone(), p)).collect();
+        for (name, position) in positions {
+            self.apply_position(&name, position);
+        }
+        self.set_input_volume(self.input_position);
+        self.set_master_volume(self.master_position);
+    }
+
+    fn set_input_mute(&mut self, muted: bool) {
+         println!("Setting input mute: {}", muted);
+         self.input_muted.store(muted, Ordering::Relaxed);
+    }
+
+    fn set_capture_fades(&mut self, fade_in_ms: u32, fade_out_ms: u32) {
+        println!("Setting capture fades: in {} ms, out {} ms", fade_in_ms, fade_out_ms);
+        self.capture_fade_in_ms = fade_in_ms.min(MAX_CAPTURE_FADE_MS);
+        self.capture_fade_out_ms = fade_out_ms.min(MAX_CAPTURE_FADE_MS);
+    }
+
+    fn set_crossfade_duration(&mut self, duration_ms: u32) {
+        println!("Setting crossfade duration: {} ms", duration_ms);
+        self.crossfade_ms.store(duration_ms.min(MAX_CROSSFADE_MS), Ordering::Relaxed);
+    }
+
+    fn add_output(&mut self, device_name: String) {
+        self.forget_recovery(&device_name);
+        // Swapped away but still fading out: fade it back in instead
+        if self.retiring_outputs.remove(&device_name).is_some() {
+            if let Some(f) = self.fades.get(&device_name) {
+                f.store(1.0);
+            }
+            return;
+        }
+        // The window restores the saved mix too; don't open a second stream
+        if self.output_streams.contains_key(&device_name) {
+            println!("Output already in the mix: {}", device_name);
+            return;
+        }
+        self.build_output(device_name, 1.0);
+    }
+
+    /// Replaces `old_name` with `new_name`: the new stream fades in while the old one
+    /// fades out, and the old one is torn down by `finish_teardowns` once the
+    /// crossfade is over.
+    fn swap_output(&mut self, old_name: String, new_name: String) {
+        // Swapping back to an output that is still fading out
+        if self.retiring_outputs.remove(&new_name).is_some() {
+            if let Some(f) = self.fades.get(&new_name) {
+                f.store(1.0);
+            }
+            self.retire_output(old_name);
+            return;
+        }
+        if !self.output_streams.contains_key(&old_name) {
+            println!("Swap source '{}' not active, adding '{}' directly", old_name, new_name);
+            self.add_output(new_name);
+            return;
+        }
+
+        // Start the new device silent so it can be faded in
+        self.build_output(new_name.clone(), 0.0);
+        if !self.output_streams.contains_key(&new_name) {
+            eprintln!("Swap aborted, could not open: {}", new_name);
+            return;
+        }
+
+        // Carry over the user's settings
+        let volume = self.volumes.get(&old_name).map(|v| v.load());
+        let muted = self.mutes.get(&old_name).map(|m| m.load(Ordering::Relaxed));
+        let width = self.widths.get(&old_name).map(|w| w.load());
+        if let Some(v) = volume { self.set_gain(new_name.clone(), v); }
+        if let Some(&p) = self.positions.get(&old_name) { self.positions.insert(new_name.clone(), p); }
+        if let Some(m) = muted { self.set_mute(new_name.clone(), m); }
+        if let Some(w) = width { self.set_width(new_name.clone(), w); }
+        let boost = self.boosts.get(&old_name).map(|b| b.load());
+        let clip = self.clippers.get(&old_name).map(|c| c.load(Ordering::Relaxed));
+        if let (Some(b), Some(h)) = (boost, self.boosts.get(&new_name)) {
+            h.store(b);
+        }
+        if let Some(c) = clip { self.set_soft_clip(new_name.clone(), c); }
+        if self.soloed.contains(&old_name) { self.set_solo(new_name.clone(), true); }
+
+        // Crossfade
+        if let Some(f) = self.fades.get(&new_name) {
+            f.store(1.0);
+        }
+        self.retire_output(old_name);
+        println!("Swapping output to: {}", new_name);
+    }
+
+    /// Fades an output out and schedules its teardown for when the fade is over.
+    fn retire_output(&mut self, name: String) {
+        if let Some(f) = self.fades.get(&name) {
+            f.store(0.0);
+        }
+        let duration_ms = self.crossfade_ms.load(Ordering::Relaxed);
+        self.retiring_outputs.insert(name, Instant::now() + Duration::from_millis(duration_ms as u64));
+    }
+
+    /// Tears down the capture and outputs whose fade-out is over, and insert
+    /// chains the outputs have replaced.
+    fn finish_teardowns(&mut self) {
+        for control in self.insert_controls.values() {
+            control.collect_retired();
+        }
+        let now = Instant::now();
+        if self.capture_teardown_at.is_some_and(|at| at <= now) {
+            self.close_capture();
+        }
+        let done: Vec<String> = self.retiring_outputs.iter()
+            .filter(|(_, &at)| at <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in done {
+            self.remove_output(name);
+        }
+    }
+
+    /// When the next fade-out is over.
+    fn next_teardown(&self) -> Option<Instant> {
+        self.retiring_outputs.values().copied().chain(self.capture_teardown_at).min()
+    }
+
+    /// Tears down and reopens the device streams with their current settings,
+    /// e.g. after the system wakes from sleep and the old streams went silent.
+    /// Network outputs are left alone; they don't depend on a device.
+    fn rebuild_streams(&mut self) {
+        println!("Rebuilding audio streams");
+        let devices: Vec<String> = self.output_streams.iter()
+            .filter(|(_, stream)| stream.is_device())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in devices {
+            self.reopen_output(name);
+        }
+        if self.is_capturing() {
+            self.close_capture();
+            self.start_loopback();
+        }
+    }
+
+    /// Closes and reopens an output, keeping its fader and processing settings.
+    fn reopen_output(&mut self, name: String) {
+        // Nothing to keep of an output that is fading out
+        if self.retiring_outputs.contains_key(&name) {
+            self.remove_output(name);
+            return;
+        }
+        let controls = self.output_controls(&name);
+        if !self.reopen_with(name.clone(), &controls) {
+            eprintln!("Could not reopen output: {}", name);
+        }
+    }
+
+    fn output_controls(&self, name: &str) -> OutputControls {
+        let read = |handles: &HashMap<String, Arc<AtomicF32>>| handles.get(name).map(|h| h.load());
+        OutputControls {
+            gain: read(&self.volumes),
+            position: self.positions.get(name).copied(),
+            muted: self.mutes.get(name).map(|m| m.load(Ordering::Relaxed)),
+            width: read(&self.widths),
+            boost: read(&self.boosts),
+            clip: self.clippers.get(name).map(|c| c.load(Ordering::Relaxed)),
+            solo: self.soloed.contains(name),
+        }
+    }
+
+    /// Closes the output if it is open and opens it again with `controls`.
+    /// False if it could not be opened.
+    fn reopen_with(&mut self, name: String, controls: &OutputControls) -> bool {
+        self.remove_output(name.clone());
+        self.build_output(name.clone(), 1.0);
+        if !self.output_streams.contains_key(&name) {
+            return false;
+        }
+        if let Some(g) = controls.gain { self.set_gain(name.clone(), g); }
+        if let Some(p) = controls.position { self.positions.insert(name.clone(), p); }
+        if let Some(m) = controls.muted { self.set_mute(name.clone(), m); }
+        if let Some(w) = controls.width { self.set_width(name.clone(), w); }
+        if let (Some(b), Some(h)) = (controls.boost, self.boosts.get(&name)) {
+            h.store(b);
+        }
+        if let Some(c) = controls.clip { self.set_soft_clip(name.clone(), c); }
+        if controls.solo { self.set_solo(name, true); }
+        true
+    }
+
+    /// Schedules a rebuild of a stream whose callback reported an error.
+    fn stream_failed(&mut self, failure: StreamFailure) {
+        let open = if failure.stream == CAPTURE_STREAM {
+            self.is_capturing()
+        } else {
+            self.output_streams.contains_key(&failure.stream) && !self.retiring_outputs.contains_key(&failure.stream)
+        };
+        if !open {
+            return;
+        }
+        if !self.recovery.is_recovering(&failure.stream) {
+            println!("Scheduling rebuild of {} after: {}", failure.stream, failure.error);
+        }
+        self.recovery.failed(failure, Instant::now());
+    }
+
+    /// Rebuilds the failed streams whose backoff has run out.
+    fn recover_streams(&mut self) {
+        for (name, attempt) in self.recovery.due(Instant::now()) {
+            println!("Rebuilding {} (attempt {})", name, attempt);
+            let ok = if name == CAPTURE_STREAM {
+                self.close_capture();
+                self.start_loopback();
+                self.is_capturing()
+            } else {
+                let controls = self.recovering_outputs.remove(&name).unwrap_or_else(|| self.output_controls(&name));
+                let ok = self.reopen_with(name.clone(), &controls);
+                if !ok {
+                    self.recovering_outputs.insert(name.clone(), controls);
+                }
+                ok
+            };
+            if let Some(error) = self.recovery.rebuilt(&name, ok, Instant::now()) {
+                eprintln!("Giving up on {}: {}", name, error);
+                self.recovering_outputs.remove(&name);
+                let _ = self.events.send(AudioEvent::StreamRecoveryFailed { stream: name, error });
+            }
+        }
+    }
+
+    /// Closes streams whose callbacks stopped firing and schedules their
+    /// rebuild. Device capture is watched too; loopback and network capture
+    /// legitimately go quiet when nothing is playing.
+    fn check_watchdog(&mut self) {
+        let mut streams: Vec<(String, u64)> = self.stats.iter()
+            .filter(|(name, _)| self.output_streams.contains_key(*name) && !self.retiring_outputs.contains_key(*name))
+            .map(|(name, stats)| (name.clone(), stats.callbacks()))
+            .collect();
+        if self.capture_stream.is_some() && self.capture_teardown_at.is_none() && matches!(self.capture_source, CaptureSource::Device { .. }) {
+            streams.push((CAPTURE_STREAM.to_string(), self.capture_stats.callbacks()));
+        }
+        for name in self.watchdog.check(streams, Instant::now()) {
+            eprintln!("No callbacks from {} for {:?}, closing it", name, watchdog::STALL_TIMEOUT);
+            let _ = self.events.send(AudioEvent::StreamStalled { stream: name.clone() });
+            self.stream_failed(StreamFailure { stream: name.clone(), error: "The stream stopped responding".to_string() });
+            if name == CAPTURE_STREAM {
+                self.close_capture();
+            } else {
+                let controls = self.output_controls(&name);
+                self.recovering_outputs.insert(name.clone(), controls);
+                self.remove_output(name);
+            }
+        }
+    }
+
+    /// Stops recovering an output the user added or removed themselves.
+    fn forget_recovery(&mut self, name: &str) {
+        self.recovery.forget(name);
+        self.recovering_outputs.remove(name);
+    }
+
+    /// Opens an output stream. `initial_fade` is the starting crossfade gain (0 = silent).
+    fn build_output(&mut self, device_name: String, initial_fade: f32) {
+        if self.output_streams.contains_key(&device_name) {
+            println!("Device exists: {}", device_name);
+            return;
+        }
+
+        if let Err(e) = check_feedback(&device_name, &self.capture_source) {
+            eprintln!("Refusing output: {}", e);
+            return;
+        }
+
+        // Network outputs are named by URL and have no cpal device
+        let network = match network_target(&device_name) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+
+        let device = if network.is_some() {
+            None
+        } else {
+            let host = host::current();
+            let device = match host.output_devices() {
+                Ok(mut devices) => devices.find(|d| d.name().unwrap_or_default() == device_name),
+                Err(_) => None,
+            };
+            match device {
+                Some(d) => Some(d),
+                None => {
+                    eprintln!("Device not found: {}", device_name);
+                    return;
+                }
+            }
+        };
+
+        // Prefer the bus rate so the output needn't resample
+        let bus = self.bus_format();
+        let target_rate = cpal::SampleRate(bus.sample_rate);
+
+        let mut supported_buffer = cpal::SupportedBufferSize::Unknown;
+        let mut sample_format = cpal::SampleFormat::F32;
+        let mut config: cpal::StreamConfig = match &device {
+            Some(device) => {
+                let best_config = device.supported_output_configs().ok()
+                    .and_then(|configs| sample_format::best_config(configs, target_rate));
+
+                match best_config {
+                    Some(c) => {
+                        supported_buffer = c.buffer_size().clone();
+                        sample_format = c.sample_format();
+                        c.into()
+                    },
+                    None => {
+                         println!("Warning: Could not match sample rate {}. Resampling to the default.", target_rate.0);
+                         device.default_output_config().map(|c| {
+                            supported_buffer = c.buffer_size().clone();
+                            sample_format = c.sample_format();
+                            c.into()
+                         }).unwrap_or_else(|_| cpal::StreamConfig { 
+                            channels: 2, sample_rate: cpal::SampleRate(44100), buffer_size: cpal::BufferSize::Default 
+                        })
+                    }
+                }
+            },
+            // Network outputs carry the bus format as-is
+            None => cpal::StreamConfig {
+                channels: bus.channels as u16,
+                sample_rate: target_rate,
+                buffer_size: cpal::BufferSize::Default,
+            },
+        };
+        
+        let buffer = self.output_buffers.get(&device_name).copied().unwrap_or_default();
+        if device.is_some() {
+            config.buffer_size = buffer_size(buffer.frames, &supported_buffer);
+        }
+        let ring_samples = buffer.ring_samples();
+        let dither = self.output_dither.get(&device_name).copied().unwrap_or_default();
+
+        println!("Output {} configured at: {} {:?} ({:?})", device_name, config.sample_rate.0, sample_format, config.buffer_size);
+
+        let (producer, mut consumer) = RingBuffer::<f32>::new(ring_samples);
+        let stats = Arc::new(StreamStats::default());
+        self.stats.insert(device_name.clone(), stats.clone());
+        
+        self.feeds.add(device_name.clone(), producer, stats.clone());
+
+        // Volume handle
+        let volume_handle = Arc::new(AtomicF32::new(1.0));
+        self.volumes.insert(device_name.clone(), volume_handle.clone());
+        self.positions.insert(device_name.clone(), 1.0);
+        
+        // Mute handle
+        let mute_handle = Arc::new(AtomicBool::new(false));
+        self.mutes.insert(device_name.clone(), mute_handle.clone());
+
+        // Stereo width handle
+        let width_handle = Arc::new(AtomicF32::new(1.0));
+        self.widths.insert(device_name.clone(), width_handle.clone());
+
+        // Boost and soft clipper handles
+        let boost_handle = Arc::new(AtomicF32::new(1.0));
+        self.boosts.insert(device_name.clone(), boost_handle.clone());
+        let clip_handle = Arc::new(AtomicBool::new(false));
+        self.clippers.insert(device_name.clone(), clip_handle.clone());
+
+        // Solo handle, set by the actor when another output is soloed
+        let solo_mute_handle = Arc::new(AtomicBool::new(false));
+        self.solo_mutes.insert(device_name.clone(), solo_mute_handle.clone());
+        self.update_solo_mutes();
+
+        // Crossfade handle, held at the initial value until a swap moves it
+        let fade_handle = Arc::new(AtomicF32::new(initial_fade));
+        self.fades.insert(device_name.clone(), fade_handle.clone());
+
+        // Peak meter, read and reset by `meters()`
+        let meter = Arc::new(AtomicF32::new(0.0));
+        self.meters.insert(device_name.clone(), meter.clone());
+
+        // Stem tap, filled while a stem recording of this output runs
+        let stem_tap = Arc::new(Mutex::new(None::<Producer<f32>>));
+        self.stem_taps.insert(device_name.clone(), stem_tap.clone());
+        self.output_formats.insert(device_name.clone(), (config.channels as usize, config.sample_rate.0));
+        if device.is_some() {
+            self.output_sample_formats.insert(device_name.clone(), sample_format);
+        }
+
+        // Effect plugins at the device format, joining the stream once loaded
+        let (insert_control, mut insert_chain) = InsertControl::new(config.channels as usize, config.sample_rate.0);
+        if let Some(inserts) = self.output_inserts.get(&device_name).filter(|i| !i.is_empty()) {
+            insert_control.load(inserts.clone());
+        }
+        self.insert_controls.insert(device_name.clone(), insert_control);
+
+        let vol_clone = volume_handle.clone();
+        let mute_clone = mute_handle.clone();
+        let width_clone = width_handle.clone();
+        let boost_clone = boost_handle.clone();
+        let clip_clone = clip_handle.clone();
+        let fade_clone = fade_handle.clone();
+        let solo_mute_clone = solo_mute_handle.clone();
+        let crossfade_clone = self.crossfade_ms.clone();
+        let passthrough_clone = self.passthrough.clone();
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0;
+        let mut output = Converter::from_bus(bus, bus::Format::new(channels, sample_rate));
+        let mut ramp = GainRamp::new(0.0, sample_rate, dsp::VOLUME_RAMP_MS);
+        let mut fade = GainRamp::new(initial_fade, sample_rate, DEFAULT_CROSSFADE_MS as f32);
+        let mut mute_fade = GainRamp::new(1.0, sample_rate, dsp::MUTE_FADE_MS);
+        // Speakers with their own volume control get the fader level instead
+        let device_volume = matches!(network, Some(NetworkTarget::AirPlay(_) | NetworkTarget::Cast(_)));
+
+        let render = move |data: &mut [f32]| {
+            let started = Instant::now();
+            let passthrough = passthrough_clone.load(Ordering::Relaxed);
+            let muted = mute_clone.load(Ordering::Relaxed);
+            let solo_muted = solo_mute_clone.load(Ordering::Relaxed);
+            let current_vol = vol_clone.load();
+            mute_fade.set_target(if muted || solo_muted { 0.0 } else { 1.0 });
+            let width = width_clone.load();
+            let boost = boost_clone.load();
+            let clip = clip_clone.load(Ordering::Relaxed);
+            ramp.set_target(if device_volume { boost } else { current_vol * boost });
+
+            let fade_ms = crossfade_clone.load(Ordering::Relaxed);
+            let fade_target = fade_clone.load();
+            fade.set_ramp_ms(sample_rate, fade_ms as f32);
+            fade.set_target(fade_target);
+            
+            let mut short = false;
+            // The ring carries the bus; convert to the device format first
+            output.pull(data, |samples| {
+                short |= tap::pop_into(&mut consumer, samples);
+            });
+            if !passthrough {
+                for frame in data.chunks_mut(channels) {
+                    let gain = ramp.next_gain() * mute_fade.next_gain() * fade.next_gain();
+                    for sample in frame.iter_mut() {
+                        *sample *= gain;
+                    }
+                    if channels == 2 {
+                        dsp::apply_stereo_width(frame, width);
+                    }
+                }
+                insert_chain.process(data);
+                if clip {
+                    for sample in data.iter_mut() {
+                        *sample = dsp::soft_clip(*sample);
+                    }
+                }
+            }
+
+            meter.fetch_max(dsp::frame_peak(data));
+            tap::push_to_tap(&stem_tap, data);
+            if short {
+                stats.add_underrun();
+            }
+            stats.set_buffer(consumer.slots(), ring_samples);
+            stats.record_callback(started);
+        };
+
+        // Exclusive mode, falling back to a shared stream when it is refused
+        #[cfg(windows)]
+        let render = if device.is_some() && self.exclusive_outputs.contains(&device_name) {
+            match ExclusiveOutput::start(&device_name, channels, sample_rate, buffer.frames, dither, render) {
+                Ok(output) => {
+                    // The exclusive stream picks its own encoding
+                    self.output_sample_formats.remove(&device_name);
+                    return self.output_opened(device_name, OutputStream::Exclusive(output));
+                },
+                Err((e, render)) => {
+                    eprintln!("Exclusive mode refused for {}, using shared mode: {}", device_name, e);
+                    render
+                },
+            }
+        } else {
+            render
+        };
+
+        let error_events = self.events.clone();
+        let error_device = device_name.clone();
+        let failures = self.failures.clone();
+        let stream_res = match (device, network) {
+            (Some(device), _) => sample_format::build_output_stream(
+                &device,
+                &config,
+                sample_format,
+                dither,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| render(data),
+                move |err| {
+                    eprintln!("Output error: {}", err);
+                    let event = if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                        AudioEvent::OutputDisconnected { device: error_device.clone(), error: err.to_string() }
+                    } else {
+                        AudioEvent::StreamError { stream: error_device.clone(), error: err.to_string() }
+                    };
+                    let _ = error_events.send(event);
+                    let _ = failures.send(StreamFailure { stream: error_device.clone(), error: err.to_string() });
+                },
+            )
+            .map(|stream| {
+                let _ = stream.play();
+                OutputStream::Device(stream)
+            })
+            .map_err(|e| e.to_string()),
+            (None, Some(NetworkTarget::Vban(target))) => {
+                VbanSender::start(target, channels, sample_rate, render).map(OutputStream::Vban)
+            },
+            (None, Some(NetworkTarget::AirPlay(speaker))) => {
+                RaopSender::start(speaker, channels, sample_rate, volume_handle.clone(), render).map(OutputStream::AirPlay)
+            },
+            (None, Some(NetworkTarget::Cast(device))) => {
+                CastSender::start(device, channels, sample_rate, volume_handle.clone(), render).map(OutputStream::Cast)
+            },
+            (None, Some(NetworkTarget::Link(addr))) => {
+                LinkSender::start(addr, channels, sample_rate, render).map(OutputStream::Link)
+            },
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            (None, Some(NetworkTarget::PipeWire(node))) => {
+                PwStream::playback(node, channels, sample_rate, render).map(OutputStream::PipeWire)
+            },
+            (None, None) => Err("No output device".to_string()),
+        };
+
+        match stream_res {
+            Ok(stream) => self.output_opened(device_name, stream),
+            Err(e) => {
+                eprintln!("Failed to build output stream: {}", e);
+                let _ = self.events.send(AudioEvent::StreamError { stream: device_name, error: e });
+            },
+        }
+    }
+
+    fn output_opened(&mut self, device_name: String, stream: OutputStream) {
+        self.output_streams.insert(device_name.clone(), stream);
+        println!("Added output with volume control: {}", device_name);
+        self.update_passthrough();
+        self.start_stem(&device_name);
+        let _ = self.events.send(AudioEvent::OutputAdded { device: device_name });
+    }
+
+    fn remove_output(&mut self, device_name: String) {
+        self.retiring_outputs.remove(&device_name);
+        self.stop_stem(&device_name);
+        // Drop the stream first to stop playback
+        if self.output_streams.remove(&device_name).is_some() {
+             println!("Stopped output stream: {}", device_name);
+             let _ = self.events.send(AudioEvent::OutputRemoved { device: device_name.clone() });
+        }
+
+        // Remove from the capture callback's feeds to stop feeding it data
+        self.feeds.remove(&device_name);
+
+        // Remove volume control
+        self.volumes.remove(&device_name);
+        self.positions.remove(&device_name);
+        // Remove mute control
+        self.mutes.remove(&device_name);
+        self.widths.remove(&device_name);
+        self.boosts.remove(&device_name);
+        self.clippers.remove(&device_name);
+        self.fades.remove(&device_name);
+        self.solo_mutes.remove(&device_name);
+        self.meters.remove(&device_name);
+        self.stats.remove(&device_name);
+        self.stem_taps.remove(&device_name);
+        self.output_formats.remove(&device_name);
+        self.output_sample_formats.remove(&device_name);
+        self.insert_controls.remove(&device_name);
+        self.update_passthrough();
+        if self.soloed.remove(&device_name) {
+            self.update_solo_mutes();
+        }
+    }
+}
+
+/// An entry in the output set: a local device or a network sender. Held only
+/// so that dropping it stops playback.
+#[allow(dead_code)]
+enum OutputStream {
+    Device(cpal::Stream),
+    #[cfg(windows)]
+    Exclusive(ExclusiveOutput),
+    Vban(VbanSender),
+    AirPlay(RaopSender),
+    Cast(CastSender),
+    Link(LinkSender),
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    PipeWire(PwStream),
+}
+
+impl OutputStream {
+    /// Plays on a local device rather than over the network.
+    fn is_device(&self) -> bool {
+        match self {
+            OutputStream::Device(_) => true,
+            #[cfg(windows)]
+            OutputStream::Exclusive(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Where a URL-named output sends its audio.
+enum NetworkTarget {
+    Vban(vban::VbanTarget),
+    AirPlay(NetworkDevice),
+    Cast(NetworkDevice),
+    Link(std::net::SocketAddr),
+    /// A PipeWire sink by node name; not networked, but named by URL too
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    PipeWire(String),
+}
+
+/// Resolves a network output name. `Ok(None)` means a local device name.
+fn network_target(name: &str) -> Result<Option<NetworkTarget>, String> {
+    if vban::is_vban_url(name) {
+        return vban::parse_url(name)
+            .map(|target| Some(NetworkTarget::Vban(target)))
+            .ok_or_else(|| format!("Invalid VBAN output: {}", name));
+    }
+    if raop::is_airplay_url(name) {
+        return raop::parse_url(name)
+            .and_then(|speaker| discovery::find(NetworkDeviceKind::AirPlay, speaker))
+            .map(|speaker| Some(NetworkTarget::AirPlay(speaker)))
+            .ok_or_else(|| format!("AirPlay speaker not found: {}", name));
+    }
+    if cast::is_cast_url(name) {
+        return cast::parse_url(name)
+            .and_then(|device| discovery::find(NetworkDeviceKind::Chromecast, device))
+            .map(|device| Some(NetworkTarget::Cast(device)))
+            .ok_or_else(|| format!("Cast device not found: {}", name));
+    }
+    if link::is_link_url(name) {
+        return link::resolve_url(name).map(|addr| Some(NetworkTarget::Link(addr)));
+    }
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    if pipewire_audio::is_pipewire_url(name) {
+        return pipewire_audio::parse_url(name)
+            .map(|node| Some(NetworkTarget::PipeWire(node.to_string())))
+            .ok_or_else(|| format!("Invalid PipeWire output: {}", name));
+    }
+    Ok(None)
+}
+
+/// Default fade applied when capture starts or stops.
+pub const DEFAULT_CAPTURE_FADE_MS: u32 = 50;
+
+/// The stream stays open until its fade-out is over, so keep that short.
+const MAX_CAPTURE_FADE_MS: u32 = 2000;
+
+/// Default duration of the crossfade used by `SwapOutput`.
+pub const DEFAULT_CROSSFADE_MS: u32 = 300;
+
+/// A removed output stays open until its crossfade is over.
+const MAX_CROSSFADE_MS: u32 = 5000;
+
+/// PipeWire converts to and from this rate, so its streams all share it.
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+const PIPEWIRE_SAMPLE_RATE: u32 = 48000;
+
+/// Rates the capture and mix can be forced to.
+pub const CAPTURE_SAMPLE_RATES: [u32; 3] = [44100, 48000, 96000];
+
+/// Samples buffered between the capture and each output.
+const OUTPUT_BUFFER_SAMPLES: usize = 16384;
+
+/// Bounds of a configured ring buffer capacity, in samples.
+const MIN_RING_SAMPLES: usize = 1024;
+const MAX_RING_SAMPLES: usize = 262144;
+
+/// Buffer sizes of one output. Small buffers lower the latency; large ones
+/// ride out the hiccups of Bluetooth and busy systems.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct OutputBuffer {
+    /// Device period in frames; None leaves it to the driver.
+    pub frames: Option<u32>,
+    /// Samples queued between the capture and the output; None for the default.
+    pub ring_samples: Option<usize>,
+}
+
+impl OutputBuffer {
+    fn ring_samples(&self) -> usize {
+        self.ring_samples.map_or(OUTPUT_BUFFER_SAMPLES, |s| s.clamp(MIN_RING_SAMPLES, MAX_RING_SAMPLES))
+    }
+}
+
+/// The device period for `frames`, kept within what the device supports.
+/// Devices that don't report a range (e.g. WASAPI in shared mode) keep their
+/// own period.
+fn buffer_size(frames: Option<u32>, supported: &cpal::SupportedBufferSize) -> cpal::BufferSize {
+    match (frames, supported) {
+        (Some(frames), cpal::SupportedBufferSize::Range { min, max }) => cpal::BufferSize::Fixed(frames.clamp(*min, *max)),
+        (Some(frames), cpal::SupportedBufferSize::Unknown) => {
+            println!("Device doesn't report buffer sizes, ignoring {} frames", frames);
+            cpal::BufferSize::Default
+        },
+        (None, _) => cpal::BufferSize::Default,
+    }
+}
+
+/// Shortest time between two clipping notifications.
+const CLIP_EVENT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest capture callback, in frames, the capture buffers are sized for
+/// up front. Longer ones still work but allocate on the audio thread.
+const MAX_CAPTURE_PERIOD_FRAMES: usize = 16384;
+
+/// Gain staging and fan-out shared by every capture backend. Runs inside the
+/// callback (or capture thread) that delivers interleaved f32 frames.
+struct CaptureProcessor {
+    feeds: Feeds,
+    input_volume: Arc<AtomicF32>,
+    input_muted: Arc<AtomicBool>,
+    gate_settings: Handoff<NoiseGateSettings>,
+    ducking_settings: Handoff<DuckingSettings>,
+    master_volume: Arc<AtomicF32>,
+    master_muted: Arc<AtomicBool>,
+    mic_source: Handoff<Option<MicSource>>,
+    network_source: Handoff<Option<MicSource>>,
+    echo_render: Handoff<Option<EchoRender>>,
+    record_tap: Handoff<Option<Producer<f32>>>,
+    record_paused: Arc<AtomicBool>,
+    replay_tap: Handoff<Option<Producer<f32>>>,
+    mix_taps: Handoff<Vec<(String, Producer<f32>)>>,
+    meter: Arc<AtomicF32>,
+    stats: Arc<StreamStats>,
+    events: Sender<AudioEvent>,
+    last_clip: Option<Instant>,
+    stopping: Arc<AtomicBool>,
+    channels: usize,
+    sample_rate: u32,
+    fade_out_ms: f32,
+    ramp: GainRamp,
+    mute_fade: GainRamp,
+    gate: NoiseGate,
+    ducker: Ducker,
+    master_ramp: GainRamp,
+    master_mute_fade: GainRamp,
+    fade: GainRamp,
+    passthrough: Arc<AtomicBool>,
+    // Converts the capture stream into the bus format
+    input: Converter,
+    converted: Vec<f32>,
+    routes: Handoff<RoutingMatrix>,
+    // Each source after its own gain stages, and the master gain per frame
+    capture_stem: Vec<f32>,
+    mic_stem: Vec<f32>,
+    network_stem: Vec<f32>,
+    master_gains: Vec<f32>,
+    // The main mix, and the mix of an output routed differently
+    scratch: Vec<f32>,
+    routed: Vec<f32>,
+    mic_frame: Vec<f32>,
+    network_frame: Vec<f32>,
+}
+
+impl CaptureProcessor {
+    fn process(&mut self, data: &[f32]) {
+        let started = Instant::now();
+        let mut converted = std::mem::take(&mut self.converted);
+        let data = if self.input.is_identity() {
+            data
+        } else {
+            converted.clear();
+            self.input.push(data, &mut converted);
+            &converted[..]
+        };
+        let passthrough = self.passthrough.load(Ordering::Relaxed);
+        if passthrough {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(data);
+        } else {
+            self.mix(data);
+        }
+        self.converted = converted;
+
+        let peak = dsp::frame_peak(&self.scratch);
+        self.meter.fetch_max(peak);
+        if peak >= 1.0 && !matches!(self.last_clip, Some(t) if t.elapsed() < CLIP_EVENT_INTERVAL) {
+            self.last_clip = Some(Instant::now());
+            let _ = self.events.send(AudioEvent::ClippingDetected { peak });
+        }
+        if !self.record_paused.load(Ordering::Relaxed) {
+            if let Some(producer) = self.record_tap.update() {
+                tap::push_block(producer, &self.scratch);
+            }
+        }
+        if let Some(producer) = self.replay_tap.update() {
+            tap::push_block(producer, &self.scratch);
+        }
+        for (_name, producer) in self.mix_taps.update().iter_mut() {
+            tap::push_block(producer, &self.scratch);
+        }
+
+        self.feeds.update();
+        let routes = self.routes.update();
+        let mix_gains = routes.gains(MIX_OUTPUT);
+        for feed in self.feeds.iter_mut() {
+            // Outputs routed like the main mix share it
+            let gains = routes.gains(&feed.name);
+            let samples = if passthrough || gains == mix_gains {
+                &self.scratch
+            } else {
+                let stems = [&self.capture_stem[..], &self.mic_stem[..], &self.network_stem[..]];
+                routing::mix_into(gains, stems, &self.master_gains, self.channels, &mut self.routed);
+                &self.routed
+            };
+            let dropped = tap::push_frames(&mut feed.producer, samples, self.channels);
+            if dropped > 0 {
+                feed.stats.add_dropped(dropped as u64);
+            }
+        }
+        self.stats.record_callback(started);
+    }
+
+    /// Applies the input, mic, network and master stages into `scratch`.
+    fn mix(&mut self, data: &[f32]) {
+        // Check Input/Master Mute and Vol
+        let in_muted = self.input_muted.load(Ordering::Relaxed);
+        let master_muted = self.master_muted.load(Ordering::Relaxed);
+        let vol = self.input_volume.load();
+        let master = self.master_volume.load();
+        self.ramp.set_target(vol);
+        self.mute_fade.set_target(if in_muted { 0.0 } else { 1.0 });
+        self.master_ramp.set_target(master);
+        self.master_mute_fade.set_target(if master_muted { 0.0 } else { 1.0 });
+        let gate = *self.gate_settings.update();
+        if gate != self.gate.settings() {
+            self.gate.set_settings(gate);
+        }
+        let ducking = *self.ducking_settings.update();
+        if ducking != self.ducker.settings() {
+            self.ducker.set_settings(ducking);
+        }
+
+        if self.stopping.load(Ordering::Relaxed) {
+            self.fade.set_ramp_ms(self.sample_rate, self.fade_out_ms);
+            self.fade.set_target(0.0);
+        }
+
+        let mut mic = self.mic_source.update().as_mut();
+        self.mic_frame.resize(self.channels, 0.0);
+        let mut network = self.network_source.update().as_mut();
+        self.network_frame.resize(self.channels, 0.0);
+        let mut echo_render = self.echo_render.update().as_mut();
+
+        self.capture_stem.clear();
+        self.mic_stem.clear();
+        self.network_stem.clear();
+        self.master_gains.clear();
+        for frame in data.chunks(self.channels) {
+            let mic_frame = &mut self.mic_frame[..frame.len()];
+            let mic_active = match mic.as_mut() {
+                Some(m) => m.read_frame(mic_frame),
+                None => {
+                    mic_frame.fill(0.0);
+                    false
+                },
+            };
+            let duck_gain = self.ducker.next_gain(if mic_active { dsp::frame_peak(mic_frame) } else { 0.0 });
+            let gate_gain = self.gate.next_gain(dsp::frame_peak(frame));
+            let input_gain = self.ramp.next_gain() * self.mute_fade.next_gain() * gate_gain * duck_gain;
+            let master_gain = self.master_ramp.next_gain() * self.master_mute_fade.next_gain() * self.fade.next_gain();
+
+            let start = self.capture_stem.len();
+            self.capture_stem.extend(frame.iter().map(|&sample| sample * input_gain));
+            // The loopback (without the mic) is what the speakers play
+            if let Some(e) = echo_render.as_mut() {
+                e.push_frame(&self.capture_stem[start..]);
+            }
+            self.mic_stem.extend_from_slice(mic_frame);
+            let network_frame = &mut self.network_frame[..frame.len()];
+            if !network.as_mut().is_some_and(|n| n.read_frame(network_frame)) {
+                network_frame.fill(0.0);
+            }
+            self.network_stem.extend_from_slice(network_frame);
+            self.master_gains.push(master_gain);
+        }
+
+        let gains = self.routes.update().gains(MIX_OUTPUT);
+        let stems = [&self.capture_stem[..], &self.mic_stem[..], &self.network_stem[..]];
+        routing::mix_into(gains, stems, &self.master_gains, self.channels, &mut self.scratch);
+    }
+}
+
+/// Scales a linked fader by the leader's relative change, falling back to the
+/// absolute difference when the leader starts from zero.
+fn linked_position(current: f32, previous: f32, volume: f32) -> f32 {
+    let linked = if previous > 0.0 {
+        current * volume / previous
+    } else {
+        current + (volume - previous)
+    };
+    linked.clamp(0.0, 1.0)
+}
+
+/// Waits for the next command, watching the streams, finishing fade-outs and
+/// rebuilding failed streams meanwhile. None once every sender has gone.
+fn next_command(
+    commands: &Receiver<AudioCommand>,
+    failures: &Receiver<StreamFailure>,
+    actor: &mut AudioActor,
+) -> Option<AudioCommand> {
+    loop {
+        actor.finish_teardowns();
+        actor.check_watchdog();
+        actor.report_echo_errors();
+        actor.recover_streams();
+        let next = [actor.recovery.next_due(), actor.next_teardown()].into_iter().flatten().min();
+        let timeout = next
+            .map(|t| t.saturating_duration_since(Instant::now()))
+            .unwrap_or(watchdog::CHECK_INTERVAL)
+            .min(watchdog::CHECK_INTERVAL);
+        crossbeam_channel::select! {
+            recv(commands) -> cmd => return cmd.ok(),
+            recv(failures) -> failure => {
+                if let Ok(failure) = failure {
+                    actor.stream_failed(failure);
+                }
+            },
+            default(timeout) => {},
+        }
+    }
+}
+
+/// Panics allowed within `RESTART_WINDOW` before the engine stays down.
+const MAX_RESTARTS: usize = 3;
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// Starts the audio thread. A panic in the actor is caught and a fresh actor
+/// started in its place, reporting `EnginePanicked` so the app can replay its
+/// saved state; after repeated panics the thread gives up.
+pub fn spawn_audio_thread() -> (Sender<AudioCommand>, Receiver<AudioEvent>) {
+    let (tx, rx) = unbounded();
+    let (event_tx, event_rx) = unbounded();
+    thread::spawn(move || {
+        let capturing = AtomicBool::new(false);
+        let mut panics: Vec<Instant> = Vec::new();
+        loop {
+            let run = panic::catch_unwind(AssertUnwindSafe(|| run_actor(&rx, event_tx.clone(), &capturing)));
+            let Err(payload) = run else {
+                break;
+            };
+            let reason = panic_message(payload.as_ref());
+            panics.retain(|t| t.elapsed() < RESTART_WINDOW);
+            panics.push(Instant::now());
+            let restarted = panics.len() <= MAX_RESTARTS;
+            eprintln!("Audio engine panicked: {}{}", reason, if restarted { ", restarting" } else { ", giving up" });
+            let was_capturing = capturing.swap(false, Ordering::Relaxed);
+            let _ = event_tx.send(AudioEvent::EnginePanicked { reason, restarted, was_capturing });
+            if !restarted {
+                break;
+            }
+        }
+    });
+
+    (tx, event_rx)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Runs one actor until every command sender is gone. `capturing` tracks
+/// whether it was capturing, for the report if it panics.
+fn run_actor(rx: &Receiver<AudioCommand>, events: Sender<AudioEvent>, capturing: &AtomicBool) {
+    let (failure_tx, failure_rx) = unbounded();
+    let mut actor = AudioActor::new(events, failure_tx);
+    while let Some(cmd) = next_command(rx, &failure_rx, &mut actor) {
+        match cmd {
+            AudioCommand::StartLoopback => actor.start_loopback(),
+            AudioCommand::StopLoopback => {
+                actor.forget_recovery(CAPTURE_STREAM);
+                actor.stop_loopback();
+            },
+            AudioCommand::SetCaptureSource(source) => actor.set_capture_source(source),
+            AudioCommand::SetCaptureSampleRate(rate) => actor.set_capture_sample_rate(rate),
+            AudioCommand::SetCaptureExclusions(apps) => actor.set_capture_exclusions(apps),
+            AudioCommand::AddOutput(name) => actor.add_output(name),
+            AudioCommand::RemoveOutput(name) => {
+                actor.forget_recovery(&name);
+                actor.remove_output(name);
+            },
+            AudioCommand::SwapOutput(old, new) => actor.swap_output(old, new),
+            AudioCommand::SetCrossfadeDuration(ms) => actor.set_crossfade_duration(ms),
+            AudioCommand::SetVolume(name, vol) => actor.set_volume(name, vol),
+            AudioCommand::SetVolumeDb(name, db) => actor.set_volume_db(name, db),
+            AudioCommand::SetMute(name, mute) => actor.set_mute(name, mute),
+            AudioCommand::SetWidth(name, width) => actor.set_width(name, width),
+            AudioCommand::SetBoost(name, db) => actor.set_boost(name, db),
+            AudioCommand::SetSoftClip(name, enabled) => actor.set_soft_clip(name, enabled),
+            AudioCommand::SetOutputBuffer(name, buffer) => actor.set_output_buffer(name, buffer),
+            AudioCommand::SetExclusive(name, exclusive) => actor.set_exclusive(name, exclusive),
+            AudioCommand::SetDither(name, dither) => actor.set_dither(name, dither),
+            AudioCommand::SetInserts(name, inserts) => actor.set_inserts(name, inserts),
+            AudioCommand::GetInsertParams(name, index, reply) => {
+                let _ = reply.send(actor.insert_params(&name, index));
+            }
+            AudioCommand::SetSolo(name, solo) => actor.set_solo(name, solo),
+            AudioCommand::SetInputVolume(vol) => actor.set_input_volume(vol),
+            AudioCommand::SetInputVolumeDb(db) => actor.set_input_volume_db(db),
+            AudioCommand::SetVolumeTaper(taper) => actor.set_volume_taper(taper),
+            AudioCommand::SetMasterVolume(vol) => actor.set_master_volume(vol),
+            AudioCommand::SetMasterMute(mute) => actor.set_master_mute(mute),
+            AudioCommand::SetVolumeCap(cap_db) => actor.set_volume_cap(cap_db),
+            AudioCommand::SetInputMute(mute) => actor.set_input_mute(mute),
+            AudioCommand::SetNoiseGate(settings) => actor.set_noise_gate(settings),
+            AudioCommand::SetDucking(settings) => actor.set_ducking(settings),
+            AudioCommand::SetCaptureFades(fade_in, fade_out) => actor.set_capture_fades(fade_in, fade_out),
+            AudioCommand::SetLinkGroups(groups) => actor.set_link_groups(groups),
+            AudioCommand::StartMic(name) => actor.start_mic(name),
+            AudioCommand::StopMic => actor.stop_mic(),
+            AudioCommand::SetMicVolume(vol) => actor.set_mic_volume(vol),
+            AudioCommand::SetMicMute(mute) => actor.set_mic_mute(mute),
+            AudioCommand::SetMicNoiseSuppression(enabled) => actor.set_mic_noise_suppression(enabled),
+            AudioCommand::SetEchoCancellation(enabled) => actor.set_echo_cancellation(enabled),
+            AudioCommand::SetBitPerfect(enabled) => actor.set_bit_perfect(enabled),
+            AudioCommand::SetRoute(source, output, gain) => actor.set_route(source, output, gain),
+            AudioCommand::SetRoutes(routes) => actor.set_routes(routes),
+            AudioCommand::StartRecording(directory, split, tags) => actor.start_recording(directory, split, tags),
+            AudioCommand::StopRecording => actor.stop_recording(),
+            AudioCommand::SetRecordingSettings(settings) => actor.set_recording_settings(settings),
+            AudioCommand::SaveReplay(directory) => actor.save_replay(directory),
+            AudioCommand::SetReplaySettings(settings) => actor.set_replay_settings(settings),
+            AudioCommand::PauseRecording => actor.pause_recording(),
+            AudioCommand::ResumeRecording => actor.resume_recording(),
+            AudioCommand::StartIcecast(settings) => actor.start_icecast(settings),
+            AudioCommand::StopIcecast => actor.stop_icecast(),
+            AudioCommand::SetHttpStreamSettings(settings) => actor.set_http_stream_settings(settings),
+            AudioCommand::SetHlsSettings(settings) => actor.set_hls_settings(settings),
+            AudioCommand::SetWebRtcSettings(settings) => actor.set_webrtc_settings(settings),
+            AudioCommand::SetVbanReceiver(settings) => actor.set_vban_receiver(settings),
+            AudioCommand::SetRtpSettings(settings) => actor.set_rtp_settings(settings),
+            AudioCommand::SetSyncServer(settings) => actor.set_sync_server(settings),
+            AudioCommand::SetSyncClient(settings) => actor.set_sync_client(settings),
+            AudioCommand::GetState(reply) => { let _ = reply.send(actor.snapshot()); },
+            AudioCommand::GetMeters(reply) => { let _ = reply.send(actor.meters()); },
+            AudioCommand::GetMetrics(reply) => { let _ = reply.send(actor.metrics()); },
+            AudioCommand::RebuildStreams => actor.rebuild_streams(),
+        }
+        capturing.store(actor.is_capturing(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtrb::RingBuffer;
+
+    #[test]
+    fn test_ring_buffer_transfer() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(100);
+        let _ = producer.push(0.5);
+        assert_eq!(consumer.pop().unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_volume_application_logic() {
+        let volume = 0.5;
+        let input_sample = 1.0;
+        let output_sample = input_sample * volume;
+        assert_eq!(output_sample, 0.5);
+    }
+
+    #[test]
+    fn test_buffer_size_is_clamped() {
+        let supported = cpal::SupportedBufferSize::Range { min: 64, max: 4096 };
+        assert_eq!(buffer_size(Some(32), &supported), cpal::BufferSize::Fixed(64));
+        assert_eq!(buffer_size(Some(256), &supported), cpal::BufferSize::Fixed(256));
+        assert_eq!(buffer_size(None, &supported), cpal::BufferSize::Default);
+        assert_eq!(buffer_size(Some(256), &cpal::SupportedBufferSize::Unknown), cpal::BufferSize::Default);
+        assert_eq!(OutputBuffer { ring_samples: Some(10), ..OutputBuffer::default() }.ring_samples(), MIN_RING_SAMPLES);
+    }
+
+    #[test]
+    fn test_exclusion_lists_are_checked_up_front() {
+        assert_eq!(check_exclusions(&[]), Ok(()));
+        let two = ["game.exe".to_string(), "discord.exe".to_string()];
+        if cfg!(windows) {
+            assert_eq!(check_exclusions(&two[..1]), Ok(()));
+            assert_eq!(check_exclusions(&two), Err(AudioError::TooManyExclusions(2)));
+        } else {
+            assert_eq!(check_exclusions(&two[..1]), Err(AudioError::ExclusionUnsupported));
+        }
+    }
+
+    #[test]
+    fn test_feedback_devices_per_source() {
+        let system = || vec!["Speakers".to_string(), "BlackHole 2ch".to_string()];
+        let loopback = feedback_devices(&CaptureSource::SystemLoopback, system);
+        assert_eq!(loopback, system());
+        assert_eq!(
+            refuse_feedback("BlackHole 2ch", &loopback),
+            Err(AudioError::FeedbackLoop("BlackHole 2ch".to_string()))
+        );
+        assert_eq!(refuse_feedback("Headphones", &loopback), Ok(()));
+
+        // An input device never asks for the system outputs
+        let device = CaptureSource::Device { name: "Speakers".to_string() };
+        assert!(feedback_devices(&device, || panic!("not a loopback")).is_empty());
+        assert_eq!(check_feedback("Speakers", &device), Ok(()));
+
+        let monitor = CaptureSource::Monitor { sink: "alsa_output.usb".to_string() };
+        let expected: Vec<String> = if cfg!(all(target_os = "linux", feature = "pipewire")) {
+            vec!["pipewire://alsa_output.usb".to_string()]
+        } else {
+            Vec::new()
+        };
+        assert_eq!(feedback_devices(&monitor, || panic!("not a loopback")), expected);
+    }
+
+    #[test]
+    fn test_linked_position() {
+        // Leader halves, follower halves
+        assert_eq!(linked_position(0.5, 1.0, 0.5), 0.25);
+        // Clamped at full scale
+        assert_eq!(linked_position(0.8, 0.5, 1.0), 1.0);
+        // Leader starting from zero moves followers by the same step
+        assert_eq!(linked_position(0.5, 0.0, 0.25), 0.75);
+    }
+}
+
+/// Describes a local device from the stream configs it supports.
+fn device_info(
+    device: &cpal::Device,
+    index: usize,
+    host_api: &str,
+    default_name: Option<&str>,
+    configs: Vec<cpal::SupportedStreamConfigRange>,
+) -> AudioDeviceInfo {
+    let name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
+    let mut channels: Vec<u16> = configs.iter().map(|c| c.channels()).collect();
+    channels.sort_unstable();
+    channels.dedup();
+    AudioDeviceInfo {
+        default: default_name == Some(name.as_str()),
+        name,
+        index,
+        host_api: host_api.to_string(),
+        channels,
+        min_sample_rate: configs.iter().map(|c| c.min_sample_rate().0).min(),
+        max_sample_rate: configs.iter().map(|c| c.max_sample_rate().0).max(),
+        ..AudioDeviceInfo::default()
     }
 }
 
 pub fn get_output_devices() -> Vec<AudioDeviceInfo> {
-    let host = cpal::default_host();
-    match host.output_devices() {
+    let host = host::current();
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+    let mut devices: Vec<AudioDeviceInfo> = match host.output_devices() {
         Ok(devices) => devices
             .enumerate()
             .map(|(index, device)| {
-                let name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
-                AudioDeviceInfo { name, index }
+                let configs = device.supported_output_configs().map(|c| c.collect()).unwrap_or_default();
+                device_info(&device, index, host.id().name(), default_name.as_deref(), configs)
             })
             .collect(),
         Err(_) => Vec::new()
+    };
+
+    // PipeWire sinks, played natively rather than through ALSA
+    #[cfg(all(target_os = "linux", feature = "pipewire"))]
+    for sink in pipewire_audio::sinks().unwrap_or_default() {
+        devices.push(AudioDeviceInfo {
+            name: pipewire_audio::url(&sink.name),
+            index: devices.len(),
+            alias: Some(sink.description),
+            host_api: "PipeWire".to_string(),
+            channels: vec![2],
+            ..AudioDeviceInfo::default()
+        });
+    }
+
+    // Discovered network speakers follow the local devices
+    for speaker in discovery::devices() {
+        let (name, host_api) = match speaker.kind {
+            NetworkDeviceKind::AirPlay => (raop::url(&speaker.name), "AirPlay"),
+            NetworkDeviceKind::Chromecast => (cast::url(&speaker.name), "Chromecast"),
+            NetworkDeviceKind::AudioMerge => (link::url(&speaker.name), "Audio Merge"),
+        };
+        devices.push(AudioDeviceInfo {
+            name,
+            index: devices.len(),
+            host_api: host_api.to_string(),
+            channels: vec![2],
+            ..AudioDeviceInfo::default()
+        });
+    }
+    devices
+}
+
+/// Microphones and line-ins of the selected host.
+pub fn get_input_devices() -> Vec<AudioDeviceInfo> {
+    let host = host::current();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    match host.input_devices() {
+        Ok(devices) => devices
+            .enumerate()
+            .map(|(index, device)| {
+                let configs = device.supported_input_configs().map(|c| c.collect()).unwrap_or_default();
+                device_info(&device, index, host.id().name(), default_name.as_deref(), configs)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The rates in `CAPTURE_SAMPLE_RATES` the capture device of `source`
+/// supports. None when the source runs at a rate of its own (applications
+/// and linked instances) and ignores the override.
+pub fn supported_capture_rates(source: &CaptureSource) -> Option<Vec<u32>> {
+    let host = host::current();
+    let find_input = |name: &str| host.input_devices().ok()?.find(|d| d.name().unwrap_or_default() == name);
+    let configs: Vec<cpal::SupportedStreamConfigRange> = match source {
+        CaptureSource::Application { .. } | CaptureSource::Network { .. } => return None,
+        // PipeWire converts to whatever rate the stream asks for
+        CaptureSource::Monitor { .. } => return Some(CAPTURE_SAMPLE_RATES.to_vec()),
+        CaptureSource::Device { name } => find_input(name)
+            .and_then(|d| d.supported_input_configs().ok())
+            .map(|c| c.collect())
+            .unwrap_or_default(),
+        CaptureSource::SystemLoopback => match loopback_input() {
+            Some(name) => find_input(&name)
+                .and_then(|d| d.supported_input_configs().ok())
+                .map(|c| c.collect())
+                .unwrap_or_default(),
+            None => host.default_output_device()
+                .and_then(|d| d.supported_output_configs().ok())
+                .map(|c| c.collect())
+                .unwrap_or_default(),
+        },
+    };
+    Some(
+        CAPTURE_SAMPLE_RATES
+            .into_iter()
+            .filter(|&rate| configs.iter().any(|c| c.min_sample_rate().0 <= rate && c.max_sample_rate().0 >= rate))
+            .collect(),
+    )
+}
+
+/// Output devices that would loop back into the given capture source.
+pub fn get_feedback_devices(source: &CaptureSource) -> Vec<String> {
+    feedback_devices(source, || {
+        // A loopback driver's output shares its name with the input recorded
+        host::current()
+            .default_output_device()
+            .and_then(|d| d.name().ok())
+            .into_iter()
+            .chain(loopback_input())
+            .collect()
+    })
+}
+
+/// The outputs `source` would hear itself through. `system_outputs` lists
+/// what the system loopback records, and is only asked for that source.
+fn feedback_devices(source: &CaptureSource, system_outputs: impl FnOnce() -> Vec<String>) -> Vec<String> {
+    match source {
+        // Per-application capture only hears that application
+        CaptureSource::Application { .. } => Vec::new(),
+        // A linked instance's mix never reaches local devices on its own
+        CaptureSource::Network { .. } => Vec::new(),
+        // An input device hears the room, not the outputs
+        CaptureSource::Device { .. } => Vec::new(),
+        // Playing into the recorded sink would feed it back
+        #[cfg(all(target_os = "linux", feature = "pipewire"))]
+        CaptureSource::Monitor { sink } => vec![pipewire_audio::url(sink)],
+        #[cfg(not(all(target_os = "linux", feature = "pipewire")))]
+        CaptureSource::Monitor { .. } => Vec::new(),
+        CaptureSource::SystemLoopback => system_outputs(),
+    }
+}
+
+/// Loopback drivers on macOS, matched case-insensitively within device names.
+#[cfg(target_os = "macos")]
+const LOOPBACK_DRIVERS: [&str; 4] = ["blackhole", "soundflower", "loopback audio", "background music"];
+
+/// The input of an installed loopback driver (macOS), which a Multi-Output
+/// Device feeds with the system audio.
+#[cfg(target_os = "macos")]
+fn loopback_input() -> Option<String> {
+    host::current()
+        .input_devices()
+        .ok()?
+        .filter_map(|d| d.name().ok())
+        .find(|name| {
+            let name = name.to_lowercase();
+            LOOPBACK_DRIVERS.iter().any(|driver| name.contains(driver))
+        })
+}
+
+/// Other platforms record the default output directly.
+#[cfg(not(target_os = "macos"))]
+fn loopback_input() -> Option<String> {
+    None
+}
+
+/// Applications process loopback can leave out of one capture stream. Each
+/// stream captures everything but one process tree, and mixing several such
+/// streams would play everything else several times.
+pub const MAX_CAPTURE_EXCLUSIONS: usize = 1;
+
+/// Refuses capture exclusions the system loopback can't honour.
+pub fn check_exclusions(applications: &[String]) -> Result<(), AudioError> {
+    if applications.is_empty() {
+        return Ok(());
+    }
+    if !cfg!(windows) {
+        return Err(AudioError::ExclusionUnsupported);
+    }
+    if applications.len() > MAX_CAPTURE_EXCLUSIONS {
+        return Err(AudioError::TooManyExclusions(applications.len()));
+    }
+    Ok(())
+}
+
+/// Refuses outputs that would feed the captured signal back into itself.
+pub fn check_feedback(device_name: &str, source: &CaptureSource) -> Result<(), AudioError> {
+    refuse_feedback(device_name, &get_feedback_devices(source))
+}
+
+fn refuse_feedback(device_name: &str, feedback_devices: &[String]) -> Result<(), AudioError> {
+    if feedback_devices.iter().any(|d| d == device_name) {
+        return Err(AudioError::FeedbackLoop(device_name.to_string()));
     }
+    Ok(())
 }
 
 pub fn get_default_device_name() -> String {
-    let host = cpal::default_host();
+    let host = host::current();
     host.default_output_device()
         .and_then(|d| d.name().ok())
         .unwrap_or_else(|| "Unknown".to_string())
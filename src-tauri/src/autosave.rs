@@ -0,0 +1,195 @@
+// Optional backend auto-save of the mixer: output list, faders and mutes are
+// written to the config once they have stopped changing for a moment, no
+// matter which control changed them. The engine is polled rather than
+// followed through events, since most fader moves don't raise one. Master
+// volume and mute are saved by their commands already and are left alone, so
+// a mute applied while the session is locked doesn't outlive a crash.
+
+use crate::audio::{AudioEvent, AudioStateSnapshot};
+use crate::config;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Polls the mix has to stay unchanged before it is written.
+const SETTLE_POLLS: u32 = 2;
+
+/// The part of the engine state that is saved.
+#[derive(Clone, PartialEq)]
+struct Mix {
+    outputs: Vec<String>,
+    volumes: BTreeMap<String, f32>,
+    muted_outputs: Vec<String>,
+    input_volume: f32,
+    input_muted: bool,
+}
+
+impl From<&AudioStateSnapshot> for Mix {
+    fn from(s: &AudioStateSnapshot) -> Self {
+        Self {
+            outputs: s.outputs.clone(),
+            volumes: s.volumes.clone(),
+            muted_outputs: s.muted_outputs.clone(),
+            input_volume: s.input_volume,
+            input_muted: s.input_muted,
+        }
+    }
+}
+
+/// Tells when the polled mix has settled on something not saved yet.
+#[derive(Default)]
+struct Debounce {
+    saved: Option<Mix>,
+    last: Option<Mix>,
+    unchanged: u32,
+}
+
+impl Debounce {
+    /// Forgets what was saved; the next poll counts as saved again.
+    fn reset(&mut self) {
+        self.saved = None;
+    }
+
+    /// Feeds one poll. True once `mix` has held for `SETTLE_POLLS` polls and
+    /// differs from the saved mix.
+    fn poll(&mut self, mix: &Mix) -> bool {
+        if self.saved.is_none() {
+            self.saved = Some(mix.clone());
+        }
+        self.unchanged = if self.last.as_ref() == Some(mix) { self.unchanged + 1 } else { 0 };
+        self.last = Some(mix.clone());
+        self.unchanged >= SETTLE_POLLS && self.saved.as_ref() != Some(mix)
+    }
+
+    fn saved(&mut self, mix: Mix) {
+        self.saved = Some(mix);
+    }
+}
+
+#[derive(Default)]
+pub struct AutoSave {
+    enabled: Mutex<bool>,
+    /// Outputs taken out of the mix since the last save. Outputs that are
+    /// missing for other reasons (failed to open, unplugged) stay saved.
+    removed: Mutex<HashSet<String>>,
+}
+
+impl AutoSave {
+    pub fn set_enabled(&self, enabled: bool) {
+        if let Ok(mut e) = self.enabled.lock() {
+            *e = enabled;
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled.lock().is_ok_and(|e| *e)
+    }
+
+    pub fn on_event(&self, event: &AudioEvent) {
+        if let Ok(mut removed) = self.removed.lock() {
+            match event {
+                AudioEvent::OutputRemoved { device } => {
+                    removed.insert(device.clone());
+                },
+                AudioEvent::OutputAdded { device } => {
+                    removed.remove(device);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn take_removed(&self) -> HashSet<String> {
+        self.removed.lock().map(|mut r| std::mem::take(&mut *r)).unwrap_or_default()
+    }
+}
+
+/// Polls the engine for the rest of the app's life, saving settled changes
+/// while auto-save is on.
+pub fn spawn(app: AppHandle) {
+    thread::spawn(move || {
+        // The mix at startup counts as saved
+        let mut debounce = Debounce::default();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if !app.state::<AutoSave>().enabled() {
+                debounce.reset();
+                continue;
+            }
+            let Ok(live) = crate::get_audio_state(app.state()) else {
+                continue;
+            };
+            let mix = Mix::from(&live);
+            if !debounce.poll(&mix) {
+                continue;
+            }
+            let removed = app.state::<AutoSave>().take_removed();
+            let result = config::update_config(&app, |c| {
+                c.outputs.retain(|o| !removed.contains(&o.name));
+                c.merge_engine_state(&live);
+            });
+            match result {
+                Ok(()) => debounce.saved(mix),
+                Err(e) => eprintln!("Failed to auto-save the mix: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(volume: f32) -> Mix {
+        Mix {
+            outputs: vec!["Speakers".to_string()],
+            volumes: BTreeMap::from([("Speakers".to_string(), volume)]),
+            muted_outputs: Vec::new(),
+            input_volume: 1.0,
+            input_muted: false,
+        }
+    }
+
+    #[test]
+    fn test_changes_are_saved_once_settled() {
+        let mut debounce = Debounce::default();
+        // The first mix seen counts as saved
+        assert!(!debounce.poll(&mix(1.0)));
+        assert!(!debounce.poll(&mix(1.0)));
+        assert!(!debounce.poll(&mix(1.0)));
+
+        // A fader still moving isn't written
+        assert!(!debounce.poll(&mix(0.8)));
+        assert!(!debounce.poll(&mix(0.6)));
+        assert!(!debounce.poll(&mix(0.5)));
+        assert!(!debounce.poll(&mix(0.5)));
+        assert!(debounce.poll(&mix(0.5)));
+        debounce.saved(mix(0.5));
+        assert!(!debounce.poll(&mix(0.5)));
+    }
+
+    #[test]
+    fn test_failed_save_is_retried() {
+        let mut debounce = Debounce::default();
+        debounce.poll(&mix(1.0));
+        for _ in 0..SETTLE_POLLS {
+            debounce.poll(&mix(0.5));
+        }
+        assert!(debounce.poll(&mix(0.5)));
+        // Not marked saved, so the next poll tries again
+        assert!(debounce.poll(&mix(0.5)));
+    }
+
+    #[test]
+    fn test_reset_takes_the_current_mix_as_saved() {
+        let mut debounce = Debounce::default();
+        debounce.poll(&mix(1.0));
+        debounce.reset();
+        for _ in 0..=SETTLE_POLLS {
+            assert!(!debounce.poll(&mix(0.5)));
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Command-line control of a running Audio Merge over the local IPC socket.
+
+use serde_json::{json, Value};
+use tauri_app_lib::ipc;
+
+const USAGE: &str = "Usage: audio-merge-ctl <command> [arguments]
+
+Commands:
+  devices                      List output devices
+  state                        Show the mixer state
+  start | stop                 Start or stop capture
+  add <device>                 Add an output to the mix
+  remove <device>              Remove an output from the mix
+  volume <device> <0-100>      Set an output's volume
+  mute <device> | unmute <device>
+  master <0-100>               Set the master volume
+  master-mute | master-unmute
+  call <command> [json-args]   Run any API command";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match run(&args) {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn run(args: &[&str]) -> Result<String, String> {
+    let (command, args) = match args {
+        ["devices"] => {
+            let devices = ipc::request("get_audio_devices", json!({}))?;
+            let names: Vec<&str> = devices
+                .as_array()
+                .map(|list| list.iter().filter_map(|d| d["name"].as_str()).collect())
+                .unwrap_or_default();
+            return Ok(names.join("\n"));
+        },
+        ["state"] => ("get_audio_state", json!({})),
+        ["start"] => ("start_capture", json!({})),
+        ["stop"] => ("stop_capture", json!({})),
+        ["add", device] => ("add_device_to_mix", json!({ "device_name": device })),
+        ["remove", device] => ("remove_device_from_mix", json!({ "device_name": device })),
+        ["volume", device, percent] => ("set_device_volume", json!({ "device_name": device, "volume": volume(percent)? })),
+        ["mute", device] => ("set_device_mute", json!({ "device_name": device, "muted": true })),
+        ["unmute", device] => ("set_device_mute", json!({ "device_name": device, "muted": false })),
+        ["master", percent] => ("set_master_volume", json!({ "volume": volume(percent)? })),
+        ["master-mute"] => ("set_master_mute", json!({ "muted": true })),
+        ["master-unmute"] => ("set_master_mute", json!({ "muted": false })),
+        ["call", command] => (*command, json!({})),
+        ["call", command, raw] => (*command, serde_json::from_str(raw).map_err(|e| format!("Bad arguments: {}", e))?),
+        _ => return Err(USAGE.to_string()),
+    };
+    let result = ipc::request(command, args)?;
+    Ok(match result {
+        Value::Object(ref map) if map.get("ok") == Some(&json!(true)) && map.len() == 1 => String::new(),
+        other => serde_json::to_string_pretty(&other).unwrap_or_default(),
+    })
+}
+
+/// Fader position from a 0-100 percentage.
+fn volume(percent: &str) -> Result<f32, String> {
+    let percent: f32 = percent.parse().map_err(|_| format!("Not a volume: {}", percent))?;
+    Ok((percent / 100.0).clamp(0.0, 1.0))
+}
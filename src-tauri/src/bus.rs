@@ -0,0 +1,210 @@
+// The internal mix bus. Every source (the capture, the mic, network inputs)
+// is converted into one fixed channel layout and rate before it is mixed, and
+// each output converts from the bus to whatever its device runs at. Sources
+// and outputs at different rates then no longer play at the wrong pitch, and
+// the DSP, meters, recordings and network streams all see a single format.
+
+use crate::dsp::LinearResampler;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Channel layout of the bus.
+pub const BUS_CHANNELS: usize = 2;
+
+/// Bus rate unless the capture rate is forced.
+pub const DEFAULT_BUS_SAMPLE_RATE: u32 = 48000;
+
+/// Channel layout and rate of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub channels: usize,
+    pub sample_rate: u32,
+}
+
+impl Format {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        Self { channels: channels.max(1), sample_rate }
+    }
+}
+
+/// The bus format: stereo at the forced capture rate, or 48 kHz.
+pub fn format(rate_override: Option<u32>) -> Format {
+    Format::new(BUS_CHANNELS, rate_override.unwrap_or(DEFAULT_BUS_SAMPLE_RATE))
+}
+
+/// Gains of channel `c` of a `from`-channel stream on the left and right of
+/// a stereo downmix, after ITU-R BS.775: centre and surrounds at -3 dB, LFE
+/// left out. Layouts are in WAVE channel order (5.1 is L R C LFE SL SR);
+/// channels of unknown layouts alternate between the sides.
+fn downmix_gains(from: usize, c: usize) -> (f32, f32) {
+    const H: f32 = FRAC_1_SQRT_2;
+    match (from, c) {
+        (_, 0) => (1.0, 0.0),
+        (_, 1) => (0.0, 1.0),
+        // Centre
+        (3 | 5 | 6 | 7 | 8, 2) => (H, H),
+        // LFE
+        (6 | 7 | 8, 3) => (0.0, 0.0),
+        // 6.1 back centre
+        (7, 4) => (0.5, 0.5),
+        // Surrounds, and the 7.1 back pair
+        (4, 2) | (5, 3) | (6, 4) | (7, 5) | (8, 4) | (8, 6) => (H, 0.0),
+        (4, 3) | (5, 4) | (6, 5) | (7, 6) | (8, 5) | (8, 7) => (0.0, H),
+        (_, c) if c % 2 == 0 => (H, 0.0),
+        _ => (0.0, H),
+    }
+}
+
+/// Copies interleaved frames into another channel layout. Mono is spread to
+/// every channel and a mono target gets the average; surround is downmixed
+/// to stereo with `downmix_gains`; otherwise channels are matched by
+/// position and extra target channels stay silent.
+pub fn remap(input: &[f32], from: usize, output: &mut [f32], to: usize) {
+    for (src, dst) in input.chunks_exact(from).zip(output.chunks_exact_mut(to)) {
+        if from == 1 {
+            dst.fill(src[0]);
+        } else if to == 1 {
+            dst[0] = src.iter().sum::<f32>() / from as f32;
+        } else if to == 2 && from > 2 {
+            let (mut left, mut right) = (0.0, 0.0);
+            for (c, &s) in src.iter().enumerate() {
+                let (l, r) = downmix_gains(from, c);
+                left += s * l;
+                right += s * r;
+            }
+            dst[0] = left;
+            dst[1] = right;
+        } else {
+            dst.fill(0.0);
+            for (d, &s) in dst.iter_mut().zip(src) {
+                *d = s;
+            }
+        }
+    }
+}
+
+/// Converts a stream between two formats: a source into the bus with `push`,
+/// or the bus into an output with `pull`. Rates are converted in the bus's
+/// channel layout.
+pub struct Converter {
+    from: Format,
+    to: Format,
+    resampler: Option<LinearResampler>,
+    scratch: Vec<f32>,
+}
+
+impl Converter {
+    fn new(from: Format, to: Format) -> Self {
+        Self { from, to, resampler: None, scratch: Vec::new() }
+    }
+
+    /// Converts into the bus: remaps `input` to the bus channels, then
+    /// resamples, appending every converted sample to `out`.
+    pub fn into_bus(from: Format, bus: Format) -> Self {
+        let resampler = (from.sample_rate != bus.sample_rate)
+            .then(|| LinearResampler::new(bus.channels, from.sample_rate, bus.sample_rate));
+        Self { resampler, ..Self::new(from, bus) }
+    }
+
+    /// Converts out of the bus: resamples, then remaps to the output channels.
+    pub fn from_bus(bus: Format, to: Format) -> Self {
+        let resampler = (bus.sample_rate != to.sample_rate)
+            .then(|| LinearResampler::new(bus.channels, bus.sample_rate, to.sample_rate));
+        Self { resampler, ..Self::new(bus, to) }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Most frames `frames` input frames can turn into.
+    pub fn output_frames(&self, frames: usize) -> usize {
+        (frames as u64 * self.to.sample_rate as u64).div_ceil(self.from.sample_rate.max(1) as u64) as usize + 1
+    }
+
+    /// Sizes the buffers for blocks of up to `frames`, so converting them
+    /// doesn't allocate.
+    pub fn reserve(&mut self, frames: usize) {
+        let frames = frames.max(self.output_frames(frames));
+        self.scratch.reserve(frames * self.from.channels.max(self.to.channels));
+        if let Some(r) = self.resampler.as_mut() {
+            r.reserve(frames);
+        }
+    }
+
+    pub fn push(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let frames = input.len() / self.from.channels;
+        self.scratch.resize(frames * self.to.channels, 0.0);
+        remap(input, self.from.channels, &mut self.scratch, self.to.channels);
+        match self.resampler.as_mut() {
+            Some(r) => r.push(&self.scratch, out),
+            None => out.extend_from_slice(&self.scratch),
+        }
+    }
+
+    /// Fills `out`, asking `pull` for as many bus samples as it needs.
+    pub fn pull<F: FnMut(&mut [f32])>(&mut self, out: &mut [f32], mut pull: F) {
+        if self.is_identity() {
+            pull(out);
+            return;
+        }
+        let frames = out.len() / self.to.channels;
+        self.scratch.resize(frames * self.from.channels, 0.0);
+        match self.resampler.as_mut() {
+            Some(r) => r.process(&mut self.scratch, pull),
+            None => pull(&mut self.scratch),
+        }
+        remap(&self.scratch, self.from.channels, out, self.to.channels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_converts_into_and_out_of_the_bus() {
+        let bus = format(None);
+        let mut into = Converter::into_bus(Format::new(1, 48000), bus);
+        let mut out = Vec::new();
+        into.push(&[0.5, 0.25], &mut out);
+        assert_eq!(out, [0.5, 0.5, 0.25, 0.25]);
+
+        let mut from = Converter::from_bus(bus, Format::new(4, 48000));
+        let mut device = [1.0; 4];
+        from.pull(&mut device, |bus| bus.copy_from_slice(&[0.1, 0.2]));
+        assert_eq!(device, [0.1, 0.2, 0.0, 0.0]);
+
+        let mut resampled = Converter::into_bus(Format::new(2, 24000), bus);
+        let mut out = Vec::new();
+        resampled.push(&[0.0, 0.0, 1.0, 1.0, 1.0, 1.0], &mut out);
+        assert_eq!(&out[..6], [0.0, 0.0, 0.5, 0.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_output_frames_bound_the_conversion() {
+        let bus = format(None);
+        let mut into = Converter::into_bus(Format::new(2, 44100), bus);
+        into.reserve(441);
+        let mut out = Vec::new();
+        for _ in 0..10 {
+            out.clear();
+            into.push(&[0.5; 882], &mut out);
+            assert!(out.len() / 2 <= into.output_frames(441));
+        }
+    }
+
+    #[test]
+    fn test_surround_is_downmixed_to_stereo() {
+        // 5.1: L R C LFE SL SR
+        let frame = [1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let mut stereo = [0.0; 2];
+        remap(&frame, 6, &mut stereo, 2);
+        assert!((stereo[0] - (1.0 + FRAC_1_SQRT_2)).abs() < 1e-6);
+        assert!((stereo[1] - 2.0 * FRAC_1_SQRT_2).abs() < 1e-6);
+
+        // Dialogue on the centre alone reaches both sides
+        let mut stereo = [0.0; 2];
+        remap(&[0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 6, &mut stereo, 2);
+        assert_eq!(stereo, [FRAC_1_SQRT_2; 2]);
+    }
+}
@@ -0,0 +1,436 @@
+// Chromecast (Google Cast) output. Cast devices found by discovery appear in
+// the output list as `cast://Name`. The device runs the Default Media Receiver
+// and pulls an MP3 stream of the output from a private HTTP listener; control
+// goes over the Cast v2 protocol (length-prefixed protobuf over TLS). The
+// output's volume is applied by the device rather than in the mix.
+
+use crate::atomic_float::AtomicF32;
+use crate::discovery::NetworkDevice;
+use crate::tap;
+use crate::streaming::{HttpStreamServer, HttpStreamSettings, StreamFormat};
+use native_tls::{TlsConnector, TlsStream};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const URL_PREFIX: &str = "cast://";
+
+const DEFAULT_MEDIA_RECEIVER: &str = "CC1AD845";
+
+const NS_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const NS_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const NS_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const NS_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+
+const SENDER_ID: &str = "sender-0";
+const RECEIVER_ID: &str = "receiver-0";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_TIMEOUT: Duration = Duration::from_millis(250);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Frames rendered per pass of the pacing loop.
+const RENDER_INTERVAL: Duration = Duration::from_millis(10);
+
+const STREAM_BITRATE_KBPS: u32 = 192;
+
+/// Cast messages larger than this are refused by receivers too.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+pub fn url(name: &str) -> String {
+    format!("{}{}", URL_PREFIX, name)
+}
+
+/// Device name from a `cast://Name` output name.
+pub fn parse_url(name: &str) -> Option<&str> {
+    name.strip_prefix(URL_PREFIX).filter(|n| !n.is_empty())
+}
+
+pub fn is_cast_url(name: &str) -> bool {
+    name.starts_with(URL_PREFIX)
+}
+
+/// Maps a linear output gain onto the receiver's 0..1 volume level.
+pub fn volume_level(gain: f32) -> f32 {
+    gain.clamp(0.0, 1.0)
+}
+
+#[derive(Debug, PartialEq)]
+struct CastMessage {
+    source: String,
+    destination: String,
+    namespace: String,
+    payload: String,
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_string_field(out: &mut Vec<u8>, field: u64, value: &str) {
+    write_varint(out, (field << 3) | 2);
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Serializes a CastMessage with a UTF-8 payload, length prefix included.
+fn encode_message(message: &CastMessage) -> Vec<u8> {
+    let mut body = Vec::new();
+    // protocol_version = CASTV2_1_0
+    write_varint(&mut body, 1 << 3);
+    write_varint(&mut body, 0);
+    write_string_field(&mut body, 2, &message.source);
+    write_string_field(&mut body, 3, &message.destination);
+    write_string_field(&mut body, 4, &message.namespace);
+    // payload_type = STRING
+    write_varint(&mut body, 5 << 3);
+    write_varint(&mut body, 0);
+    write_string_field(&mut body, 6, &message.payload);
+
+    let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// Parses a CastMessage body. Binary payloads come back empty.
+fn decode_message(body: &[u8]) -> Option<CastMessage> {
+    let mut message = CastMessage {
+        source: String::new(),
+        destination: String::new(),
+        namespace: String::new(),
+        payload: String::new(),
+    };
+    let mut pos = 0;
+    while pos < body.len() {
+        let key = read_varint(body, &mut pos)?;
+        match key & 7 {
+            0 => {
+                read_varint(body, &mut pos)?;
+            },
+            2 => {
+                let len = read_varint(body, &mut pos)? as usize;
+                let bytes = body.get(pos..pos.checked_add(len)?)?;
+                pos += len;
+                let text = || String::from_utf8_lossy(bytes).into_owned();
+                match key >> 3 {
+                    2 => message.source = text(),
+                    3 => message.destination = text(),
+                    4 => message.namespace = text(),
+                    6 => message.payload = text(),
+                    _ => {},
+                }
+            },
+            _ => return None,
+        }
+    }
+    Some(message)
+}
+
+/// TLS control channel to a Cast device.
+struct CastChannel {
+    stream: TlsStream<TcpStream>,
+    buf: Vec<u8>,
+    request_id: u64,
+}
+
+impl CastChannel {
+    fn connect(addr: SocketAddr) -> Result<(Self, std::net::IpAddr), String> {
+        let tcp = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+        tcp.set_read_timeout(Some(POLL_TIMEOUT)).map_err(|e| e.to_string())?;
+        let local_ip = tcp.local_addr().map_err(|e| e.to_string())?.ip();
+        // Cast devices present self-signed certificates
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let stream = connector.connect(&addr.ip().to_string(), tcp).map_err(|e| e.to_string())?;
+        Ok((Self { stream, buf: Vec::new(), request_id: 0 }, local_ip))
+    }
+
+    fn send(&mut self, destination: &str, namespace: &str, mut payload: Value) -> Result<(), String> {
+        if payload.get("requestId").is_some() {
+            self.request_id += 1;
+            payload["requestId"] = json!(self.request_id);
+        }
+        let message = CastMessage {
+            source: SENDER_ID.to_string(),
+            destination: destination.to_string(),
+            namespace: namespace.to_string(),
+            payload: payload.to_string(),
+        };
+        self.stream.write_all(&encode_message(&message)).map_err(|e| e.to_string())
+    }
+
+    /// Reads whatever has arrived and returns the complete messages. Partial
+    /// frames stay buffered, so read timeouts never break the framing.
+    fn poll(&mut self) -> Result<Vec<CastMessage>, String> {
+        let mut chunk = [0u8; 4096];
+        match self.stream.read(&mut chunk) {
+            Ok(0) => return Err("Cast device closed the connection".to_string()),
+            Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {},
+            Err(e) => return Err(e.to_string()),
+        }
+
+        let mut messages = Vec::new();
+        while self.buf.len() >= 4 {
+            let len = u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]) as usize;
+            if len > MAX_MESSAGE_SIZE {
+                return Err("Oversized Cast message".to_string());
+            }
+            if self.buf.len() < 4 + len {
+                break;
+            }
+            if let Some(message) = decode_message(&self.buf[4..4 + len]) {
+                messages.push(message);
+            }
+            self.buf.drain(..4 + len);
+        }
+        Ok(messages)
+    }
+
+    /// Polls once, answering heartbeats, and returns the JSON payloads.
+    fn receive(&mut self) -> Result<Vec<(CastMessage, Value)>, String> {
+        let mut received = Vec::new();
+        for message in self.poll()? {
+            let payload: Value = serde_json::from_str(&message.payload).unwrap_or(Value::Null);
+            if message.namespace == NS_HEARTBEAT && payload["type"] == "PING" {
+                self.send(&message.source, NS_HEARTBEAT, json!({ "type": "PONG" }))?;
+                continue;
+            }
+            received.push((message, payload));
+        }
+        Ok(received)
+    }
+
+    fn set_volume(&mut self, level: f32) -> Result<(), String> {
+        self.send(
+            RECEIVER_ID,
+            NS_RECEIVER,
+            json!({ "type": "SET_VOLUME", "volume": { "level": level }, "requestId": 0 }),
+        )
+    }
+}
+
+/// Application session launched on the device.
+struct CastSession {
+    transport_id: String,
+    session_id: String,
+}
+
+/// Launches the Default Media Receiver and waits for it to report its session.
+fn launch_receiver(channel: &mut CastChannel) -> Result<CastSession, String> {
+    channel.send(RECEIVER_ID, NS_CONNECTION, json!({ "type": "CONNECT" }))?;
+    channel.send(RECEIVER_ID, NS_RECEIVER, json!({ "type": "LAUNCH", "appId": DEFAULT_MEDIA_RECEIVER, "requestId": 0 }))?;
+
+    let deadline = Instant::now() + LAUNCH_TIMEOUT;
+    while Instant::now() < deadline {
+        for (message, payload) in channel.receive()? {
+            if message.namespace != NS_RECEIVER {
+                continue;
+            }
+            match payload["type"].as_str() {
+                Some("RECEIVER_STATUS") => {
+                    let apps = payload["status"]["applications"].as_array().cloned().unwrap_or_default();
+                    let app = apps.iter().find(|a| a["appId"] == DEFAULT_MEDIA_RECEIVER);
+                    if let Some(app) = app {
+                        if let (Some(transport_id), Some(session_id)) = (app["transportId"].as_str(), app["sessionId"].as_str()) {
+                            return Ok(CastSession {
+                                transport_id: transport_id.to_string(),
+                                session_id: session_id.to_string(),
+                            });
+                        }
+                    }
+                },
+                Some("LAUNCH_ERROR") => return Err(format!("Launch refused: {}", payload["reason"])),
+                _ => {},
+            }
+        }
+    }
+    Err("Timed out waiting for the media receiver".to_string())
+}
+
+/// Streams whatever `render` produces to a Cast device.
+pub struct CastSender {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    _server: HttpStreamServer,
+}
+
+impl CastSender {
+    /// `render` fills interleaved frames in the capture layout, just like an
+    /// output device callback. `volume` is the output's linear gain, forwarded
+    /// to the device.
+    pub fn start<F>(
+        device: NetworkDevice,
+        channels: usize,
+        sample_rate: u32,
+        volume: Arc<AtomicF32>,
+        mut render: F,
+    ) -> Result<Self, String>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        let addr = SocketAddr::new(device.address, device.port);
+        let (mut channel, local_ip) = CastChannel::connect(addr).map_err(|e| format!("{}: {}", device.name, e))?;
+        let session = launch_receiver(&mut channel)?;
+        channel.send(&session.transport_id, NS_CONNECTION, json!({ "type": "CONNECT" }))?;
+
+        // The device pulls the feed from us
+        let stream_settings = HttpStreamSettings {
+            enabled: true,
+            bind_address: local_ip.to_string(),
+            port: 0,
+            format: StreamFormat::Mp3,
+            bitrate_kbps: STREAM_BITRATE_KBPS,
+            ..HttpStreamSettings::default()
+        };
+        let (server, mut producer) = HttpStreamServer::start(stream_settings, channels, sample_rate)?;
+        let stream_url = format!("http://{}:{}/stream", local_ip, server.port());
+
+        let mut current_level = volume_level(volume.load());
+        channel.set_volume(current_level)?;
+        channel.send(
+            &session.transport_id,
+            NS_MEDIA,
+            json!({
+                "type": "LOAD",
+                "requestId": 0,
+                "autoplay": true,
+                "media": {
+                    "contentId": stream_url,
+                    "contentType": StreamFormat::Mp3.content_type(),
+                    "streamType": "LIVE",
+                    "metadata": { "metadataType": 0, "title": "Audio Merge" },
+                },
+            }),
+        )?;
+        println!("Casting to {} from {}", device.name, stream_url);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let renderer = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let frames = (sample_rate as f64 * RENDER_INTERVAL.as_secs_f64()) as usize;
+                let mut buf = vec![0.0f32; frames * channels];
+                let mut next = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if next > now {
+                        thread::sleep(next - now);
+                    }
+                    next += RENDER_INTERVAL;
+
+                    render(&mut buf);
+                    tap::push_frames(&mut producer, &buf, channels);
+                }
+            })
+        };
+
+        let control = {
+            let stop = stop.clone();
+            let name = device.name.clone();
+            thread::spawn(move || {
+                let mut last_heartbeat = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    match channel.receive() {
+                        Ok(messages) => {
+                            for (message, payload) in messages {
+                                if message.namespace == NS_MEDIA && payload["type"] == "LOAD_FAILED" {
+                                    eprintln!("{} could not play the stream", name);
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            eprintln!("Cast connection to {} lost: {}", name, e);
+                            return;
+                        },
+                    }
+
+                    let level = volume_level(volume.load());
+                    if level != current_level {
+                        match channel.set_volume(level) {
+                            Ok(()) => current_level = level,
+                            Err(e) => eprintln!("Cast volume error: {}", e),
+                        }
+                    }
+                    if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                        last_heartbeat = Instant::now();
+                        let _ = channel.send(RECEIVER_ID, NS_HEARTBEAT, json!({ "type": "PING" }));
+                    }
+                }
+                let _ = channel.send(
+                    RECEIVER_ID,
+                    NS_RECEIVER,
+                    json!({ "type": "STOP", "sessionId": session.session_id, "requestId": 0 }),
+                );
+            })
+        };
+
+        Ok(Self { stop, threads: vec![renderer, control], _server: server })
+    }
+}
+
+impl Drop for CastSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let message = CastMessage {
+            source: SENDER_ID.to_string(),
+            destination: RECEIVER_ID.to_string(),
+            namespace: NS_HEARTBEAT.to_string(),
+            payload: r#"{"type":"PING"}"#.to_string(),
+        };
+        let frame = encode_message(&message);
+        let len = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+        assert_eq!(len, frame.len() - 4);
+        assert_eq!(decode_message(&frame[4..]), Some(message));
+
+        // Truncated body
+        assert_eq!(decode_message(&frame[4..frame.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut out = Vec::new();
+        write_varint(&mut out, 300);
+        assert_eq!(out, [0xac, 0x02]);
+        let mut pos = 0;
+        assert_eq!(read_varint(&out, &mut pos), Some(300));
+        assert_eq!(pos, 2);
+    }
+}
@@ -0,0 +1,622 @@
+// Minimal CLAP host for effect inserts. A bundle is loaded once and shared by
+// every instance created from it. Instances are audio effects with one main
+// input and output port; they have no editor here, parameters are set through
+// the app.
+//
+// CLAP splits a plugin's functions between the main thread and the audio
+// thread. Plugins are created, activated, queried and destroyed on the app's
+// main thread (see `inserts::on_main_thread`); processing starts with the
+// first block on the output's audio thread and stops there too.
+
+use crate::inserts::{on_main_thread, ParamInfo, PluginInfo};
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_param_value, clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID,
+    CLAP_EVENT_PARAM_VALUE,
+};
+use clap_sys::ext::audio_ports::{clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS};
+use clap_sys::ext::params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::host::clap_host;
+use clap_sys::plugin::clap_plugin;
+use clap_sys::process::{clap_process, clap_process_status, CLAP_PROCESS_ERROR};
+use clap_sys::version::CLAP_VERSION;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// Largest block handed to a plugin; longer callbacks are split.
+pub const MAX_BLOCK_FRAMES: usize = 4096;
+
+/// Parameter changes queued for one block; more are dropped.
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Bundles currently loaded, so each library's entry is initialized once.
+static BUNDLES: Mutex<Option<HashMap<PathBuf, Weak<Bundle>>>> = Mutex::new(None);
+
+struct Bundle {
+    entry: *const clap_plugin_entry,
+    // Kept loaded for as long as the entry (and any plugin) is in use
+    _library: libloading::Library,
+}
+
+// The entry is only used to scan, create plugins and deinit, all on the main thread
+unsafe impl Send for Bundle {}
+unsafe impl Sync for Bundle {}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(deinit) = (*self.entry).deinit {
+                deinit();
+            }
+        }
+    }
+}
+
+impl Bundle {
+    fn open(path: &Path) -> Result<Arc<Bundle>, String> {
+        let mut bundles = BUNDLES.lock().map_err(|e| e.to_string())?;
+        let bundles = bundles.get_or_insert_with(HashMap::new);
+        if let Some(bundle) = bundles.get(path).and_then(Weak::upgrade) {
+            return Ok(bundle);
+        }
+
+        let library = unsafe { libloading::Library::new(binary_path(path)) }.map_err(|e| e.to_string())?;
+        let entry = unsafe {
+            let symbol = library
+                .get::<*const clap_plugin_entry>(b"clap_entry\0")
+                .map_err(|e| format!("Not a CLAP plugin: {}", e))?;
+            *symbol
+        };
+        let location = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+        let initialized = unsafe { (*entry).init.is_some_and(|init| init(location.as_ptr())) };
+        if !initialized {
+            return Err("The plugin failed to initialize".to_string());
+        }
+        let bundle = Arc::new(Bundle { entry, _library: library });
+        bundles.insert(path.to_path_buf(), Arc::downgrade(&bundle));
+        Ok(bundle)
+    }
+
+    fn factory(&self) -> Result<&clap_plugin_factory, String> {
+        unsafe {
+            let factory = (*self.entry)
+                .get_factory
+                .map(|get| get(CLAP_PLUGIN_FACTORY_ID.as_ptr()))
+                .unwrap_or(ptr::null());
+            (factory as *const clap_plugin_factory)
+                .as_ref()
+                .ok_or_else(|| "The bundle has no plugin factory".to_string())
+        }
+    }
+
+    fn plugins(&self, path: &Path) -> Result<Vec<PluginInfo>, String> {
+        let factory = self.factory()?;
+        let count = unsafe { factory.get_plugin_count.map(|f| f(factory)).unwrap_or(0) };
+        Ok((0..count)
+            .filter_map(|i| unsafe {
+                let desc = factory.get_plugin_descriptor?(factory, i).as_ref()?;
+                Some(PluginInfo {
+                    path: path.display().to_string(),
+                    id: c_str(desc.id),
+                    name: c_str(desc.name),
+                    vendor: c_str(desc.vendor),
+                })
+            })
+            .collect())
+    }
+}
+
+/// A .clap bundle is the library itself, except on macOS where it is a
+/// directory holding it.
+fn binary_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        let name = path.file_stem().unwrap_or_default();
+        path.join("Contents").join("MacOS").join(name)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn c_str(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+/// Plugins in the bundle at `path`.
+pub fn scan(path: &Path) -> Result<Vec<PluginInfo>, String> {
+    Bundle::open(path)?.plugins(path)
+}
+
+// Host callbacks: no extensions, restart and process requests are ignored
+unsafe extern "C" fn host_get_extension(_host: *const clap_host, _id: *const c_char) -> *const c_void {
+    ptr::null()
+}
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+/// The plugin wants `on_main_thread` called. May come from any thread,
+/// including the audio thread, so only the first request posts a task.
+unsafe extern "C" fn host_request_callback(host: *const clap_host) {
+    let data = &*((*host).host_data as *const HostData);
+    if data.callback_requested.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    if let Some(instance) = data.instance.get().cloned() {
+        on_main_thread(move || {
+            if let Some(instance) = instance.upgrade() {
+                instance.run_callback();
+            }
+        });
+    }
+}
+
+unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+    (*((*list).ctx as *const Vec<clap_event_param_value>)).len() as u32
+}
+unsafe extern "C" fn events_get(list: *const clap_input_events, index: u32) -> *const clap_event_header {
+    let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+    events.get(index as usize).map_or(ptr::null(), |e| &e.header as *const clap_event_header)
+}
+unsafe extern "C" fn events_discard(_list: *const clap_output_events, _event: *const clap_event_header) -> bool {
+    true
+}
+
+/// What the host callbacks know about their instance.
+struct HostData {
+    instance: OnceLock<Weak<Instance>>,
+    callback_requested: AtomicBool,
+}
+
+/// A created and activated plugin. Destroyed on the main thread: every owner
+/// holds it through a `PluginHandle`.
+struct Instance {
+    plugin: *const clap_plugin,
+    params: Option<*const clap_plugin_params>,
+    activated: bool,
+    // The host structs must outlive the instance, and the bundle its library
+    _host: Box<clap_host>,
+    host_data: Box<HostData>,
+    _bundle: Option<Arc<Bundle>>,
+}
+
+// Main-thread functions are only called on the main thread, audio-thread
+// functions only by the `Plugin` that processes
+unsafe impl Send for Instance {}
+unsafe impl Sync for Instance {}
+
+impl Instance {
+    /// Creates, initializes and activates a plugin. Main thread only.
+    fn create<F>(bundle: Option<Arc<Bundle>>, channels: usize, sample_rate: u32, create: F) -> Result<Arc<Self>, String>
+    where
+        F: FnOnce(*const clap_host) -> *const clap_plugin,
+    {
+        let host_data = Box::new(HostData { instance: OnceLock::new(), callback_requested: AtomicBool::new(false) });
+        let host = Box::new(clap_host {
+            clap_version: CLAP_VERSION,
+            host_data: &*host_data as *const HostData as *mut c_void,
+            name: b"Audio Merge\0".as_ptr() as *const c_char,
+            vendor: b"Audio Merge\0".as_ptr() as *const c_char,
+            url: b"\0".as_ptr() as *const c_char,
+            version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            get_extension: Some(host_get_extension),
+            request_restart: Some(host_request),
+            request_process: Some(host_request),
+            request_callback: Some(host_request_callback),
+        });
+        let plugin = create(&*host);
+        if plugin.is_null() {
+            return Err("The plugin could not be created".to_string());
+        }
+        let mut instance = Self { plugin, params: None, activated: false, _host: host, host_data, _bundle: bundle };
+        unsafe {
+            let p = &*plugin;
+            if !p.init.is_some_and(|init| init(plugin)) {
+                return Err("The plugin failed to initialize".to_string());
+            }
+            instance.check_ports(channels)?;
+            let params = p.get_extension.map(|get| get(plugin, CLAP_EXT_PARAMS.as_ptr())).unwrap_or(ptr::null());
+            instance.params = (!params.is_null()).then_some(params as *const clap_plugin_params);
+            instance.activated = p
+                .activate
+                .is_some_and(|activate| activate(plugin, sample_rate as f64, 1, MAX_BLOCK_FRAMES as u32));
+            if !instance.activated {
+                return Err("The plugin failed to activate".to_string());
+            }
+        }
+        let instance = Arc::new(instance);
+        let _ = instance.host_data.instance.set(Arc::downgrade(&instance));
+        // A callback requested while initializing; we're on the main thread already
+        if instance.host_data.callback_requested.load(Ordering::Acquire) {
+            instance.run_callback();
+        }
+        Ok(instance)
+    }
+
+    /// Refuses plugins whose main ports don't take the stream's channels.
+    unsafe fn check_ports(&self, channels: usize) -> Result<(), String> {
+        let p = &*self.plugin;
+        let ports = p.get_extension.map(|get| get(self.plugin, CLAP_EXT_AUDIO_PORTS.as_ptr())).unwrap_or(ptr::null());
+        let ports = match (ports as *const clap_plugin_audio_ports).as_ref() {
+            Some(ports) => ports,
+            None => return Err("The plugin has no audio ports".to_string()),
+        };
+        for is_input in [true, false] {
+            let mut info: clap_audio_port_info = std::mem::zeroed();
+            let ok = ports.count.is_some_and(|count| count(self.plugin, is_input) > 0)
+                && ports.get.is_some_and(|get| get(self.plugin, 0, is_input, &mut info));
+            if !ok || info.channel_count as usize != channels {
+                return Err(format!("The plugin does not process {} channels", channels));
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the plugin's `on_main_thread`. Main thread only.
+    fn run_callback(&self) {
+        self.host_data.callback_requested.store(false, Ordering::Release);
+        unsafe {
+            if let Some(on_main_thread) = (*self.plugin).on_main_thread {
+                on_main_thread(self.plugin);
+            }
+        }
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            let p = &*self.plugin;
+            if let (true, Some(deactivate)) = (self.activated, p.deactivate) {
+                deactivate(self.plugin);
+            }
+            if let Some(destroy) = p.destroy {
+                destroy(self.plugin);
+            }
+        }
+    }
+}
+
+/// A reference to a plugin instance for main-thread calls, such as reading
+/// its parameters. Dropping a handle releases the instance on the main thread.
+pub struct PluginHandle(Arc<Instance>);
+
+impl Clone for PluginHandle {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for PluginHandle {
+    fn drop(&mut self) {
+        let instance = self.0.clone();
+        on_main_thread(move || drop(instance));
+    }
+}
+
+impl PluginHandle {
+    /// Parameters with their current values. Main thread only.
+    pub fn params(&self) -> Vec<ParamInfo> {
+        let instance = &self.0;
+        let Some(params) = instance.params.and_then(|p| unsafe { p.as_ref() }) else {
+            return Vec::new();
+        };
+        unsafe {
+            let count = params.count.map(|count| count(instance.plugin)).unwrap_or(0);
+            (0..count)
+                .filter_map(|i| {
+                    let mut info: clap_param_info = std::mem::zeroed();
+                    if !params.get_info?(instance.plugin, i, &mut info) {
+                        return None;
+                    }
+                    let mut value = info.default_value;
+                    if let Some(get_value) = params.get_value {
+                        get_value(instance.plugin, info.id, &mut value);
+                    }
+                    Some(ParamInfo {
+                        id: info.id,
+                        name: c_str(info.name.as_ptr()),
+                        min: info.min_value,
+                        max: info.max_value,
+                        default: info.default_value,
+                        value,
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// The audio side of a plugin instance: runs it on an output's stream.
+pub struct Plugin {
+    handle: PluginHandle,
+    processing: bool,
+    channels: usize,
+    steady_time: i64,
+    // Parameter changes waiting for the next block
+    pending: Vec<clap_event_param_value>,
+    input_ptrs: Vec<*mut f32>,
+    output_ptrs: Vec<*mut f32>,
+}
+
+// Built on the main thread, then only used by the stream's callback
+unsafe impl Send for Plugin {}
+
+impl Plugin {
+    /// Creates and activates the plugin `id` (the first in the bundle when
+    /// empty) for a stream of `channels` at `sample_rate`. Main thread only.
+    pub fn load(path: &Path, id: &str, channels: usize, sample_rate: u32) -> Result<Self, String> {
+        let bundle = Bundle::open(path)?;
+        let id = match id {
+            "" => bundle.plugins(path)?.first().map(|p| p.id.clone()).ok_or("The bundle has no plugins")?,
+            id => id.to_string(),
+        };
+        let plugin_id = CString::new(id.clone()).map_err(|e| e.to_string())?;
+        let factory = bundle.factory()?;
+        let create = |host: *const clap_host| unsafe {
+            factory
+                .create_plugin
+                .map(|create| create(factory, host, plugin_id.as_ptr()))
+                .unwrap_or(ptr::null())
+        };
+        let instance = Instance::create(Some(bundle.clone()), channels, sample_rate, create)
+            .map_err(|e| format!("{}: {}", id, e))?;
+        Ok(Self::new(instance, channels))
+    }
+
+    fn new(instance: Arc<Instance>, channels: usize) -> Self {
+        Self {
+            handle: PluginHandle(instance),
+            processing: false,
+            channels,
+            steady_time: 0,
+            pending: Vec::with_capacity(MAX_PENDING_EVENTS),
+            input_ptrs: vec![ptr::null_mut(); channels],
+            output_ptrs: vec![ptr::null_mut(); channels],
+        }
+    }
+
+    pub fn handle(&self) -> PluginHandle {
+        self.handle.clone()
+    }
+
+    /// Queues a parameter change for the next processed block.
+    pub fn set_param(&mut self, id: u32, value: f64) {
+        self.pending.retain(|e| e.param_id != id);
+        if self.pending.len() == MAX_PENDING_EVENTS {
+            return;
+        }
+        self.pending.push(clap_event_param_value {
+            header: clap_event_header {
+                size: std::mem::size_of::<clap_event_param_value>() as u32,
+                time: 0,
+                space_id: CLAP_CORE_EVENT_SPACE_ID,
+                type_: CLAP_EVENT_PARAM_VALUE,
+                flags: 0,
+            },
+            param_id: id,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value,
+        });
+    }
+
+    /// Processes `frames` frames (at most `MAX_BLOCK_FRAMES`) of planar audio
+    /// from `input` into `output`, starting processing on the first call.
+    /// Audio thread only. On error the input is passed through.
+    pub fn process(&mut self, input: &mut [Vec<f32>], output: &mut [Vec<f32>], frames: usize) {
+        let plugin = self.handle.0.plugin;
+        if !self.processing {
+            self.processing = unsafe { (*plugin).start_processing.is_some_and(|start| start(plugin)) };
+        }
+        let status = if self.processing { self.run(input, output, frames) } else { CLAP_PROCESS_ERROR };
+        if status == CLAP_PROCESS_ERROR {
+            for (out, inp) in output.iter_mut().zip(input.iter()) {
+                out[..frames].copy_from_slice(&inp[..frames]);
+            }
+        }
+    }
+
+    fn run(&mut self, input: &mut [Vec<f32>], output: &mut [Vec<f32>], frames: usize) -> clap_process_status {
+        for (ptr, buf) in self.input_ptrs.iter_mut().zip(input.iter_mut()) {
+            *ptr = buf.as_mut_ptr();
+        }
+        for (ptr, buf) in self.output_ptrs.iter_mut().zip(output.iter_mut()) {
+            *ptr = buf.as_mut_ptr();
+        }
+        let audio_in = clap_audio_buffer {
+            data32: self.input_ptrs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: self.channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let mut audio_out = clap_audio_buffer {
+            data32: self.output_ptrs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: self.channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let in_events = clap_input_events {
+            ctx: &self.pending as *const Vec<clap_event_param_value> as *mut c_void,
+            size: Some(events_size),
+            get: Some(events_get),
+        };
+        let out_events = clap_output_events { ctx: ptr::null_mut(), try_push: Some(events_discard) };
+        let process = clap_process {
+            steady_time: self.steady_time,
+            frames_count: frames as u32,
+            transport: ptr::null(),
+            audio_inputs: &audio_in,
+            audio_outputs: &mut audio_out,
+            audio_inputs_count: 1,
+            audio_outputs_count: 1,
+            in_events: &in_events,
+            out_events: &out_events,
+        };
+        let plugin = self.handle.0.plugin;
+        let status = unsafe { (*plugin).process.map_or(CLAP_PROCESS_ERROR, |p| p(plugin, &process)) };
+        self.pending.clear();
+        self.steady_time += frames as i64;
+        status
+    }
+
+    /// Stops processing, on the audio thread before the plugin is handed
+    /// back for destruction.
+    pub fn stop(&mut self) {
+        if self.processing {
+            let plugin = self.handle.0.plugin;
+            unsafe {
+                if let Some(stop) = (*plugin).stop_processing {
+                    stop(plugin);
+                }
+            }
+            self.processing = false;
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        // Normally stopped already; a stream torn down mid-block isn't
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_sys::plugin::clap_plugin_descriptor;
+    use clap_sys::process::CLAP_PROCESS_CONTINUE;
+    use std::sync::atomic::AtomicU32;
+
+    /// A stereo gain effect recording which of its functions were called.
+    #[derive(Default)]
+    struct Gain {
+        gain: Mutex<f64>,
+        started: AtomicU32,
+        stopped: AtomicU32,
+        callbacks: AtomicU32,
+        destroyed: AtomicBool,
+        host: OnceLock<usize>,
+    }
+
+    unsafe fn gain(plugin: *const clap_plugin) -> &'static Gain {
+        &*((*plugin).plugin_data as *const Gain)
+    }
+
+    unsafe extern "C" fn init(_plugin: *const clap_plugin) -> bool {
+        true
+    }
+    unsafe extern "C" fn destroy(plugin: *const clap_plugin) {
+        gain(plugin).destroyed.store(true, Ordering::SeqCst);
+    }
+    unsafe extern "C" fn activate(_plugin: *const clap_plugin, _rate: f64, _min: u32, _max: u32) -> bool {
+        true
+    }
+    unsafe extern "C" fn deactivate(_plugin: *const clap_plugin) {}
+    unsafe extern "C" fn start_processing(plugin: *const clap_plugin) -> bool {
+        gain(plugin).started.fetch_add(1, Ordering::SeqCst);
+        // Ask for a main-thread callback from the audio thread
+        let host = *gain(plugin).host.get().unwrap() as *const clap_host;
+        (*host).request_callback.unwrap()(host);
+        true
+    }
+    unsafe extern "C" fn stop_processing(plugin: *const clap_plugin) {
+        gain(plugin).stopped.fetch_add(1, Ordering::SeqCst);
+    }
+    unsafe extern "C" fn reset(_plugin: *const clap_plugin) {}
+    unsafe extern "C" fn process(plugin: *const clap_plugin, process: *const clap_process) -> clap_process_status {
+        let process = &*process;
+        let events = &*process.in_events;
+        for i in 0..events.size.unwrap()(events) {
+            let event = &*(events.get.unwrap()(events, i) as *const clap_event_param_value);
+            *gain(plugin).gain.lock().unwrap() = event.value;
+        }
+        let gain = *gain(plugin).gain.lock().unwrap() as f32;
+        let (input, output) = (&*process.audio_inputs, &*process.audio_outputs);
+        for c in 0..input.channel_count as usize {
+            for i in 0..process.frames_count as usize {
+                *(*output.data32.add(c)).add(i) = *(*input.data32.add(c)).add(i) * gain;
+            }
+        }
+        CLAP_PROCESS_CONTINUE
+    }
+    unsafe extern "C" fn on_main_thread(plugin: *const clap_plugin) {
+        gain(plugin).callbacks.fetch_add(1, Ordering::SeqCst);
+    }
+    unsafe extern "C" fn port_count(_plugin: *const clap_plugin, _is_input: bool) -> u32 {
+        1
+    }
+    unsafe extern "C" fn port_get(_plugin: *const clap_plugin, _index: u32, _is_input: bool, info: *mut clap_audio_port_info) -> bool {
+        (*info).channel_count = 2;
+        true
+    }
+    static PORTS: clap_plugin_audio_ports = clap_plugin_audio_ports { count: Some(port_count), get: Some(port_get) };
+    unsafe extern "C" fn get_extension(_plugin: *const clap_plugin, id: *const c_char) -> *const c_void {
+        if CStr::from_ptr(id) == CLAP_EXT_AUDIO_PORTS {
+            &PORTS as *const clap_plugin_audio_ports as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    fn fake_plugin(state: &'static Gain) -> clap_plugin {
+        clap_plugin {
+            desc: ptr::null::<clap_plugin_descriptor>(),
+            plugin_data: state as *const Gain as *mut c_void,
+            init: Some(init),
+            destroy: Some(destroy),
+            activate: Some(activate),
+            deactivate: Some(deactivate),
+            start_processing: Some(start_processing),
+            stop_processing: Some(stop_processing),
+            reset: Some(reset),
+            process: Some(process),
+            get_extension: Some(get_extension),
+            on_main_thread: Some(on_main_thread),
+        }
+    }
+
+    #[test]
+    fn test_processing_starts_on_the_first_block() {
+        let state: &'static Gain = Box::leak(Box::default());
+        *state.gain.lock().unwrap() = 1.0;
+        let plugin: &'static clap_plugin = Box::leak(Box::new(fake_plugin(state)));
+        let create = |host: *const clap_host| {
+            let _ = state.host.set(host as usize);
+            plugin as *const clap_plugin
+        };
+        assert!(Instance::create(None, 1, 48_000, create).unwrap_err().contains("1 channels"));
+        assert!(state.destroyed.swap(false, Ordering::SeqCst));
+
+        let instance = Instance::create(None, 2, 48_000, create).unwrap();
+        let mut plugin = Plugin::new(instance, 2);
+        assert_eq!(state.started.load(Ordering::SeqCst), 0);
+
+        plugin.set_param(0, 0.5);
+        let mut input = vec![vec![1.0; MAX_BLOCK_FRAMES]; 2];
+        let mut output = vec![vec![0.0; MAX_BLOCK_FRAMES]; 2];
+        plugin.process(&mut input, &mut output, 4);
+        plugin.process(&mut input, &mut output, 4);
+        assert_eq!(state.started.load(Ordering::SeqCst), 1);
+        assert_eq!(&output[1][..4], &[0.5; 4]);
+        // Without a main thread set, the requested callback ran right away
+        assert_eq!(state.callbacks.load(Ordering::SeqCst), 1);
+
+        plugin.stop();
+        drop(plugin);
+        assert_eq!(state.stopped.load(Ordering::SeqCst), 1);
+        assert!(state.destroyed.load(Ordering::SeqCst));
+    }
+}
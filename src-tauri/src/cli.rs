@@ -0,0 +1,94 @@
+// Startup flags for kiosk and autostart setups:
+//
+//   --headless          run without opening the window
+//   --minimized         start hidden in the tray
+//   --start-capture     start capturing right away
+//   --profile <name>    load a saved profile
+//   --config <path>     use another config file
+//   --portable          keep settings next to the executable
+//   audio-merge://...   run a deep link (see `deeplink`)
+
+use crate::deeplink;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliArgs {
+    pub headless: bool,
+    pub minimized: bool,
+    pub start_capture: bool,
+    pub profile: Option<String>,
+    pub config: Option<PathBuf>,
+    pub portable: bool,
+    /// Deep links passed by the OS when one is opened.
+    pub links: Vec<String>,
+}
+
+impl CliArgs {
+    /// Unknown flags are reported and skipped.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Self {
+        let (args, errors) = parse(args);
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        args
+    }
+}
+
+/// Parses the arguments after the program name, collecting problems instead
+/// of stopping at the first one.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> (CliArgs, Vec<String>) {
+    let mut parsed = CliArgs::default();
+    let mut errors = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        match flag.as_str() {
+            "--headless" => parsed.headless = true,
+            "--minimized" => parsed.minimized = true,
+            "--start-capture" => parsed.start_capture = true,
+            "--portable" => parsed.portable = true,
+            "--profile" | "--config" => match inline.or_else(|| args.next()) {
+                Some(value) if flag == "--profile" => parsed.profile = Some(value),
+                Some(value) => parsed.config = Some(PathBuf::from(value)),
+                None => errors.push(format!("{} needs a value", flag)),
+            },
+            _ if deeplink::is_link(&arg) => parsed.links.push(arg),
+            _ => errors.push(format!("Ignoring unknown argument: {}", arg)),
+        }
+    }
+    (parsed, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let (parsed, errors) = parse(args(&["--minimized", "--profile", "Party", "--config=/tmp/a.json", "--start-capture"]));
+        assert!(errors.is_empty());
+        assert_eq!(
+            parsed,
+            CliArgs {
+                minimized: true,
+                start_capture: true,
+                profile: Some("Party".to_string()),
+                config: Some(PathBuf::from("/tmp/a.json")),
+                ..CliArgs::default()
+            }
+        );
+
+        let (parsed, errors) = parse(args(&["--headless", "audio-merge://mute/Speakers", "--bogus", "--profile"]));
+        assert!(parsed.headless);
+        assert_eq!(parsed.links, vec!["audio-merge://mute/Speakers".to_string()]);
+        assert_eq!(parsed.profile, None);
+        assert_eq!(errors.len(), 2);
+    }
+}
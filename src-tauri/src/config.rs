@@ -1,58 +1,820 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::fs;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::Shortcut;
+use crate::api::ApiSettings;
+use crate::audio::{AudioStateSnapshot, CaptureSource, OutputBuffer};
+use crate::dsp::{Dither, DuckingSettings, NoiseGateSettings, VolumeTaper};
+use crate::hls::HlsSettings;
+use crate::hotkeys::HotkeyBinding;
+use crate::inserts::InsertConfig;
+use crate::metrics::MetricsSettings;
+use crate::midi::MidiSettings;
+use crate::mqtt::MqttSettings;
+use crate::osc::OscSettings;
+use crate::rtp::RtpSettings;
+use crate::recording::{RecordingSettings, ReplaySettings};
+use crate::routing::Route;
+use crate::scheduler::ScheduledRecording;
+use crate::scripting::ScriptSettings;
+use crate::quiet_hours::{QuietAction, QuietWindow};
+use crate::session::LockAction;
+use crate::window_state::WindowGeometry;
+use crate::sync::{SyncClientSettings, SyncServerSettings};
+use crate::streaming::{HttpStreamSettings, IcecastSettings};
+use crate::vban::VbanReceiverSettings;
+use crate::webhooks::Webhook;
+use crate::webrtc_out::WebRtcSettings;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct OutputConfig {
     pub name: String,
     pub volume: f32,
     pub muted: bool,
+    /// Mid/side stereo width: 0 = mono, 1 = normal, >1 = widened.
+    pub width: f32,
+    /// Extra gain above unity in dB (0 to +12).
+    pub boost_db: f32,
+    pub soft_clip: bool,
+    /// Device period and ring buffer size.
+    pub buffer: OutputBuffer,
+    /// Open the device in WASAPI exclusive mode (Windows).
+    pub exclusive: bool,
+    /// Dither when the device takes 16-bit (or narrower) samples.
+    pub dither: Dither,
+    /// Effect plugins run on the output, in order.
+    pub inserts: Vec<InsertConfig>,
+}
+
+impl OutputConfig {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            volume: 1.0,
+            muted: false,
+            width: 1.0,
+            boost_db: 0.0,
+            soft_clip: false,
+            buffer: OutputBuffer::default(),
+            exclusive: false,
+            dither: Dither::default(),
+            inserts: Vec::new(),
+        }
+    }
+}
+
+/// Outputs whose faders move together by the same relative amount.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkGroup {
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+/// A saved mix that can be switched to in one step: the outputs with their
+/// faders, the input and master stages, and the processing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub outputs: Vec<OutputConfig>,
+    pub input_volume: f32,
+    pub input_muted: bool,
+    pub master_volume: f32,
+    pub master_muted: bool,
+    pub noise_gate: NoiseGateSettings,
+    pub ducking: DuckingSettings,
+    pub volume_taper: VolumeTaper,
+    pub link_groups: Vec<LinkGroup>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::from_config("", &AppConfig::default())
+    }
+}
+
+impl Profile {
+    /// The mix as stored in `config`.
+    pub fn from_config(name: &str, config: &AppConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            outputs: config.outputs.clone(),
+            input_volume: config.input_volume,
+            input_muted: config.input_muted,
+            master_volume: config.master_volume,
+            master_muted: config.master_muted,
+            noise_gate: config.noise_gate,
+            ducking: config.ducking,
+            volume_taper: config.volume_taper,
+            link_groups: config.link_groups.clone(),
+        }
+    }
+
+    /// Makes this profile the current mix of `config`. An unnamed profile (a
+    /// snapshot taken with an empty name) leaves no profile active.
+    pub fn apply_to(&self, config: &mut AppConfig) {
+        config.outputs = self.outputs.clone();
+        config.input_volume = self.input_volume;
+        config.input_muted = self.input_muted;
+        config.master_volume = self.master_volume;
+        config.master_muted = self.master_muted;
+        config.noise_gate = self.noise_gate;
+        config.ducking = self.ducking;
+        config.volume_taper = self.volume_taper;
+        config.link_groups = self.link_groups.clone();
+        config.active_profile = Some(self.name.clone()).filter(|n| !n.is_empty());
+    }
+}
+
+/// Layout version written by this build. Bump it together with a new entry
+/// in `MIGRATIONS` whenever a field is renamed, moved or changes type.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [migrate_v0];
+
+/// Files from before versioning. Their fields all kept their meaning, so
+/// there is nothing to convert yet.
+fn migrate_v0(_config: &mut Map<String, Value>) {}
+
+/// Upgrades a config of any earlier version to `CONFIG_VERSION`, one step at
+/// a time. Newer files are left as they are and read as far as understood.
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let config = value.as_object_mut().ok_or("Config is not a JSON object")?;
+    let version = config.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if version > CONFIG_VERSION {
+        eprintln!("Config version {} is newer than this build ({}); unknown settings are ignored", version, CONFIG_VERSION);
+        return Ok(value);
+    }
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        println!("Migrating config from version {} to {}", from, from + 1);
+        step(config);
+    }
+    config.insert("version".to_string(), json!(CONFIG_VERSION));
+    Ok(value)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct AppConfig {
+    /// Layout version of the file, see `CONFIG_VERSION`.
+    pub version: u32,
     pub input_volume: f32,
     pub input_muted: bool,
     pub outputs: Vec<OutputConfig>,
+    pub capture_fade_in_ms: u32,
+    pub capture_fade_out_ms: u32,
+    pub crossfade_ms: u32,
+    /// Copy samples untouched when a single output matches the capture format.
+    pub bit_perfect: bool,
+    pub noise_gate: NoiseGateSettings,
+    /// Lowers the loopback while the mic is active.
+    pub ducking: DuckingSettings,
+    pub volume_taper: VolumeTaper,
+    pub master_volume: f32,
+    pub master_muted: bool,
+    pub link_groups: Vec<LinkGroup>,
+    /// Input device mixed alongside the loopback, if any.
+    pub mic_device: Option<String>,
+    pub mic_volume: f32,
+    pub mic_muted: bool,
+    pub mic_noise_suppression: bool,
+    /// Cancel loopback audio picked up by the mic from the speakers.
+    pub mic_echo_cancellation: bool,
+    /// Source-to-output send levels that differ from unity.
+    pub routes: Vec<Route>,
+    pub capture_source: CaptureSource,
+    /// Applications left out of the system loopback (Windows only).
+    pub capture_exclusions: Vec<String>,
+    /// Rate the capture and mix run at; None takes the device default.
+    pub capture_sample_rate: Option<u32>,
+    pub recording: RecordingSettings,
+    /// Rolling "save the last N seconds" buffer of the mix.
+    pub replay: ReplaySettings,
+    pub scheduled_recordings: Vec<ScheduledRecording>,
+    /// Icecast/Shoutcast server the mix is streamed to on request.
+    pub icecast: IcecastSettings,
+    /// Built-in server for listening to the mix from a browser.
+    pub http_stream: HttpStreamSettings,
+    /// HLS playlist of the mix for TVs and iOS devices.
+    pub hls: HlsSettings,
+    /// Low-latency browser monitoring over WebRTC.
+    pub webrtc: WebRtcSettings,
+    /// VBAN stream from another machine mixed into the bus.
+    pub vban_receiver: VbanReceiverSettings,
+    /// RTP/AES67 stream of the mix.
+    pub rtp: RtpSettings,
+    /// Serve the mix to synced players in other rooms.
+    pub sync_server: SyncServerSettings,
+    /// Play another machine's synced mix.
+    pub sync_client: SyncClientSettings,
+    /// HTTP control API for scripts and other tools.
+    pub api: ApiSettings,
+    /// Home automation bridge with Home Assistant discovery.
+    pub mqtt: MqttSettings,
+    /// OSC control surface (TouchOSC, lighting consoles).
+    pub osc: OscSettings,
+    /// MIDI controller input and its learned mappings.
+    pub midi: MidiSettings,
+    /// Global shortcuts bound to mixer actions.
+    pub hotkeys: Vec<HotkeyBinding>,
+    /// Keyboard volume keys control the master volume of the mix.
+    pub media_keys: bool,
+    /// URLs notified of audio events.
+    pub webhooks: Vec<Webhook>,
+    /// Prometheus endpoint for monitoring long-running installations.
+    pub metrics: MetricsSettings,
+    /// Launch the app when the user logs in.
+    pub autostart: bool,
+    /// Keep the window hidden at launch, leaving only the tray icon.
+    pub start_minimized: bool,
+    /// Restore the saved outputs and start capturing at launch.
+    pub auto_start_capture: bool,
+    /// What happens to the mix while the session is locked.
+    pub lock_action: LockAction,
+    /// Master fader step of the tray's volume entries.
+    pub tray_volume_step: f32,
+    /// Desktop notifications for lost outputs and audio errors.
+    pub notifications: bool,
+    /// Main window geometry at the last close or exit.
+    pub window: Option<WindowGeometry>,
+    /// The window was hidden to the tray when the app last exited.
+    pub window_hidden: bool,
+    /// Saved mixes, switched between from the window, tray or links.
+    pub profiles: Vec<Profile>,
+    /// The profile last applied or saved, checked in the tray.
+    pub active_profile: Option<String>,
+    /// Times of day when a profile or a volume cap takes over.
+    pub quiet_hours: Vec<QuietWindow>,
+    /// Save the mix from the backend whenever it changes.
+    pub auto_save: bool,
+    /// The user's names for devices, by raw device name.
+    pub device_aliases: BTreeMap<String, String>,
+    /// The cpal host devices are opened through (e.g. "ASIO"); None for the
+    /// platform default.
+    pub audio_host: Option<String>,
+    /// Connect JACK ports to the system ports as streams open.
+    pub jack_auto_connect: bool,
+    /// Raw names of devices left out of the device list and never added to
+    /// the mix automatically.
+    pub hidden_devices: Vec<String>,
+    /// Raw names of the user's favorite devices, pinned in the device list.
+    pub favorite_devices: Vec<String>,
+    /// Rhai automation scripts run on audio events.
+    pub scripting: ScriptSettings,
 }
 
-impl AppConfig {
-    fn default_config() -> Self {
+impl Default for AppConfig {
+    fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             input_volume: 1.0,
             input_muted: false,
             outputs: Vec::new(),
+            capture_fade_in_ms: crate::audio::DEFAULT_CAPTURE_FADE_MS,
+            capture_fade_out_ms: crate::audio::DEFAULT_CAPTURE_FADE_MS,
+            crossfade_ms: crate::audio::DEFAULT_CROSSFADE_MS,
+            bit_perfect: false,
+            noise_gate: NoiseGateSettings::default(),
+            ducking: DuckingSettings::default(),
+            volume_taper: VolumeTaper::default(),
+            master_volume: 1.0,
+            master_muted: false,
+            link_groups: Vec::new(),
+            mic_device: None,
+            mic_volume: 1.0,
+            mic_muted: false,
+            mic_noise_suppression: false,
+            mic_echo_cancellation: false,
+            routes: Vec::new(),
+            capture_source: CaptureSource::default(),
+            capture_exclusions: Vec::new(),
+            capture_sample_rate: None,
+            recording: RecordingSettings::default(),
+            replay: ReplaySettings::default(),
+            scheduled_recordings: Vec::new(),
+            icecast: IcecastSettings::default(),
+            http_stream: HttpStreamSettings::default(),
+            hls: HlsSettings::default(),
+            webrtc: WebRtcSettings::default(),
+            vban_receiver: VbanReceiverSettings::default(),
+            rtp: RtpSettings::default(),
+            sync_server: SyncServerSettings::default(),
+            sync_client: SyncClientSettings::default(),
+            api: ApiSettings::default(),
+            mqtt: MqttSettings::default(),
+            osc: OscSettings::default(),
+            midi: MidiSettings::default(),
+            hotkeys: Vec::new(),
+            media_keys: false,
+            webhooks: Vec::new(),
+            metrics: MetricsSettings::default(),
+            autostart: false,
+            start_minimized: false,
+            auto_start_capture: false,
+            lock_action: LockAction::None,
+            tray_volume_step: 0.05,
+            notifications: true,
+            window: None,
+            window_hidden: false,
+            profiles: Vec::new(),
+            active_profile: None,
+            quiet_hours: Vec::new(),
+            auto_save: false,
+            device_aliases: BTreeMap::new(),
+            audio_host: None,
+            jack_auto_connect: true,
+            hidden_devices: Vec::new(),
+            favorite_devices: Vec::new(),
+            scripting: ScriptSettings::default(),
         }
     }
 }
 
+impl AppConfig {
+    /// Returns the stored settings for an output, creating an entry if the
+    /// device has not been persisted yet.
+    pub fn output_mut(&mut self, name: &str) -> &mut OutputConfig {
+        match self.outputs.iter().position(|o| o.name == name) {
+            Some(i) => &mut self.outputs[i],
+            None => {
+                self.outputs.push(OutputConfig::new(name));
+                self.outputs.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Moves the fader and processing settings of `old` onto `new`, as the
+    /// engine does when it swaps one output for the other. A device that
+    /// already has an entry keeps its own buffer, exclusive mode, dither and
+    /// inserts; `old`'s entry goes away either way.
+    pub fn swap_output(&mut self, old: &str, new: &str) {
+        let Some(i) = self.outputs.iter().position(|o| o.name == old) else {
+            return;
+        };
+        let from = self.outputs.remove(i);
+        match self.outputs.iter_mut().find(|o| o.name == new) {
+            Some(to) => {
+                to.volume = from.volume;
+                to.muted = from.muted;
+                to.width = from.width;
+                to.boost_db = from.boost_db;
+                to.soft_clip = from.soft_clip;
+            },
+            None => self.outputs.insert(i, OutputConfig { name: new.to_string(), ..from }),
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Checks values the app can't recover from silently: faders out of
+    /// range, duplicate profiles, unparseable shortcuts and references to
+    /// profiles that don't exist.
+    pub fn validate(&self) -> Result<(), String> {
+        let fader = |what: &str, value: f32| {
+            if (0.0..=1.0).contains(&value) { Ok(()) } else { Err(format!("{} volume out of range: {}", what, value)) }
+        };
+        let mixes = std::iter::once(Profile::from_config("", self)).chain(self.profiles.iter().cloned());
+        for mix in mixes {
+            fader("Input", mix.input_volume)?;
+            fader("Master", mix.master_volume)?;
+            for out in &mix.outputs {
+                fader(&out.name, out.volume)?;
+            }
+        }
+        let mut names = HashSet::new();
+        for profile in &self.profiles {
+            if profile.name.trim().is_empty() {
+                return Err("Profile without a name".to_string());
+            }
+            if !names.insert(profile.name.as_str()) {
+                return Err(format!("Duplicate profile: {}", profile.name));
+            }
+        }
+        let referenced = self.active_profile.iter().chain(self.quiet_hours.iter().filter_map(|w| match &w.action {
+            QuietAction::Profile { name } => Some(name),
+            QuietAction::VolumeCap { .. } => None,
+        }));
+        if let Some(missing) = referenced.find(|name| !names.contains(name.as_str())) {
+            return Err(format!("Unknown profile: {}", missing));
+        }
+        let shortcuts = self.hotkeys.iter().map(|h| &h.shortcut).chain(self.replay.shortcut.iter());
+        for shortcut in shortcuts {
+            shortcut.parse::<Shortcut>().map_err(|e| format!("Invalid shortcut {}: {}", shortcut, e))?;
+        }
+        if self.tray_volume_step == 0.0 || !(0.0..=1.0).contains(&self.tray_volume_step) {
+            return Err(format!("Tray volume step out of range: {}", self.tray_volume_step));
+        }
+        Ok(())
+    }
+
+    /// Applies the state owned by the frontend (input gain, output list,
+    /// output volume/mute) while keeping backend-managed settings intact.
+    pub fn merge_ui_state(&mut self, ui: AppConfig) {
+        self.input_volume = ui.input_volume;
+        self.input_muted = ui.input_muted;
+        self.outputs = ui
+            .outputs
+            .into_iter()
+            .map(|out| {
+                let mut merged = self
+                    .outputs
+                    .iter()
+                    .find(|o| o.name == out.name)
+                    .cloned()
+                    .unwrap_or_else(|| OutputConfig::new(&out.name));
+                merged.volume = out.volume;
+                merged.muted = out.muted;
+                merged
+            })
+            .collect();
+    }
+
+    /// Takes the input stage and the faders and mutes of the active outputs
+    /// from the engine. Outputs that aren't active are kept as they are.
+    pub fn merge_engine_state(&mut self, live: &AudioStateSnapshot) {
+        self.input_volume = live.input_volume;
+        self.input_muted = live.input_muted;
+        for device in &live.outputs {
+            let out = self.output_mut(device);
+            out.volume = live.volumes.get(device).copied().unwrap_or(out.volume);
+            out.muted = live.muted_outputs.contains(device);
+        }
+    }
+}
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static PORTABLE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// A file of this name next to the executable turns on portable mode.
+pub const PORTABLE_MARKER: &str = "portable";
+
+/// Uses `path` instead of the app data folder; set once at startup.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// Turns on portable mode if `requested` or the marker file exists: the
+/// config and default recordings folder then live next to the executable,
+/// e.g. on a USB stick. Returns the folder in use.
+pub fn init_portable(requested: bool) -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?.to_path_buf();
+    if !requested && !dir.join(PORTABLE_MARKER).exists() {
+        return None;
+    }
+    let _ = PORTABLE_DIR.set(dir.clone());
+    Some(dir)
+}
+
+/// The folder next to the executable, in portable mode.
+pub fn portable_dir() -> Option<&'static Path> {
+    PORTABLE_DIR.get().map(PathBuf::as_path)
+}
+
 pub fn get_config_path(app: &AppHandle) -> Option<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Some(path.clone());
+    }
+    if let Some(dir) = portable_dir() {
+        return Some(dir.join("config.json"));
+    }
     app.path().app_data_dir().ok().map(|p| p.join("config.json"))
 }
 
+/// Content of the last save, so the file watcher can tell the app's own
+/// writes from external edits.
+static LAST_WRITTEN: Mutex<Option<String>> = Mutex::new(None);
+
 pub fn save_config(app: &AppHandle, config: AppConfig) -> Result<(), String> {
     let path = get_config_path(app).ok_or("Failed to get config path")?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
     let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
-    fs::write(path, json).map_err(|e| e.to_string())?;
+    if let Err(e) = backup(&path, false) {
+        eprintln!("Failed to back up the config: {}", e);
+    }
+    fs::write(path, &json).map_err(|e| e.to_string())?;
+    if let Ok(mut last) = LAST_WRITTEN.lock() {
+        *last = Some(json);
+    }
     Ok(())
 }
 
+/// Whether `content` is what the app itself last wrote.
+pub fn is_own_write(content: &str) -> bool {
+    LAST_WRITTEN.lock().is_ok_and(|last| last.as_deref() == Some(content))
+}
+
+/// Outcome of reading the config file.
+#[derive(Debug)]
+pub enum ConfigLoad {
+    /// Read as is, or there was no file yet.
+    Loaded(AppConfig),
+    /// Some settings couldn't be read and were reset; the rest were kept.
+    Recovered { config: AppConfig, error: String },
+    /// Nothing could be read.
+    Failed { error: String },
+}
+
+pub fn read_config(path: &Path) -> ConfigLoad {
+    if !path.exists() {
+        return ConfigLoad::Loaded(AppConfig::default());
+    }
+    match fs::read_to_string(path) {
+        Ok(content) => read_content(&content),
+        Err(e) => ConfigLoad::Failed { error: e.to_string() },
+    }
+}
+
+fn read_content(content: &str) -> ConfigLoad {
+    let value: Value = match serde_json::from_str(content) {
+        Ok(value) => value,
+        Err(e) => return ConfigLoad::Failed { error: e.to_string() },
+    };
+    let value = match migrate(value) {
+        Ok(value) => value,
+        Err(error) => return ConfigLoad::Failed { error },
+    };
+    let error = match serde_json::from_value::<AppConfig>(value.clone()) {
+        Ok(config) => return ConfigLoad::Loaded(config),
+        Err(e) => e.to_string(),
+    };
+    // Keep every setting that reads on its own; the others take defaults
+    let mut kept = Map::new();
+    for (key, field) in value.as_object().cloned().unwrap_or_default() {
+        let single = Value::Object(Map::from_iter([(key.clone(), field.clone())]));
+        if serde_json::from_value::<AppConfig>(single).is_ok() {
+            kept.insert(key, field);
+        }
+    }
+    match serde_json::from_value(Value::Object(kept)) {
+        Ok(config) => ConfigLoad::Recovered { config, error },
+        Err(_) => ConfigLoad::Failed { error },
+    }
+}
+
+/// A config file that couldn't be read completely, reported to the window.
+#[derive(Serialize, Clone, Debug)]
+pub struct ConfigProblem {
+    /// Whether part of the settings were kept.
+    pub recovered: bool,
+    /// Found while reloading an edited file, which was then ignored.
+    pub reload: bool,
+    pub error: String,
+    /// Copy of the file as it was found.
+    pub backup: Option<PathBuf>,
+}
+
+/// Last problem found, for a window that opens after it was reported.
+static LAST_PROBLEM: Mutex<Option<ConfigProblem>> = Mutex::new(None);
+
+/// Modification time of the unreadable file last backed up and reported.
+static BROKEN_FILE: Mutex<Option<std::time::SystemTime>> = Mutex::new(None);
+
+pub fn last_problem() -> Option<ConfigProblem> {
+    LAST_PROBLEM.lock().ok().and_then(|p| p.clone())
+}
+
 pub fn load_config(app: &AppHandle) -> AppConfig {
-    let path = match get_config_path(app) {
-        Some(p) => p,
-        None => return AppConfig::default_config(),
+    let Some(path) = get_config_path(app) else {
+        return AppConfig::default();
     };
+    let (config, recovered, error) = match read_config(&path) {
+        ConfigLoad::Loaded(config) => return config,
+        ConfigLoad::Recovered { config, error } => (config, true, error),
+        ConfigLoad::Failed { error } => (AppConfig::default(), false, error),
+    };
+    // Every load reads the broken file again; back it up and report it once
+    let stamp = fs::metadata(&path).and_then(|m| m.modified()).ok();
+    if let Ok(mut reported) = BROKEN_FILE.lock() {
+        if reported.is_some() && *reported == stamp {
+            return config;
+        }
+        *reported = stamp;
+    }
+    eprintln!("Failed to read {}: {}", path.display(), error);
 
-    if !path.exists() {
-        return AppConfig::default_config();
+    // Keep the broken file before anything overwrites it. What was recovered
+    // is written back so the next load doesn't run into the same error; a
+    // file nothing could be read from is left for the user to fix.
+    let name = format!("config-{}.json.bak", Local::now().format("%Y%m%d-%H%M%S"));
+    let backup_path = path.with_file_name(name);
+    let backup = match fs::copy(&path, &backup_path) {
+        Ok(_) => Some(backup_path),
+        Err(e) => {
+            eprintln!("Failed to back up {}: {}", path.display(), e);
+            None
+        },
+    };
+    if backup.is_some() && recovered {
+        if let Err(e) = save_config(app, config.clone()) {
+            eprintln!("Failed to rewrite the config: {}", e);
+        }
     }
+    report_problem(app, ConfigProblem { recovered, reload: false, error, backup });
+    config
+}
 
-    match fs::read_to_string(path) {
-        Ok(content) => serde_json::from_str(&content).unwrap_or(AppConfig::default_config()),
-        Err(_) => AppConfig::default_config(),
+/// Keeps `problem` for `last_problem` and tells the window about it.
+pub fn report_problem(app: &AppHandle, problem: ConfigProblem) {
+    if let Ok(mut last) = LAST_PROBLEM.lock() {
+        *last = Some(problem.clone());
+    }
+    let _ = app.emit("config-load-error", problem);
+}
+
+/// Copies kept in the `backups` folder next to the config.
+const MAX_BACKUPS: usize = 10;
+/// Saves closer together than this share one backup, so dragging a fader
+/// doesn't rotate out every older copy.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConfigBackup {
+    /// 0 is the newest.
+    pub index: usize,
+    pub path: PathBuf,
+    pub created: DateTime<Local>,
+}
+
+fn backup_dir(config_path: &Path) -> PathBuf {
+    config_path.with_file_name("backups")
+}
+
+/// Backups of the config at `config_path`, newest first.
+pub fn list_backups(config_path: &Path) -> Vec<ConfigBackup> {
+    let Ok(entries) = fs::read_dir(backup_dir(config_path)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("config-") && n.ends_with(".json")))
+        .collect();
+    // The timestamped names sort by age
+    paths.sort_unstable_by(|a, b| b.cmp(a));
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let created = fs::metadata(&path).and_then(|m| m.modified()).map(DateTime::from).unwrap_or_else(|_| Local::now());
+            ConfigBackup { index, path, created }
+        })
+        .collect()
+}
+
+/// Copies the current config file into the backups, unless the newest backup
+/// is recent (when not `forced`), then drops the oldest beyond `MAX_BACKUPS`.
+fn backup(config_path: &Path, forced: bool) -> Result<(), String> {
+    if !config_path.exists() {
+        return Ok(());
+    }
+    let interval = chrono::Duration::from_std(BACKUP_INTERVAL).unwrap_or_default();
+    let recent = list_backups(config_path).first().is_some_and(|b| Local::now() - b.created < interval);
+    if recent && !forced {
+        return Ok(());
+    }
+    let dir = backup_dir(config_path);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let name = format!("config-{}.json", Local::now().format("%Y%m%d-%H%M%S"));
+    fs::copy(config_path, dir.join(name)).map_err(|e| e.to_string())?;
+    for old in list_backups(config_path).iter().skip(MAX_BACKUPS) {
+        let _ = fs::remove_file(&old.path);
+    }
+    Ok(())
+}
+
+/// Puts backup `index` back in place of the config, keeping a backup of the
+/// current file. The file watcher then applies it like an external edit.
+pub fn restore_backup(app: &AppHandle, index: usize) -> Result<PathBuf, String> {
+    let path = get_config_path(app).ok_or("Failed to get config path")?;
+    let chosen = list_backups(&path).into_iter().nth(index).ok_or_else(|| format!("No backup {}", index))?;
+    if let ConfigLoad::Failed { error } = read_config(&chosen.path) {
+        return Err(format!("Backup is unreadable: {}", error));
+    }
+    // Read first: backing up the current file may rotate the chosen one out
+    let content = fs::read(&chosen.path).map_err(|e| e.to_string())?;
+    backup(&path, true)?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(chosen.path)
+}
+
+/// Marks files written by `export_to`, so unrelated JSON isn't imported as an
+/// all-default config.
+const EXPORT_FORMAT: &str = "audio-merge-config";
+
+#[derive(Serialize, Deserialize)]
+struct ConfigExport<T> {
+    format: String,
+    config: T,
+}
+
+/// Writes `config` to a standalone file for `import_from`.
+pub fn export_to(path: &Path, config: &AppConfig) -> Result<(), String> {
+    let export = ConfigExport { format: EXPORT_FORMAT.to_string(), config };
+    let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Reads and validates a file written by `export_to`.
+pub fn import_from(path: &Path) -> Result<AppConfig, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let export: ConfigExport<Value> = serde_json::from_str(&content).map_err(|e| format!("Not a valid settings file: {}", e))?;
+    if export.format != EXPORT_FORMAT {
+        return Err(format!("Not an Audio Merge settings file: {}", path.display()));
+    }
+    // Exports from older versions are upgraded like config files
+    let config: AppConfig = serde_json::from_value(migrate(export.config)?).map_err(|e| format!("Invalid settings: {}", e))?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Held across each read-modify-write, so concurrent updates (a command and
+/// the auto-save thread, say) don't overwrite each other's changes.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Loads the config from disk, applies `f` and writes it back.
+pub fn update_config<F: FnOnce(&mut AppConfig)>(app: &AppHandle, f: F) -> Result<(), String> {
+    let _guard = UPDATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut config = load_config(app);
+    f(&mut config);
+    save_config(app, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = AppConfig::default();
+        config.profiles.push(Profile::from_config("Night", &config));
+        config.active_profile = Some("Night".to_string());
+        assert!(config.validate().is_ok());
+
+        config.profiles[0].master_volume = 1.5;
+        assert!(config.validate().is_err());
+        config.profiles[0].master_volume = 0.5;
+        config.active_profile = Some("Day".to_string());
+        assert_eq!(config.validate(), Err("Unknown profile: Day".to_string()));
+        config.active_profile = None;
+        config.profiles.push(config.profiles[0].clone());
+        assert_eq!(config.validate(), Err("Duplicate profile: Night".to_string()));
+    }
+
+    #[test]
+    fn test_swap_onto_a_saved_output_merges_the_entries() {
+        let mut config = AppConfig::default();
+        config.output_mut("Speakers").volume = 0.3;
+        config.output_mut("Headphones").exclusive = true;
+        config.swap_output("Speakers", "Headphones");
+        assert_eq!(config.outputs.len(), 1);
+        assert_eq!(config.outputs[0].name, "Headphones");
+        assert_eq!(config.outputs[0].volume, 0.3);
+        assert!(config.outputs[0].exclusive);
+
+        config.swap_output("Headphones", "HDMI");
+        assert_eq!(config.outputs.len(), 1);
+        assert_eq!(config.outputs[0].name, "HDMI");
+    }
+
+    #[test]
+    fn test_unversioned_config_is_migrated() {
+        let content = r#"{"input_volume": 0.5, "outputs": [{"name": "Speakers", "volume": 0.8, "muted": true}]}"#;
+        let ConfigLoad::Loaded(config) = read_content(content) else { panic!("not loaded") };
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.input_volume, 0.5);
+        assert_eq!(config.outputs[0].name, "Speakers");
+        assert!(config.outputs[0].muted);
+        assert!(matches!(read_content("[]"), ConfigLoad::Failed { .. }));
+    }
+
+    #[test]
+    fn test_unreadable_settings_are_reset() {
+        let content = r#"{"input_volume": "loud", "master_volume": 0.25, "crossfade_ms": 300}"#;
+        let ConfigLoad::Recovered { config, .. } = read_content(content) else { panic!("not recovered") };
+        assert_eq!(config.input_volume, 1.0);
+        assert_eq!(config.master_volume, 0.25);
+        assert_eq!(config.crossfade_ms, 300);
+        assert!(matches!(read_content("{\"input_volume\": 0.5,"), ConfigLoad::Failed { .. }));
     }
 }
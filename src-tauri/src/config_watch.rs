@@ -0,0 +1,288 @@
+// Hot reload of `config.json`: external edits are picked up while the app
+// runs and applied by comparing the new settings with the previous ones, so
+// only what changed is sent to the engine and services. The file is polled
+// and read once it has stopped changing, so an editor's half-written save
+// isn't taken for a broken file. A file that doesn't read is reported and
+// left alone, and the running settings stay as they were. The app's own
+// saves are recognized and skipped.
+
+use crate::audio::AudioCommand;
+use crate::config::{self, AppConfig, ConfigLoad, ConfigProblem};
+use crate::AppState;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches the config file for the rest of the app's life.
+pub fn spawn_watcher(app: AppHandle) {
+    let Some(path) = config::get_config_path(&app) else {
+        return;
+    };
+    thread::spawn(move || {
+        let mut current = config::load_config(&app);
+        let mut seen = modified(&path);
+        let mut pending: Option<SystemTime> = None;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let stamp = modified(&path);
+            if stamp == seen {
+                continue;
+            }
+            // Wait for one quiet interval before reading
+            if stamp.is_some() && pending != stamp {
+                pending = stamp;
+                continue;
+            }
+            seen = stamp;
+            pending = None;
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let own = config::is_own_write(&content);
+            let next = match config::read_config(&path) {
+                ConfigLoad::Loaded(config) => config,
+                // Likely still being edited; wait for the next save
+                ConfigLoad::Recovered { error, .. } | ConfigLoad::Failed { error } if !own => {
+                    eprintln!("Failed to reload {}: {}", path.display(), error);
+                    config::report_problem(&app, ConfigProblem { recovered: false, reload: true, error, backup: None });
+                    continue;
+                },
+                _ => continue,
+            };
+            if !own {
+                println!("Config file changed, reloading");
+                apply(&app, &current, &next);
+            }
+            current = next;
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn changed<T: Serialize>(old: &T, new: &T) -> bool {
+    serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+}
+
+/// Brings the running app from `old` to `new`.
+fn apply(app: &AppHandle, old: &AppConfig, new: &AppConfig) {
+    let state = app.state::<AppState>();
+    crate::host::set_jack_auto_connect(new.jack_auto_connect);
+    if old.audio_host != new.audio_host {
+        if let Err(e) = crate::switch_audio_host(&state, new.audio_host.as_deref()) {
+            eprintln!("Failed to switch the audio host: {}", e);
+        }
+    }
+    for command in engine_changes(old, new) {
+        let _ = state.tx.send(command);
+    }
+    // Outputs joining the mix go through the same checks as from the window
+    for out in new.outputs.iter().filter(|o| !old.outputs.iter().any(|p| p.name == o.name)) {
+        if state.devices.is_hidden(&out.name) {
+            continue;
+        }
+        let added = crate::add_device_to_mix(app.clone(), app.state(), out.name.clone())
+            .map_err(|e| e.to_string())
+            .and_then(|_| crate::set_device_volume(app.state(), out.name.clone(), out.volume))
+            .and_then(|_| crate::set_device_mute(app.state(), out.name.clone(), out.muted));
+        if let Err(e) = added {
+            eprintln!("Failed to add output {}: {}", out.name, e);
+        }
+    }
+
+    app.state::<crate::session::SessionLock>().set(new.lock_action);
+    app.state::<crate::notify::Notifier>().set_enabled(new.notifications);
+    app.state::<crate::autosave::AutoSave>().set_enabled(new.auto_save);
+    state.devices.apply(new);
+    app.state::<crate::quiet_hours::QuietHours>().set(new.quiet_hours.clone());
+    if changed(&old.scheduled_recordings, &new.scheduled_recordings) {
+        app.state::<crate::scheduler::Scheduler>().load(new.scheduled_recordings.clone());
+    }
+    let services_changed = changed(&old.api, &new.api)
+        || changed(&old.mqtt, &new.mqtt)
+        || changed(&old.osc, &new.osc)
+        || changed(&old.midi, &new.midi)
+        || changed(&old.metrics, &new.metrics)
+        || changed(&old.scripting, &new.scripting)
+        || changed(&old.webhooks, &new.webhooks)
+        || changed(&old.hotkeys, &new.hotkeys)
+        || changed(&old.replay.shortcut, &new.replay.shortcut)
+        || old.media_keys != new.media_keys;
+    if services_changed {
+        crate::apply_services(app, new);
+    }
+    if old.autostart != new.autostart {
+        if let Err(e) = crate::apply_autostart(app, new.autostart) {
+            eprintln!("Failed to update launch at login: {}", e);
+        }
+    }
+    crate::tray::refresh_menu(app);
+    let _ = app.emit("config-reloaded", ());
+}
+
+/// Engine commands for the settings that differ, including outputs leaving
+/// the mix and setting changes of outputs in both.
+fn engine_changes(old: &AppConfig, new: &AppConfig) -> Vec<AudioCommand> {
+    let mut commands = Vec::new();
+    // The taper first, since faders are converted through it
+    if old.volume_taper != new.volume_taper {
+        commands.push(AudioCommand::SetVolumeTaper(new.volume_taper));
+    }
+    if old.input_volume != new.input_volume {
+        commands.push(AudioCommand::SetInputVolume(new.input_volume));
+    }
+    if old.input_muted != new.input_muted {
+        commands.push(AudioCommand::SetInputMute(new.input_muted));
+    }
+    if old.master_volume != new.master_volume {
+        commands.push(AudioCommand::SetMasterVolume(new.master_volume));
+    }
+    if old.master_muted != new.master_muted {
+        commands.push(AudioCommand::SetMasterMute(new.master_muted));
+    }
+    if old.noise_gate != new.noise_gate {
+        commands.push(AudioCommand::SetNoiseGate(new.noise_gate));
+    }
+    if old.ducking != new.ducking {
+        commands.push(AudioCommand::SetDucking(new.ducking));
+    }
+    if old.link_groups != new.link_groups {
+        commands.push(AudioCommand::SetLinkGroups(new.link_groups.clone()));
+    }
+    if changed(&old.capture_source, &new.capture_source) {
+        commands.push(AudioCommand::SetCaptureSource(new.capture_source.clone()));
+    }
+    if old.capture_sample_rate != new.capture_sample_rate {
+        commands.push(AudioCommand::SetCaptureSampleRate(new.capture_sample_rate));
+    }
+    if old.capture_exclusions != new.capture_exclusions {
+        commands.push(AudioCommand::SetCaptureExclusions(new.capture_exclusions.clone()));
+    }
+    if (old.capture_fade_in_ms, old.capture_fade_out_ms) != (new.capture_fade_in_ms, new.capture_fade_out_ms) {
+        commands.push(AudioCommand::SetCaptureFades(new.capture_fade_in_ms, new.capture_fade_out_ms));
+    }
+    if old.crossfade_ms != new.crossfade_ms {
+        commands.push(AudioCommand::SetCrossfadeDuration(new.crossfade_ms));
+    }
+    if old.bit_perfect != new.bit_perfect {
+        commands.push(AudioCommand::SetBitPerfect(new.bit_perfect));
+    }
+
+    if old.mic_device != new.mic_device {
+        commands.push(match &new.mic_device {
+            Some(device) => AudioCommand::StartMic(device.clone()),
+            None => AudioCommand::StopMic,
+        });
+    }
+    if old.mic_volume != new.mic_volume {
+        commands.push(AudioCommand::SetMicVolume(new.mic_volume));
+    }
+    if old.mic_muted != new.mic_muted {
+        commands.push(AudioCommand::SetMicMute(new.mic_muted));
+    }
+    if old.mic_noise_suppression != new.mic_noise_suppression {
+        commands.push(AudioCommand::SetMicNoiseSuppression(new.mic_noise_suppression));
+    }
+    if old.mic_echo_cancellation != new.mic_echo_cancellation {
+        commands.push(AudioCommand::SetEchoCancellation(new.mic_echo_cancellation));
+    }
+    if old.routes != new.routes {
+        commands.push(AudioCommand::SetRoutes(new.routes.clone()));
+    }
+
+    if changed(&old.recording, &new.recording) {
+        commands.push(AudioCommand::SetRecordingSettings(new.recording.clone()));
+    }
+    if changed(&old.replay, &new.replay) {
+        commands.push(AudioCommand::SetReplaySettings(new.replay.clone()));
+    }
+    if changed(&old.http_stream, &new.http_stream) {
+        commands.push(AudioCommand::SetHttpStreamSettings(new.http_stream.clone()));
+    }
+    if changed(&old.hls, &new.hls) {
+        commands.push(AudioCommand::SetHlsSettings(new.hls.clone()));
+    }
+    if changed(&old.webrtc, &new.webrtc) {
+        commands.push(AudioCommand::SetWebRtcSettings(new.webrtc.clone()));
+    }
+    if changed(&old.vban_receiver, &new.vban_receiver) {
+        commands.push(AudioCommand::SetVbanReceiver(new.vban_receiver.clone()));
+    }
+    if changed(&old.rtp, &new.rtp) {
+        commands.push(AudioCommand::SetRtpSettings(new.rtp.clone()));
+    }
+    if changed(&old.sync_server, &new.sync_server) {
+        commands.push(AudioCommand::SetSyncServer(new.sync_server.clone()));
+    }
+    if changed(&old.sync_client, &new.sync_client) {
+        commands.push(AudioCommand::SetSyncClient(new.sync_client.clone()));
+    }
+
+    for out in &old.outputs {
+        let Some(next) = new.outputs.iter().find(|o| o.name == out.name) else {
+            commands.push(AudioCommand::RemoveOutput(out.name.clone()));
+            continue;
+        };
+        let name = || out.name.clone();
+        if out.volume != next.volume {
+            commands.push(AudioCommand::SetVolume(name(), next.volume));
+        }
+        if out.muted != next.muted {
+            commands.push(AudioCommand::SetMute(name(), next.muted));
+        }
+        if out.width != next.width {
+            commands.push(AudioCommand::SetWidth(name(), next.width));
+        }
+        if out.boost_db != next.boost_db {
+            commands.push(AudioCommand::SetBoost(name(), next.boost_db));
+        }
+        if out.soft_clip != next.soft_clip {
+            commands.push(AudioCommand::SetSoftClip(name(), next.soft_clip));
+        }
+        if out.buffer != next.buffer {
+            commands.push(AudioCommand::SetOutputBuffer(name(), next.buffer));
+        }
+        if out.exclusive != next.exclusive {
+            commands.push(AudioCommand::SetExclusive(name(), next.exclusive));
+        }
+        if out.dither != next.dither {
+            commands.push(AudioCommand::SetDither(name(), next.dither));
+        }
+        if out.inserts != next.inserts {
+            commands.push(AudioCommand::SetInserts(name(), next.inserts.clone()));
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputConfig;
+
+    #[test]
+    fn test_only_changes_are_sent() {
+        let old = AppConfig {
+            outputs: vec![OutputConfig::new("Speakers"), OutputConfig::new("Headphones")],
+            ..AppConfig::default()
+        };
+        assert!(engine_changes(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.master_volume = 0.5;
+        new.outputs.remove(0);
+        new.outputs[0].muted = true;
+        let commands = engine_changes(&old, &new);
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(commands[0], AudioCommand::SetMasterVolume(v) if v == 0.5));
+        assert!(matches!(&commands[1], AudioCommand::RemoveOutput(name) if name == "Speakers"));
+        assert!(matches!(&commands[2], AudioCommand::SetMute(name, true) if name == "Headphones"));
+    }
+}
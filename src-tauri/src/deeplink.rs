@@ -0,0 +1,103 @@
+// `audio-merge://` links, for launchers, browsers and automation tools:
+//
+//   audio-merge://show
+//   audio-merge://capture/start | stop | toggle
+//   audio-merge://add/<output>  audio-merge://remove/<output>
+//   audio-merge://mute/<output> | unmute/<output> | toggle-mute/<output>
+//   audio-merge://volume/<output>/<0-100>
+//   audio-merge://master/<0-100> | master/mute | master/unmute
+//   audio-merge://profile/<name>  (or preset/<name>)
+//
+// Output names are percent-encoded, e.g. `mute/Speakers%20(USB)`.
+
+use crate::api;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+pub const SCHEME: &str = "audio-merge";
+
+#[derive(Debug, PartialEq)]
+pub enum LinkAction {
+    ShowWindow,
+    /// A control API command and its arguments.
+    Command(&'static str, Value),
+    Preset(String),
+}
+
+pub fn is_link(arg: &str) -> bool {
+    arg.strip_prefix(SCHEME).is_some_and(|rest| rest.starts_with("://"))
+}
+
+/// Works out what a link asks for.
+pub fn resolve(url: &str) -> Result<LinkAction, String> {
+    let path = url
+        .strip_prefix(SCHEME)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .ok_or_else(|| format!("Not an {} link: {}", SCHEME, url))?;
+    // Query strings and fragments carry nothing we use
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<String> = path.split('/').filter(|s| !s.is_empty()).map(api::percent_decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let action = match segments.as_slice() {
+        ["show"] => LinkAction::ShowWindow,
+        ["capture", "start"] => LinkAction::Command("start_capture", json!({})),
+        ["capture", "stop"] => LinkAction::Command("stop_capture", json!({})),
+        ["capture", "toggle"] => LinkAction::Command("toggle_capture", json!({})),
+        ["add", name] => LinkAction::Command("add_device_to_mix", json!({ "device_name": name })),
+        ["remove", name] => LinkAction::Command("remove_device_from_mix", json!({ "device_name": name })),
+        ["mute", name] => LinkAction::Command("set_device_mute", json!({ "device_name": name, "muted": true })),
+        ["unmute", name] => LinkAction::Command("set_device_mute", json!({ "device_name": name, "muted": false })),
+        ["toggle-mute", name] => LinkAction::Command("toggle_device_mute", json!({ "device_name": name })),
+        ["volume", name, percent] => {
+            LinkAction::Command("set_device_volume", json!({ "device_name": name, "volume": volume(percent)? }))
+        },
+        ["master", "mute"] => LinkAction::Command("set_master_mute", json!({ "muted": true })),
+        ["master", "unmute"] => LinkAction::Command("set_master_mute", json!({ "muted": false })),
+        ["master", percent] => LinkAction::Command("set_master_volume", json!({ "volume": volume(percent)? })),
+        ["profile" | "preset", name] => LinkAction::Preset(name.to_string()),
+        _ => return Err(format!("Unknown link: {}", url)),
+    };
+    Ok(action)
+}
+
+/// Runs the action of a link.
+pub fn open(app: &AppHandle, url: &str) -> Result<(), String> {
+    match resolve(url)? {
+        LinkAction::ShowWindow => crate::show_main_window(app),
+        LinkAction::Command(command, args) => {
+            api::execute(app, command, &args)?;
+        },
+        LinkAction::Preset(name) => crate::apply_profile(app.clone(), app.state(), name)?,
+    }
+    Ok(())
+}
+
+/// Fader position from a 0-100 percentage.
+fn volume(percent: &str) -> Result<f32, String> {
+    let percent: f32 = percent.parse().map_err(|_| format!("Not a volume: {}", percent))?;
+    Ok((percent / 100.0).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_links() {
+        assert_eq!(
+            resolve("audio-merge://mute/Speakers%20(USB)").unwrap(),
+            LinkAction::Command("set_device_mute", json!({ "device_name": "Speakers (USB)", "muted": true }))
+        );
+        assert_eq!(
+            resolve("audio-merge://volume/Headphones/150/").unwrap(),
+            LinkAction::Command("set_device_volume", json!({ "device_name": "Headphones", "volume": 1.0 }))
+        );
+        assert_eq!(resolve("audio-merge://preset/party?from=deck").unwrap(), LinkAction::Preset("party".to_string()));
+        assert_eq!(resolve("audio-merge://master/mute").unwrap(), LinkAction::Command("set_master_mute", json!({ "muted": true })));
+        assert!(resolve("audio-merge://volume/Headphones/loud").is_err());
+        assert!(resolve("https://example.com").is_err());
+        assert!(is_link("audio-merge://show"));
+        assert!(!is_link("--minimized"));
+    }
+}
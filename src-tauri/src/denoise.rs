@@ -0,0 +1,124 @@
+// RNNoise-based noise suppression for the microphone source.
+// RNNoise works on 10 ms mono frames at 48 kHz, so every channel gets its own
+// state and the stream is delayed by exactly one frame. Mics at other rates
+// are resampled to 48 kHz and back around it.
+
+use crate::dsp::LinearResampler;
+use nnnoiseless::DenoiseState;
+
+/// Sample rate RNNoise was trained for.
+pub const DENOISE_SAMPLE_RATE: u32 = 48000;
+
+/// RNNoise expects samples in 16-bit range rather than -1.0..1.0.
+const I16_SCALE: f32 = 32768.0;
+
+/// Frames per callback the scratch buffers are sized for up front.
+const MAX_BLOCK_FRAMES: usize = 8192;
+
+/// Converters to 48 kHz and back, for mics at other rates.
+struct RateBridge {
+    to_denoise: LinearResampler,
+    from_denoise: LinearResampler,
+    scratch: Vec<f32>,
+}
+
+pub struct NoiseSuppressor {
+    channels: usize,
+    states: Vec<Box<DenoiseState<'static>>>,
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+    pos: usize,
+    bridge: Option<RateBridge>,
+}
+
+impl NoiseSuppressor {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let bridge = (sample_rate != DENOISE_SAMPLE_RATE).then(|| {
+            // Room for a block upsampled from as low as 8 kHz
+            let capacity = MAX_BLOCK_FRAMES * channels * (DENOISE_SAMPLE_RATE / 8000) as usize;
+            RateBridge {
+                to_denoise: LinearResampler::new(channels, sample_rate, DENOISE_SAMPLE_RATE),
+                from_denoise: LinearResampler::new(channels, DENOISE_SAMPLE_RATE, sample_rate),
+                scratch: Vec::with_capacity(capacity),
+            }
+        });
+        Self {
+            channels,
+            states: (0..channels).map(|_| DenoiseState::new()).collect(),
+            input: vec![vec![0.0; DenoiseState::FRAME_SIZE]; channels],
+            output: vec![vec![0.0; DenoiseState::FRAME_SIZE]; channels],
+            pos: 0,
+            bridge,
+        }
+    }
+
+    /// Denoises a block of interleaved frames in place. At rates other than
+    /// 48 kHz the block may come back a frame shorter or longer.
+    pub fn process(&mut self, samples: &mut Vec<f32>) {
+        let Some(mut bridge) = self.bridge.take() else {
+            for frame in samples.chunks_exact_mut(self.channels) {
+                self.process_frame(frame);
+            }
+            return;
+        };
+
+        bridge.scratch.clear();
+        bridge.to_denoise.push(samples, &mut bridge.scratch);
+        for frame in bridge.scratch.chunks_exact_mut(self.channels) {
+            self.process_frame(frame);
+        }
+        samples.clear();
+        bridge.from_denoise.push(&bridge.scratch, samples);
+        self.bridge = Some(bridge);
+    }
+
+    /// Denoises one interleaved 48 kHz frame in place.
+    fn process_frame(&mut self, frame: &mut [f32]) {
+        for (c, sample) in frame.iter_mut().enumerate().take(self.states.len()) {
+            self.input[c][self.pos] = *sample * I16_SCALE;
+            *sample = self.output[c][self.pos] / I16_SCALE;
+        }
+
+        self.pos += 1;
+        if self.pos == DenoiseState::FRAME_SIZE {
+            for (c, state) in self.states.iter_mut().enumerate() {
+                state.process_frame(&mut self.output[c], &self.input[c]);
+            }
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_channel_is_delayed_by_one_frame() {
+        let mut denoiser = NoiseSuppressor::new(2, DENOISE_SAMPLE_RATE);
+        let frames = DenoiseState::FRAME_SIZE * 3;
+        // Left carries a tone, right stays silent
+        let mut samples: Vec<f32> = (0..frames)
+            .flat_map(|i| [(i as f32 * 0.05).sin() * 0.5, 0.0])
+            .collect();
+        denoiser.process(&mut samples);
+
+        assert_eq!(samples.len(), frames * 2);
+        assert!(samples[..DenoiseState::FRAME_SIZE * 2].iter().all(|&s| s == 0.0));
+        assert!(samples.iter().skip(1).step_by(2).all(|&s| s.abs() < 1e-3));
+        assert_eq!(denoiser.pos, 0);
+    }
+
+    #[test]
+    fn test_other_rates_are_bridged_to_48k() {
+        let mut denoiser = NoiseSuppressor::new(1, 44100);
+        let mut total = 0;
+        for _ in 0..10 {
+            let mut samples = vec![0.0; 441];
+            denoiser.process(&mut samples);
+            total += samples.len();
+        }
+        // Resampling holds back at most a frame in each direction
+        assert!((4405..=4410).contains(&total), "{} samples", total);
+    }
+}
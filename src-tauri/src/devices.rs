@@ -0,0 +1,123 @@
+// The user's own names for devices ("Kitchen Speaker"). Aliases are keyed by
+// the device's raw name, which is the identity the engine and the config use
+// for outputs. Commands accept either name; aliases are resolved to the raw
+// name before anything reaches the engine.
+//
+// Hidden devices (e.g. an unused "NVIDIA HDMI Output 3") are left out of the
+// device list and never join the mix on their own: restoring the saved mix,
+// switching profiles and config reloads skip them. Adding one by name still
+// works.
+//
+// Favorites are flagged as pinned in the device list and can be listed first,
+// in the order they were favorited.
+
+use crate::audio::AudioDeviceInfo;
+use crate::config::AppConfig;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Aliases, hidden devices and favorites by raw device name, held in
+/// `AppState` next to the engine channel.
+#[derive(Default)]
+pub struct DevicePrefs {
+    aliases: Mutex<BTreeMap<String, String>>,
+    hidden: Mutex<Vec<String>>,
+    favorites: Mutex<Vec<String>>,
+}
+
+impl DevicePrefs {
+    /// Takes the device settings of `config`.
+    pub fn apply(&self, config: &AppConfig) {
+        self.set_aliases(config.device_aliases.clone());
+        self.set_hidden(config.hidden_devices.clone());
+        self.set_favorites(config.favorite_devices.clone());
+    }
+
+    pub fn set_aliases(&self, aliases: BTreeMap<String, String>) {
+        if let Ok(mut a) = self.aliases.lock() {
+            *a = aliases;
+        }
+    }
+
+    pub fn set_hidden(&self, hidden: Vec<String>) {
+        if let Ok(mut h) = self.hidden.lock() {
+            *h = hidden;
+        }
+    }
+
+    pub fn set_favorites(&self, favorites: Vec<String>) {
+        if let Ok(mut f) = self.favorites.lock() {
+            *f = favorites;
+        }
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.hidden.lock().is_ok_and(|h| h.iter().any(|d| d == name))
+    }
+
+    /// The raw device name for `name`, which may be an alias. Raw names and
+    /// unknown names pass through unchanged.
+    pub fn resolve(&self, name: &str) -> String {
+        let Ok(aliases) = self.aliases.lock() else {
+            return name.to_string();
+        };
+        if aliases.contains_key(name) {
+            return name.to_string();
+        }
+        aliases
+            .iter()
+            .find(|(_, alias)| alias.as_str() == name)
+            .map_or_else(|| name.to_string(), |(raw, _)| raw.clone())
+    }
+
+    /// Drops hidden devices and fills in the alias and pin of the rest.
+    pub fn annotate(&self, devices: &mut Vec<AudioDeviceInfo>) {
+        devices.retain(|d| !self.is_hidden(&d.name));
+        let favorites = self.favorites.lock().map(|f| f.clone()).unwrap_or_default();
+        if let Ok(aliases) = self.aliases.lock() {
+            for device in devices {
+                if let Some(alias) = aliases.get(&device.name) {
+                    device.alias = Some(alias.clone());
+                }
+                device.pinned = favorites.contains(&device.name);
+            }
+        }
+    }
+
+    /// Moves favorites to the front in the order they were favorited; the
+    /// rest keep their order.
+    pub fn favorites_first(&self, devices: &mut [AudioDeviceInfo]) {
+        let favorites = self.favorites.lock().map(|f| f.clone()).unwrap_or_default();
+        devices.sort_by_key(|d| favorites.iter().position(|f| f == &d.name).unwrap_or(favorites.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_aliases() {
+        let prefs = DevicePrefs::default();
+        prefs.set_aliases(BTreeMap::from([("Speakers (Realtek)".to_string(), "Kitchen Speaker".to_string())]));
+        assert_eq!(prefs.resolve("Kitchen Speaker"), "Speakers (Realtek)");
+        assert_eq!(prefs.resolve("Speakers (Realtek)"), "Speakers (Realtek)");
+        assert_eq!(prefs.resolve("Headphones"), "Headphones");
+    }
+
+    #[test]
+    fn test_device_list() {
+        let prefs = DevicePrefs::default();
+        prefs.set_hidden(vec!["HDMI".to_string()]);
+        prefs.set_favorites(vec!["USB DAC".to_string(), "Headphones".to_string()]);
+        let mut devices: Vec<_> = ["Speakers", "HDMI", "Headphones", "USB DAC"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| AudioDeviceInfo { name: name.to_string(), index, ..AudioDeviceInfo::default() })
+            .collect();
+        prefs.annotate(&mut devices);
+        prefs.favorites_first(&mut devices);
+        let names: Vec<_> = devices.iter().map(|d| (d.name.as_str(), d.pinned)).collect();
+        assert_eq!(names, [("USB DAC", true), ("Headphones", true), ("Speakers", false)]);
+    }
+}
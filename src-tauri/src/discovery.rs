@@ -0,0 +1,216 @@
+// mDNS discovery of network audio targets: AirPlay speakers, Cast devices and
+// other Audio Merge instances. Browsing runs in the background for the life of
+// the app and keeps a registry of what is currently announced; device lists
+// and output builders read from it, and the UI is told whenever it changes.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+pub const RAOP_SERVICE: &str = "_raop._tcp.local.";
+pub const CAST_SERVICE: &str = "_googlecast._tcp.local.";
+pub const AUDIO_MERGE_SERVICE: &str = "_audiomerge._tcp.local.";
+
+/// Every service type browsed, with the kind of device it announces.
+const SERVICES: [(&str, NetworkDeviceKind); 3] = [
+    (RAOP_SERVICE, NetworkDeviceKind::AirPlay),
+    (CAST_SERVICE, NetworkDeviceKind::Chromecast),
+    (AUDIO_MERGE_SERVICE, NetworkDeviceKind::AudioMerge),
+];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetworkDeviceKind {
+    AirPlay,
+    Chromecast,
+    /// Another instance of this app.
+    AudioMerge,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NetworkDevice {
+    pub kind: NetworkDeviceKind,
+    /// Name shown to the user.
+    pub name: String,
+    /// Full mDNS instance name, unique on the network.
+    pub instance: String,
+    pub address: IpAddr,
+    pub port: u16,
+    /// TXT record of the announcement.
+    pub properties: HashMap<String, String>,
+}
+
+fn registry() -> &'static Mutex<HashMap<String, NetworkDevice>> {
+    static DEVICES: OnceLock<Mutex<HashMap<String, NetworkDevice>>> = OnceLock::new();
+    DEVICES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Instances this app announces itself, kept out of the registry.
+fn own_instances() -> &'static Mutex<HashSet<String>> {
+    static OWN: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    OWN.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Shared daemon, set once browsing starts.
+static DAEMON: OnceLock<ServiceDaemon> = OnceLock::new();
+
+/// Starts browsing for every supported service type. `on_change` receives
+/// the full device list whenever a device appears, changes or goes away.
+pub fn start<F>(on_change: F) -> Result<(), String>
+where
+    F: Fn(Vec<NetworkDevice>) + Send + Sync + 'static,
+{
+    let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    let on_change = Arc::new(on_change);
+    for (service, kind) in SERVICES {
+        let events = daemon.browse(service).map_err(|e| e.to_string())?;
+        let on_change = on_change.clone();
+        thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                if handle_event(kind, event) {
+                    on_change(devices());
+                }
+            }
+        });
+    }
+    let _ = DAEMON.set(daemon);
+    Ok(())
+}
+
+/// Announces a service of this app until the returned guard is dropped.
+pub struct Advertisement {
+    instance: String,
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        if let Ok(mut own) = own_instances().lock() {
+            own.remove(&self.instance);
+        }
+        if let Some(daemon) = DAEMON.get() {
+            let _ = daemon.unregister(&self.instance);
+        }
+    }
+}
+
+pub fn advertise(service: &str, port: u16, properties: HashMap<String, String>) -> Result<Advertisement, String> {
+    let daemon = DAEMON.get().ok_or_else(|| "Network discovery is not running".to_string())?;
+    let host = local_host_name();
+    let info = ServiceInfo::new(service, &host, &format!("{}.local.", host), "", port, properties)
+        .map_err(|e| e.to_string())?
+        .enable_addr_auto();
+    let instance = info.get_fullname().to_string();
+    if let Ok(mut own) = own_instances().lock() {
+        own.insert(instance.clone());
+    }
+    daemon.register(info).map_err(|e| e.to_string())?;
+    println!("Announcing {} on port {}", instance, port);
+    Ok(Advertisement { instance })
+}
+
+/// This machine's name, reduced to characters valid in an mDNS host label.
+pub fn local_host_name() -> String {
+    let name = std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "audio-merge".to_string());
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Applies one browse event to the registry. Returns whether it changed.
+fn handle_event(kind: NetworkDeviceKind, event: ServiceEvent) -> bool {
+    let Ok(mut devices) = registry().lock() else {
+        return false;
+    };
+    match event {
+        ServiceEvent::ServiceResolved(info) => match network_device(kind, &info) {
+            Some(device) if own_instances().lock().is_ok_and(|own| own.contains(&device.instance)) => false,
+            Some(device) if devices.get(&device.instance) != Some(&device) => {
+                println!("Discovered {:?} device: {}", kind, device.name);
+                devices.insert(device.instance.clone(), device);
+                true
+            },
+            _ => false,
+        },
+        ServiceEvent::ServiceRemoved(_, instance) => match devices.remove(&instance) {
+            Some(device) => {
+                println!("{:?} device went away: {}", kind, device.name);
+                true
+            },
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+fn network_device(kind: NetworkDeviceKind, info: &ServiceInfo) -> Option<NetworkDevice> {
+    // Prefer IPv4, which every receiver supports
+    let addresses = info.get_addresses();
+    let address = addresses
+        .iter()
+        .find(|a| a.is_ipv4())
+        .or_else(|| addresses.iter().next())
+        .copied()?;
+    let instance = info.get_fullname().to_string();
+    let properties: HashMap<String, String> = info
+        .get_properties()
+        .iter()
+        .map(|p| (p.key().to_string(), p.val_str().to_string()))
+        .collect();
+    Some(NetworkDevice {
+        kind,
+        name: display_name(kind, &instance, &properties),
+        instance,
+        address,
+        port: info.get_port(),
+        properties,
+    })
+}
+
+/// Name the device announces for itself. AirPlay instance names carry a
+/// `MAC@` prefix; Cast instance names are IDs, with the friendly name in TXT.
+fn display_name(kind: NetworkDeviceKind, instance: &str, properties: &HashMap<String, String>) -> String {
+    let name = instance.split("._").next().unwrap_or(instance);
+    match kind {
+        NetworkDeviceKind::AirPlay => name.split_once('@').map(|(_, n)| n).unwrap_or(name).to_string(),
+        NetworkDeviceKind::Chromecast => properties.get("fn").cloned().unwrap_or_else(|| name.to_string()),
+        NetworkDeviceKind::AudioMerge => name.to_string(),
+    }
+}
+
+/// Devices currently announced on the network, sorted by name.
+pub fn devices() -> Vec<NetworkDevice> {
+    let mut list: Vec<NetworkDevice> = registry()
+        .lock()
+        .map(|d| d.values().cloned().collect())
+        .unwrap_or_default();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
+
+pub fn find(kind: NetworkDeviceKind, name: &str) -> Option<NetworkDevice> {
+    devices().into_iter().find(|d| d.kind == kind && d.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_names() {
+        let none = HashMap::new();
+        assert_eq!(
+            display_name(NetworkDeviceKind::AirPlay, "A1B2C3D4E5F6@Living Room._raop._tcp.local.", &none),
+            "Living Room"
+        );
+        assert_eq!(display_name(NetworkDeviceKind::AirPlay, "Kitchen._raop._tcp.local.", &none), "Kitchen");
+
+        let txt = HashMap::from([("fn".to_string(), "Office speaker".to_string())]);
+        let instance = "Google-Nest-Mini-0123abcd._googlecast._tcp.local.";
+        assert_eq!(display_name(NetworkDeviceKind::Chromecast, instance, &txt), "Office speaker");
+        assert_eq!(display_name(NetworkDeviceKind::Chromecast, instance, &none), "Google-Nest-Mini-0123abcd");
+    }
+}
@@ -0,0 +1,590 @@
+// DSP building blocks used inside the real-time audio callbacks.
+// Everything here must stay allocation-free and lock-free.
+
+use serde::{Deserialize, Serialize};
+
+/// Upper bound for the stereo width parameter (2 = side signal doubled).
+pub const MAX_STEREO_WIDTH: f32 = 2.0;
+
+/// Duration of the gain ramp applied when a volume changes.
+pub const VOLUME_RAMP_MS: f32 = 20.0;
+
+/// Duration of the fade applied when muting or unmuting.
+pub const MUTE_FADE_MS: f32 = 15.0;
+
+/// Gains at or below this level are treated as silence.
+pub const MIN_DB: f32 = -96.0;
+
+/// Maximum per-output boost above unity gain.
+pub const MAX_BOOST_DB: f32 = 12.0;
+
+/// Level where the soft clipper starts bending the signal.
+const SOFT_CLIP_KNEE: f32 = 0.8;
+
+/// Range covered by the logarithmic taper between slider 0 and 1.
+const LOG_TAPER_RANGE_DB: f32 = 60.0;
+
+/// How a 0.0–1.0 slider position maps to linear gain.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeTaper {
+    #[default]
+    Linear,
+    Log,
+    Cubic,
+}
+
+impl VolumeTaper {
+    pub fn position_to_gain(self, position: f32) -> f32 {
+        let p = position.clamp(0.0, 1.0);
+        match self {
+            VolumeTaper::Linear => p,
+            VolumeTaper::Cubic => p * p * p,
+            VolumeTaper::Log => {
+                if p <= 0.0 { 0.0 } else { db_to_gain((p - 1.0) * LOG_TAPER_RANGE_DB) }
+            }
+        }
+    }
+
+    /// Slider position for a gain of at most unity; the inverse of
+    /// `position_to_gain`.
+    pub fn gain_to_position(self, gain: f32) -> f32 {
+        let g = gain.clamp(0.0, 1.0);
+        match self {
+            VolumeTaper::Linear => g,
+            VolumeTaper::Cubic => g.cbrt(),
+            VolumeTaper::Log => {
+                if g <= 0.0 { 0.0 } else { (1.0 + 20.0 * g.log10() / LOG_TAPER_RANGE_DB).max(0.0) }
+            }
+        }
+    }
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    if db <= MIN_DB { 0.0 } else { 10f32.powf(db / 20.0) }
+}
+
+/// Soft-knee clipper: linear below the knee, then a tanh curve that approaches
+/// full scale without ever exceeding it.
+pub fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SOFT_CLIP_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - SOFT_CLIP_KNEE;
+    let bent = SOFT_CLIP_KNEE + headroom * ((magnitude - SOFT_CLIP_KNEE) / headroom).tanh();
+    bent.copysign(sample)
+}
+
+/// User-facing noise gate parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct NoiseGateSettings {
+    pub enabled: bool,
+    pub threshold_db: f32,
+    pub attack_ms: f32,
+    pub hold_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for NoiseGateSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -50.0,
+            attack_ms: 5.0,
+            hold_ms: 100.0,
+            release_ms: 150.0,
+        }
+    }
+}
+
+/// Frame-based noise gate: opens when the frame peak crosses the threshold,
+/// stays open for the hold time, then releases to silence.
+pub struct NoiseGate {
+    settings: NoiseGateSettings,
+    sample_rate: u32,
+    threshold: f32,
+    attack_step: f32,
+    release_step: f32,
+    hold_frames: u32,
+    hold_left: u32,
+    gain: f32,
+}
+
+impl NoiseGate {
+    pub fn new(settings: NoiseGateSettings, sample_rate: u32) -> Self {
+        let mut gate = Self {
+            settings,
+            sample_rate,
+            threshold: 0.0,
+            attack_step: 1.0,
+            release_step: 1.0,
+            hold_frames: 0,
+            hold_left: 0,
+            gain: 1.0,
+        };
+        gate.set_settings(settings);
+        gate
+    }
+
+    pub fn settings(&self) -> NoiseGateSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: NoiseGateSettings) {
+        let frames_per_ms = self.sample_rate as f32 / 1000.0;
+        self.settings = settings;
+        self.threshold = db_to_gain(settings.threshold_db);
+        self.attack_step = 1.0 / (settings.attack_ms * frames_per_ms).max(1.0);
+        self.release_step = 1.0 / (settings.release_ms * frames_per_ms).max(1.0);
+        self.hold_frames = (settings.hold_ms * frames_per_ms) as u32;
+    }
+
+    /// Returns the gate gain for a frame whose peak level is `level`.
+    pub fn next_gain(&mut self, level: f32) -> f32 {
+        if !self.settings.enabled {
+            return 1.0;
+        }
+
+        let open = if level >= self.threshold {
+            self.hold_left = self.hold_frames;
+            true
+        } else if self.hold_left > 0 {
+            self.hold_left -= 1;
+            true
+        } else {
+            false
+        };
+
+        self.gain = if open {
+            (self.gain + self.attack_step).min(1.0)
+        } else {
+            (self.gain - self.release_step).max(0.0)
+        };
+        self.gain
+    }
+}
+
+/// User-facing sidechain ducking parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct DuckingSettings {
+    pub enabled: bool,
+    pub threshold_db: f32,
+    /// How far the loopback is pulled down while the mic is active.
+    pub depth_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for DuckingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -40.0,
+            depth_db: 12.0,
+            attack_ms: 20.0,
+            release_ms: 500.0,
+        }
+    }
+}
+
+/// Sidechain ducker keyed by the mic level: pulls the gain down to the duck
+/// depth while the mic is above the threshold, then releases back to unity.
+pub struct Ducker {
+    settings: DuckingSettings,
+    sample_rate: u32,
+    threshold: f32,
+    floor: f32,
+    attack_step: f32,
+    release_step: f32,
+    gain: f32,
+}
+
+impl Ducker {
+    pub fn new(settings: DuckingSettings, sample_rate: u32) -> Self {
+        let mut ducker = Self {
+            settings,
+            sample_rate,
+            threshold: 0.0,
+            floor: 1.0,
+            attack_step: 1.0,
+            release_step: 1.0,
+            gain: 1.0,
+        };
+        ducker.set_settings(settings);
+        ducker
+    }
+
+    pub fn settings(&self) -> DuckingSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: DuckingSettings) {
+        let frames_per_ms = self.sample_rate as f32 / 1000.0;
+        self.settings = settings;
+        self.threshold = db_to_gain(settings.threshold_db);
+        self.floor = db_to_gain(-settings.depth_db.max(0.0));
+        let range = 1.0 - self.floor;
+        self.attack_step = range / (settings.attack_ms * frames_per_ms).max(1.0);
+        self.release_step = range / (settings.release_ms * frames_per_ms).max(1.0);
+    }
+
+    /// Returns the loopback gain for a frame whose mic peak level is `level`.
+    pub fn next_gain(&mut self, level: f32) -> f32 {
+        if !self.settings.enabled {
+            self.gain = 1.0;
+            return 1.0;
+        }
+
+        self.gain = if level >= self.threshold {
+            (self.gain - self.attack_step).max(self.floor)
+        } else {
+            (self.gain + self.release_step).min(1.0)
+        };
+        self.gain
+    }
+}
+
+/// Peak absolute sample value of an interleaved frame.
+pub fn frame_peak(frame: &[f32]) -> f32 {
+    frame.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+}
+
+/// Scales the side component of a stereo frame: 0 = mono, 1 = unchanged, >1 = wider.
+pub fn apply_stereo_width(frame: &mut [f32], width: f32) {
+    let mid = (frame[0] + frame[1]) * 0.5;
+    let side = (frame[0] - frame[1]) * 0.5 * width;
+    frame[0] = mid + side;
+    frame[1] = mid - side;
+}
+
+/// Linear gain smoother. Moves from the current gain to the target over a
+/// fixed number of frames so slider drags don't produce zipper noise.
+pub struct GainRamp {
+    current: f32,
+    target: f32,
+    step: f32,
+    ramp_frames: f32,
+}
+
+impl GainRamp {
+    pub fn new(initial: f32, sample_rate: u32, ramp_ms: f32) -> Self {
+        Self {
+            current: initial,
+            target: initial,
+            step: 0.0,
+            ramp_frames: (sample_rate as f32 * ramp_ms / 1000.0).max(1.0),
+        }
+    }
+
+    /// Changes the ramp length used by the next `set_target`.
+    pub fn set_ramp_ms(&mut self, sample_rate: u32, ramp_ms: f32) {
+        self.ramp_frames = (sample_rate as f32 * ramp_ms / 1000.0).max(1.0);
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        if target != self.target {
+            self.target = target;
+            self.step = (target - self.current) / self.ramp_frames;
+        }
+    }
+
+    /// Advances the ramp by one frame and returns the gain for that frame.
+    pub fn next_gain(&mut self) -> f32 {
+        if self.current != self.target {
+            self.current += self.step;
+            let reached = (self.step > 0.0 && self.current >= self.target)
+                || (self.step < 0.0 && self.current <= self.target)
+                || self.step == 0.0;
+            if reached {
+                self.current = self.target;
+            }
+        }
+        self.current
+    }
+}
+
+/// Linear-interpolating sample rate converter for interleaved audio. Used by
+/// the mix bus converters and by network outputs whose receivers only accept
+/// a fixed rate.
+pub struct LinearResampler {
+    channels: usize,
+    /// Input frames consumed per output frame.
+    step: f64,
+    /// Fractional read position into `input`, in frames.
+    position: f64,
+    input: Vec<f32>,
+}
+
+impl LinearResampler {
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            channels: channels.max(1),
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            input: Vec::new(),
+        }
+    }
+
+    /// Fills `out`, asking `pull` for exactly as many input frames as needed.
+    pub fn process<F: FnMut(&mut [f32])>(&mut self, out: &mut [f32], mut pull: F) {
+        let channels = self.channels;
+        let frames = out.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        // Interpolation reads one frame past the last position
+        let needed = (self.position + (frames - 1) as f64 * self.step).floor() as usize + 2;
+        let queued = self.input.len() / channels;
+        if needed > queued {
+            let start = self.input.len();
+            self.input.resize(needed * channels, 0.0);
+            pull(&mut self.input[start..]);
+        }
+
+        for (i, frame) in out.chunks_exact_mut(channels).enumerate() {
+            let position = self.position + i as f64 * self.step;
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            for (c, sample) in frame.iter_mut().enumerate() {
+                let a = self.input[index * channels + c];
+                let b = self.input[(index + 1) * channels + c];
+                *sample = a + (b - a) * frac;
+            }
+        }
+
+        let end = self.position + frames as f64 * self.step;
+        let consumed = (end as usize).min(self.input.len() / channels);
+        self.input.drain(..consumed * channels);
+        self.position = end - consumed as f64;
+    }
+
+    /// Makes room to queue `frames` input frames without allocating.
+    pub fn reserve(&mut self, frames: usize) {
+        self.input.reserve((frames + 2) * self.channels);
+    }
+
+    /// Queues `input` and appends every output frame it completes to `out`,
+    /// for sources that push blocks rather than being pulled.
+    pub fn push(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let channels = self.channels;
+        self.input.extend_from_slice(input);
+        let queued = self.input.len() / channels;
+        while (self.position as usize) + 1 < queued {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            for c in 0..channels {
+                let a = self.input[index * channels + c];
+                let b = self.input[(index + 1) * channels + c];
+                out.push(a + (b - a) * frac);
+            }
+            self.position += self.step;
+        }
+        let consumed = (self.position as usize).min(queued);
+        self.input.drain(..consumed * channels);
+        self.position -= consumed as f64;
+    }
+}
+
+/// Dither added when the f32 mix is quantized to fewer bits.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    #[default]
+    Off,
+    /// Triangular (TPDF) noise of ±1 LSB, which decorrelates the truncation
+    /// error from the signal.
+    Tpdf,
+    /// TPDF with first-order error feedback, moving the noise toward high
+    /// frequencies where it is less audible.
+    NoiseShaped,
+}
+
+/// Quantizes interleaved samples onto the grid of a `scale`-step integer
+/// format (e.g. 32768 for i16), with dither. The output is still f32 but
+/// converts to the integer format without further rounding error.
+pub struct Ditherer {
+    mode: Dither,
+    scale: f32,
+    /// Last quantization error per channel, fed back when noise shaping.
+    error: Vec<f32>,
+    rng: u32,
+}
+
+impl Ditherer {
+    pub fn new(mode: Dither, scale: f32, channels: usize) -> Self {
+        Self { mode, scale, error: vec![0.0; channels.max(1)], rng: 0x9E37_79B9 }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.mode == Dither::Off {
+            return;
+        }
+        let channels = self.error.len();
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let c = i % channels;
+            let mut x = *sample * self.scale;
+            if self.mode == Dither::NoiseShaped {
+                x -= self.error[c];
+            }
+            let noise = self.uniform() - self.uniform();
+            let q = (x + noise).round();
+            self.error[c] = q - x;
+            *sample = q / self.scale;
+        }
+    }
+
+    /// Uniform in [0, 1) from a xorshift generator; cheap enough for the callback.
+    fn uniform(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stereo_width() {
+        let mut frame = [1.0, 0.0];
+        apply_stereo_width(&mut frame, 1.0);
+        assert_eq!(frame, [1.0, 0.0]);
+
+        apply_stereo_width(&mut frame, 0.0);
+        assert_eq!(frame, [0.5, 0.5]);
+
+        let mut frame = [1.0, 0.0];
+        apply_stereo_width(&mut frame, 2.0);
+        assert_eq!(frame, [1.5, -0.5]);
+    }
+
+    #[test]
+    fn test_volume_tapers() {
+        for taper in [VolumeTaper::Linear, VolumeTaper::Log, VolumeTaper::Cubic] {
+            assert_eq!(taper.position_to_gain(0.0), 0.0);
+            assert_eq!(taper.position_to_gain(1.0), 1.0);
+        }
+        assert_eq!(VolumeTaper::Cubic.position_to_gain(0.5), 0.125);
+        // Halfway on the log taper is -30 dB
+        assert!((VolumeTaper::Log.position_to_gain(0.5) - db_to_gain(-30.0)).abs() < 1e-6);
+        for taper in [VolumeTaper::Linear, VolumeTaper::Log, VolumeTaper::Cubic] {
+            let position = taper.gain_to_position(taper.position_to_gain(0.7));
+            assert!((position - 0.7).abs() < 1e-5, "{:?}: {}", taper, position);
+        }
+        assert_eq!(db_to_gain(MIN_DB), 0.0);
+        assert!((db_to_gain(-6.0) - 0.501).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_dither_lands_on_the_grid() {
+        let mut samples = [0.3 / 32768.0, 0.25, -0.5];
+        Ditherer::new(Dither::Off, 32768.0, 1).process(&mut samples);
+        assert_eq!(samples[0], 0.3 / 32768.0);
+
+        for mode in [Dither::Tpdf, Dither::NoiseShaped] {
+            let mut ditherer = Ditherer::new(mode, 32768.0, 1);
+            let mut samples = [0.3 / 32768.0; 64];
+            ditherer.process(&mut samples);
+            for s in samples {
+                let steps = s * 32768.0;
+                assert_eq!(steps, steps.round());
+                assert!(steps.abs() <= 3.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_soft_clip() {
+        assert_eq!(soft_clip(0.5), 0.5);
+        assert_eq!(soft_clip(-0.5), -0.5);
+        assert!(soft_clip(4.0) < 1.0);
+        assert!(soft_clip(-4.0) > -1.0);
+        assert!(soft_clip(0.9) > SOFT_CLIP_KNEE);
+    }
+
+    #[test]
+    fn test_noise_gate_closes_after_hold_and_release() {
+        let settings = NoiseGateSettings {
+            enabled: true,
+            threshold_db: -20.0,
+            attack_ms: 1.0,
+            hold_ms: 2.0,
+            release_ms: 2.0,
+        };
+        // 1000 Hz: 1 frame attack, 2 frames hold, 2 frames release
+        let mut gate = NoiseGate::new(settings, 1000);
+        assert_eq!(gate.next_gain(0.5), 1.0);
+        assert_eq!(gate.next_gain(0.0), 1.0);
+        assert_eq!(gate.next_gain(0.0), 1.0);
+        assert_eq!(gate.next_gain(0.0), 0.5);
+        assert_eq!(gate.next_gain(0.0), 0.0);
+
+        gate.set_settings(NoiseGateSettings { enabled: false, ..settings });
+        assert_eq!(gate.next_gain(0.0), 1.0);
+    }
+
+    #[test]
+    fn test_ducker_reaches_depth_and_releases() {
+        let settings = DuckingSettings {
+            enabled: true,
+            threshold_db: -20.0,
+            depth_db: 20.0 * 0.5f32.log10().abs(),
+            attack_ms: 1.0,
+            release_ms: 2.0,
+        };
+        // 1000 Hz: 1 frame down to the floor, 2 frames back up
+        let mut ducker = Ducker::new(settings, 1000);
+        assert!((ducker.next_gain(0.5) - 0.5).abs() < 1e-6);
+        assert!((ducker.next_gain(0.5) - 0.5).abs() < 1e-6);
+        assert!((ducker.next_gain(0.0) - 0.75).abs() < 1e-6);
+        assert!((ducker.next_gain(0.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_gain_ramp_reaches_target() {
+        // 1000 Hz * 10 ms = 10 frames
+        let mut ramp = GainRamp::new(0.0, 1000, 10.0);
+        ramp.set_target(1.0);
+        let first = ramp.next_gain();
+        assert!(first > 0.0 && first < 1.0);
+        for _ in 0..9 {
+            ramp.next_gain();
+        }
+        assert_eq!(ramp.next_gain(), 1.0);
+    }
+
+    #[test]
+    fn test_linear_resampler() {
+        // Same rate passes samples straight through
+        let mut resampler = LinearResampler::new(1, 48000, 48000);
+        let mut next = 0.0;
+        let mut out = [0.0; 4];
+        for _ in 0..3 {
+            resampler.process(&mut out, |input| {
+                for sample in input.iter_mut() {
+                    *sample = next;
+                    next += 1.0;
+                }
+            });
+        }
+        assert_eq!(out, [8.0, 9.0, 10.0, 11.0]);
+
+        // A constant survives conversion, and the rate ratio holds over time
+        let mut resampler = LinearResampler::new(2, 48000, 44100);
+        let mut pulled = 0;
+        let mut out = vec![0.0; 2 * 441];
+        for _ in 0..100 {
+            resampler.process(&mut out, |input| {
+                input.fill(0.5);
+                pulled += input.len() / 2;
+            });
+            assert!(out.iter().all(|s| (s - 0.5).abs() < 1e-6));
+        }
+        assert!((pulled as i64 - 48000).abs() <= 2);
+    }
+}
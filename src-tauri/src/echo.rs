@@ -0,0 +1,170 @@
+// Acoustic echo cancellation for the mic source, using the loopback signal as
+// the far-end reference. WebRTC APM works on 10 ms interleaved frames at 48 kHz;
+// the render half is fed from the capture callback and the capture half runs in
+// the mic callback, each buffering its own frames.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use webrtc_audio_processing::{
+    Config, EchoCancellation, EchoCancellationSuppressionLevel, InitializationConfig, Processor,
+    NUM_SAMPLES_PER_FRAME,
+};
+
+/// Sample rate both the mic and the loopback must run at for AEC.
+pub const ECHO_SAMPLE_RATE: u32 = 48000;
+
+const FRAME_FRAMES: usize = NUM_SAMPLES_PER_FRAME as usize;
+
+/// APM calls that failed in either half. The halves run in audio callbacks,
+/// so they only count; the actor takes the count and reports it.
+#[derive(Clone, Default)]
+pub struct EchoErrors(Arc<AtomicU32>);
+
+impl EchoErrors {
+    /// Errors since the last call.
+    pub fn take(&self) -> u32 {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+
+    fn record(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Creates the mic-side and loopback-side halves of one echo canceller, and
+/// the error count they share.
+pub fn new_pair(mic_channels: usize, render_channels: usize) -> Result<(EchoCapture, EchoRender, EchoErrors), String> {
+    let mut processor = Processor::new(&InitializationConfig {
+        num_capture_channels: mic_channels as i32,
+        num_render_channels: render_channels as i32,
+        ..InitializationConfig::default()
+    })
+    .map_err(|e| e.to_string())?;
+
+    processor.set_config(Config {
+        echo_cancellation: Some(EchoCancellation {
+            suppression_level: EchoCancellationSuppressionLevel::High,
+            stream_delay_ms: None,
+            enable_delay_agnostic: true,
+            enable_extended_filter: true,
+        }),
+        ..Config::default()
+    });
+
+    let errors = EchoErrors::default();
+    let capture = EchoCapture {
+        processor: processor.clone(),
+        channels: mic_channels,
+        input: vec![0.0; FRAME_FRAMES * mic_channels],
+        output: vec![0.0; FRAME_FRAMES * mic_channels],
+        pos: 0,
+        errors: errors.clone(),
+    };
+    let render = EchoRender {
+        processor,
+        channels: render_channels,
+        buffer: vec![0.0; FRAME_FRAMES * render_channels],
+        pos: 0,
+        errors: errors.clone(),
+    };
+    Ok((capture, render, errors))
+}
+
+/// Mic half: removes the echo from mic frames, delaying them by one APM frame.
+pub struct EchoCapture {
+    processor: Processor,
+    channels: usize,
+    input: Vec<f32>,
+    output: Vec<f32>,
+    pos: usize,
+    errors: EchoErrors,
+}
+
+impl EchoCapture {
+    pub fn process_frame(&mut self, frame: &mut [f32]) {
+        let base = self.pos * self.channels;
+        for (c, sample) in frame.iter_mut().enumerate().take(self.channels) {
+            self.input[base + c] = *sample;
+            *sample = self.output[base + c];
+        }
+
+        self.pos += 1;
+        if self.pos == FRAME_FRAMES {
+            self.output.copy_from_slice(&self.input);
+            if self.processor.process_capture_frame(&mut self.output).is_err() {
+                self.errors.record();
+            }
+            self.pos = 0;
+        }
+    }
+}
+
+/// Loopback half: feeds the far-end reference into the canceller.
+pub struct EchoRender {
+    processor: Processor,
+    channels: usize,
+    buffer: Vec<f32>,
+    pos: usize,
+    errors: EchoErrors,
+}
+
+impl EchoRender {
+    pub fn push_frame(&mut self, frame: &[f32]) {
+        let base = self.pos * self.channels;
+        for (c, &sample) in frame.iter().enumerate().take(self.channels) {
+            self.buffer[base + c] = sample;
+        }
+
+        self.pos += 1;
+        if self.pos == FRAME_FRAMES {
+            if self.processor.process_render_frame(&mut self.buffer).is_err() {
+                self.errors.record();
+            }
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mic_frames_are_delayed_by_one_apm_frame() {
+        let (mut capture, mut render, errors) = new_pair(1, 2).unwrap();
+
+        // The first APM frame comes out as silence while it is being filled
+        let mut first = Vec::new();
+        for _ in 0..FRAME_FRAMES {
+            render.push_frame(&[0.0, 0.0]);
+            let mut frame = [0.5];
+            capture.process_frame(&mut frame);
+            first.push(frame[0]);
+        }
+        assert!(first.iter().all(|&s| s == 0.0));
+        assert_eq!(capture.pos, 0);
+        assert_eq!(render.pos, 0);
+        assert_eq!(errors.take(), 0);
+    }
+
+    #[test]
+    fn test_extra_channels_are_ignored() {
+        let (mut capture, mut render, errors) = new_pair(1, 1).unwrap();
+        let mut frame = [0.25, 0.9];
+        capture.process_frame(&mut frame);
+        render.push_frame(&[0.1, 0.2, 0.3]);
+        assert_eq!(capture.input[0], 0.25);
+        assert_eq!(frame[1], 0.9);
+        assert_eq!(render.buffer[0], 0.1);
+        assert_eq!(errors.take(), 0);
+    }
+
+    #[test]
+    fn test_errors_are_taken_once() {
+        let errors = EchoErrors::default();
+        errors.record();
+        errors.clone().record();
+        assert_eq!(errors.take(), 2);
+        assert_eq!(errors.take(), 0);
+    }
+}
@@ -0,0 +1,525 @@
+// Streamable encoders shared by recordings and network outputs. Each one
+// writes its container bytes to any `Write`, a file or a socket alike.
+
+use crate::dsp::LinearResampler;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Sink for interleaved f32 samples; `samples` always holds whole frames.
+/// Encoders are created and used only on the thread that writes them.
+pub trait Encoder {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String>;
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// MP3 at a constant bitrate through LAME.
+pub struct Mp3Encoder<W: Write> {
+    encoder: mp3lame_encoder::Encoder,
+    out_writer: W,
+    pcm: Vec<i16>,
+    out: Vec<u8>,
+}
+
+/// Bitrates LAME accepts, in kbps.
+const MP3_BITRATES: [u32; 16] = [8, 16, 24, 32, 40, 48, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+
+/// Closest LAME bitrate to the requested one.
+fn lame_bitrate(kbps: u32) -> mp3lame_encoder::Bitrate {
+    use mp3lame_encoder::Bitrate::*;
+    let nearest = MP3_BITRATES.iter().copied().min_by_key(|b| b.abs_diff(kbps)).unwrap_or(192);
+    match nearest {
+        8 => Kbps8,
+        16 => Kbps16,
+        24 => Kbps24,
+        32 => Kbps32,
+        40 => Kbps40,
+        48 => Kbps48,
+        64 => Kbps64,
+        80 => Kbps80,
+        96 => Kbps96,
+        112 => Kbps112,
+        128 => Kbps128,
+        160 => Kbps160,
+        224 => Kbps224,
+        256 => Kbps256,
+        320 => Kbps320,
+        _ => Kbps192,
+    }
+}
+
+impl<W: Write> Mp3Encoder<W> {
+    pub fn new(out_writer: W, channels: usize, sample_rate: u32, bitrate_kbps: u32) -> Result<Self, String> {
+        if channels > 2 {
+            return Err(format!("MP3 supports at most 2 channels, capture has {}", channels));
+        }
+        let mut builder = mp3lame_encoder::Builder::new().ok_or_else(|| "Failed to create MP3 encoder".to_string())?;
+        builder.set_num_channels(channels as u8).map_err(|e| format!("{:?}", e))?;
+        builder.set_sample_rate(sample_rate).map_err(|e| format!("{:?}", e))?;
+        builder.set_brate(lame_bitrate(bitrate_kbps)).map_err(|e| format!("{:?}", e))?;
+        builder.set_quality(mp3lame_encoder::Quality::Good).map_err(|e| format!("{:?}", e))?;
+        let encoder = builder.build().map_err(|e| format!("{:?}", e))?;
+
+        Ok(Self { encoder, out_writer, pcm: Vec::new(), out: Vec::new() })
+    }
+}
+
+impl<W: Write> Encoder for Mp3Encoder<W> {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.pcm.clear();
+        self.pcm.extend(samples.iter().map(|&s| to_i16(s)));
+        self.out.clear();
+        self.out.reserve(mp3lame_encoder::max_required_buffer_size(self.pcm.len()));
+        let size = self
+            .encoder
+            .encode(mp3lame_encoder::InterleavedPcm(&self.pcm), self.out.spare_capacity_mut())
+            .map_err(|e| format!("{:?}", e))?;
+        // SAFETY: LAME initialized `size` bytes of the spare capacity
+        unsafe { self.out.set_len(size) };
+        self.out_writer.write_all(&self.out).map_err(|e| e.to_string())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.out.clear();
+        self.out.reserve(mp3lame_encoder::max_required_buffer_size(0));
+        let size = self
+            .encoder
+            .flush::<mp3lame_encoder::FlushNoGap>(self.out.spare_capacity_mut())
+            .map_err(|e| format!("{:?}", e))?;
+        // SAFETY: LAME initialized `size` bytes of the spare capacity
+        unsafe { self.out.set_len(size) };
+        self.out_writer.write_all(&self.out).map_err(|e| e.to_string())?;
+        self.out_writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Largest packet libopus will produce for one frame.
+const OPUS_MAX_PACKET: usize = 4000;
+
+/// Encoder requests from opus_defines.h.
+const OPUS_OK: i32 = 0;
+const OPUS_SET_BITRATE_REQUEST: i32 = 4002;
+const OPUS_SET_COMPLEXITY_REQUEST: i32 = 4010;
+const OPUS_SET_INBAND_FEC_REQUEST: i32 = 4012;
+const OPUS_SET_PACKET_LOSS_PERC_REQUEST: i32 = 4014;
+const OPUS_GET_LOOKAHEAD_REQUEST: i32 = 4027;
+
+/// What libopus tunes the encoder for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpusApplication {
+    /// Best quality for music at a given bitrate.
+    Audio,
+    /// Lowest algorithmic delay, for live monitoring.
+    LowDelay,
+}
+
+impl OpusApplication {
+    fn code(&self) -> i32 {
+        match self {
+            OpusApplication::Audio => 2049,
+            OpusApplication::LowDelay => 2051,
+        }
+    }
+}
+
+/// Duration of one Opus packet. Shorter frames cut latency; longer ones
+/// spend fewer bits on overhead, which helps on slow links.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum OpusFrameSize {
+    Ms2_5,
+    Ms5,
+    Ms10,
+    #[default]
+    Ms20,
+    Ms40,
+    Ms60,
+}
+
+impl OpusFrameSize {
+    /// Samples per channel in one frame at 48 kHz.
+    pub fn samples(&self) -> usize {
+        match self {
+            OpusFrameSize::Ms2_5 => 120,
+            OpusFrameSize::Ms5 => 240,
+            OpusFrameSize::Ms10 => 480,
+            OpusFrameSize::Ms20 => 960,
+            OpusFrameSize::Ms40 => 1920,
+            OpusFrameSize::Ms60 => 2880,
+        }
+    }
+}
+
+/// Opus tuning shared by recordings and network outputs. The bitrate lives
+/// with each feature's own settings.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct OpusSettings {
+    pub frame_size: OpusFrameSize,
+    /// In-band forward error correction: each packet carries a coarse copy of
+    /// the previous one, so a single lost packet can be rebuilt.
+    pub fec: bool,
+    /// Loss the encoder plans for; FEC only spends bits when this is above 0.
+    pub expected_loss_percent: u8,
+    /// 0 (fastest) to 10 (best quality).
+    pub complexity: u8,
+}
+
+impl Default for OpusSettings {
+    fn default() -> Self {
+        Self {
+            frame_size: OpusFrameSize::default(),
+            fec: false,
+            expected_loss_percent: 0,
+            complexity: 10,
+        }
+    }
+}
+
+/// Owner of a libopus encoder state. The `opus` crate doesn't expose every
+/// encoder request (complexity among them), so this talks to libopus directly.
+struct OpusHandle(*mut audiopus_sys::OpusEncoder);
+
+// SAFETY: the state is only touched through `&mut self`, on one thread at a time
+unsafe impl Send for OpusHandle {}
+
+impl OpusHandle {
+    fn new(channels: usize, application: OpusApplication) -> Result<Self, String> {
+        let mut error = 0;
+        // SAFETY: plain constructor call; the result is checked before use
+        let state = unsafe {
+            audiopus_sys::opus_encoder_create(OPUS_SAMPLE_RATE as i32, channels as i32, application.code(), &mut error)
+        };
+        if state.is_null() || error != OPUS_OK {
+            return Err(opus_error(error));
+        }
+        Ok(Self(state))
+    }
+
+    fn set(&mut self, request: i32, value: i32) -> Result<(), String> {
+        // SAFETY: every SET request used here takes a single opus_int32
+        let result = unsafe { audiopus_sys::opus_encoder_ctl(self.0, request, value) };
+        if result == OPUS_OK {
+            Ok(())
+        } else {
+            Err(opus_error(result))
+        }
+    }
+
+    fn lookahead(&mut self) -> Result<i32, String> {
+        let mut value = 0i32;
+        // SAFETY: GET_LOOKAHEAD writes one opus_int32 through the pointer
+        let result = unsafe { audiopus_sys::opus_encoder_ctl(self.0, OPUS_GET_LOOKAHEAD_REQUEST, &mut value as *mut i32) };
+        if result == OPUS_OK {
+            Ok(value)
+        } else {
+            Err(opus_error(result))
+        }
+    }
+
+    /// Encodes one interleaved frame of `frame_size` samples per channel.
+    fn encode(&mut self, frame: &[f32], frame_size: usize, packet: &mut [u8]) -> Result<usize, String> {
+        // SAFETY: `frame` holds frame_size samples per channel and `packet`
+        // is writable for its full length
+        let size = unsafe {
+            audiopus_sys::opus_encode_float(
+                self.0,
+                frame.as_ptr(),
+                frame_size as i32,
+                packet.as_mut_ptr(),
+                packet.len() as i32,
+            )
+        };
+        if size < 0 {
+            Err(opus_error(size))
+        } else {
+            Ok(size as usize)
+        }
+    }
+}
+
+impl Drop for OpusHandle {
+    fn drop(&mut self) {
+        // SAFETY: created by opus_encoder_create and destroyed exactly once
+        unsafe { audiopus_sys::opus_encoder_destroy(self.0) };
+    }
+}
+
+fn opus_error(code: i32) -> String {
+    // SAFETY: opus_strerror returns a static C string for any code
+    let message = unsafe { std::ffi::CStr::from_ptr(audiopus_sys::opus_strerror(code)) };
+    format!("Opus error: {}", message.to_string_lossy())
+}
+
+/// Bare Opus packets, one per frame, for containers and transports to wrap.
+/// Captures at other rates are resampled to 48 kHz on the way in, so frame
+/// sizes and granule positions are always in 48 kHz samples.
+pub struct OpusPacketEncoder {
+    encoder: OpusHandle,
+    channels: usize,
+    frame_size: usize,
+    resampler: Option<LinearResampler>,
+    pending: Vec<f32>,
+    packet: Vec<u8>,
+}
+
+impl OpusPacketEncoder {
+    pub fn new(
+        channels: usize,
+        sample_rate: u32,
+        bitrate_kbps: u32,
+        settings: &OpusSettings,
+        application: OpusApplication,
+    ) -> Result<Self, String> {
+        if sample_rate == 0 {
+            return Err("Opus needs a capture sample rate".to_string());
+        }
+        if !(1..=2).contains(&channels) {
+            return Err(format!("Opus supports at most 2 channels, capture has {}", channels));
+        }
+
+        let mut encoder = OpusHandle::new(channels, application)?;
+        encoder.set(OPUS_SET_BITRATE_REQUEST, bitrate_kbps as i32 * 1000)?;
+        encoder.set(OPUS_SET_COMPLEXITY_REQUEST, settings.complexity.min(10) as i32)?;
+        encoder.set(OPUS_SET_INBAND_FEC_REQUEST, settings.fec as i32)?;
+        encoder.set(OPUS_SET_PACKET_LOSS_PERC_REQUEST, settings.expected_loss_percent.min(100) as i32)?;
+        Ok(Self {
+            encoder,
+            channels,
+            frame_size: settings.frame_size.samples(),
+            resampler: (sample_rate != OPUS_SAMPLE_RATE)
+                .then(|| LinearResampler::new(channels, sample_rate, OPUS_SAMPLE_RATE)),
+            pending: Vec::new(),
+            packet: vec![0; OPUS_MAX_PACKET],
+        })
+    }
+
+    /// Samples per channel in each packet.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Encoder delay in samples, to be skipped by the decoder.
+    pub fn lookahead(&mut self) -> Result<u64, String> {
+        self.encoder.lookahead().map(|l| l as u64)
+    }
+
+    /// Buffers `samples` and calls `on_packet` for every complete frame.
+    pub fn encode<F>(&mut self, samples: &[f32], mut on_packet: F) -> Result<(), String>
+    where
+        F: FnMut(&[u8]) -> Result<(), String>,
+    {
+        match &mut self.resampler {
+            Some(resampler) => resampler.push(samples, &mut self.pending),
+            None => self.pending.extend_from_slice(samples),
+        }
+        let frame_len = self.frame_size * self.channels;
+        while self.pending.len() >= frame_len {
+            let frame: Vec<f32> = self.pending.drain(..frame_len).collect();
+            let size = self.encoder.encode(&frame, self.frame_size, &mut self.packet)?;
+            on_packet(&self.packet[..size])?;
+        }
+        Ok(())
+    }
+
+    /// Pads the buffered tail to a full frame and encodes it. Returns the
+    /// packet and how many real samples per channel it holds.
+    pub fn flush(&mut self) -> Result<(Vec<u8>, u64), String> {
+        let tail = (self.pending.len() / self.channels) as u64;
+        let mut frame = std::mem::take(&mut self.pending);
+        frame.resize(self.frame_size * self.channels, 0.0);
+        let size = self.encoder.encode(&frame, self.frame_size, &mut self.packet)?;
+        Ok((self.packet[..size].to_vec(), tail))
+    }
+}
+
+/// Opus in an Ogg container (RFC 7845).
+pub struct OggOpusEncoder<W: Write> {
+    encoder: OpusPacketEncoder,
+    writer: ogg::PacketWriter<'static, W>,
+    serial: u32,
+    pre_skip: u64,
+    samples_written: u64,
+}
+
+impl<W: Write> OggOpusEncoder<W> {
+    pub fn new(
+        out_writer: W,
+        channels: usize,
+        sample_rate: u32,
+        bitrate_kbps: u32,
+        settings: &OpusSettings,
+    ) -> Result<Self, String> {
+        let mut encoder = OpusPacketEncoder::new(channels, sample_rate, bitrate_kbps, settings, OpusApplication::Audio)?;
+        let pre_skip = encoder.lookahead()?;
+
+        let mut writer = ogg::PacketWriter::new(out_writer);
+        let serial = std::process::id();
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // version
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // mono/stereo mapping
+        writer
+            .write_packet(head, serial, ogg::PacketWriteEndInfo::EndPage, 0)
+            .map_err(|e| e.to_string())?;
+
+        let vendor = concat!("audio_merge ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes()); // no user comments
+        writer
+            .write_packet(tags, serial, ogg::PacketWriteEndInfo::EndPage, 0)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            encoder,
+            writer,
+            serial,
+            pre_skip,
+            samples_written: 0,
+        })
+    }
+}
+
+impl<W: Write> Encoder for OggOpusEncoder<W> {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let Self { encoder, writer, serial, pre_skip, samples_written } = self;
+        let frame_size = encoder.frame_size() as u64;
+        encoder.encode(samples, |packet| {
+            *samples_written += frame_size;
+            writer
+                .write_packet(packet.to_vec(), *serial, ogg::PacketWriteEndInfo::NormalPacket, *pre_skip + *samples_written)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        // The tail is padded to a full frame; the granule position trims it
+        let (packet, tail) = self.encoder.flush()?;
+        self.samples_written += tail;
+        let granule = self.pre_skip + self.samples_written;
+        self.writer
+            .write_packet(packet, self.serial, ogg::PacketWriteEndInfo::EndStream, granule)
+            .map_err(|e| e.to_string())?;
+        self.writer.into_inner().flush().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Keeps what an encoder wrote after `finish` drops it.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lame_bitrate_picks_the_nearest() {
+        use mp3lame_encoder::Bitrate;
+        assert!(matches!(lame_bitrate(128), Bitrate::Kbps128));
+        assert!(matches!(lame_bitrate(130), Bitrate::Kbps128));
+        assert!(matches!(lame_bitrate(150), Bitrate::Kbps160));
+        // 192 has no arm of its own; it is the fallback
+        assert!(matches!(lame_bitrate(190), Bitrate::Kbps192));
+        assert!(matches!(lame_bitrate(0), Bitrate::Kbps8));
+        assert!(matches!(lame_bitrate(1000), Bitrate::Kbps320));
+    }
+
+    #[test]
+    fn test_ogg_opus_granule_trims_the_padded_tail() {
+        let settings = OpusSettings {
+            frame_size: OpusFrameSize::Ms10,
+            ..OpusSettings::default()
+        };
+        let out = Captured::default();
+        let mut encoder: Box<dyn Encoder> =
+            Box::new(OggOpusEncoder::new(out.clone(), 2, OPUS_SAMPLE_RATE, 64, &settings).unwrap());
+        // 25 ms: two full packets and a 5 ms tail padded to a third
+        encoder.write(&vec![0.0; 1200 * 2]).unwrap();
+        encoder.finish().unwrap();
+
+        let bytes = out.0.borrow().clone();
+        let mut reader = ogg::PacketReader::new(std::io::Cursor::new(bytes));
+        let head = reader.read_packet().unwrap().unwrap();
+        assert_eq!(&head.data[..8], b"OpusHead");
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        assert!(pre_skip > 0);
+
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.read_packet().unwrap() {
+            packets.push(packet);
+        }
+        // Tags, then the three audio packets
+        assert_eq!(packets.len(), 4);
+        let last = packets.last().unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), pre_skip + 1200);
+    }
+
+    #[test]
+    fn test_opus_packets_follow_frame_size() {
+        let settings = OpusSettings {
+            frame_size: OpusFrameSize::Ms10,
+            fec: true,
+            expected_loss_percent: 10,
+            complexity: 5,
+        };
+        let mut encoder = OpusPacketEncoder::new(2, OPUS_SAMPLE_RATE, 64, &settings, OpusApplication::Audio).unwrap();
+        assert_eq!(encoder.frame_size(), 480);
+
+        // 25 ms of audio makes two 10 ms packets and leaves 5 ms pending
+        let mut packets = 0;
+        encoder
+            .encode(&vec![0.0; 1200 * 2], |_| {
+                packets += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(packets, 2);
+        assert_eq!(encoder.flush().unwrap().1, 240);
+    }
+
+    #[test]
+    fn test_opus_resamples_other_capture_rates() {
+        let settings = OpusSettings {
+            frame_size: OpusFrameSize::Ms20,
+            ..OpusSettings::default()
+        };
+        let mut encoder = OpusPacketEncoder::new(2, 44100, 64, &settings, OpusApplication::Audio).unwrap();
+        assert_eq!(encoder.frame_size(), 960);
+
+        // One second at 44.1 kHz becomes 48000 samples, 50 packets of 20 ms
+        let mut packets = 0;
+        encoder
+            .encode(&vec![0.0; 44100 * 2], |_| {
+                packets += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert!((49..=50).contains(&packets), "{} packets", packets);
+    }
+}
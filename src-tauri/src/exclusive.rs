@@ -0,0 +1,238 @@
+// WASAPI exclusive-mode rendering for outputs that ask for it. The device is
+// taken over by the app: no system mixer, no resampling and a shorter period.
+// cpal only opens shared streams, so this runs its own render thread. When the
+// device refuses (another app holds it, or the format isn't supported), the
+// render callback is handed back so the output can open in shared mode.
+
+use crate::dsp::{Dither, Ditherer};
+use crate::priority;
+use crossbeam_channel::bounded;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use wasapi::{DeviceCollection, Direction, SampleType, StreamMode, WaveFormat};
+
+/// How long to wait for the device to ask for data before re-checking the
+/// stop flag.
+const EVENT_TIMEOUT_MS: u32 = 100;
+
+/// Sample layouts tried in order of preference.
+const ENCODINGS: [Encoding; 3] = [Encoding::Float32, Encoding::Int24In32, Encoding::Int16];
+
+#[derive(Clone, Copy, Debug)]
+enum Encoding {
+    Float32,
+    Int24In32,
+    Int16,
+}
+
+impl Encoding {
+    fn format(self, sample_rate: u32, channels: usize) -> WaveFormat {
+        let (bits, valid, sample_type) = match self {
+            Encoding::Float32 => (32, 32, SampleType::Float),
+            Encoding::Int24In32 => (32, 24, SampleType::Int),
+            Encoding::Int16 => (16, 16, SampleType::Int),
+        };
+        WaveFormat::new(bits, valid, &sample_type, sample_rate as usize, channels, None)
+    }
+
+    fn encode(self, samples: &[f32], out: &mut Vec<u8>) {
+        out.clear();
+        for &s in samples {
+            let s = s.clamp(-1.0, 1.0);
+            match self {
+                Encoding::Float32 => out.extend_from_slice(&s.to_le_bytes()),
+                Encoding::Int24In32 => out.extend_from_slice(&(((s * 8_388_607.0) as i32) << 8).to_le_bytes()),
+                Encoding::Int16 => out.extend_from_slice(&((s * 32_767.0) as i16).to_le_bytes()),
+            }
+        }
+    }
+}
+
+/// A running exclusive-mode output. Dropping it stops the render thread and
+/// releases the device.
+pub struct ExclusiveOutput {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ExclusiveOutput {
+    /// Opens `device_name` exclusively at the given format, with a period of
+    /// `period_frames` when set. `dither` applies if the device only takes
+    /// 16-bit samples. On failure the error comes back together with `render`.
+    pub fn start<F>(
+        device_name: &str,
+        channels: usize,
+        sample_rate: u32,
+        period_frames: Option<u32>,
+        dither: Dither,
+        render: F,
+    ) -> Result<Self, (String, F)>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let name = device_name.to_string();
+        let (ready_tx, ready_rx) = bounded(1);
+
+        let thread = thread::spawn(move || {
+            let mut render = render;
+            let opened = open(&name, channels, sample_rate, period_frames);
+            let (client, render_client, event, encoding, frames) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    let _ = ready_tx.send(Err((e, render)));
+                    return;
+                },
+            };
+            println!("Exclusive mode on {}: {:?}, {} frames", name, encoding, frames);
+            let _priority = priority::promote_current_thread("Exclusive output", frames as u32, sample_rate);
+
+            let mut ditherer = matches!(encoding, Encoding::Int16).then(|| Ditherer::new(dither, 32_767.0, channels));
+            let mut samples = vec![0.0f32; frames * channels];
+            let mut bytes = Vec::with_capacity(samples.len() * 4);
+            // Start with a buffer of silence so the first period isn't a glitch
+            encoding.encode(&samples, &mut bytes);
+            let started = render_client
+                .write_to_device(frames, &bytes, None)
+                .and_then(|_| client.start_stream())
+                .map_err(|e| e.to_string());
+            if let Err(e) = started {
+                let _ = ready_tx.send(Err((e, render)));
+                return;
+            }
+            let _ = ready_tx.send(Ok(()));
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                if event.wait_for_event(EVENT_TIMEOUT_MS).is_err() {
+                    continue;
+                }
+                render(&mut samples);
+                if let Some(d) = ditherer.as_mut() {
+                    d.process(&mut samples);
+                }
+                encoding.encode(&samples, &mut bytes);
+                if let Err(e) = render_client.write_to_device(frames, &bytes, None) {
+                    eprintln!("Exclusive output error on {}: {}", name, e);
+                    break;
+                }
+            }
+            let _ = client.stop_stream();
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { stop, thread: Some(thread) }),
+            Ok(Err(failed)) => {
+                let _ = thread.join();
+                Err(failed)
+            },
+            // Every path reports before the render callback first runs
+            Err(_) => unreachable!("exclusive output thread exited without reporting"),
+        }
+    }
+}
+
+impl Drop for ExclusiveOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+type Opened = (wasapi::AudioClient, wasapi::AudioRenderClient, wasapi::Handle, Encoding, usize);
+
+/// Initializes an event-driven exclusive client on the first encoding the
+/// device accepts. Returns it with the number of frames per period.
+fn open(device_name: &str, channels: usize, sample_rate: u32, period_frames: Option<u32>) -> Result<Opened, String> {
+    let _ = wasapi::initialize_mta();
+    let device = DeviceCollection::new(&Direction::Render)
+        .and_then(|devices| devices.get_device_with_name(device_name))
+        .map_err(|e| e.to_string())?;
+    let mut client = device.get_iaudioclient().map_err(|e| e.to_string())?;
+
+    let (encoding, format) = choose_encoding(|e| {
+        client.is_supported_exclusive_with_quirks(&e.format(sample_rate, channels)).ok()
+    })
+    .ok_or_else(|| format!("{} Hz with {} channels is not supported in exclusive mode", sample_rate, channels))?;
+
+    let (default_period, min_period) = client.get_device_period().map_err(|e| e.to_string())?;
+    let period_hns = period_hns(period_frames, sample_rate, default_period, min_period);
+    let mode = StreamMode::EventsExclusive { period_hns };
+    client
+        .initialize_client(&format, &Direction::Render, &mode)
+        .map_err(|e| e.to_string())?;
+    let event = client.set_get_eventhandle().map_err(|e| e.to_string())?;
+    let frames = client.get_buffer_size().map_err(|e| e.to_string())? as usize;
+    let render_client = client.get_audiorenderclient().map_err(|e| e.to_string())?;
+    Ok((client, render_client, event, encoding, frames))
+}
+
+/// The first encoding, in order of preference, that `supported` accepts,
+/// with the format it settled on.
+fn choose_encoding<T>(mut supported: impl FnMut(Encoding) -> Option<T>) -> Option<(Encoding, T)> {
+    ENCODINGS.into_iter().find_map(|e| supported(e).map(|format| (e, format)))
+}
+
+/// Device period in 100 ns units: the requested frames, never below the
+/// device minimum, or the device default.
+fn period_hns(period_frames: Option<u32>, sample_rate: u32, default_period: i64, min_period: i64) -> i64 {
+    match period_frames {
+        Some(frames) => (frames as i64 * 10_000_000 / sample_rate as i64).max(min_period),
+        None => default_period,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_encodings_are_tried_in_order() {
+        let mut tried = Vec::new();
+        let chosen = choose_encoding(|e| {
+            tried.push(e);
+            matches!(e, Encoding::Int24In32 | Encoding::Int16).then_some(e as u8)
+        });
+        assert!(matches!(chosen, Some((Encoding::Int24In32, 1))));
+        assert!(matches!(tried[..], [Encoding::Float32, Encoding::Int24In32]));
+        assert!(choose_encoding(|_| None::<()>).is_none());
+    }
+
+    #[test]
+    fn test_period_respects_the_device_minimum() {
+        // 10 ms default, 3 ms minimum
+        assert_eq!(period_hns(None, 48000, 100_000, 30_000), 100_000);
+        assert_eq!(period_hns(Some(240), 48000, 100_000, 30_000), 50_000);
+        assert_eq!(period_hns(Some(64), 48000, 100_000, 30_000), 30_000);
+    }
+
+    #[test]
+    fn test_samples_are_encoded_little_endian_and_clamped() {
+        let mut out = Vec::new();
+        Encoding::Int16.encode(&[1.5, -1.0], &mut out);
+        assert_eq!(out, [0xff, 0x7f, 0x01, 0x80]);
+        Encoding::Int24In32.encode(&[1.0], &mut out);
+        assert_eq!(out, (8_388_607i32 << 8).to_le_bytes());
+        Encoding::Float32.encode(&[0.5], &mut out);
+        assert_eq!(out, 0.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_refused_device_hands_the_callback_back() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let render = move |_: &mut [f32]| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        };
+        let Err((_, mut render)) = ExclusiveOutput::start("No such device", 2, 48000, None, Dither::Off, render) else {
+            panic!("opened a device that doesn't exist");
+        };
+        // The shared-mode fallback renders with the same callback
+        render(&mut [0.0; 4]);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,114 @@
+// The capture callback's list of output rings. The callback owns the list
+// outright, so feeding the outputs never waits on the actor; outputs are added
+// and removed by messages it picks up at the start of each block. The list
+// outlives a capture stream: when the callback is dropped, its feeds go back
+// into the queue for the next one.
+
+use crate::metrics::StreamStats;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rtrb::Producer;
+use std::sync::Arc;
+
+/// The ring of one output, written by the capture callback.
+pub struct OutputFeed {
+    pub name: String,
+    pub producer: Producer<f32>,
+    pub stats: Arc<StreamStats>,
+}
+
+enum FeedChange {
+    Add(OutputFeed),
+    Remove(String),
+}
+
+/// Actor side: queues changes to the list.
+#[derive(Clone)]
+pub struct FeedControl {
+    tx: Sender<FeedChange>,
+    rx: Receiver<FeedChange>,
+}
+
+impl FeedControl {
+    pub fn new() -> Self {
+        let (tx, rx) = unbounded();
+        Self { tx, rx }
+    }
+
+    pub fn add(&self, name: String, producer: Producer<f32>, stats: Arc<StreamStats>) {
+        let _ = self.tx.send(FeedChange::Add(OutputFeed { name, producer, stats }));
+    }
+
+    pub fn remove(&self, name: &str) {
+        let _ = self.tx.send(FeedChange::Remove(name.to_string()));
+    }
+
+    /// The list for a new capture callback, with every feed queued so far.
+    pub fn feeds(&self) -> Feeds {
+        let mut feeds = Feeds { list: Vec::new(), tx: self.tx.clone(), rx: self.rx.clone() };
+        feeds.update();
+        feeds
+    }
+}
+
+/// Callback side: the feeds themselves.
+pub struct Feeds {
+    list: Vec<OutputFeed>,
+    tx: Sender<FeedChange>,
+    rx: Receiver<FeedChange>,
+}
+
+impl Feeds {
+    /// Applies the queued changes. Never blocks.
+    pub fn update(&mut self) {
+        while let Ok(change) = self.rx.try_recv() {
+            match change {
+                FeedChange::Add(feed) => {
+                    self.list.retain(|f| f.name != feed.name);
+                    self.list.push(feed);
+                },
+                FeedChange::Remove(name) => self.list.retain(|f| f.name != name),
+            }
+        }
+        // An output torn down without a remove no longer reads its ring
+        self.list.retain(|f| !f.producer.is_abandoned());
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut OutputFeed> {
+        self.list.iter_mut()
+    }
+}
+
+impl Drop for Feeds {
+    fn drop(&mut self) {
+        self.update();
+        for feed in self.list.drain(..) {
+            let _ = self.tx.send(FeedChange::Add(feed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtrb::RingBuffer;
+
+    #[test]
+    fn test_feeds_survive_the_callback() {
+        let control = FeedControl::new();
+        let (producer, _speakers) = RingBuffer::new(8);
+        control.add("Speakers".to_string(), producer, Arc::new(StreamStats::default()));
+        let (producer, _headphones) = RingBuffer::new(8);
+        control.add("Headphones".to_string(), producer, Arc::new(StreamStats::default()));
+
+        let mut feeds = control.feeds();
+        assert_eq!(feeds.iter_mut().count(), 2);
+        control.remove("Headphones");
+        feeds.update();
+        assert_eq!(feeds.iter_mut().count(), 1);
+        drop(feeds);
+
+        let mut feeds = control.feeds();
+        let names: Vec<_> = feeds.iter_mut().map(|f| f.name.clone()).collect();
+        assert_eq!(names, ["Speakers"]);
+    }
+}
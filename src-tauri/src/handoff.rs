@@ -0,0 +1,99 @@
+// Values read by a real-time callback and changed by the actor, without a
+// lock between them. The callback owns its copy outright; the actor queues
+// changes that the callback applies at the start of each block, the same way
+// `Feeds` hands over the output rings. The value outlives a stream: when the
+// callback is dropped, its copy goes back into the queue for the next one.
+// Streams are only built and dropped on the actor thread, so the copy that
+// goes back can't overtake a later change.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::mem;
+
+type Change<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Actor side: queues changes to the value.
+pub struct HandoffControl<T: Default + Send + 'static> {
+    tx: Sender<Change<T>>,
+    rx: Receiver<Change<T>>,
+}
+
+impl<T: Default + Send + 'static> Clone for HandoffControl<T> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone(), rx: self.rx.clone() }
+    }
+}
+
+impl<T: Default + Send + 'static> Default for HandoffControl<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Default + Send + 'static> HandoffControl<T> {
+    pub fn new() -> Self {
+        let (tx, rx) = unbounded();
+        Self { tx, rx }
+    }
+
+    pub fn set(&self, value: T) {
+        self.update(move |v| *v = value);
+    }
+
+    /// Queues a change made in place. It runs on the callback's thread, so it
+    /// should only move things, not build them.
+    pub fn update(&self, change: impl FnOnce(&mut T) + Send + 'static) {
+        let _ = self.tx.send(Box::new(change));
+    }
+
+    /// The value for a new callback, with every change queued so far.
+    pub fn handoff(&self) -> Handoff<T> {
+        let mut handoff = Handoff { value: T::default(), tx: self.tx.clone(), rx: self.rx.clone() };
+        handoff.update();
+        handoff
+    }
+}
+
+/// Callback side: the value itself.
+pub struct Handoff<T: Default + Send + 'static> {
+    value: T,
+    tx: Sender<Change<T>>,
+    rx: Receiver<Change<T>>,
+}
+
+impl<T: Default + Send + 'static> Handoff<T> {
+    /// Applies the queued changes and returns the value. Never blocks.
+    pub fn update(&mut self) -> &mut T {
+        while let Ok(change) = self.rx.try_recv() {
+            change(&mut self.value);
+        }
+        &mut self.value
+    }
+}
+
+impl<T: Default + Send + 'static> Drop for Handoff<T> {
+    fn drop(&mut self) {
+        self.update();
+        let value = mem::take(&mut self.value);
+        let _ = self.tx.send(Box::new(move |v| *v = value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_survives_the_callback() {
+        let control = HandoffControl::<Vec<u32>>::new();
+        control.set(vec![1]);
+        let mut handoff = control.handoff();
+        assert_eq!(handoff.update(), &[1]);
+        control.update(|v| v.push(2));
+        assert_eq!(handoff.update(), &[1, 2]);
+        drop(handoff);
+
+        control.update(|v| v.push(3));
+        let mut handoff = control.handoff();
+        assert_eq!(handoff.update(), &[1, 2, 3]);
+    }
+}
@@ -0,0 +1,278 @@
+// HLS output of the mix: MP3 "packed audio" segments and a sliding live
+// playlist, served over HTTP. Higher latency than the direct stream, but it
+// plays on smart TVs and iOS devices that only speak HLS.
+
+use crate::encoder::{Encoder, Mp3Encoder};
+use crate::streaming::{self, read_request, stream_tap, write_response};
+use crate::tap;
+use rtrb::Producer;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Segments listed in the live playlist.
+const PLAYLIST_SEGMENTS: usize = 6;
+
+/// Extra segments kept after they leave the playlist, for slow clients.
+const RETAINED_SEGMENTS: usize = 2;
+
+/// MPEG-TS clock used by HLS timestamps.
+const HLS_CLOCK_HZ: u64 = 90_000;
+
+const SEGMENT_POLL_MS: u64 = 20;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HlsSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub segment_seconds: u32,
+    pub bitrate_kbps: u32,
+}
+
+impl Default for HlsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8091,
+            segment_seconds: 6,
+            bitrate_kbps: 128,
+        }
+    }
+}
+
+struct Segment {
+    sequence: u64,
+    duration: f64,
+    data: Arc<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Segments {
+    list: VecDeque<Segment>,
+    target_duration: u32,
+}
+
+impl Segments {
+    fn playlist(&self) -> String {
+        let live: Vec<&Segment> = self.list.iter().rev().take(PLAYLIST_SEGMENTS).rev().collect();
+        let mut out = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            self.target_duration,
+            live.first().map(|s| s.sequence).unwrap_or(0)
+        );
+        for segment in live {
+            out.push_str(&format!("#EXTINF:{:.3},\nsegment{}.mp3\n", segment.duration, segment.sequence));
+        }
+        out
+    }
+
+    fn get(&self, sequence: u64) -> Option<Arc<Vec<u8>>> {
+        self.list.iter().find(|s| s.sequence == sequence).map(|s| s.data.clone())
+    }
+
+    fn push(&mut self, segment: Segment) {
+        self.list.push_back(segment);
+        while self.list.len() > PLAYLIST_SEGMENTS + RETAINED_SEGMENTS {
+            self.list.pop_front();
+        }
+    }
+}
+
+/// Encoder output collected in memory until the segment is cut.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        self.0.lock().map(|mut b| std::mem::take(&mut *b)).unwrap_or_default()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Ok(mut data) = self.0.lock() {
+            data.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// ID3 tag carrying the segment's start time, required at the head of every
+/// packed audio segment.
+fn timestamp_tag(pts: u64) -> Vec<u8> {
+    const OWNER: &[u8] = b"com.apple.streaming.transportStreamTimestamp\0";
+    let frame_size = OWNER.len() + 8;
+    let tag_size = 10 + frame_size;
+
+    let mut tag = Vec::with_capacity(10 + tag_size);
+    tag.extend_from_slice(b"ID3\x04\x00\x00");
+    tag.extend_from_slice(&syncsafe(tag_size as u32));
+    tag.extend_from_slice(b"PRIV");
+    tag.extend_from_slice(&syncsafe(frame_size as u32));
+    tag.extend_from_slice(&[0, 0]);
+    tag.extend_from_slice(OWNER);
+    tag.extend_from_slice(&(pts & 0x1_FFFF_FFFF).to_be_bytes());
+    tag
+}
+
+fn syncsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7f) as u8,
+        ((value >> 14) & 0x7f) as u8,
+        ((value >> 7) & 0x7f) as u8,
+        (value & 0x7f) as u8,
+    ]
+}
+
+/// Cuts the mix into segments and serves them with the playlist at
+/// `/live.m3u8`.
+pub struct HlsServer {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl HlsServer {
+    pub fn start(settings: HlsSettings, channels: usize, sample_rate: u32) -> Result<(Self, Producer<f32>), String> {
+        let (producer, mut consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let segment_seconds = settings.segment_seconds.max(1);
+        let segments = Arc::new(Mutex::new(Segments {
+            target_duration: segment_seconds + 1,
+            ..Segments::default()
+        }));
+
+        let accept = {
+            let segments = segments.clone();
+            streaming::spawn_tcp_listener(settings.port, stop.clone(), move |stream| {
+                if let Err(e) = serve_request(stream, &segments) {
+                    println!("HLS request failed: {}", e);
+                }
+            })?
+        };
+        println!("Serving HLS on http://0.0.0.0:{}/live.m3u8", settings.port);
+
+        let packager = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let output = SharedBuffer::default();
+                let mut encoder: Box<dyn Encoder> =
+                    match Mp3Encoder::new(output.clone(), channels, sample_rate, settings.bitrate_kbps) {
+                        Ok(encoder) => Box::new(encoder),
+                        Err(e) => {
+                            eprintln!("HLS encoder error: {}", e);
+                            return;
+                        }
+                    };
+
+                let segment_frames = segment_seconds as u64 * sample_rate as u64;
+                let mut buf = Vec::new();
+                let mut sequence = 0;
+                let mut total_frames = 0u64;
+                let mut segment_start = 0u64;
+                while !stop.load(Ordering::Relaxed) {
+                    tap::drain_frames(&mut consumer, channels, &mut buf);
+                    if !buf.is_empty() {
+                        if let Err(e) = encoder.write(&buf) {
+                            eprintln!("HLS encoder error: {}", e);
+                            return;
+                        }
+                        total_frames += (buf.len() / channels) as u64;
+                    }
+
+                    // LAME emits whole MP3 frames per call, so any cut between
+                    // writes lands on a frame boundary
+                    if total_frames - segment_start >= segment_frames {
+                        let mut data = timestamp_tag(segment_start * HLS_CLOCK_HZ / sample_rate as u64);
+                        data.extend_from_slice(&output.take());
+                        if let Ok(mut segments) = segments.lock() {
+                            segments.push(Segment {
+                                sequence,
+                                duration: (total_frames - segment_start) as f64 / sample_rate as f64,
+                                data: Arc::new(data),
+                            });
+                        }
+                        sequence += 1;
+                        segment_start = total_frames;
+                    }
+                    thread::sleep(Duration::from_millis(SEGMENT_POLL_MS));
+                }
+            })
+        };
+
+        Ok((Self { stop, threads: vec![packager, accept] }, producer))
+    }
+}
+
+impl Drop for HlsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve_request(mut stream: TcpStream, segments: &Mutex<Segments>) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+    let request = read_request(&mut stream)?;
+    let path = request.path.as_str();
+
+    let body = if request.method != "GET" {
+        None
+    } else if path == "/live.m3u8" {
+        segments
+            .lock()
+            .ok()
+            .map(|s| ("application/vnd.apple.mpegurl", Arc::new(s.playlist().into_bytes())))
+    } else {
+        path.strip_prefix("/segment")
+            .and_then(|rest| rest.strip_suffix(".mp3"))
+            .and_then(|n| n.parse().ok())
+            .and_then(|sequence| segments.lock().ok().and_then(|s| s.get(sequence)))
+            .map(|data| ("audio/mpeg", data))
+    };
+
+    match body {
+        Some((content_type, data)) => write_response(&mut stream, "200 OK", content_type, &data),
+        None => write_response(&mut stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playlist_slides_over_recent_segments() {
+        let mut segments = Segments { target_duration: 7, ..Segments::default() };
+        for sequence in 0..10 {
+            segments.push(Segment { sequence, duration: 6.0, data: Arc::new(Vec::new()) });
+        }
+
+        let playlist = segments.playlist();
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:4\n"));
+        assert!(playlist.contains("segment9.mp3"));
+        assert!(!playlist.contains("segment3.mp3"));
+        // Just-expired segments stay fetchable
+        assert!(segments.get(2).is_some());
+        assert!(segments.get(1).is_none());
+    }
+
+    #[test]
+    fn test_timestamp_tag_layout() {
+        let tag = timestamp_tag(90_000);
+        assert_eq!(&tag[..3], b"ID3");
+        assert_eq!(tag.len(), 10 + 10 + 45 + 8);
+        assert_eq!(&tag[tag.len() - 8..], &90_000u64.to_be_bytes());
+    }
+}
@@ -0,0 +1,74 @@
+// The cpal host the engine opens devices through. The platform default
+// (WASAPI, CoreAudio, ALSA) unless the user picked another host compiled into
+// this build, such as ASIO on Windows (the `asio` feature) or JACK on Linux
+// (the `jack` feature). Under JACK each stream is a client whose ports can be
+// patched in the JACK or PipeWire graph.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static SELECTED: Mutex<Option<cpal::HostId>> = Mutex::new(None);
+static JACK_AUTO_CONNECT: AtomicBool = AtomicBool::new(true);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioHostInfo {
+    pub name: String,
+    /// Devices are currently opened through this host.
+    pub active: bool,
+}
+
+/// Uses the host called `name` from now on; None goes back to the default.
+pub fn select(name: Option<&str>) -> Result<(), String> {
+    let id = match name {
+        Some(name) => Some(
+            cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name().eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("Audio host not available: {}", name))?,
+        ),
+        None => None,
+    };
+    if let Ok(mut selected) = SELECTED.lock() {
+        *selected = id;
+    }
+    Ok(())
+}
+
+/// Whether JACK ports are connected to the system ports when a stream
+/// opens. Off leaves the patching to the user.
+pub fn set_jack_auto_connect(enabled: bool) {
+    JACK_AUTO_CONNECT.store(enabled, Ordering::Relaxed);
+}
+
+/// The selected host, or the default one if it can't be opened.
+pub fn current() -> cpal::Host {
+    let selected = SELECTED.lock().ok().and_then(|s| *s);
+    match selected.map(open) {
+        Some(Ok(host)) => host,
+        Some(Err(e)) => {
+            eprintln!("Failed to open audio host, using the default: {}", e);
+            cpal::default_host()
+        },
+        None => cpal::default_host(),
+    }
+}
+
+fn open(id: cpal::HostId) -> Result<cpal::Host, cpal::HostUnavailable> {
+    #[cfg(all(feature = "jack", target_os = "linux"))]
+    if id == cpal::HostId::Jack {
+        let mut host = cpal::host::jack::Host::new()?;
+        host.set_connect_automatically(JACK_AUTO_CONNECT.load(Ordering::Relaxed));
+        return Ok(host.into());
+    }
+    cpal::host_from_id(id)
+}
+
+/// The hosts available in this build on this machine.
+pub fn available() -> Vec<AudioHostInfo> {
+    let active = current().id();
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| AudioHostInfo { name: id.name().to_string(), active: id == active })
+        .collect()
+}
@@ -0,0 +1,67 @@
+// Global hotkeys bound to mixer actions. The bindings live in the config; the
+// shortcuts themselves are registered in lib.rs next to the replay shortcut.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Master volume change per media key press, matching the system's 2% step.
+const MEDIA_KEY_STEP: f32 = 0.02;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HotkeyBinding {
+    /// Accelerator string, e.g. "CmdOrCtrl+Alt+M".
+    pub shortcut: String,
+    pub action: HotkeyAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HotkeyAction {
+    ToggleMasterMute,
+    ToggleInputMute,
+    ToggleCapture,
+    StartCapture,
+    StopCapture,
+    /// Moves an output's fader by `step` (a fader position, negative to lower it).
+    NudgeVolume { device: String, step: f32 },
+    NudgeMasterVolume { step: f32 },
+    ToggleMiniWindow,
+}
+
+impl HotkeyAction {
+    /// The control API command that carries out the action.
+    pub fn command(&self) -> (&'static str, Value) {
+        match self {
+            HotkeyAction::ToggleMasterMute => ("toggle_master_mute", json!({})),
+            HotkeyAction::ToggleInputMute => ("toggle_input_mute", json!({})),
+            HotkeyAction::ToggleCapture => ("toggle_capture", json!({})),
+            HotkeyAction::StartCapture => ("start_capture", json!({})),
+            HotkeyAction::StopCapture => ("stop_capture", json!({})),
+            HotkeyAction::NudgeVolume { device, step } => {
+                ("nudge_device_volume", json!({ "device_name": device, "step": step }))
+            },
+            HotkeyAction::NudgeMasterVolume { step } => ("nudge_master_volume", json!({ "step": step })),
+            HotkeyAction::ToggleMiniWindow => ("toggle_mini_window", json!({})),
+        }
+    }
+}
+
+/// The keyboard volume keys, redirected to the master stage of the mix.
+/// Registering them as global shortcuts keeps the system from also changing
+/// the default device's volume.
+pub fn media_key_bindings() -> Vec<HotkeyBinding> {
+    vec![
+        HotkeyBinding {
+            shortcut: "AudioVolumeUp".to_string(),
+            action: HotkeyAction::NudgeMasterVolume { step: MEDIA_KEY_STEP },
+        },
+        HotkeyBinding {
+            shortcut: "AudioVolumeDown".to_string(),
+            action: HotkeyAction::NudgeMasterVolume { step: -MEDIA_KEY_STEP },
+        },
+        HotkeyBinding {
+            shortcut: "AudioVolumeMute".to_string(),
+            action: HotkeyAction::ToggleMasterMute,
+        },
+    ]
+}
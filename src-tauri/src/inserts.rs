@@ -0,0 +1,363 @@
+// Effect inserts on an output: third-party plugins run on the output's stream
+// after its fader and width, before the soft clipper. CLAP plugins are hosted
+// when the app is built with the `plugins` feature. VST3 hosting is out of
+// scope: its SDK is C++ with no maintained Rust host, so VST3 bundles are
+// refused and plugins that ship both formats are loaded from their CLAP build.
+//
+// Chains are loaded on the main thread and handed to the output's callback
+// through a queue, as the capture feeds are, so the callback never waits on a
+// lock. Bypass and parameter changes follow through the same queue, and
+// replaced chains come back through another one to be destroyed off the
+// audio thread.
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[cfg(feature = "plugins")]
+use crate::clap_host::{self, Plugin, PluginHandle, MAX_BLOCK_FRAMES};
+
+/// Replaced chains waiting to be collected; the callback drops any beyond.
+const RETIRED_CHAINS: usize = 4;
+
+type MainThreadRunner = Box<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+/// Runs tasks on the app's main thread, set once at startup.
+static MAIN_THREAD: OnceLock<MainThreadRunner> = OnceLock::new();
+
+pub fn set_main_thread(run: impl Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static) {
+    let _ = MAIN_THREAD.set(Box::new(run));
+}
+
+/// Runs `task` on the main thread, where CLAP wants plugins created, queried
+/// and destroyed. Runs it right away when no main thread is set, as in tests.
+pub fn on_main_thread(task: impl FnOnce() + Send + 'static) {
+    match MAIN_THREAD.get() {
+        Some(run) => run(Box::new(task)),
+        None => task(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct InsertConfig {
+    /// Path of the .clap bundle.
+    pub path: PathBuf,
+    /// Plugin id within the bundle; the first plugin when empty.
+    pub plugin_id: String,
+    pub bypass: bool,
+    /// Parameter values set by the user, by CLAP parameter id.
+    pub params: BTreeMap<u32, f64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PluginInfo {
+    pub path: String,
+    pub id: String,
+    pub name: String,
+    pub vendor: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ParamInfo {
+    pub id: u32,
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    pub value: f64,
+}
+
+fn check_format(path: &Path) -> Result<(), String> {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("clap") => Ok(()),
+        Some("vst3") => Err("VST3 plugins are not supported; load the plugin's CLAP version".to_string()),
+        _ => Err(format!("Not a CLAP plugin: {}", path.display())),
+    }
+}
+
+/// Plugins in the bundle at `path`.
+pub fn scan(path: &Path) -> Result<Vec<PluginInfo>, String> {
+    check_format(path)?;
+    #[cfg(feature = "plugins")]
+    return clap_host::scan(path);
+    #[cfg(not(feature = "plugins"))]
+    Err(NOT_BUILT.to_string())
+}
+
+#[cfg(not(feature = "plugins"))]
+const NOT_BUILT: &str = "Plugin hosting is not included in this build";
+
+/// The loaded inserts of one output stream, in order.
+pub struct InsertChain {
+    #[cfg(feature = "plugins")]
+    inserts: Vec<(Plugin, bool)>, // plugin, bypassed
+    channels: usize,
+    planar: Vec<Vec<f32>>,
+    processed: Vec<Vec<f32>>,
+}
+
+impl InsertChain {
+    pub fn empty(channels: usize) -> Self {
+        Self {
+            #[cfg(feature = "plugins")]
+            inserts: Vec::new(),
+            channels: channels.max(1),
+            planar: Vec::new(),
+            processed: Vec::new(),
+        }
+    }
+
+    /// Loads the configured plugins for a stream. Plugins that fail to load
+    /// are reported and left out. Main thread only.
+    pub fn load(configs: &[InsertConfig], channels: usize, sample_rate: u32) -> Self {
+        let mut chain = Self::empty(channels);
+        for config in configs {
+            if let Err(e) = chain.push(config, sample_rate) {
+                eprintln!("Failed to load plugin {}: {}", config.path.display(), e);
+            }
+        }
+        chain
+    }
+
+    #[cfg(feature = "plugins")]
+    fn push(&mut self, config: &InsertConfig, sample_rate: u32) -> Result<(), String> {
+        check_format(&config.path)?;
+        let mut plugin = Plugin::load(&config.path, &config.plugin_id, self.channels, sample_rate)?;
+        for (&id, &value) in &config.params {
+            plugin.set_param(id, value);
+        }
+        self.inserts.push((plugin, config.bypass));
+        self.planar = vec![vec![0.0; MAX_BLOCK_FRAMES]; self.channels];
+        self.processed = self.planar.clone();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "plugins"))]
+    fn push(&mut self, config: &InsertConfig, _sample_rate: u32) -> Result<(), String> {
+        check_format(&config.path)?;
+        Err(NOT_BUILT.to_string())
+    }
+
+    fn set_bypass(&mut self, index: usize, bypass: bool) {
+        #[cfg(feature = "plugins")]
+        if let Some(insert) = self.inserts.get_mut(index) {
+            insert.1 = bypass;
+        }
+        #[cfg(not(feature = "plugins"))]
+        let _ = (index, bypass);
+    }
+
+    fn set_param(&mut self, index: usize, id: u32, value: f64) {
+        #[cfg(feature = "plugins")]
+        if let Some((plugin, _)) = self.inserts.get_mut(index) {
+            plugin.set_param(id, value);
+        }
+        #[cfg(not(feature = "plugins"))]
+        let _ = (index, id, value);
+    }
+
+    /// Stops the plugins' processing, on the audio thread.
+    fn stop(&mut self) {
+        #[cfg(feature = "plugins")]
+        for (plugin, _) in &mut self.inserts {
+            plugin.stop();
+        }
+    }
+
+    fn params(&self) -> Vec<InsertParams> {
+        #[cfg(feature = "plugins")]
+        return self.inserts.iter().map(|(plugin, _)| InsertParams { plugin: plugin.handle() }).collect();
+        #[cfg(not(feature = "plugins"))]
+        Vec::new()
+    }
+
+    /// Runs interleaved `data` through every insert that isn't bypassed.
+    fn process(&mut self, data: &mut [f32]) {
+        #[cfg(feature = "plugins")]
+        {
+            if self.inserts.iter().all(|(_, bypass)| *bypass) {
+                return;
+            }
+            let channels = self.channels;
+            for block in data.chunks_mut(MAX_BLOCK_FRAMES * channels) {
+                let frames = block.len() / channels;
+                for (i, frame) in block.chunks_exact(channels).enumerate() {
+                    for (c, &sample) in frame.iter().enumerate() {
+                        self.planar[c][i] = sample;
+                    }
+                }
+                for (plugin, bypass) in &mut self.inserts {
+                    if !*bypass {
+                        plugin.process(&mut self.planar, &mut self.processed, frames);
+                        std::mem::swap(&mut self.planar, &mut self.processed);
+                    }
+                }
+                for (i, frame) in block.chunks_exact_mut(channels).enumerate() {
+                    for (c, sample) in frame.iter_mut().enumerate() {
+                        *sample = self.planar[c][i];
+                    }
+                }
+            }
+        }
+        #[cfg(not(feature = "plugins"))]
+        let _ = data;
+    }
+}
+
+/// A loaded insert whose parameters can be read on the main thread.
+#[derive(Clone)]
+pub struct InsertParams {
+    #[cfg(feature = "plugins")]
+    plugin: PluginHandle,
+}
+
+impl InsertParams {
+    /// The parameters with their current values. Main thread only.
+    pub fn read(&self) -> Vec<ParamInfo> {
+        #[cfg(feature = "plugins")]
+        return self.plugin.params();
+        #[cfg(not(feature = "plugins"))]
+        Vec::new()
+    }
+}
+
+enum InsertChange {
+    Replace(InsertChain),
+    Bypass(usize, bool),
+    Param(usize, u32, f64),
+}
+
+/// Actor side of an output's inserts.
+pub struct InsertControl {
+    changes: Sender<InsertChange>,
+    retired: Receiver<InsertChain>,
+    params: Arc<Mutex<Vec<InsertParams>>>,
+    channels: usize,
+    sample_rate: u32,
+}
+
+impl InsertControl {
+    /// The control and the callback side of the inserts of a stream with
+    /// `channels` at `sample_rate`. The callback starts with no plugins.
+    pub fn new(channels: usize, sample_rate: u32) -> (Self, Inserts) {
+        let (changes_tx, changes_rx) = unbounded();
+        let (retired_tx, retired_rx) = bounded(RETIRED_CHAINS);
+        let control = Self {
+            changes: changes_tx,
+            retired: retired_rx,
+            params: Arc::new(Mutex::new(Vec::new())),
+            channels,
+            sample_rate,
+        };
+        let inserts = Inserts { chain: InsertChain::empty(channels), changes: changes_rx, retired: retired_tx };
+        (control, inserts)
+    }
+
+    /// Loads `configs` on the main thread and hands the chain to the callback
+    /// once it is ready; the output keeps playing through the old chain meanwhile.
+    pub fn load(&self, configs: Vec<InsertConfig>) {
+        let changes = self.changes.clone();
+        let params = self.params.clone();
+        let (channels, sample_rate) = (self.channels, self.sample_rate);
+        on_main_thread(move || {
+            let chain = InsertChain::load(&configs, channels, sample_rate);
+            if let Ok(mut params) = params.lock() {
+                *params = chain.params();
+            }
+            // Fails when the output closed meanwhile; the chain is dropped here
+            let _ = changes.send(InsertChange::Replace(chain));
+        });
+    }
+
+    /// Sends the bypass and parameter changes from `old` to `new` when both
+    /// hold the same plugins. False when the chain has to be reloaded.
+    pub fn update(&self, old: &[InsertConfig], new: &[InsertConfig]) -> bool {
+        let same_plugins = old.len() == new.len()
+            && old.iter().zip(new).all(|(a, b)| a.path == b.path && a.plugin_id == b.plugin_id);
+        if !same_plugins {
+            return false;
+        }
+        for (index, (old, new)) in old.iter().zip(new).enumerate() {
+            if old.bypass != new.bypass {
+                let _ = self.changes.send(InsertChange::Bypass(index, new.bypass));
+            }
+            for (&id, &value) in &new.params {
+                if old.params.get(&id) != Some(&value) {
+                    let _ = self.changes.send(InsertChange::Param(index, id, value));
+                }
+            }
+        }
+        true
+    }
+
+    /// The insert at `index` of the loaded chain.
+    pub fn params(&self, index: usize) -> Result<InsertParams, String> {
+        let params = self.params.lock().map_err(|_| "Insert chain is unavailable".to_string())?;
+        params.get(index).cloned().ok_or_else(|| format!("No insert at {}", index))
+    }
+
+    /// Drops the chains the callback has replaced. Their plugins are
+    /// destroyed on the main thread.
+    pub fn collect_retired(&self) {
+        while self.retired.try_recv().is_ok() {}
+    }
+}
+
+/// Callback side: the chain itself.
+pub struct Inserts {
+    chain: InsertChain,
+    changes: Receiver<InsertChange>,
+    retired: Sender<InsertChain>,
+}
+
+impl Inserts {
+    /// Applies the queued changes, then runs `data` through the chain.
+    pub fn process(&mut self, data: &mut [f32]) {
+        while let Ok(change) = self.changes.try_recv() {
+            match change {
+                InsertChange::Replace(chain) => {
+                    let mut old = std::mem::replace(&mut self.chain, chain);
+                    old.stop();
+                    let _ = self.retired.try_send(old);
+                },
+                InsertChange::Bypass(index, bypass) => self.chain.set_bypass(index, bypass),
+                InsertChange::Param(index, id, value) => self.chain.set_param(index, id, value),
+            }
+        }
+        self.chain.process(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_clap_bundles_load() {
+        assert!(check_format(Path::new("/plugins/Reverb.clap")).is_ok());
+        assert!(check_format(Path::new("/plugins/Reverb.CLAP")).is_ok());
+        assert!(check_format(Path::new("/plugins/Reverb.vst3")).unwrap_err().contains("VST3"));
+        assert!(check_format(Path::new("/plugins/reverb.dll")).is_err());
+    }
+
+    #[test]
+    fn test_chains_are_handed_to_the_callback() {
+        let (control, mut inserts) = InsertControl::new(2, 48_000);
+        control.load(Vec::new());
+        let mut data = [0.5, -0.5];
+        inserts.process(&mut data);
+        assert_eq!(data, [0.5, -0.5]);
+        assert_eq!(control.retired.len(), 1);
+        control.collect_retired();
+        assert!(control.retired.is_empty());
+
+        let reverb = InsertConfig { path: PathBuf::from("/plugins/Reverb.clap"), ..InsertConfig::default() };
+        let bypassed = InsertConfig { bypass: true, ..reverb.clone() };
+        assert!(!control.update(&[], &[reverb.clone()]));
+        assert!(control.update(&[reverb], &[bypassed]));
+        assert!(control.params(0).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+// Local IPC for `audio-merge-ctl` and other scripts on this machine. The app
+// listens on a named pipe (Windows) or a Unix socket in the user's runtime
+// directory; each line sent is a JSON command message, answered by one JSON
+// line, in the same format as the WebSocket API:
+//
+//   {"id": 1, "command": "set_device_volume", "args": {"device_name": "Speakers", "volume": 0.5}}
+//   {"id": 1, "result": {"ok": true}}
+
+use crossbeam_channel::{bounded, RecvTimeoutError};
+use interprocess::local_socket::{prelude::*, Listener, ListenerNonblockingMode, ListenerOptions, Name, Stream};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const SOCKET_NAME: &str = "audio-merge";
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// How long a client waits to connect and for the reply.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(windows)]
+fn socket_name() -> std::io::Result<Name<'static>> {
+    use interprocess::local_socket::GenericNamespaced;
+    SOCKET_NAME.to_ns_name::<GenericNamespaced>()
+}
+
+#[cfg(not(windows))]
+fn socket_name() -> std::io::Result<Name<'static>> {
+    use interprocess::local_socket::GenericFilePath;
+    socket_path().to_fs_name::<GenericFilePath>()
+}
+
+/// Socket file in the per-user runtime directory, so other users can't connect.
+#[cfg(not(windows))]
+fn socket_path() -> std::path::PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("{}.sock", SOCKET_NAME))
+}
+
+pub struct IpcServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IpcServer {
+    /// Listens for clients, answering each message with `handle`.
+    pub fn start<F>(handle: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        let listener = bind().map_err(|e| format!("IPC socket: {}", e))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let handle = Arc::new(handle);
+
+        let thread = thread::spawn(move || {
+            while !stop_flag.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok(stream) => {
+                        let handle = handle.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve_client(stream, handle.as_ref()) {
+                                println!("IPC client left: {}", e);
+                            }
+                        });
+                    },
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                    Err(e) => {
+                        eprintln!("IPC accept error: {}", e);
+                        thread::sleep(ACCEPT_POLL);
+                    },
+                }
+            }
+        });
+        println!("Listening for local control clients");
+
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn bind() -> std::io::Result<Listener> {
+    let create = || {
+        ListenerOptions::new()
+            .name(socket_name()?)
+            .nonblocking(ListenerNonblockingMode::Accept)
+            .create_sync()
+    };
+    match create() {
+        // A socket file left behind by a crash; nothing answers on it
+        #[cfg(not(windows))]
+        Err(e) if e.kind() == ErrorKind::AddrInUse && connect().is_err() => {
+            std::fs::remove_file(socket_path())?;
+            create()
+        },
+        result => result,
+    }
+}
+
+fn serve_client(stream: Stream, handle: &(dyn Fn(&str) -> String + Send + Sync)) -> Result<(), String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = handle(line.trim());
+        let stream = reader.get_mut();
+        stream.write_all(reply.as_bytes()).map_err(|e| e.to_string())?;
+        stream.write_all(b"\n").map_err(|e| e.to_string())?;
+    }
+}
+
+fn connect() -> std::io::Result<Stream> {
+    Stream::connect(socket_name()?)
+}
+
+/// How one request to the running app went.
+enum Exchange {
+    NotRunning(String),
+    Answered(Result<Value, String>),
+    TimedOut,
+}
+
+/// Connects and exchanges one message on a helper thread, so a stuck
+/// instance can't hang the caller. Neither socket type has portable timeouts.
+fn exchange(command: &str, args: Value) -> Exchange {
+    let message = json!({ "id": 1, "command": command, "args": args }).to_string();
+    let (tx, rx) = bounded(2);
+    thread::spawn(move || match connect() {
+        Ok(stream) => {
+            let _ = tx.send(None);
+            let _ = tx.send(Some(send_message(stream, &message)));
+        },
+        Err(e) => {
+            let _ = tx.send(Some(Err(e.to_string())));
+        },
+    });
+
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    match rx.recv_deadline(deadline) {
+        Ok(Some(Err(e))) => Exchange::NotRunning(e),
+        Ok(Some(reply)) => Exchange::Answered(reply),
+        Ok(None) => match rx.recv_deadline(deadline) {
+            Ok(Some(reply)) => Exchange::Answered(reply),
+            Ok(None) | Err(RecvTimeoutError::Disconnected) => Exchange::Answered(Err("No reply".to_string())),
+            Err(RecvTimeoutError::Timeout) => Exchange::TimedOut,
+        },
+        Err(_) => Exchange::TimedOut,
+    }
+}
+
+/// Sends one command to the running app and returns its result.
+pub fn request(command: &str, args: Value) -> Result<Value, String> {
+    match exchange(command, args) {
+        Exchange::NotRunning(e) => Err(format!("Audio Merge is not running ({})", e)),
+        Exchange::Answered(reply) => reply,
+        Exchange::TimedOut => Err("Audio Merge did not answer".to_string()),
+    }
+}
+
+/// Hands a command to an instance that is already running. Anything that
+/// holds the socket counts as one, even if it answers with an error or not at
+/// all; only a failed connection means this process should start the app.
+pub fn forward_to_running(command: &str, args: Value) -> bool {
+    match exchange(command, args) {
+        Exchange::NotRunning(_) => false,
+        Exchange::Answered(Ok(_)) => true,
+        Exchange::Answered(Err(e)) => {
+            println!("The running instance answered: {}", e);
+            true
+        },
+        Exchange::TimedOut => {
+            println!("The running instance did not answer");
+            true
+        },
+    }
+}
+
+fn send_message(stream: Stream, message: &str) -> Result<Value, String> {
+    let mut reader = BufReader::new(stream);
+    let stream = reader.get_mut();
+    stream.write_all(message.as_bytes()).map_err(|e| e.to_string())?;
+    stream.write_all(b"\n").map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut reply: Value = serde_json::from_str(&line).map_err(|e| format!("Bad reply: {}", e))?;
+    match reply.get("error").and_then(Value::as_str) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(reply["result"].take()),
+    }
+}
@@ -1,23 +1,208 @@
 use tauri::State;
 use crossbeam_channel::Sender;
 
+mod api;
 mod audio;
+mod autosave;
+mod atomic_float;
+#[cfg(windows)]
+mod app_capture;
+mod bus;
+mod cast;
+#[cfg(feature = "plugins")]
+mod clap_host;
+mod cli;
+mod config_watch;
+mod deeplink;
+mod denoise;
+mod devices;
+mod discovery;
+mod echo;
+mod encoder;
+#[cfg(windows)]
+mod exclusive;
+mod dsp;
+mod feeds;
+mod handoff;
+mod hls;
+mod host;
+mod hotkeys;
+mod inserts;
+mod link;
+mod metrics;
+mod mic;
+mod midi;
+mod mqtt;
+mod notify;
+mod now_playing;
+mod osc;
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+mod pipewire_audio;
+mod power;
+mod priority;
+mod quiet_hours;
+mod raop;
+mod recording;
+mod recovery;
+mod routing;
+mod rtp;
+mod sample_format;
+mod scheduler;
+mod scripting;
+mod session;
+mod streaming;
+mod sync;
+mod tap;
+mod tray;
+mod vban;
+mod watchdog;
+mod webhooks;
+mod webrtc_out;
+mod window_state;
 
 pub mod config;
-use tauri::{
-    menu::{Menu, MenuItem},
-    tray::{MouseButton, TrayIconBuilder, TrayIconEvent},
-    Manager, WindowEvent,
-};
-use config::AppConfig;
+pub mod ipc;
+use tauri::{Emitter, Manager, WindowEvent};
+use config::{AppConfig, LinkGroup};
+use scheduler::{ScheduledRecording, Scheduler, SchedulerAction};
+use tauri_plugin_autostart::ManagerExt as _;
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 
 struct AppState {
     tx: Sender<audio::AudioCommand>,
+    /// Device aliases, resolved by every command that takes a device name.
+    devices: devices::DevicePrefs,
 }
 
+/// Output devices, without hidden ones. With `favorites_first`, favorites
+/// lead the list.
 #[tauri::command]
-fn get_audio_devices() -> Vec<audio::AudioDeviceInfo> {
-    audio::get_output_devices()
+fn get_audio_devices(state: State<'_, AppState>, favorites_first: Option<bool>) -> Vec<audio::AudioDeviceInfo> {
+    let mut devices = audio::get_output_devices();
+    state.devices.annotate(&mut devices);
+    if favorites_first.unwrap_or(false) {
+        state.devices.favorites_first(&mut devices);
+    }
+    devices
+}
+
+/// The audio hosts of this build, e.g. WASAPI and ASIO on Windows.
+#[tauri::command]
+fn get_audio_hosts() -> Vec<host::AudioHostInfo> {
+    host::available()
+}
+
+/// Opens devices through the host called `name` from now on, or the default
+/// host for None. Open outputs and the capture are reopened on it.
+#[tauri::command]
+fn set_audio_host(app: tauri::AppHandle, state: State<'_, AppState>, name: Option<String>) -> Result<(), String> {
+    switch_audio_host(&state, name.as_deref())?;
+    config::update_config(&app, |c| c.audio_host = name)
+}
+
+/// Whether JACK streams connect to the system ports on their own, or wait
+/// to be patched. Streams opened from now on follow it.
+#[tauri::command]
+fn set_jack_auto_connect(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    host::set_jack_auto_connect(enabled);
+    config::update_config(&app, |c| c.jack_auto_connect = enabled)
+}
+
+fn switch_audio_host(state: &AppState, name: Option<&str>) -> Result<(), String> {
+    host::select(name)?;
+    println!("Audio host: {}", name.unwrap_or("default"));
+    state.tx.send(audio::AudioCommand::RebuildStreams).map_err(|e| e.to_string())
+}
+
+/// PipeWire sinks whose monitors can be captured with the `monitor`
+/// capture source.
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+#[tauri::command]
+fn get_monitor_sources() -> Result<Vec<pipewire_audio::PwSink>, String> {
+    pipewire_audio::sinks()
+}
+
+/// Monitor capture needs PipeWire on Linux.
+#[cfg(not(all(target_os = "linux", feature = "pipewire")))]
+#[tauri::command]
+fn get_monitor_sources() -> Result<Vec<String>, String> {
+    Err("Monitor sources require PipeWire on Linux".to_string())
+}
+
+/// Input devices, without hidden ones.
+#[tauri::command]
+fn get_input_devices(state: State<'_, AppState>) -> Vec<audio::AudioDeviceInfo> {
+    let mut devices = audio::get_input_devices();
+    state.devices.annotate(&mut devices);
+    devices
+}
+
+/// Names `device_name` `alias` in every command and list; an empty alias
+/// removes it.
+#[tauri::command]
+fn set_device_alias(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, alias: String) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    let alias = alias.trim().to_string();
+    let outputs = audio::get_output_devices();
+    let mut result = Ok(());
+    config::update_config(&app, |c| {
+        let aliases = &mut c.device_aliases;
+        if alias.is_empty() {
+            aliases.remove(&device_name);
+        } else if aliases.iter().any(|(raw, a)| a == &alias && raw != &device_name)
+            || outputs.iter().any(|d| d.name == alias && d.name != device_name)
+        {
+            result = Err(format!("Name already in use: {}", alias));
+            return;
+        } else {
+            aliases.insert(device_name, alias);
+        }
+        state.devices.set_aliases(aliases.clone());
+    })?;
+    result
+}
+
+/// Raw names of the devices left out of `get_audio_devices`.
+#[tauri::command]
+fn get_hidden_devices(app: tauri::AppHandle) -> Vec<String> {
+    config::load_config(&app).hidden_devices
+}
+
+/// Hides `device_name` from the device list and from automatic adds, or
+/// shows it again.
+#[tauri::command]
+fn set_device_hidden(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, hidden: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    config::update_config(&app, |c| {
+        c.hidden_devices.retain(|d| d != &device_name);
+        if hidden {
+            c.hidden_devices.push(device_name);
+        }
+        state.devices.set_hidden(c.hidden_devices.clone());
+    })
+}
+
+/// Adds `device_name` to the favorites, after the ones already there, or
+/// removes it.
+#[tauri::command]
+fn set_device_favorite(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, favorite: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    config::update_config(&app, |c| {
+        let devices = &mut c.favorite_devices;
+        if !favorite {
+            devices.retain(|d| d != &device_name);
+        } else if !devices.contains(&device_name) {
+            devices.push(device_name);
+        }
+        state.devices.set_favorites(devices.clone());
+    })
+}
+
+/// AirPlay, Cast and Audio Merge devices currently announced on the network.
+#[tauri::command]
+fn get_network_devices() -> Vec<discovery::NetworkDevice> {
+    discovery::devices()
 }
 
 #[tauri::command]
@@ -31,28 +216,277 @@ fn start_audio(state: State<'_, AppState>) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn add_device_to_mix(state: State<'_, AppState>, device_name: String) -> Result<(), String> {
-    state.tx.send(audio::AudioCommand::AddOutput(device_name)).map_err(|e| e.to_string())
+fn add_device_to_mix(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String) -> Result<(), audio::AudioError> {
+    let device_name = state.devices.resolve(&device_name);
+    let config = config::load_config(&app);
+    audio::check_feedback(&device_name, &config.capture_source)?;
+    // The buffer sizes and exclusive mode are needed to open the output
+    let saved = config.outputs.iter().find(|o| o.name == device_name);
+    let buffer = saved.map(|o| o.buffer).unwrap_or_default();
+    state.tx.send(audio::AudioCommand::SetOutputBuffer(device_name.clone(), buffer))?;
+    let exclusive = saved.is_some_and(|o| o.exclusive);
+    state.tx.send(audio::AudioCommand::SetExclusive(device_name.clone(), exclusive))?;
+    let dither = saved.map(|o| o.dither).unwrap_or_default();
+    state.tx.send(audio::AudioCommand::SetDither(device_name.clone(), dither))?;
+    let inserts = saved.map(|o| o.inserts.clone()).unwrap_or_default();
+    state.tx.send(audio::AudioCommand::SetInserts(device_name.clone(), inserts))?;
+    state.tx.send(audio::AudioCommand::AddOutput(device_name.clone()))?;
+    Ok(restore_output_settings(&app, &state, &device_name)?)
+}
+
+/// Adds a VBAN network output to the mix. Returns its output name, which is
+/// used like a device name from then on.
+#[tauri::command]
+fn add_vban_output(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    host: String,
+    port: Option<u16>,
+    stream_name: String,
+) -> Result<String, audio::AudioError> {
+    let name = vban::VbanTarget { host, port: port.unwrap_or(vban::DEFAULT_PORT), stream_name }.url();
+    if vban::parse_url(&name).is_none() {
+        return Err(audio::AudioError::from(format!("Invalid VBAN output: {}", name)));
+    }
+    add_device_to_mix(app, state, name.clone())?;
+    Ok(name)
+}
+
+#[tauri::command]
+fn set_vban_receiver(app: tauri::AppHandle, state: State<'_, AppState>, settings: vban::VbanReceiverSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetVbanReceiver(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.vban_receiver = settings)
+}
+
+#[tauri::command]
+fn set_rtp_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: rtp::RtpSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetRtpSettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.rtp = settings)
+}
+
+#[tauri::command]
+fn set_sync_server(app: tauri::AppHandle, state: State<'_, AppState>, settings: sync::SyncServerSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetSyncServer(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.sync_server = settings)
+}
+
+#[tauri::command]
+fn set_sync_client(app: tauri::AppHandle, state: State<'_, AppState>, settings: sync::SyncClientSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetSyncClient(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.sync_client = settings)
+}
+
+/// Output devices the UI should grey out because they would create a feedback loop.
+#[tauri::command]
+fn get_feedback_devices(app: tauri::AppHandle) -> Vec<String> {
+    audio::get_feedback_devices(&config::load_config(&app).capture_source)
+}
+
+/// Re-applies the backend-managed settings persisted for an output after it joins the mix.
+fn restore_output_settings(app: &tauri::AppHandle, state: &AppState, device_name: &str) -> Result<(), String> {
+    let config = config::load_config(app);
+    if let Some(out) = config.outputs.iter().find(|o| o.name == device_name) {
+        state.tx.send(audio::AudioCommand::SetWidth(out.name.clone(), out.width)).map_err(|e| e.to_string())?;
+        state.tx.send(audio::AudioCommand::SetBoost(out.name.clone(), out.boost_db)).map_err(|e| e.to_string())?;
+        state.tx.send(audio::AudioCommand::SetSoftClip(out.name.clone(), out.soft_clip)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
 }
 
 #[tauri::command]
 fn set_device_volume(state: State<'_, AppState>, device_name: String, volume: f32) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
     state.tx.send(audio::AudioCommand::SetVolume(device_name, volume)).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn set_device_volume_db(state: State<'_, AppState>, device_name: String, db: f32) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetVolumeDb(device_name, db)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn remove_device_from_mix(state: State<'_, AppState>, device_name: String) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
     state.tx.send(audio::AudioCommand::RemoveOutput(device_name)).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn swap_device_in_mix(app: tauri::AppHandle, state: State<'_, AppState>, old_device_name: String, new_device_name: String) -> Result<(), audio::AudioError> {
+    let old_device_name = state.devices.resolve(&old_device_name);
+    let new_device_name = state.devices.resolve(&new_device_name);
+    audio::check_feedback(&new_device_name, &config::load_config(&app).capture_source)?;
+    state.tx.send(audio::AudioCommand::SwapOutput(old_device_name.clone(), new_device_name.clone()))?;
+    Ok(config::update_config(&app, |c| c.swap_output(&old_device_name, &new_device_name))?)
+}
+
+#[tauri::command]
+fn set_crossfade_duration(app: tauri::AppHandle, state: State<'_, AppState>, duration_ms: u32) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetCrossfadeDuration(duration_ms)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.crossfade_ms = duration_ms)
+}
+
+/// Bit-perfect mode bypasses every gain and DSP stage while only one output
+/// plays and its format matches the capture, so faders have no effect then.
+#[tauri::command]
+fn set_bit_perfect(app: tauri::AppHandle, state: State<'_, AppState>, enabled: bool) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetBitPerfect(enabled)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.bit_perfect = enabled)
+}
+
 #[tauri::command]
 fn set_device_mute(state: State<'_, AppState>, device_name: String, muted: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
     state.tx.send(audio::AudioCommand::SetMute(device_name, muted)).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn get_audio_state() -> String {
-    "TodoState".to_string() 
+fn set_device_solo(state: State<'_, AppState>, device_name: String, solo: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetSolo(device_name, solo)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_device_width(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, width: f32) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    // Saved as the engine applies it
+    let width = width.clamp(0.0, dsp::MAX_STEREO_WIDTH);
+    state.tx.send(audio::AudioCommand::SetWidth(device_name.clone(), width)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).width = width)
+}
+
+#[tauri::command]
+fn set_device_boost(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, boost_db: f32) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    let boost_db = boost_db.clamp(0.0, dsp::MAX_BOOST_DB);
+    state.tx.send(audio::AudioCommand::SetBoost(device_name.clone(), boost_db)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).boost_db = boost_db)
+}
+
+/// Sets the device period and ring buffer size of an output, reopening it
+/// if it is in the mix.
+#[tauri::command]
+fn set_device_buffer(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, buffer: audio::OutputBuffer) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetOutputBuffer(device_name.clone(), buffer)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).buffer = buffer)
+}
+
+/// Opens an output in WASAPI exclusive mode from now on, or back in shared
+/// mode. An output the device refuses exclusively plays shared instead.
+#[tauri::command]
+fn set_device_exclusive(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, exclusive: bool) -> Result<(), String> {
+    if exclusive && !cfg!(windows) {
+        return Err("Exclusive mode is only available on Windows".to_string());
+    }
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetExclusive(device_name.clone(), exclusive)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).exclusive = exclusive)
+}
+
+/// Dither for an output whose device only takes 16-bit samples, reopening
+/// it if it is in the mix.
+#[tauri::command]
+fn set_device_dither(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, dither: dsp::Dither) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetDither(device_name.clone(), dither)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).dither = dither)
+}
+
+/// Lists the plugins in a CLAP bundle.
+#[tauri::command]
+fn scan_plugins(path: String) -> Result<Vec<inserts::PluginInfo>, String> {
+    inserts::scan(std::path::Path::new(&path))
+}
+
+/// Applies `f` to an output's saved inserts, then hands the result to the
+/// engine.
+fn update_inserts<F>(app: &tauri::AppHandle, state: &AppState, device_name: &str, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Vec<inserts::InsertConfig>) -> Result<(), String>,
+{
+    let mut inserts = config::load_config(app).outputs.iter()
+        .find(|o| o.name == device_name)
+        .map(|o| o.inserts.clone())
+        .unwrap_or_default();
+    f(&mut inserts)?;
+    state.tx.send(audio::AudioCommand::SetInserts(device_name.to_string(), inserts.clone())).map_err(|e| e.to_string())?;
+    config::update_config(app, |c| c.output_mut(device_name).inserts = inserts)
+}
+
+/// Appends a plugin to an output's inserts. Without a plugin id the bundle's
+/// first plugin is used.
+#[tauri::command]
+fn add_output_insert(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, path: String, plugin_id: Option<String>) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    let path = std::path::PathBuf::from(path);
+    let plugins = inserts::scan(&path)?;
+    let plugin_id = plugin_id.unwrap_or_default();
+    if !plugin_id.is_empty() && !plugins.iter().any(|p| p.id == plugin_id) {
+        return Err(format!("No plugin '{}' in {}", plugin_id, path.display()));
+    }
+    update_inserts(&app, &state, &device_name, |inserts| {
+        inserts.push(inserts::InsertConfig { path, plugin_id, ..Default::default() });
+        Ok(())
+    })
+}
+
+#[tauri::command]
+fn remove_output_insert(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, index: usize) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    update_inserts(&app, &state, &device_name, |inserts| {
+        if index >= inserts.len() {
+            return Err(format!("No insert at {}", index));
+        }
+        inserts.remove(index);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+fn set_insert_param(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, index: usize, param_id: u32, value: f64) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    update_inserts(&app, &state, &device_name, |inserts| {
+        let insert = inserts.get_mut(index).ok_or_else(|| format!("No insert at {}", index))?;
+        insert.params.insert(param_id, value);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+fn set_insert_bypass(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, index: usize, bypass: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    update_inserts(&app, &state, &device_name, |inserts| {
+        let insert = inserts.get_mut(index).ok_or_else(|| format!("No insert at {}", index))?;
+        insert.bypass = bypass;
+        Ok(())
+    })
+}
+
+/// Parameters of a loaded insert, with their current values. The output has
+/// to be in the mix. Read here because sync commands run on the main thread,
+/// which CLAP requires for parameter queries.
+#[tauri::command]
+fn get_insert_params(state: State<'_, AppState>, device_name: String, index: usize) -> Result<Vec<inserts::ParamInfo>, String> {
+    let device_name = state.devices.resolve(&device_name);
+    let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+    state.tx.send(audio::AudioCommand::GetInsertParams(device_name, index, reply_tx)).map_err(|e| e.to_string())?;
+    let insert = reply_rx.recv_timeout(std::time::Duration::from_secs(2)).map_err(|e| e.to_string())??;
+    Ok(insert.read())
+}
+
+#[tauri::command]
+fn set_device_soft_clip(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String, enabled: bool) -> Result<(), String> {
+    let device_name = state.devices.resolve(&device_name);
+    state.tx.send(audio::AudioCommand::SetSoftClip(device_name.clone(), enabled)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.output_mut(&device_name).soft_clip = enabled)
+}
+
+#[tauri::command]
+fn get_audio_state(state: State<'_, AppState>) -> Result<audio::AudioStateSnapshot, String> {
+    let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+    state.tx.send(audio::AudioCommand::GetState(reply_tx)).map_err(|e| e.to_string())?;
+    reply_rx.recv_timeout(std::time::Duration::from_secs(2)).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -60,6 +494,41 @@ fn set_input_volume(state: State<'_, AppState>, volume: f32) -> Result<(), Strin
     state.tx.send(audio::AudioCommand::SetInputVolume(volume)).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn set_input_volume_db(state: State<'_, AppState>, db: f32) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetInputVolumeDb(db)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_volume_taper(app: tauri::AppHandle, state: State<'_, AppState>, taper: dsp::VolumeTaper) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetVolumeTaper(taper)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.volume_taper = taper)
+}
+
+#[tauri::command]
+fn set_noise_gate(app: tauri::AppHandle, state: State<'_, AppState>, settings: dsp::NoiseGateSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetNoiseGate(settings)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.noise_gate = settings)
+}
+
+#[tauri::command]
+fn set_ducking(app: tauri::AppHandle, state: State<'_, AppState>, settings: dsp::DuckingSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetDucking(settings)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.ducking = settings)
+}
+
+#[tauri::command]
+fn set_master_volume(app: tauri::AppHandle, state: State<'_, AppState>, volume: f32) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetMasterVolume(volume)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.master_volume = volume)
+}
+
+#[tauri::command]
+fn set_master_mute(app: tauri::AppHandle, state: State<'_, AppState>, muted: bool) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetMasterMute(muted)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.master_muted = muted)
+}
+
 #[tauri::command]
 fn set_input_mute(state: State<'_, AppState>, muted: bool) -> Result<(), String> {
     state.tx.send(audio::AudioCommand::SetInputMute(muted)).map_err(|e| e.to_string())
@@ -75,10 +544,610 @@ fn stop_capture(state: State<'_, AppState>) -> Result<(), String> {
     state.tx.send(audio::AudioCommand::StopLoopback).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn set_capture_source(app: tauri::AppHandle, state: State<'_, AppState>, source: audio::CaptureSource) -> Result<(), audio::AudioError> {
+    // Refuse sources that would capture one of the active outputs
+    for out in &config::load_config(&app).outputs {
+        audio::check_feedback(&out.name, &source)?;
+    }
+    state.tx.send(audio::AudioCommand::SetCaptureSource(source.clone()))?;
+    Ok(config::update_config(&app, |c| c.capture_source = source)?)
+}
+
+/// Forces the capture and mix to one of the standard rates, or back to the
+/// device default with None. Rates the capture device can't run at are refused.
+#[tauri::command]
+fn set_capture_sample_rate(app: tauri::AppHandle, state: State<'_, AppState>, rate: Option<u32>) -> Result<(), String> {
+    if let Some(rate) = rate {
+        if !audio::CAPTURE_SAMPLE_RATES.contains(&rate) {
+            return Err(format!("Unsupported sample rate: {} Hz", rate));
+        }
+        let source = config::load_config(&app).capture_source;
+        if audio::supported_capture_rates(&source).is_some_and(|rates| !rates.contains(&rate)) {
+            return Err(format!("The capture device does not support {} Hz", rate));
+        }
+    }
+    state.tx.send(audio::AudioCommand::SetCaptureSampleRate(rate)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.capture_sample_rate = rate)
+}
+
+/// Rates the current capture source can be forced to; None when it runs at
+/// its own rate.
+#[tauri::command]
+fn get_capture_sample_rates(app: tauri::AppHandle) -> Option<Vec<u32>> {
+    audio::supported_capture_rates(&config::load_config(&app).capture_source)
+}
+
+#[tauri::command]
+fn set_capture_exclusions(app: tauri::AppHandle, state: State<'_, AppState>, applications: Vec<String>) -> Result<(), audio::AudioError> {
+    audio::check_exclusions(&applications)?;
+    state.tx.send(audio::AudioCommand::SetCaptureExclusions(applications.clone()))?;
+    Ok(config::update_config(&app, |c| c.capture_exclusions = applications)?)
+}
+
+/// Applications that can be picked as a capture source.
+#[cfg(windows)]
+#[tauri::command]
+fn get_capture_applications() -> Vec<String> {
+    app_capture::list_applications()
+}
+
+/// Per-application capture is Windows-only.
+#[cfg(not(windows))]
+#[tauri::command]
+fn get_capture_applications() -> Vec<String> {
+    Vec::new()
+}
+
+#[tauri::command]
+fn set_capture_fades(app: tauri::AppHandle, state: State<'_, AppState>, fade_in_ms: u32, fade_out_ms: u32) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetCaptureFades(fade_in_ms, fade_out_ms)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| {
+        c.capture_fade_in_ms = fade_in_ms;
+        c.capture_fade_out_ms = fade_out_ms;
+    })
+}
+
+#[tauri::command]
+fn start_mic(app: tauri::AppHandle, state: State<'_, AppState>, device_name: String) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::StartMic(device_name.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_device = Some(device_name))
+}
+
+#[tauri::command]
+fn stop_mic(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::StopMic).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_device = None)
+}
+
+#[tauri::command]
+fn set_mic_volume(app: tauri::AppHandle, state: State<'_, AppState>, volume: f32) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetMicVolume(volume)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_volume = volume)
+}
+
+#[tauri::command]
+fn set_mic_mute(app: tauri::AppHandle, state: State<'_, AppState>, muted: bool) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetMicMute(muted)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_muted = muted)
+}
+
+#[tauri::command]
+fn set_mic_noise_suppression(app: tauri::AppHandle, state: State<'_, AppState>, enabled: bool) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetMicNoiseSuppression(enabled)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_noise_suppression = enabled)
+}
+
+#[tauri::command]
+fn set_mic_echo_cancellation(app: tauri::AppHandle, state: State<'_, AppState>, enabled: bool) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetEchoCancellation(enabled)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.mic_echo_cancellation = enabled)
+}
+
+/// Sets how much of a source (capture, mic, network) reaches an output, or
+/// the main mix ("mix") that recordings and network streams take.
+#[tauri::command]
+fn set_route(app: tauri::AppHandle, state: State<'_, AppState>, source: routing::RouteSource, output: String, gain: f32) -> Result<(), String> {
+    let output = if output == routing::MIX_OUTPUT { output } else { state.devices.resolve(&output) };
+    state.tx.send(audio::AudioCommand::SetRoute(source, output.clone(), gain)).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| routing::upsert(&mut c.routes, source, &output, gain))
+}
+
+/// Routes that differ from unity.
+#[tauri::command]
+fn get_routes(app: tauri::AppHandle) -> Vec<routing::Route> {
+    config::load_config(&app).routes
+}
+
+#[tauri::command]
+fn get_link_groups(app: tauri::AppHandle) -> Vec<LinkGroup> {
+    config::load_config(&app).link_groups
+}
+
+#[tauri::command]
+fn set_link_group(app: tauri::AppHandle, state: State<'_, AppState>, name: String, members: Vec<String>) -> Result<(), String> {
+    let mut groups = Vec::new();
+    config::update_config(&app, |c| {
+        match c.link_groups.iter_mut().find(|g| g.name == name) {
+            Some(group) => group.members = members,
+            None => c.link_groups.push(LinkGroup { name, members }),
+        }
+        groups = c.link_groups.clone();
+    })?;
+    state.tx.send(audio::AudioCommand::SetLinkGroups(groups)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_link_group(app: tauri::AppHandle, state: State<'_, AppState>, name: String) -> Result<(), String> {
+    let mut groups = Vec::new();
+    config::update_config(&app, |c| {
+        c.link_groups.retain(|g| g.name != name);
+        groups = c.link_groups.clone();
+    })?;
+    state.tx.send(audio::AudioCommand::SetLinkGroups(groups)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn start_recording(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    scheduler: State<'_, Scheduler>,
+    duration_secs: Option<u64>,
+    split: Option<recording::SplitSettings>,
+    tags: Option<recording::TagOptions>,
+) -> Result<(), String> {
+    let directory = recording_directory(&app, &config::load_config(&app))?;
+    let command = audio::AudioCommand::StartRecording(directory, split.unwrap_or_default(), tags.unwrap_or_default());
+    state.tx.send(command).map_err(|e| e.to_string())?;
+    scheduler.set_stop_at(duration_secs.map(|secs| chrono::Local::now() + chrono::Duration::seconds(secs as i64)));
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_recording(state: State<'_, AppState>, scheduler: State<'_, Scheduler>) -> Result<(), String> {
+    scheduler.set_stop_at(None);
+    state.tx.send(audio::AudioCommand::StopRecording).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pause_recording(state: State<'_, AppState>) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::PauseRecording).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resume_recording(state: State<'_, AppState>) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::ResumeRecording).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_scheduled_recordings(scheduler: State<'_, Scheduler>) -> Vec<ScheduledRecording> {
+    scheduler.schedules()
+}
+
+#[tauri::command]
+fn add_scheduled_recording(
+    app: tauri::AppHandle,
+    scheduler: State<'_, Scheduler>,
+    start: chrono::DateTime<chrono::Local>,
+    duration_secs: Option<u64>,
+) -> Result<ScheduledRecording, String> {
+    let schedule = scheduler.add(start, duration_secs)?;
+    config::update_config(&app, |c| c.scheduled_recordings = scheduler.schedules())?;
+    Ok(schedule)
+}
+
+#[tauri::command]
+fn remove_scheduled_recording(app: tauri::AppHandle, scheduler: State<'_, Scheduler>, id: u64) -> Result<(), String> {
+    scheduler.remove(id);
+    config::update_config(&app, |c| c.scheduled_recordings = scheduler.schedules())
+}
+
+/// Ticks the recording scheduler once a second for the lifetime of the app.
+fn spawn_scheduler(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let scheduler = app.state::<Scheduler>();
+        let (actions, changed) = scheduler.tick(chrono::Local::now());
+        if changed {
+            let _ = config::update_config(&app, |c| c.scheduled_recordings = scheduler.schedules());
+        }
+
+        let tx = &app.state::<AppState>().tx;
+        for action in actions {
+            match action {
+                SchedulerAction::StartRecording { stop_at } => {
+                    let snapshot = match get_audio_state(app.state::<AppState>()) {
+                        Ok(snapshot) => snapshot,
+                        Err(e) => {
+                            eprintln!("Scheduled recording failed: {}", e);
+                            continue;
+                        },
+                    };
+                    // A recording started by hand keeps running until stopped by hand
+                    if snapshot.recording.is_some() {
+                        println!("Recording already running, skipping the scheduled one");
+                        continue;
+                    }
+                    println!("Starting scheduled recording");
+                    match recording_directory(&app, &config::load_config(&app)) {
+                        Ok(directory) => {
+                            if !snapshot.capturing {
+                                let _ = tx.send(audio::AudioCommand::StartLoopback);
+                            }
+                            let _ = tx.send(audio::AudioCommand::StartRecording(directory, Default::default(), Default::default()));
+                            scheduler.set_stop_at(stop_at);
+                        },
+                        Err(e) => eprintln!("Scheduled recording failed: {}", e),
+                    }
+                },
+                SchedulerAction::StopRecording => {
+                    println!("Stopping timed recording");
+                    let _ = tx.send(audio::AudioCommand::StopRecording);
+                },
+            }
+        }
+    });
+}
+
+#[tauri::command]
+fn set_recording_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: recording::RecordingSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetRecordingSettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.recording = settings)
+}
+
+#[tauri::command]
+fn save_replay(app: tauri::AppHandle) -> Result<(), String> {
+    request_replay_save(&app, &config::load_config(&app))
+}
+
+#[tauri::command]
+fn set_replay_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: recording::ReplaySettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetReplaySettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.replay = settings)?;
+    register_shortcuts(&app, &config::load_config(&app));
+    Ok(())
+}
+
+#[tauri::command]
+fn start_icecast(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let settings = config::load_config(&app).icecast;
+    state.tx.send(audio::AudioCommand::StartIcecast(settings)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn stop_icecast(state: State<'_, AppState>) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::StopIcecast).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_icecast_settings(app: tauri::AppHandle, settings: streaming::IcecastSettings) -> Result<(), String> {
+    config::update_config(&app, |c| c.icecast = settings)
+}
+
+#[tauri::command]
+fn set_http_stream_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: streaming::HttpStreamSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetHttpStreamSettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.http_stream = settings)
+}
+
+#[tauri::command]
+fn set_hls_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: hls::HlsSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetHlsSettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.hls = settings)
+}
+
+#[tauri::command]
+fn set_webrtc_settings(app: tauri::AppHandle, state: State<'_, AppState>, settings: webrtc_out::WebRtcSettings) -> Result<(), String> {
+    state.tx.send(audio::AudioCommand::SetWebRtcSettings(settings.clone())).map_err(|e| e.to_string())?;
+    config::update_config(&app, |c| c.webrtc = settings)
+}
+
+#[tauri::command]
+fn set_api_settings(app: tauri::AppHandle, service: State<'_, api::ApiService>, settings: api::ApiSettings) -> Result<(), String> {
+    service.apply(&app, &settings)?;
+    config::update_config(&app, |c| c.api = settings)
+}
+
+#[tauri::command]
+fn set_mqtt_settings(app: tauri::AppHandle, service: State<'_, mqtt::MqttService>, settings: mqtt::MqttSettings) -> Result<(), String> {
+    service.apply(&app, &settings)?;
+    config::update_config(&app, |c| c.mqtt = settings)
+}
+
+#[tauri::command]
+fn set_osc_settings(app: tauri::AppHandle, service: State<'_, osc::OscService>, settings: osc::OscSettings) -> Result<(), String> {
+    service.apply(&app, &settings)?;
+    config::update_config(&app, |c| c.osc = settings)
+}
+
+#[tauri::command]
+fn set_metrics_settings(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    service: State<'_, metrics::MetricsService>,
+    settings: metrics::MetricsSettings,
+) -> Result<(), String> {
+    service.apply(&state.tx, &settings)?;
+    config::update_config(&app, |c| c.metrics = settings)
+}
+
+/// Saves the script settings and reloads the scripts.
+#[tauri::command]
+fn set_script_settings(
+    app: tauri::AppHandle,
+    service: State<'_, scripting::ScriptService>,
+    settings: scripting::ScriptSettings,
+) -> Result<(), String> {
+    service.apply(&app, &settings)?;
+    config::update_config(&app, |c| c.scripting = settings)
+}
+
+/// MIDI input ports that can be listened to.
+#[tauri::command]
+fn get_midi_inputs() -> Result<Vec<String>, String> {
+    midi::input_ports()
+}
+
+#[tauri::command]
+fn set_midi_settings(app: tauri::AppHandle, service: State<'_, midi::MidiService>, settings: midi::MidiSettings) -> Result<(), String> {
+    service.apply(&app, &settings)?;
+    config::update_config(&app, |c| c.midi = settings)
+}
+
+/// Binds the next MIDI controller that moves to `target`; the new mapping is
+/// saved and reported with a "midi-learned" event.
+#[tauri::command]
+fn start_midi_learn(service: State<'_, midi::MidiService>, target: midi::MidiTarget) -> Result<(), String> {
+    service.learn(target)
+}
+
+#[tauri::command]
+fn cancel_midi_learn(service: State<'_, midi::MidiService>) -> Result<(), String> {
+    service.cancel_learn()
+}
+
+#[tauri::command]
+fn set_webhooks(app: tauri::AppHandle, service: State<'_, webhooks::WebhookService>, hooks: Vec<webhooks::Webhook>) -> Result<(), String> {
+    service.set(hooks.clone());
+    config::update_config(&app, |c| c.webhooks = hooks)
+}
+
+fn request_replay_save(app: &tauri::AppHandle, config: &AppConfig) -> Result<(), String> {
+    let directory = recording_directory(app, config)?;
+    app.state::<AppState>().tx.send(audio::AudioCommand::SaveReplay(directory)).map_err(|e| e.to_string())
+}
+
+/// Replaces the hotkey bindings. Every shortcut must parse; the ones already
+/// taken by another app are reported when registering and skipped.
+#[tauri::command]
+fn set_hotkeys(app: tauri::AppHandle, bindings: Vec<hotkeys::HotkeyBinding>) -> Result<(), String> {
+    for binding in &bindings {
+        binding
+            .shortcut
+            .parse::<Shortcut>()
+            .map_err(|e| format!("Invalid shortcut {}: {}", binding.shortcut, e))?;
+    }
+    config::update_config(&app, |c| c.hotkeys = bindings)?;
+    register_shortcuts(&app, &config::load_config(&app));
+    Ok(())
+}
+
+/// Sends the keyboard volume keys to the master volume instead of the default device.
+#[tauri::command]
+fn set_media_keys(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    config::update_config(&app, |c| c.media_keys = enabled)?;
+    register_shortcuts(&app, &config::load_config(&app));
+    Ok(())
+}
+
+/// Registers or removes the app from the OS login items.
+#[tauri::command]
+fn set_autostart(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    apply_autostart(&app, enabled)?;
+    config::update_config(&app, |c| c.autostart = enabled)
+}
+
+/// Starts the app in the tray from the next launch on.
+#[tauri::command]
+fn set_start_minimized(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    config::update_config(&app, |c| c.start_minimized = enabled)
+}
+
+/// Restores the mix and starts capturing at the next launch.
+#[tauri::command]
+fn set_auto_start_capture(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    config::update_config(&app, |c| c.auto_start_capture = enabled)
+}
+
+/// Mutes or stops the mix while the session is locked.
+#[tauri::command]
+fn set_lock_action(app: tauri::AppHandle, lock: State<'_, session::SessionLock>, action: session::LockAction) -> Result<(), String> {
+    lock.set(action);
+    config::update_config(&app, |c| c.lock_action = action)
+}
+
+/// Saves output, fader and mute changes from any control without waiting
+/// for the window to do it.
+#[tauri::command]
+fn set_auto_save(app: tauri::AppHandle, auto_save: State<'_, autosave::AutoSave>, enabled: bool) -> Result<(), String> {
+    auto_save.set_enabled(enabled);
+    config::update_config(&app, |c| c.auto_save = enabled)
+}
+
+/// Replaces the quiet-hours windows; changes take effect within a few seconds.
+#[tauri::command]
+fn set_quiet_hours(app: tauri::AppHandle, quiet: State<'_, quiet_hours::QuietHours>, windows: Vec<quiet_hours::QuietWindow>) -> Result<(), String> {
+    quiet.set(windows.clone());
+    config::update_config(&app, |c| c.quiet_hours = windows)
+}
+
+#[tauri::command]
+fn set_notifications(app: tauri::AppHandle, notifier: State<'_, notify::Notifier>, enabled: bool) -> Result<(), String> {
+    notifier.set_enabled(enabled);
+    config::update_config(&app, |c| c.notifications = enabled)
+}
+
+/// Shows or hides the always-on-top mini mixer; returns whether it is visible.
+/// Async because creating a window from a sync command deadlocks on Windows.
+#[tauri::command]
+async fn toggle_mini_window(app: tauri::AppHandle) -> Result<bool, String> {
+    window_state::toggle_mini(&app)
+}
+
+fn apply_autostart(app: &tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let launcher = app.autolaunch();
+    let result = if enabled { launcher.enable() } else { launcher.disable() };
+    result.map_err(|e| e.to_string())
+}
+
+/// Hotkey bindings in effect: the configured ones plus the media keys, if enabled.
+fn active_hotkeys(config: &AppConfig) -> Vec<hotkeys::HotkeyBinding> {
+    let mut bindings = config.hotkeys.clone();
+    if config.media_keys {
+        bindings.extend(hotkeys::media_key_bindings());
+    }
+    bindings
+}
+
+/// Registers the configured global shortcuts, replacing any previous ones.
+fn register_shortcuts(app: &tauri::AppHandle, config: &AppConfig) {
+    let shortcuts = app.global_shortcut();
+    let _ = shortcuts.unregister_all();
+    let hotkeys = active_hotkeys(config);
+    let bound = config.replay.shortcut.iter().chain(hotkeys.iter().map(|h| &h.shortcut));
+    for shortcut in bound {
+        if let Err(e) = shortcuts.register(shortcut.as_str()) {
+            eprintln!("Failed to register shortcut {}: {}", shortcut, e);
+        }
+    }
+}
+
+fn handle_shortcut(app: &tauri::AppHandle, shortcut: &Shortcut) {
+    let config = config::load_config(app);
+    let is_replay = config
+        .replay
+        .shortcut
+        .as_deref()
+        .and_then(|s| s.parse::<Shortcut>().ok())
+        .is_some_and(|s| &s == shortcut);
+    if is_replay {
+        if let Err(e) = request_replay_save(app, &config) {
+            eprintln!("Failed to save replay: {}", e);
+        }
+    }
+    let actions = active_hotkeys(&config)
+        .into_iter()
+        .filter(|h| h.shortcut.parse::<Shortcut>().is_ok_and(|s| &s == shortcut));
+    for hotkey in actions {
+        let (command, args) = hotkey.action.command();
+        if let Err(e) = api::execute(app, command, &args) {
+            eprintln!("Hotkey {} failed: {}", hotkey.shortcut, e);
+        }
+    }
+}
+
+/// Configured recording folder, or "Audio Merge" in the user's audio folder.
+/// In portable mode the default is "Recordings" next to the executable, and
+/// relative folders are taken from there.
+fn recording_directory(app: &tauri::AppHandle, config: &AppConfig) -> Result<std::path::PathBuf, String> {
+    match (&config.recording.directory, config::portable_dir()) {
+        (Some(dir), Some(portable)) if dir.is_relative() => Ok(portable.join(dir)),
+        (Some(dir), _) => Ok(dir.clone()),
+        (None, Some(portable)) => Ok(portable.join("Recordings")),
+        (None, None) => app.path().audio_dir().map(|d| d.join("Audio Merge")).map_err(|e| e.to_string()),
+    }
+}
+
+/// Pushes the persisted engine-wide settings to the audio thread at startup.
+fn restore_engine_settings(tx: &Sender<audio::AudioCommand>, config: &AppConfig) {
+    let _ = tx.send(audio::AudioCommand::SetCaptureSource(config.capture_source.clone()));
+    let _ = tx.send(audio::AudioCommand::SetCaptureSampleRate(config.capture_sample_rate));
+    let _ = tx.send(audio::AudioCommand::SetCaptureExclusions(config.capture_exclusions.clone()));
+    let _ = tx.send(audio::AudioCommand::SetCaptureFades(config.capture_fade_in_ms, config.capture_fade_out_ms));
+    let _ = tx.send(audio::AudioCommand::SetCrossfadeDuration(config.crossfade_ms));
+    let _ = tx.send(audio::AudioCommand::SetBitPerfect(config.bit_perfect));
+    let _ = tx.send(audio::AudioCommand::SetNoiseGate(config.noise_gate));
+    let _ = tx.send(audio::AudioCommand::SetDucking(config.ducking));
+    let _ = tx.send(audio::AudioCommand::SetVolumeTaper(config.volume_taper));
+    let _ = tx.send(audio::AudioCommand::SetMasterVolume(config.master_volume));
+    let _ = tx.send(audio::AudioCommand::SetMasterMute(config.master_muted));
+    let _ = tx.send(audio::AudioCommand::SetLinkGroups(config.link_groups.clone()));
+    let _ = tx.send(audio::AudioCommand::SetMicVolume(config.mic_volume));
+    let _ = tx.send(audio::AudioCommand::SetMicMute(config.mic_muted));
+    let _ = tx.send(audio::AudioCommand::SetMicNoiseSuppression(config.mic_noise_suppression));
+    let _ = tx.send(audio::AudioCommand::SetEchoCancellation(config.mic_echo_cancellation));
+    let _ = tx.send(audio::AudioCommand::SetRoutes(config.routes.clone()));
+    let _ = tx.send(audio::AudioCommand::SetRecordingSettings(config.recording.clone()));
+    let _ = tx.send(audio::AudioCommand::SetReplaySettings(config.replay.clone()));
+    let _ = tx.send(audio::AudioCommand::SetHttpStreamSettings(config.http_stream.clone()));
+    let _ = tx.send(audio::AudioCommand::SetHlsSettings(config.hls.clone()));
+    let _ = tx.send(audio::AudioCommand::SetWebRtcSettings(config.webrtc.clone()));
+    let _ = tx.send(audio::AudioCommand::SetVbanReceiver(config.vban_receiver.clone()));
+    let _ = tx.send(audio::AudioCommand::SetRtpSettings(config.rtp.clone()));
+    let _ = tx.send(audio::AudioCommand::SetSyncServer(config.sync_server.clone()));
+    let _ = tx.send(audio::AudioCommand::SetSyncClient(config.sync_client.clone()));
+    if let Some(mic) = &config.mic_device {
+        let _ = tx.send(audio::AudioCommand::StartMic(mic.clone()));
+    }
+}
+
+/// Replays the saved settings and outputs into an audio engine that
+/// restarted after a panic, capturing again if it was.
+fn replay_engine_state(app: &tauri::AppHandle, was_capturing: bool) {
+    let config = config::load_config(app);
+    restore_engine_settings(&app.state::<AppState>().tx, &config);
+    restore_mix(app, &config, was_capturing);
+}
+
+/// Starts or reconfigures the shortcuts, hooks and control servers
+/// from `config`.
+fn apply_services(app: &tauri::AppHandle, config: &AppConfig) {
+    register_shortcuts(app, config);
+    app.state::<webhooks::WebhookService>().set(config.webhooks.clone());
+    if let Err(e) = app.state::<api::ApiService>().apply(app, &config.api) {
+        eprintln!("Failed to start the control API: {}", e);
+    }
+    if let Err(e) = app.state::<mqtt::MqttService>().apply(app, &config.mqtt) {
+        eprintln!("Failed to start the MQTT bridge: {}", e);
+    }
+    if let Err(e) = app.state::<osc::OscService>().apply(app, &config.osc) {
+        eprintln!("Failed to start the OSC server: {}", e);
+    }
+    if let Err(e) = app.state::<midi::MidiService>().apply(app, &config.midi) {
+        eprintln!("Failed to open MIDI input: {}", e);
+    }
+    if let Err(e) = app.state::<metrics::MetricsService>().apply(&app.state::<AppState>().tx, &config.metrics) {
+        eprintln!("Failed to start the metrics endpoint: {}", e);
+    }
+    if let Err(e) = app.state::<scripting::ScriptService>().apply(app, &config.scripting) {
+        eprintln!("Failed to start scripts: {}", e);
+    }
+}
+
+/// Rebuilds the saved mix, without waiting for the window, and starts
+/// capturing if `capture`.
+fn restore_mix(app: &tauri::AppHandle, config: &AppConfig, capture: bool) {
+    let _ = set_input_volume(app.state(), config.input_volume);
+    let _ = set_input_mute(app.state(), config.input_muted);
+    let state = app.state::<AppState>();
+    for out in config.outputs.iter().filter(|o| !state.devices.is_hidden(&o.name)) {
+        let restored = add_device_to_mix(app.clone(), app.state(), out.name.clone())
+            .map_err(|e| e.to_string())
+            .and_then(|_| set_device_volume(app.state(), out.name.clone(), out.volume))
+            .and_then(|_| set_device_mute(app.state(), out.name.clone(), out.muted));
+        if let Err(e) = restored {
+            eprintln!("Failed to restore output {}: {}", out.name, e);
+        }
+    }
+    if capture {
+        let _ = start_capture(app.state());
+    }
+}
+
 // Config Commands
 #[tauri::command]
 fn save_app_config(app: tauri::AppHandle, config: AppConfig) -> Result<(), String> {
-    config::save_config(&app, config)
+    config::update_config(&app, |current| current.merge_ui_state(config))
 }
 
 #[tauri::command]
@@ -86,51 +1155,368 @@ fn load_app_config(app: tauri::AppHandle) -> AppConfig {
     config::load_config(&app)
 }
 
+/// The last config file problem, for a window that opened after it was
+/// reported through `config-load-error`.
+#[tauri::command]
+fn get_config_problem() -> Option<config::ConfigProblem> {
+    config::last_problem()
+}
+
+/// Saved copies of the config, newest first.
+#[tauri::command]
+fn list_config_backups(app: tauri::AppHandle) -> Vec<config::ConfigBackup> {
+    config::get_config_path(&app).map(|path| config::list_backups(&path)).unwrap_or_default()
+}
+
+/// Rolls the config back to backup `index` (0 is the newest); it is applied
+/// within a few seconds, like an edit of the file.
+#[tauri::command]
+fn restore_config_backup(app: tauri::AppHandle, index: usize) -> Result<(), String> {
+    let restored = config::restore_backup(&app, index)?;
+    println!("Restored config from {}", restored.display());
+    Ok(())
+}
+
+/// Writes the whole configuration to `path`, to carry it to another machine.
+#[tauri::command]
+fn export_config(app: tauri::AppHandle, path: std::path::PathBuf) -> Result<(), String> {
+    config::export_to(&path, &config::load_config(&app))
+}
+
+/// Replaces the configuration with a file written by `export_config`, after
+/// validating it, and applies it right away. Window placement stays local.
+#[tauri::command]
+fn import_config(app: tauri::AppHandle, state: State<'_, AppState>, path: std::path::PathBuf) -> Result<(), String> {
+    let mut imported = config::import_from(&path)?;
+    let mut previous = None;
+    config::update_config(&app, |current| {
+        imported.window = current.window;
+        imported.window_hidden = current.window_hidden;
+        previous = Some((
+            current.audio_host.take(),
+            std::mem::take(&mut current.scheduled_recordings),
+            current.autostart,
+        ));
+        *current = imported.clone();
+    })?;
+    let (audio_host, scheduled_recordings, autostart) = previous.unwrap_or_default();
+    println!("Imported settings from {}", path.display());
+
+    host::set_jack_auto_connect(imported.jack_auto_connect);
+    if imported.audio_host != audio_host {
+        switch_audio_host(&state, imported.audio_host.as_deref())?;
+    }
+    restore_engine_settings(&state.tx, &imported);
+    app.state::<session::SessionLock>().set(imported.lock_action);
+    app.state::<notify::Notifier>().set_enabled(imported.notifications);
+    app.state::<autosave::AutoSave>().set_enabled(imported.auto_save);
+    state.devices.apply(&imported);
+    app.state::<quiet_hours::QuietHours>().set(imported.quiet_hours.clone());
+    // Reloading restarts schedules already under way, so only when they differ
+    if imported.scheduled_recordings != scheduled_recordings {
+        app.state::<Scheduler>().load(imported.scheduled_recordings.clone());
+    }
+    apply_services(&app, &imported);
+    if imported.autostart != autostart {
+        apply_autostart(&app, imported.autostart)?;
+    }
+    // The mix goes last; it emits `profile-applied`, which reloads the window
+    let mix = config::Profile::from_config(imported.active_profile.as_deref().unwrap_or_default(), &imported);
+    switch_to_profile(&app, &state, &mix)
+}
+
+#[tauri::command]
+fn list_profiles(app: tauri::AppHandle) -> Vec<String> {
+    config::load_config(&app).profiles.into_iter().map(|p| p.name).collect()
+}
+
+/// Saves the current mix under `name`, replacing a profile of that name.
+#[tauri::command]
+fn save_profile(app: tauri::AppHandle, state: State<'_, AppState>, name: String) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Profile name is empty".to_string());
+    }
+    // Faders are saved by the frontend with a delay, so take them from the engine
+    let live = get_audio_state(state)?;
+    config::update_config(&app, |c| {
+        c.merge_engine_state(&live);
+        c.outputs.retain(|o| live.outputs.contains(&o.name));
+        c.master_volume = live.master_volume;
+        c.master_muted = live.master_muted;
+        let profile = config::Profile::from_config(&name, c);
+        match c.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => c.profiles.push(profile),
+        }
+        c.active_profile = Some(name.clone());
+    })?;
+    tray::refresh_menu(&app);
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_profile(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    config::update_config(&app, |c| {
+        c.profiles.retain(|p| p.name != name);
+        if c.active_profile.as_deref() == Some(name.as_str()) {
+            c.active_profile = None;
+        }
+    })?;
+    tray::refresh_menu(&app);
+    Ok(())
+}
+
+/// Switches the mix to a saved profile. Emits `profile-applied` so the window
+/// reloads its config.
+#[tauri::command]
+fn apply_profile(app: tauri::AppHandle, state: State<'_, AppState>, name: String) -> Result<(), String> {
+    let profile = config::load_config(&app).profile(&name).cloned().ok_or_else(|| format!("Unknown profile: {}", name))?;
+    switch_to_profile(&app, &state, &profile)?;
+    println!("Applied profile {}", name);
+    Ok(())
+}
+
+/// Makes `profile` the mix: outputs not in it leave, its outputs join with
+/// their faders, and the input, master and processing settings follow.
+fn switch_to_profile(app: &tauri::AppHandle, state: &AppState, profile: &config::Profile) -> Result<(), String> {
+    // Written first so joining outputs pick up the profile's width and boost
+    config::update_config(app, |c| profile.apply_to(c))?;
+
+    let tx = &state.tx;
+    // The faders are converted through the taper, so it goes first
+    tx.send(audio::AudioCommand::SetVolumeTaper(profile.volume_taper)).map_err(|e| e.to_string())?;
+    let current = get_audio_state(app.state())?;
+    for device in current.outputs.iter().filter(|d| !profile.outputs.iter().any(|o| &o.name == *d)) {
+        tx.send(audio::AudioCommand::RemoveOutput(device.clone())).map_err(|e| e.to_string())?;
+    }
+    for out in profile.outputs.iter().filter(|o| !state.devices.is_hidden(&o.name)) {
+        let restored = add_device_to_mix(app.clone(), app.state(), out.name.clone())
+            .map_err(|e| e.to_string())
+            .and_then(|_| set_device_volume(app.state(), out.name.clone(), out.volume))
+            .and_then(|_| set_device_mute(app.state(), out.name.clone(), out.muted));
+        if let Err(e) = restored {
+            eprintln!("Failed to restore output {}: {}", out.name, e);
+        }
+    }
+    let commands = [
+        audio::AudioCommand::SetInputVolume(profile.input_volume),
+        audio::AudioCommand::SetInputMute(profile.input_muted),
+        audio::AudioCommand::SetMasterVolume(profile.master_volume),
+        audio::AudioCommand::SetMasterMute(profile.master_muted),
+        audio::AudioCommand::SetNoiseGate(profile.noise_gate),
+        audio::AudioCommand::SetDucking(profile.ducking),
+        audio::AudioCommand::SetLinkGroups(profile.link_groups.clone()),
+    ];
+    for command in commands {
+        tx.send(command).map_err(|e| e.to_string())?;
+    }
+    let _ = app.emit("profile-applied", &profile.name);
+    tray::refresh_menu(app);
+    Ok(())
+}
+
+/// Shows and focuses the main window, creating it on first use in headless mode.
+fn show_main_window(app: &tauri::AppHandle) {
+    let window = match app.get_webview_window("main") {
+        Some(window) => window,
+        None => {
+            let Some(config) = app.config().app.windows.first() else { return };
+            match tauri::WebviewWindowBuilder::from_config(app, config).and_then(|builder| builder.build()) {
+                Ok(window) => {
+                    if let Some(geometry) = &config::load_config(app).window {
+                        window_state::restore(app, geometry);
+                    }
+                    window
+                },
+                Err(e) => {
+                    eprintln!("Failed to open the main window: {}", e);
+                    return;
+                },
+            }
+        },
+    };
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+/// Startup actions requested on the command line, by this launch or by a
+/// second one forwarded over IPC.
+fn apply_launch_args(app: &tauri::AppHandle, args: &cli::CliArgs) {
+    if let Some(profile) = &args.profile {
+        if let Err(e) = apply_profile(app.clone(), app.state(), profile.clone()) {
+            eprintln!("Failed to apply profile {}: {}", profile, e);
+        }
+    }
+    if args.start_capture {
+        let _ = app.state::<AppState>().tx.send(audio::AudioCommand::StartLoopback);
+    }
+    for link in &args.links {
+        if let Err(e) = deeplink::open(app, link) {
+            eprintln!("Failed to open {}: {}", link, e);
+        }
+    }
+}
+
+/// Handles a second launch: applies its arguments and brings the window up
+/// unless it asked to stay in the background or only carried deep links.
+fn activate(app: &tauri::AppHandle, raw_args: Vec<String>) {
+    let args = cli::CliArgs::from_args(raw_args);
+    apply_launch_args(app, &args);
+    if !args.headless && !args.minimized && args.links.is_empty() {
+        show_main_window(app);
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let tx = audio::spawn_audio_thread();
+    // Another instance already owns the devices; hand it our arguments
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    if ipc::forward_to_running("activate", serde_json::json!({ "args": raw_args })) {
+        println!("Audio Merge is already running");
+        return;
+    }
+    let args = cli::CliArgs::from_args(raw_args);
+    if let Some(path) = &args.config {
+        config::set_config_path(path.clone());
+    }
+    if let Some(dir) = config::init_portable(args.portable) {
+        println!("Portable mode: settings are kept in {}", dir.display());
+    }
+    // Headless runs the engine and control APIs without creating the window;
+    // the tray can still open it later. Otherwise the window starts hidden and
+    // setup shows it unless the app should start in the tray.
+    let mut context = tauri::generate_context!();
+    if args.headless {
+        for window in &mut context.config_mut().app.windows {
+            window.create = false;
+        }
+    }
+    let (tx, events) = audio::spawn_audio_thread();
     
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(AppState { tx })
-        .setup(|app| {
-            let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>).unwrap();
-            let show_i = MenuItem::with_id(app, "show", "Show", true, None::<&str>).unwrap();
-            let menu = Menu::with_items(app, &[&show_i, &quit_i]).unwrap();
-            
-            let _tray = TrayIconBuilder::new()
-                .menu(&menu)
-                .on_menu_event(|app, event| {
-                    match event.id.as_ref() {
-                        "quit" => app.exit(0),
-                        "show" => {
-                            if let Some(window) = app.get_webview_window("main") {
-                                let _ = window.show();
-                                let _ = window.set_focus();
-                            }
-                        }
-                        _ => {}
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_autostart::init(tauri_plugin_autostart::MacosLauncher::LaunchAgent, None))
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app, shortcut, event| {
+                    if event.state() == ShortcutState::Pressed {
+                        handle_shortcut(app, shortcut);
                     }
                 })
-                .on_tray_icon_event(|tray, event| {
-                     if let TrayIconEvent::Click {
-                        button: MouseButton::Left,
-                        ..
-                    } = event
-                    {
-                        let app = tray.app_handle();
-                        if let Some(window) = app.get_webview_window("main") {
-                            let _ = window.show();
-                            let _ = window.set_focus();
-                        }
+                .build(),
+        )
+        .manage(AppState { tx, devices: devices::DevicePrefs::default() })
+        .manage(Scheduler::default())
+        .manage(api::ApiService::default())
+        .manage(mqtt::MqttService::default())
+        .manage(osc::OscService::default())
+        .manage(midi::MidiService::default())
+        .manage(webhooks::WebhookService::default())
+        .manage(metrics::MetricsService::default())
+        .manage(scripting::ScriptService::default())
+        .manage(session::SessionLock::default())
+        .manage(quiet_hours::QuietHours::default())
+        .manage(autosave::AutoSave::default())
+        .manage(tray::TrayState::default())
+        .manage(notify::Notifier::default())
+        .manage(window_state::WindowTracker::default())
+        .setup(move |app| {
+            let handle = app.handle().clone();
+            inserts::set_main_thread(move |task| {
+                if let Err(e) = handle.run_on_main_thread(task) {
+                    eprintln!("Could not run plugin task on the main thread: {}", e);
+                }
+            });
+            let config = config::load_config(app.handle());
+            host::set_jack_auto_connect(config.jack_auto_connect);
+            if let Err(e) = host::select(config.audio_host.as_deref()) {
+                eprintln!("{}", e);
+            }
+            restore_engine_settings(&app.state::<AppState>().tx, &config);
+            power::spawn_resume_watcher(app.state::<AppState>().tx.clone());
+            app.state::<session::SessionLock>().set(config.lock_action);
+            app.state::<notify::Notifier>().set_enabled(config.notifications);
+            app.state::<autosave::AutoSave>().set_enabled(config.auto_save);
+            app.state::<AppState>().devices.apply(&config);
+            autosave::spawn(app.handle().clone());
+            session::spawn_lock_watcher(app.handle().clone());
+            if config.auto_start_capture {
+                restore_mix(app.handle(), &config, true);
+            }
+            apply_launch_args(app.handle(), &args);
+            app.state::<quiet_hours::QuietHours>().set(config.quiet_hours.clone());
+            quiet_hours::spawn_watcher(app.handle().clone());
+            if let Some(geometry) = &config.window {
+                window_state::restore(app.handle(), geometry);
+            }
+            if !args.headless && !args.minimized && !config.start_minimized && !config.window_hidden {
+                show_main_window(app.handle());
+            }
+            // Re-register so the login item follows the app if it was moved
+            if config.autostart {
+                if let Err(e) = apply_autostart(app.handle(), true) {
+                    eprintln!("Failed to register launch at login: {}", e);
+                }
+            }
+            // macOS delivers links as events; elsewhere they arrive as arguments
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                eprintln!("Failed to register {} links: {}", deeplink::SCHEME, e);
+            }
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    if let Err(e) = deeplink::open(&handle, url.as_str()) {
+                        eprintln!("Failed to open {}: {}", url, e);
                     }
-                })
-                .icon(app.default_window_icon().unwrap().clone())
-                .build(app)?;
+                }
+            });
+            app.state::<Scheduler>().load(config.scheduled_recordings.clone());
+            spawn_scheduler(app.handle().clone());
+            let handle = app.handle().clone();
+            match ipc::IpcServer::start(move |message| api::handle_command_message(&handle, message)) {
+                Ok(server) => {
+                    app.manage(server);
+                },
+                Err(e) => eprintln!("Local control unavailable: {}", e),
+            }
+            apply_services(app.handle(), &config);
+            config_watch::spawn_watcher(app.handle().clone());
+            let handle = app.handle().clone();
+            let discovered = discovery::start(move |devices| {
+                handle.state::<api::ApiService>().broadcast("network-devices-changed", &devices);
+                let _ = handle.emit("network-devices-changed", devices);
+            });
+            if let Err(e) = discovered {
+                eprintln!("Network device discovery unavailable: {}", e);
+            }
+
+            // Forward audio thread notifications to the frontend
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                while let Ok(event) = events.recv() {
+                    if let audio::AudioEvent::EnginePanicked { restarted: true, was_capturing, .. } = &event {
+                        replay_engine_state(&handle, *was_capturing);
+                    }
+                    handle.state::<api::ApiService>().broadcast("audio-event", &event);
+                    handle.state::<webhooks::WebhookService>().fire(&event);
+                    handle.state::<scripting::ScriptService>().notify(&event);
+                    tray::on_event(&handle, &event);
+                    handle.state::<notify::Notifier>().on_event(&handle, &event);
+                    handle.state::<autosave::AutoSave>().on_event(&event);
+                    let _ = handle.emit("audio-event", event);
+                }
+            });
+
+            tray::build(app.handle(), config.master_muted)?;
 
             Ok(())
         })
         .on_window_event(|window, event| {
+            window_state::on_window_event(window, event);
             if let WindowEvent::CloseRequested { api, .. } = event {
                 window.hide().unwrap();
                 api.prevent_close();
@@ -138,19 +1524,128 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             get_audio_devices,
+            get_input_devices,
+            get_monitor_sources,
+            get_audio_hosts,
+            set_audio_host,
+            set_jack_auto_connect,
+            set_device_alias,
+            get_hidden_devices,
+            set_device_hidden,
+            set_device_favorite,
+            get_network_devices,
             get_default_audio_device,
+            get_audio_state,
             start_audio,
             add_device_to_mix,
             set_device_volume,
+            set_device_volume_db,
             remove_device_from_mix,
+            swap_device_in_mix,
+            set_crossfade_duration,
+            set_bit_perfect,
             set_device_mute,
+            set_device_width,
+            set_device_boost,
+            set_device_soft_clip,
+            set_device_buffer,
+            set_device_exclusive,
+            set_device_dither,
+            scan_plugins,
+            add_output_insert,
+            remove_output_insert,
+            set_insert_param,
+            set_insert_bypass,
+            get_insert_params,
+            set_device_solo,
+            start_mic,
+            stop_mic,
+            set_mic_volume,
+            set_mic_mute,
+            set_mic_noise_suppression,
+            set_mic_echo_cancellation,
+            set_route,
+            get_routes,
+            get_link_groups,
+            set_link_group,
+            remove_link_group,
             set_input_volume,
+            set_input_volume_db,
+            set_volume_taper,
+            set_noise_gate,
+            set_ducking,
+            set_master_volume,
+            set_master_mute,
             set_input_mute,
             start_capture,
             stop_capture,
+            set_capture_source,
+            set_capture_sample_rate,
+            get_capture_sample_rates,
+            get_capture_applications,
+            get_feedback_devices,
+            set_capture_exclusions,
+            set_capture_fades,
+            start_recording,
+            stop_recording,
+            pause_recording,
+            resume_recording,
+            set_recording_settings,
+            get_scheduled_recordings,
+            add_scheduled_recording,
+            remove_scheduled_recording,
+            save_replay,
+            set_replay_settings,
+            set_hotkeys,
+            set_media_keys,
+            set_autostart,
+            set_start_minimized,
+            set_auto_start_capture,
+            set_lock_action,
+            set_quiet_hours,
+            set_auto_save,
+            set_notifications,
+            toggle_mini_window,
+            set_webhooks,
+            start_icecast,
+            stop_icecast,
+            set_icecast_settings,
+            set_http_stream_settings,
+            set_hls_settings,
+            set_webrtc_settings,
+            add_vban_output,
+            set_vban_receiver,
+            set_rtp_settings,
+            set_sync_server,
+            set_sync_client,
+            set_api_settings,
+            set_mqtt_settings,
+            set_osc_settings,
+            set_metrics_settings,
+            set_script_settings,
+            get_midi_inputs,
+            set_midi_settings,
+            start_midi_learn,
+            cancel_midi_learn,
             save_app_config,
-            load_app_config
+            load_app_config,
+            get_config_problem,
+            list_config_backups,
+            restore_config_backup,
+            export_config,
+            import_config,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            apply_profile
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(context)
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                if let Some(window) = app.get_webview_window(window_state::MAIN_WINDOW) {
+                    window_state::save(app, !window.is_visible().unwrap_or(false));
+                }
+            }
+        });
 }
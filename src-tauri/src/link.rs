@@ -0,0 +1,455 @@
+// Audio link between two instances of the app, so one PC's mix can feed
+// another's. The receiving side runs the "network" capture source: it listens
+// on one port for a TCP handshake and for UDP audio, and announces itself over
+// mDNS. The sending side is an output named `audiomerge://Name` (a discovered
+// receiver) or `audiomerge://host[:port]`. The handshake tells the sender the
+// receiver's format and a session token; audio then goes out as 16-bit PCM,
+// already converted, for as long as the TCP connection stays open.
+
+use crate::discovery::{self, Advertisement, NetworkDeviceKind};
+use crate::dsp::LinearResampler;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const URL_PREFIX: &str = "audiomerge://";
+
+pub const DEFAULT_PORT: u16 = 6990;
+
+const PROTOCOL: &str = "audio_merge";
+const VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"AMLK";
+const HEADER_SIZE: usize = 16;
+
+/// 5 ms at 48 kHz; keeps stereo packets well under the MTU.
+const FRAMES_PER_PACKET: usize = 240;
+const MAX_PACKET_SIZE: usize = 1500;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+const POLL_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// First line a sender writes after connecting.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Hello {
+    protocol: String,
+    version: u32,
+    /// Shown in the receiver's log.
+    name: String,
+}
+
+/// Receiver's answer to `Hello`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Reply {
+    accepted: bool,
+    #[serde(default)]
+    reason: String,
+    #[serde(default)]
+    sample_rate: u32,
+    #[serde(default)]
+    channels: usize,
+    /// Stamped on every audio packet of the session.
+    #[serde(default)]
+    token: u32,
+}
+
+pub fn url(name: &str) -> String {
+    format!("{}{}", URL_PREFIX, name)
+}
+
+pub fn is_link_url(name: &str) -> bool {
+    name.starts_with(URL_PREFIX)
+}
+
+/// Resolves `audiomerge://Name` to a discovered receiver, or
+/// `audiomerge://host[:port]` to an address.
+pub fn resolve_url(name: &str) -> Result<SocketAddr, String> {
+    let target = name
+        .strip_prefix(URL_PREFIX)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| format!("Invalid link output: {}", name))?;
+    if let Some(device) = discovery::find(NetworkDeviceKind::AudioMerge, target) {
+        return Ok(SocketAddr::new(device.address, device.port));
+    }
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| format!("Invalid port in {}", name))?),
+        None => (target, DEFAULT_PORT),
+    };
+    (host, port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", host))
+}
+
+fn write_header(packet: &mut Vec<u8>, token: u32, sequence: u32, frames: usize, channels: usize) {
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&token.to_le_bytes());
+    packet.extend_from_slice(&sequence.to_le_bytes());
+    packet.extend_from_slice(&(frames as u16).to_le_bytes());
+    packet.push(channels as u8);
+    packet.push(0);
+}
+
+/// Audio carried by one packet.
+#[derive(Debug, PartialEq)]
+struct AudioPacket {
+    token: u32,
+    sequence: u32,
+    channels: usize,
+    samples: Vec<f32>,
+}
+
+fn parse_packet(packet: &[u8]) -> Option<AudioPacket> {
+    if packet.len() < HEADER_SIZE || &packet[..4] != MAGIC {
+        return None;
+    }
+    let token = u32::from_le_bytes(packet[4..8].try_into().ok()?);
+    let sequence = u32::from_le_bytes(packet[8..12].try_into().ok()?);
+    let frames = u16::from_le_bytes([packet[12], packet[13]]) as usize;
+    let channels = packet[14] as usize;
+    let data = packet.get(HEADER_SIZE..HEADER_SIZE + frames * channels * 2)?;
+    let samples = data
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+        .collect();
+    Some(AudioPacket { token, sequence, channels, samples })
+}
+
+fn new_token() -> u32 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_nanos() as u32) ^ std::process::id().rotate_left(16)
+}
+
+fn read_line(stream: &TcpStream) -> Result<String, String> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(|e| e.to_string())?;
+    if line.is_empty() {
+        return Err("Connection closed during handshake".to_string());
+    }
+    Ok(line)
+}
+
+fn write_line<T: Serialize>(mut stream: &TcpStream, message: &T) -> Result<(), String> {
+    let mut line = serde_json::to_vec(message).map_err(|e| e.to_string())?;
+    line.push(b'\n');
+    stream.write_all(&line).map_err(|e| e.to_string())
+}
+
+/// The sender currently allowed to stream.
+#[derive(Clone, Copy)]
+struct Session {
+    token: u32,
+    peer: IpAddr,
+}
+
+/// Receives another instance's mix and hands it to the capture callback.
+pub struct LinkReceiver {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    _advertisement: Option<Advertisement>,
+}
+
+impl LinkReceiver {
+    /// `on_audio` gets interleaved frames at `channels` and `sample_rate`,
+    /// which are announced to senders in the handshake.
+    pub fn start<F>(port: u16, channels: usize, sample_rate: u32, mut on_audio: F) -> Result<Self, String>
+    where
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| format!("Port {}: {}", port, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|e| format!("Port {}: {}", port, e))?;
+        socket.set_read_timeout(Some(POLL_TIMEOUT)).map_err(|e| e.to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let session: Arc<Mutex<Option<Session>>> = Arc::new(Mutex::new(None));
+
+        let control = {
+            let stop = stop.clone();
+            let session = session.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, addr)) => {
+                            let stop = stop.clone();
+                            let session = session.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = serve_sender(stream, addr, channels, sample_rate, &session, &stop) {
+                                    println!("Link sender {} left: {}", addr, e);
+                                }
+                            });
+                        },
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(POLL_TIMEOUT),
+                        Err(e) => eprintln!("Link accept error: {}", e),
+                    }
+                }
+            })
+        };
+
+        let audio = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; MAX_PACKET_SIZE];
+                // (token, sequence) of the last packet played
+                let mut last: Option<(u32, u32)> = None;
+                while !stop.load(Ordering::Relaxed) {
+                    let (len, from) = match socket.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    };
+                    let current = if let Ok(s) = session.lock() { *s } else { None };
+                    let Some(current) = current else {
+                        continue;
+                    };
+                    let packet = match parse_packet(&buf[..len]) {
+                        Some(p) if p.token == current.token && from.ip() == current.peer && p.channels == channels => p,
+                        _ => continue,
+                    };
+                    // Drop late packets; playing them out of order is worse than a gap
+                    let late = last.is_some_and(|(token, sequence)| {
+                        token == packet.token && (packet.sequence.wrapping_sub(sequence) as i32) <= 0
+                    });
+                    if late {
+                        continue;
+                    }
+                    last = Some((packet.token, packet.sequence));
+                    on_audio(&packet.samples);
+                }
+            })
+        };
+
+        let properties = HashMap::from([
+            ("sr".to_string(), sample_rate.to_string()),
+            ("ch".to_string(), channels.to_string()),
+        ]);
+        let advertisement = match discovery::advertise(discovery::AUDIO_MERGE_SERVICE, port, properties) {
+            Ok(advertisement) => Some(advertisement),
+            Err(e) => {
+                eprintln!("Could not announce the link receiver: {}", e);
+                None
+            }
+        };
+        println!("Receiving links on port {} ({} Hz, {} ch)", port, sample_rate, channels);
+
+        Ok(Self { stop, threads: vec![control, audio], _advertisement: advertisement })
+    }
+}
+
+impl Drop for LinkReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs the handshake, then holds the session until the sender disconnects.
+fn serve_sender(
+    stream: TcpStream,
+    addr: SocketAddr,
+    channels: usize,
+    sample_rate: u32,
+    session: &Mutex<Option<Session>>,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+    let hello: Hello = serde_json::from_str(&read_line(&stream)?).map_err(|e| format!("Bad hello: {}", e))?;
+
+    let refusal = if hello.protocol != PROTOCOL || hello.version != VERSION {
+        Some(format!("unsupported protocol {} v{}", hello.protocol, hello.version))
+    } else if session.lock().map(|s| s.is_some()).unwrap_or(true) {
+        Some("another sender is connected".to_string())
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        write_line(&stream, &Reply { accepted: false, reason: reason.clone(), sample_rate: 0, channels: 0, token: 0 })?;
+        return Err(reason);
+    }
+
+    let token = new_token();
+    if let Ok(mut s) = session.lock() {
+        *s = Some(Session { token, peer: addr.ip() });
+    }
+    println!("Link from '{}' at {}", hello.name, addr);
+    let result = write_line(&stream, &Reply { accepted: true, reason: String::new(), sample_rate, channels, token })
+        .and_then(|_| hold_connection(stream, stop));
+
+    if let Ok(mut s) = session.lock() {
+        *s = None;
+    }
+    result
+}
+
+/// Blocks until the peer closes the connection or `stop` is set.
+fn hold_connection(mut stream: TcpStream, stop: &AtomicBool) -> Result<(), String> {
+    stream.set_read_timeout(Some(POLL_TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut buf = [0u8; 64];
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err("disconnected".to_string()),
+            Ok(_) => {},
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {},
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Connects to a receiver and returns the control stream with its reply.
+fn handshake(addr: SocketAddr) -> Result<(TcpStream, Reply), String> {
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| format!("{}: {}", addr, e))?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+    let name = std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "audio_merge".to_string());
+    write_line(&stream, &Hello { protocol: PROTOCOL.to_string(), version: VERSION, name })?;
+    let reply: Reply = serde_json::from_str(&read_line(&stream)?).map_err(|e| format!("Bad reply: {}", e))?;
+    if !reply.accepted {
+        return Err(format!("Receiver refused the link: {}", reply.reason));
+    }
+    if reply.channels == 0 || reply.sample_rate == 0 {
+        return Err("Receiver sent an invalid format".to_string());
+    }
+    stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+    Ok((stream, reply))
+}
+
+/// Whether the receiver has closed the control connection.
+fn connection_closed(mut stream: &TcpStream) -> bool {
+    let mut buf = [0u8; 64];
+    match stream.read(&mut buf) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => e.kind() != std::io::ErrorKind::WouldBlock,
+    }
+}
+
+/// Sends whatever `render` produces to a link receiver, in real time.
+pub struct LinkSender {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LinkSender {
+    /// `render` fills interleaved frames in the capture layout, just like an
+    /// output device callback; they are converted to the receiver's format
+    /// here. Fails if the first handshake is refused; after that the sender
+    /// reconnects on its own.
+    pub fn start<F>(addr: SocketAddr, channels: usize, sample_rate: u32, mut render: F) -> Result<Self, String>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        let mut connection = Some(handshake(addr)?);
+        let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).map_err(|e| e.to_string())?;
+        socket.connect(addr).map_err(|e| e.to_string())?;
+        println!("Linked to {}", addr);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut sequence: u32 = 0;
+            while !stop_flag.load(Ordering::Relaxed) {
+                let (control, reply) = match connection.take() {
+                    Some(c) => c,
+                    None => match handshake(addr) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            eprintln!("Link to {} failed: {}", addr, e);
+                            thread::sleep(RECONNECT_DELAY);
+                            continue;
+                        }
+                    },
+                };
+
+                let out_channels = reply.channels;
+                let packet_duration = Duration::from_secs_f64(FRAMES_PER_PACKET as f64 / reply.sample_rate as f64);
+                let mut resampler = LinearResampler::new(channels, sample_rate, reply.sample_rate);
+                let mut rendered = vec![0.0f32; FRAMES_PER_PACKET * channels];
+                let mut packet = Vec::with_capacity(HEADER_SIZE + FRAMES_PER_PACKET * out_channels * 2);
+                let mut next = Instant::now();
+
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if next > now {
+                        thread::sleep(next - now);
+                    }
+                    next += packet_duration;
+
+                    resampler.process(&mut rendered, &mut render);
+                    packet.clear();
+                    write_header(&mut packet, reply.token, sequence, FRAMES_PER_PACKET, out_channels);
+                    for frame in rendered.chunks_exact(channels) {
+                        for c in 0..out_channels {
+                            // Mono spreads across the receiver's channels; extras are dropped
+                            let sample = frame.get(c).or(frame.last()).copied().unwrap_or(0.0);
+                            packet.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+                        }
+                    }
+                    let _ = socket.send(&packet);
+                    sequence = sequence.wrapping_add(1);
+
+                    if sequence % 50 == 0 && connection_closed(&control) {
+                        eprintln!("Link receiver {} went away", addr);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for LinkSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let mut packet = Vec::new();
+        write_header(&mut packet, 42, 7, 2, 2);
+        for value in [0i16, 16384, -16384, i16::MAX] {
+            packet.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(packet.len(), HEADER_SIZE + 8);
+
+        let parsed = parse_packet(&packet).unwrap();
+        assert_eq!(parsed.token, 42);
+        assert_eq!(parsed.sequence, 7);
+        assert_eq!(parsed.channels, 2);
+        assert!((parsed.samples[1] - 0.5).abs() < 1e-4);
+        assert!(parse_packet(&packet[..packet.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_handshake_with_receiver() {
+        let port = 16990;
+        let _receiver = LinkReceiver::start(port, 2, 48000, |_| {}).unwrap();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let (_control, reply) = handshake(addr).unwrap();
+        assert_eq!((reply.sample_rate, reply.channels), (48000, 2));
+        // Only one sender at a time
+        assert!(handshake(addr).is_err());
+    }
+}
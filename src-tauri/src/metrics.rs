@@ -0,0 +1,268 @@
+// Prometheus metrics for long-running installations. The engine keeps
+// lock-free counters per stream; an optional HTTP endpoint serves them at
+// `/metrics` in the Prometheus text format.
+
+use crate::audio::AudioCommand;
+use crate::streaming::{self, read_request, write_response};
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    /// Served on every interface, since scrapers usually run elsewhere.
+    pub port: u16,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: 9101 }
+    }
+}
+
+/// Counters of one audio stream, updated from its callback.
+#[derive(Default)]
+pub struct StreamStats {
+    underruns: AtomicU64,
+    dropped_samples: AtomicU64,
+    buffered_samples: AtomicU64,
+    buffer_capacity: AtomicU64,
+    callbacks: AtomicU64,
+    callback_nanos: AtomicU64,
+    max_callback_nanos: AtomicU64,
+}
+
+impl StreamStats {
+    pub fn record_callback(&self, started: Instant) {
+        let nanos = started.elapsed().as_nanos() as u64;
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.callback_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_callback_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Callbacks so far; the watchdog's heartbeat.
+    pub fn callbacks(&self) -> u64 {
+        self.callbacks.load(Ordering::Relaxed)
+    }
+
+    /// A callback that ran short of audio and played silence.
+    pub fn add_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Samples thrown away because the stream's buffer was full.
+    pub fn add_dropped(&self, samples: u64) {
+        self.dropped_samples.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    pub fn set_buffer(&self, buffered: usize, capacity: usize) {
+        self.buffered_samples.store(buffered as u64, Ordering::Relaxed);
+        self.buffer_capacity.store(capacity as u64, Ordering::Relaxed);
+    }
+
+    /// Current values; the longest callback is reset to cover the next interval.
+    pub fn snapshot(&self) -> StreamStatsSnapshot {
+        StreamStatsSnapshot {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            dropped_samples: self.dropped_samples.load(Ordering::Relaxed),
+            buffered_samples: self.buffered_samples.load(Ordering::Relaxed),
+            buffer_capacity: self.buffer_capacity.load(Ordering::Relaxed),
+            callbacks: self.callbacks.load(Ordering::Relaxed),
+            callback_seconds: self.callback_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+            max_callback_seconds: self.max_callback_nanos.swap(0, Ordering::Relaxed) as f64 / 1e9,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct StreamStatsSnapshot {
+    pub underruns: u64,
+    pub dropped_samples: u64,
+    pub buffered_samples: u64,
+    pub buffer_capacity: u64,
+    pub callbacks: u64,
+    pub callback_seconds: f64,
+    pub max_callback_seconds: f64,
+}
+
+/// Engine-wide metrics, answered by the audio thread.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct EngineMetrics {
+    pub capturing: bool,
+    pub capture: StreamStatsSnapshot,
+    pub outputs: BTreeMap<String, StreamStatsSnapshot>,
+}
+
+pub struct MetricsServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(settings: &MetricsSettings, tx: Sender<AudioCommand>) -> Result<Self, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = streaming::spawn_tcp_listener(settings.port, stop.clone(), move |stream| {
+            if let Err(e) = serve_request(stream, &tx) {
+                println!("Metrics request failed: {}", e);
+            }
+        })?;
+        println!("Serving metrics on http://0.0.0.0:{}/metrics", settings.port);
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Running server, managed as app state so settings changes can restart it.
+#[derive(Default)]
+pub struct MetricsService {
+    server: Mutex<Option<MetricsServer>>,
+}
+
+impl MetricsService {
+    /// Stops the current server and starts a new one if `settings` enable it.
+    pub fn apply(&self, tx: &Sender<AudioCommand>, settings: &MetricsSettings) -> Result<(), String> {
+        let mut server = self.server.lock().map_err(|e| e.to_string())?;
+        *server = None;
+        if settings.enabled {
+            *server = Some(MetricsServer::start(settings, tx.clone())?);
+        }
+        Ok(())
+    }
+}
+
+fn serve_request(mut stream: TcpStream, tx: &Sender<AudioCommand>) -> Result<(), String> {
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT)).map_err(|e| e.to_string())?;
+    let request = read_request(&mut stream)?;
+    if (request.method.as_str(), request.path.as_str()) != ("GET", "/metrics") {
+        return write_response(&mut stream, "404 Not Found", "text/plain", b"");
+    }
+    let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+    tx.send(AudioCommand::GetMetrics(reply_tx)).map_err(|e| e.to_string())?;
+    match reply_rx.recv_timeout(SCRAPE_TIMEOUT) {
+        Ok(metrics) => write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", render(&metrics).as_bytes()),
+        Err(e) => {
+            write_response(&mut stream, "503 Service Unavailable", "text/plain", b"Audio engine busy")?;
+            Err(e.to_string())
+        },
+    }
+}
+
+/// Formats the metrics in the Prometheus text exposition format.
+fn render(metrics: &EngineMetrics) -> String {
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP audio_merge_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE audio_merge_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "audio_merge_{}{} {}", name, labels, value);
+        }
+    };
+    let per_output = |value: &dyn Fn(&StreamStatsSnapshot) -> String| -> Vec<(String, String)> {
+        metrics
+            .outputs
+            .iter()
+            .map(|(name, stats)| (format!("{{output=\"{}\"}}", escape_label(name)), value(stats)))
+            .collect()
+    };
+    let fill_ratio = |s: &StreamStatsSnapshot| match s.buffer_capacity {
+        0 => 0.0,
+        capacity => s.buffered_samples as f64 / capacity as f64,
+    };
+
+    family("capturing", "gauge", "Whether capture is running.", vec![(String::new(), (metrics.capturing as u8).to_string())]);
+    family("active_outputs", "gauge", "Outputs in the mix.", vec![(String::new(), metrics.outputs.len().to_string())]);
+    family(
+        "capture_callback_duration_seconds",
+        "summary",
+        "Time spent in the capture callback.",
+        vec![
+            ("_sum".to_string(), metrics.capture.callback_seconds.to_string()),
+            ("_count".to_string(), metrics.capture.callbacks.to_string()),
+        ],
+    );
+    family(
+        "capture_callback_duration_max_seconds",
+        "gauge",
+        "Longest capture callback since the previous scrape.",
+        vec![(String::new(), metrics.capture.max_callback_seconds.to_string())],
+    );
+    family(
+        "output_underruns_total",
+        "counter",
+        "Output callbacks that ran short of audio.",
+        per_output(&|s| s.underruns.to_string()),
+    );
+    family(
+        "output_dropped_samples_total",
+        "counter",
+        "Samples dropped because an output's buffer was full.",
+        per_output(&|s| s.dropped_samples.to_string()),
+    );
+    family(
+        "output_buffer_fill_ratio",
+        "gauge",
+        "How full each output's buffer is, from 0 to 1.",
+        per_output(&|s| fill_ratio(s).to_string()),
+    );
+    family(
+        "output_callback_duration_seconds_sum",
+        "counter",
+        "Total time spent in each output callback.",
+        per_output(&|s| s.callback_seconds.to_string()),
+    );
+    family(
+        "output_callback_duration_seconds_count",
+        "counter",
+        "Number of output callbacks.",
+        per_output(&|s| s.callbacks.to_string()),
+    );
+    family(
+        "output_callback_duration_max_seconds",
+        "gauge",
+        "Longest output callback since the previous scrape.",
+        per_output(&|s| s.max_callback_seconds.to_string()),
+    );
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = EngineMetrics { capturing: true, ..EngineMetrics::default() };
+        metrics.outputs.insert(
+            "Speakers \"Front\"".to_string(),
+            StreamStatsSnapshot { underruns: 3, buffered_samples: 4096, buffer_capacity: 16384, ..Default::default() },
+        );
+        let text = render(&metrics);
+        assert!(text.contains("audio_merge_capturing 1\n"));
+        assert!(text.contains("audio_merge_active_outputs 1\n"));
+        assert!(text.contains("# TYPE audio_merge_output_underruns_total counter\n"));
+        assert!(text.contains("audio_merge_output_underruns_total{output=\"Speakers \\\"Front\\\"\"} 3\n"));
+        assert!(text.contains("audio_merge_output_buffer_fill_ratio{output=\"Speakers \\\"Front\\\"\"} 0.25\n"));
+    }
+}
@@ -0,0 +1,203 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rtrb::{Consumer, RingBuffer};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::atomic_float::AtomicF32;
+use crate::denoise::NoiseSuppressor;
+use crate::echo::EchoCapture;
+use crate::handoff::HandoffControl;
+use crate::host;
+use crate::sample_format;
+use crate::tap;
+use crate::dsp::{self, GainRamp, LinearResampler};
+
+/// Capacity of the ring buffer between the mic callback and the capture callback.
+const MIC_BUFFER_SIZE: usize = 8192;
+
+/// Shared controls read by the mic callback.
+#[derive(Clone)]
+pub struct MicControls {
+    pub volume: Arc<AtomicF32>,
+    pub muted: Arc<AtomicBool>,
+    pub noise_suppression: Arc<AtomicBool>,
+    /// Mic half of the echo canceller, installed by the audio thread when AEC is on.
+    pub echo: HandoffControl<Option<EchoCapture>>,
+}
+
+impl Default for MicControls {
+    fn default() -> Self {
+        Self {
+            volume: Arc::new(AtomicF32::new(1.0)),
+            muted: Arc::new(AtomicBool::new(false)),
+            noise_suppression: Arc::new(AtomicBool::new(false)),
+            echo: HandoffControl::new(),
+        }
+    }
+}
+
+/// Read side of an open microphone, drained by the capture callback.
+pub struct MicSource {
+    consumer: Consumer<f32>,
+    channels: usize,
+    /// Rate of the samples in the ring, converted to the bus rate.
+    sample_rate: u32,
+    /// Rate the device itself runs at, which the echo canceller sees.
+    device_sample_rate: u32,
+}
+
+impl MicSource {
+    /// Wraps a ring already laid out in `channels`, for other live inputs
+    /// mixed like the mic (e.g. network receivers).
+    pub fn from_consumer(consumer: Consumer<f32>, channels: usize, sample_rate: u32) -> Self {
+        Self { consumer, channels, sample_rate, device_sample_rate: sample_rate }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn device_sample_rate(&self) -> u32 {
+        self.device_sample_rate
+    }
+
+    /// Reads one mic frame laid out like a capture frame. Mono mics are spread
+    /// across all channels; extra mic channels beyond the capture layout are
+    /// dropped. Returns false (and leaves silence) when no frame is buffered.
+    pub fn read_frame(&mut self, frame: &mut [f32]) -> bool {
+        frame.fill(0.0);
+        if self.consumer.slots() < self.channels {
+            return false;
+        }
+        let mut last = 0.0;
+        for c in 0..self.channels {
+            let sample = self.consumer.pop().unwrap_or(0.0);
+            if let Some(out) = frame.get_mut(c) {
+                *out = sample;
+            }
+            last = sample;
+        }
+        for out in frame.iter_mut().skip(self.channels) {
+            *out = last;
+        }
+        true
+    }
+}
+
+/// Opens an input device by name, applying the mic's own processing and
+/// volume/mute before handing samples to the capture callback through a
+/// `MicSource`. A device that can't run at `target_rate` is resampled to it.
+pub fn open_mic(
+    device_name: &str,
+    target_rate: cpal::SampleRate,
+    controls: MicControls,
+) -> Result<(cpal::Stream, MicSource), String> {
+    let host = host::current();
+    let device = host
+        .input_devices()
+        .map_err(|e| e.to_string())?
+        .find(|d| d.name().unwrap_or_default() == device_name)
+        .ok_or_else(|| format!("Input device not found: {}", device_name))?;
+
+    // Prefer the capture rate so the mix doesn't drift in pitch
+    let best_config = device
+        .supported_input_configs()
+        .ok()
+        .and_then(|configs| sample_format::best_config(configs, target_rate));
+
+    let supported = match best_config {
+        Some(c) => c,
+        None => {
+            println!("Warning: Mic cannot run at {}. Resampling from the default.", target_rate.0);
+            device.default_input_config().map_err(|e| e.to_string())?
+        }
+    };
+    let format = supported.sample_format();
+    let config: cpal::StreamConfig = supported.into();
+
+    println!("Mic {} configured at: {}", device_name, config.sample_rate.0);
+
+    let channels = config.channels as usize;
+    let (mut producer, consumer) = RingBuffer::<f32>::new(MIC_BUFFER_SIZE);
+    let mut ramp = GainRamp::new(0.0, config.sample_rate.0, dsp::VOLUME_RAMP_MS);
+    let mut mute_fade = GainRamp::new(1.0, config.sample_rate.0, dsp::MUTE_FADE_MS);
+
+    let mut denoiser = NoiseSuppressor::new(channels, config.sample_rate.0);
+    let mut frame_buf = vec![0.0f32; channels];
+    let mut resampler = (config.sample_rate != target_rate)
+        .then(|| LinearResampler::new(channels, config.sample_rate.0, target_rate.0));
+    let mut processed: Vec<f32> = Vec::new();
+    let mut resampled: Vec<f32> = Vec::new();
+    let mut echo_capture = controls.echo.handoff();
+
+    let stream = sample_format::build_input_stream(
+        &device,
+        &config,
+        format,
+        move |data: &[f32], _: &cpal::InputCallbackInfo| {
+            let is_muted = controls.muted.load(Ordering::Relaxed);
+            let vol = controls.volume.load();
+            let denoise = controls.noise_suppression.load(Ordering::Relaxed);
+            let echo = echo_capture.update();
+            ramp.set_target(vol);
+            mute_fade.set_target(if is_muted { 0.0 } else { 1.0 });
+
+            processed.clear();
+            for frame in data.chunks(channels) {
+                let frame_buf = &mut frame_buf[..frame.len()];
+                frame_buf.copy_from_slice(frame);
+                // Echo cancellation needs the raw mic signal, so it runs first
+                if let Some(e) = echo.as_mut() {
+                    e.process_frame(frame_buf);
+                }
+                processed.extend_from_slice(frame_buf);
+            }
+            if denoise {
+                denoiser.process(&mut processed);
+            }
+            for frame in processed.chunks_mut(channels) {
+                let gain = ramp.next_gain() * mute_fade.next_gain();
+                frame.iter_mut().for_each(|sample| *sample *= gain);
+            }
+
+            let samples = match resampler.as_mut() {
+                Some(r) => {
+                    resampled.clear();
+                    r.push(&processed, &mut resampled);
+                    &resampled
+                },
+                None => &processed,
+            };
+            // Drops whole frames so channels stay aligned
+            tap::push_frames(&mut producer, samples, channels);
+        },
+        move |err| eprintln!("Mic error: {}", err),
+    )
+    .map_err(|e| e.to_string())?;
+
+    stream.play().map_err(|e| e.to_string())?;
+    Ok((stream, MicSource { consumer, channels, sample_rate: target_rate.0, device_sample_rate: config.sample_rate.0 }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mono_mic_spreads_to_stereo() {
+        let (mut producer, consumer) = RingBuffer::<f32>::new(16);
+        let mut mic = MicSource::from_consumer(consumer, 1, 48000);
+        let _ = producer.push(0.25);
+
+        let mut frame = [0.5, 0.5];
+        assert!(mic.read_frame(&mut frame));
+        assert_eq!(frame, [0.25, 0.25]);
+
+        // Empty buffer reads as silence
+        assert!(!mic.read_frame(&mut frame));
+        assert_eq!(frame, [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,233 @@
+// MIDI controller input. Control Change messages are looked up in the mapping
+// table from the config and applied through the Tauri commands, so faders and
+// buttons on a control surface move the mixer. In learn mode the next CC that
+// arrives is bound to the parameter being learned instead.
+
+use crate::{config, AppState};
+use midir::{MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+
+const CLIENT_NAME: &str = "Audio Merge";
+const CONTROL_CHANGE: u8 = 0xB0;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct MidiSettings {
+    pub enabled: bool,
+    /// Input port to listen on; every port when unset.
+    pub device: Option<String>,
+    pub mappings: Vec<MidiMapping>,
+}
+
+/// A controller bound to a mixer parameter.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MidiMapping {
+    /// MIDI channel, 0-15.
+    pub channel: u8,
+    pub controller: u8,
+    pub target: MidiTarget,
+}
+
+/// Parameter a controller drives. Volumes follow the controller's full range;
+/// mutes are on for values of 64 and above.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MidiTarget {
+    OutputVolume { device: String },
+    OutputMute { device: String },
+    InputVolume,
+    InputMute,
+    MasterVolume,
+    MasterMute,
+}
+
+/// Open input ports and the mapping state shared with their callbacks,
+/// managed as app state.
+#[derive(Default)]
+pub struct MidiService {
+    connections: Mutex<Vec<MidiInputConnection<()>>>,
+    mappings: Arc<Mutex<Vec<MidiMapping>>>,
+    learning: Arc<Mutex<Option<MidiTarget>>>,
+}
+
+impl MidiService {
+    /// Closes the open ports and reopens them for `settings`.
+    pub fn apply(&self, app: &AppHandle, settings: &MidiSettings) -> Result<(), String> {
+        let mut connections = self.connections.lock().map_err(|e| e.to_string())?;
+        connections.clear();
+        if let Ok(mut mappings) = self.mappings.lock() {
+            *mappings = settings.mappings.clone();
+        }
+        if !settings.enabled {
+            return Ok(());
+        }
+
+        for port_name in input_ports()? {
+            if settings.device.as_ref().is_some_and(|d| *d != port_name) {
+                continue;
+            }
+            match self.connect(app, &port_name) {
+                Ok(connection) => {
+                    println!("Listening to MIDI input {}", port_name);
+                    connections.push(connection);
+                },
+                Err(e) => eprintln!("Failed to open MIDI input {}: {}", port_name, e),
+            }
+        }
+        if connections.is_empty() {
+            return Err(match &settings.device {
+                Some(device) => format!("MIDI input not found: {}", device),
+                None => "No MIDI inputs found".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn connect(&self, app: &AppHandle, port_name: &str) -> Result<MidiInputConnection<()>, String> {
+        // Each connection consumes its own client
+        let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|p| input.port_name(p).is_ok_and(|n| n == port_name))
+            .ok_or_else(|| format!("MIDI input not found: {}", port_name))?;
+        let app = app.clone();
+        let mappings = self.mappings.clone();
+        let learning = self.learning.clone();
+        input
+            .connect(
+                &port,
+                CLIENT_NAME,
+                move |_stamp, message, _| {
+                    if let Some((channel, controller, value)) = parse_control_change(message) {
+                        handle_control_change(&app, &mappings, &learning, channel, controller, value);
+                    }
+                },
+                (),
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    /// Binds the next controller that moves to `target`.
+    pub fn learn(&self, target: MidiTarget) -> Result<(), String> {
+        let mut learning = self.learning.lock().map_err(|e| e.to_string())?;
+        println!("Learning MIDI controller for {:?}", target);
+        *learning = Some(target);
+        Ok(())
+    }
+
+    pub fn cancel_learn(&self) -> Result<(), String> {
+        let mut learning = self.learning.lock().map_err(|e| e.to_string())?;
+        *learning = None;
+        Ok(())
+    }
+}
+
+/// Names of the MIDI input ports currently available.
+pub fn input_ports() -> Result<Vec<String>, String> {
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+    Ok(input.ports().iter().filter_map(|p| input.port_name(p).ok()).collect())
+}
+
+/// Channel, controller and value of a Control Change message.
+fn parse_control_change(message: &[u8]) -> Option<(u8, u8, u8)> {
+    match *message {
+        [status, controller, value] if status & 0xF0 == CONTROL_CHANGE => Some((status & 0x0F, controller, value)),
+        _ => None,
+    }
+}
+
+fn handle_control_change(
+    app: &AppHandle,
+    mappings: &Mutex<Vec<MidiMapping>>,
+    learning: &Mutex<Option<MidiTarget>>,
+    channel: u8,
+    controller: u8,
+    value: u8,
+) {
+    let learned = learning.lock().ok().and_then(|mut l| l.take());
+    if let Some(target) = learned {
+        let mapping = MidiMapping { channel, controller, target };
+        let updated = match mappings.lock() {
+            Ok(mut mappings) => {
+                bind(&mut mappings, mapping.clone());
+                mappings.clone()
+            },
+            Err(_) => return,
+        };
+        println!("Bound MIDI channel {} CC {} to {:?}", channel + 1, controller, mapping.target);
+        if let Err(e) = config::update_config(app, |c| c.midi.mappings = updated) {
+            eprintln!("Failed to save MIDI mapping: {}", e);
+        }
+        let _ = app.emit("midi-learned", mapping);
+        return;
+    }
+
+    let targets: Vec<MidiTarget> = match mappings.lock() {
+        Ok(mappings) => mappings
+            .iter()
+            .filter(|m| m.channel == channel && m.controller == controller)
+            .map(|m| m.target.clone())
+            .collect(),
+        Err(_) => return,
+    };
+    for target in targets {
+        if let Err(e) = apply_target(app, &target, value) {
+            eprintln!("MIDI {:?} failed: {}", target, e);
+        }
+    }
+}
+
+/// Adds `mapping`, replacing whatever the controller or the target was bound to.
+fn bind(mappings: &mut Vec<MidiMapping>, mapping: MidiMapping) {
+    mappings.retain(|m| {
+        !(m.channel == mapping.channel && m.controller == mapping.controller) && m.target != mapping.target
+    });
+    mappings.push(mapping);
+}
+
+fn apply_target(app: &AppHandle, target: &MidiTarget, value: u8) -> Result<(), String> {
+    let state = || app.state::<AppState>();
+    let volume = value as f32 / 127.0;
+    let on = value >= 64;
+    match target {
+        MidiTarget::OutputVolume { device } => crate::set_device_volume(state(), device.clone(), volume),
+        MidiTarget::OutputMute { device } => crate::set_device_mute(state(), device.clone(), on),
+        MidiTarget::InputVolume => crate::set_input_volume(state(), volume),
+        MidiTarget::InputMute => crate::set_input_mute(state(), on),
+        MidiTarget::MasterVolume => crate::set_master_volume(app.clone(), state(), volume),
+        MidiTarget::MasterMute => crate::set_master_mute(app.clone(), state(), on),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_control_change() {
+        assert_eq!(parse_control_change(&[0xB3, 7, 100]), Some((3, 7, 100)));
+        // Note on
+        assert_eq!(parse_control_change(&[0x90, 60, 100]), None);
+        assert_eq!(parse_control_change(&[0xB0, 7]), None);
+    }
+
+    #[test]
+    fn test_bind_replaces_controller_and_target() {
+        let mut mappings = vec![
+            MidiMapping { channel: 0, controller: 7, target: MidiTarget::MasterVolume },
+            MidiMapping { channel: 0, controller: 8, target: MidiTarget::InputVolume },
+        ];
+        bind(&mut mappings, MidiMapping { channel: 0, controller: 7, target: MidiTarget::MasterMute });
+        bind(&mut mappings, MidiMapping { channel: 0, controller: 9, target: MidiTarget::InputVolume });
+        assert_eq!(
+            mappings,
+            vec![
+                MidiMapping { channel: 0, controller: 7, target: MidiTarget::MasterMute },
+                MidiMapping { channel: 0, controller: 9, target: MidiTarget::InputVolume },
+            ]
+        );
+    }
+}
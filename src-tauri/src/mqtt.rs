@@ -0,0 +1,385 @@
+// MQTT bridge for home automation. The mixer state is published as retained
+// topics under `<prefix>/`, along with Home Assistant discovery payloads so
+// each output shows up as a volume slider and a mute switch. Commands arrive
+// on the matching `.../set` topics and run through the Tauri commands.
+//
+//   <prefix>/status                    online / offline
+//   <prefix>/capture[/set]             ON / OFF
+//   <prefix>/master/volume[/set]       0-100
+//   <prefix>/master/mute[/set]         ON / OFF
+//   <prefix>/output/<id>/volume[/set]  0-100
+//   <prefix>/output/<id>/mute[/set]    ON / OFF
+//
+// `<id>` is the output name reduced to a topic-safe slug.
+
+use crate::audio::AudioStateSnapshot;
+use crate::{discovery, AppState};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Outgoing, Packet, QoS, RecvTimeoutError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// How often the engine state is checked for changes to publish.
+const STATE_POLL: Duration = Duration::from_millis(500);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Root of the state and command topics.
+    pub topic_prefix: String,
+    /// Where Home Assistant looks for discovery payloads; `None` skips them.
+    pub discovery_prefix: Option<String>,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            username: None,
+            password: None,
+            topic_prefix: "audio_merge".to_string(),
+            discovery_prefix: Some("homeassistant".to_string()),
+        }
+    }
+}
+
+/// Output slugs currently published, mapped back to device names.
+type OutputIds = Arc<Mutex<HashMap<String, String>>>;
+
+pub struct MqttBridge {
+    client: Client,
+    status_topic: String,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl MqttBridge {
+    pub fn start(settings: MqttSettings, app: AppHandle) -> Result<Self, String> {
+        if settings.host.trim().is_empty() {
+            return Err("MQTT broker host is empty".to_string());
+        }
+        let prefix = settings.topic_prefix.trim_end_matches('/').to_string();
+        let status_topic = format!("{}/status", prefix);
+
+        let mut options = MqttOptions::new(client_id(), settings.host.clone(), settings.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&status_topic, "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = &settings.username {
+            options.set_credentials(username, settings.password.clone().unwrap_or_default());
+        }
+        let (client, mut connection) = Client::new(options, 64);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let outputs: OutputIds = Arc::new(Mutex::new(HashMap::new()));
+        // Set on every (re)connect so the retained topics are refreshed
+        let republish = Arc::new(AtomicBool::new(false));
+
+        let network = {
+            let client = client.clone();
+            let stop = stop.clone();
+            let outputs = outputs.clone();
+            let republish = republish.clone();
+            let app = app.clone();
+            let prefix = prefix.clone();
+            let status_topic = status_topic.clone();
+            // After `stop`, keeps polling until the queued offline status and
+            // disconnect have gone out
+            thread::spawn(move || loop {
+                match connection.recv_timeout(STATE_POLL) {
+                    Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                        println!("Connected to MQTT broker");
+                        for filter in ["+/set", "+/+/set", "output/+/+/set"] {
+                            let _ = client.subscribe(format!("{}/{}", prefix, filter), QoS::AtLeastOnce);
+                        }
+                        let _ = client.publish(&status_topic, QoS::AtLeastOnce, true, "online");
+                        republish.store(true, Ordering::Relaxed);
+                    },
+                    Ok(Ok(Event::Outgoing(Outgoing::Disconnect))) => break,
+                    Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                        let payload = String::from_utf8_lossy(&publish.payload);
+                        if let Some(command) = publish.topic.strip_prefix(&format!("{}/", prefix)) {
+                            if let Err(e) = handle_command(&app, &outputs, command, payload.trim()) {
+                                eprintln!("MQTT command {} failed: {}", publish.topic, e);
+                            }
+                        }
+                    },
+                    Ok(Ok(_)) => {},
+                    Ok(Err(_)) | Err(RecvTimeoutError::Timeout) if stop.load(Ordering::Relaxed) => break,
+                    Ok(Err(e)) => {
+                        eprintln!("MQTT connection error: {}", e);
+                        thread::sleep(RECONNECT_DELAY);
+                    },
+                    Err(RecvTimeoutError::Timeout) => {},
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            })
+        };
+
+        let publisher = {
+            let client = client.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut last: Option<AudioStateSnapshot> = None;
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(STATE_POLL);
+                    let Ok(state) = crate::get_audio_state(app.state::<AppState>()) else {
+                        continue;
+                    };
+                    let refresh = republish.swap(false, Ordering::Relaxed);
+                    if refresh || last.as_ref().map(state_key) != Some(state_key(&state)) {
+                        publish_state(&client, &settings, &prefix, &outputs, &state, refresh);
+                        last = Some(state);
+                    }
+                }
+            })
+        };
+
+        Ok(Self { client, status_topic, stop, threads: vec![network, publisher] })
+    }
+}
+
+impl Drop for MqttBridge {
+    fn drop(&mut self) {
+        let _ = self.client.publish(&self.status_topic, QoS::AtLeastOnce, true, "offline");
+        let _ = self.client.disconnect();
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Running bridge, managed as app state so settings changes can restart it.
+#[derive(Default)]
+pub struct MqttService {
+    bridge: Mutex<Option<MqttBridge>>,
+}
+
+impl MqttService {
+    /// Stops the current bridge and starts a new one if `settings` enable it.
+    pub fn apply(&self, app: &AppHandle, settings: &MqttSettings) -> Result<(), String> {
+        let mut bridge = self.bridge.lock().map_err(|e| e.to_string())?;
+        *bridge = None;
+        if settings.enabled {
+            *bridge = Some(MqttBridge::start(settings.clone(), app.clone())?);
+        }
+        Ok(())
+    }
+}
+
+fn client_id() -> String {
+    format!("audio-merge-{}", discovery::local_host_name())
+}
+
+/// The parts of the state that are published; meters and streams are left out.
+fn state_key(state: &AudioStateSnapshot) -> Value {
+    json!([state.capturing, state.volumes, state.muted_outputs, state.master_volume, state.master_muted])
+}
+
+/// Reduces an output name to a topic level and entity id: lowercase ASCII
+/// letters, digits and underscores.
+pub fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_matches('_').to_string()
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "ON" } else { "OFF" }
+}
+
+fn percent(position: f32) -> String {
+    format!("{}", (position * 100.0).round() as i32)
+}
+
+fn publish_state(
+    client: &Client,
+    settings: &MqttSettings,
+    prefix: &str,
+    outputs: &OutputIds,
+    state: &AudioStateSnapshot,
+    refresh: bool,
+) {
+    let publish = |topic: String, payload: String| {
+        if let Err(e) = client.publish(topic, QoS::AtLeastOnce, true, payload) {
+            eprintln!("MQTT publish failed: {}", e);
+        }
+    };
+
+    let current: HashMap<String, String> = state.outputs.iter().map(|name| (slug(name), name.clone())).collect();
+    let previous = match outputs.lock() {
+        Ok(mut outputs) => std::mem::replace(&mut *outputs, current.clone()),
+        Err(_) => return,
+    };
+
+    if let Some(discovery_prefix) = &settings.discovery_prefix {
+        let host = discovery::local_host_name();
+        if refresh {
+            for (component, id, config) in mixer_entities(prefix, &host) {
+                publish(format!("{}/{}/{}/config", discovery_prefix, component, id), config.to_string());
+            }
+        }
+        for (id, name) in &current {
+            if refresh || !previous.contains_key(id) {
+                for (component, entity_id, config) in output_entities(prefix, &host, id, name) {
+                    publish(format!("{}/{}/{}/config", discovery_prefix, component, entity_id), config.to_string());
+                }
+            }
+        }
+        // An empty retained config removes the entity from Home Assistant
+        for (id, name) in &previous {
+            if !current.contains_key(id) {
+                for (component, entity_id, _) in output_entities(prefix, &host, id, name) {
+                    publish(format!("{}/{}/{}/config", discovery_prefix, component, entity_id), String::new());
+                }
+            }
+        }
+    }
+
+    publish(format!("{}/capture", prefix), on_off(state.capturing).to_string());
+    publish(format!("{}/master/volume", prefix), percent(state.master_volume));
+    publish(format!("{}/master/mute", prefix), on_off(state.master_muted).to_string());
+    for (id, name) in &current {
+        let volume = state.volumes.get(name).copied().unwrap_or(1.0);
+        let muted = state.muted_outputs.contains(name);
+        publish(format!("{}/output/{}/volume", prefix, id), percent(volume));
+        publish(format!("{}/output/{}/mute", prefix, id), on_off(muted).to_string());
+    }
+}
+
+/// Discovery payload for a Home Assistant entity bound to `topic`.
+fn entity_config(prefix: &str, host: &str, unique_id: &str, name: &str, topic: &str, slider: bool) -> Value {
+    let mut config = json!({
+        "name": name,
+        "unique_id": format!("audio_merge_{}_{}", slug(host), unique_id),
+        "state_topic": format!("{}/{}", prefix, topic),
+        "command_topic": format!("{}/{}/set", prefix, topic),
+        "availability_topic": format!("{}/status", prefix),
+        "device": {
+            "identifiers": [format!("audio_merge_{}", slug(host))],
+            "name": format!("Audio Merge ({})", host),
+            "manufacturer": "Audio Merge",
+        },
+    });
+    if slider {
+        config["min"] = json!(0);
+        config["max"] = json!(100);
+        config["step"] = json!(1);
+        config["unit_of_measurement"] = json!("%");
+        config["icon"] = json!("mdi:volume-high");
+    }
+    config
+}
+
+fn mixer_entities(prefix: &str, host: &str) -> Vec<(&'static str, String, Value)> {
+    vec![
+        ("switch", "capture".to_string(), entity_config(prefix, host, "capture", "Capture", "capture", false)),
+        ("number", "master_volume".to_string(), entity_config(prefix, host, "master_volume", "Master volume", "master/volume", true)),
+        ("switch", "master_mute".to_string(), entity_config(prefix, host, "master_mute", "Master mute", "master/mute", false)),
+    ]
+}
+
+fn output_entities(prefix: &str, host: &str, id: &str, name: &str) -> Vec<(&'static str, String, Value)> {
+    let volume_id = format!("{}_volume", id);
+    let mute_id = format!("{}_mute", id);
+    vec![
+        (
+            "number",
+            volume_id.clone(),
+            entity_config(prefix, host, &volume_id, &format!("{} volume", name), &format!("output/{}/volume", id), true),
+        ),
+        (
+            "switch",
+            mute_id.clone(),
+            entity_config(prefix, host, &mute_id, &format!("{} mute", name), &format!("output/{}/mute", id), false),
+        ),
+    ]
+}
+
+fn parse_switch(payload: &str) -> Result<bool, String> {
+    match payload.to_ascii_uppercase().as_str() {
+        "ON" | "TRUE" | "1" => Ok(true),
+        "OFF" | "FALSE" | "0" => Ok(false),
+        _ => Err(format!("Expected ON or OFF, got '{}'", payload)),
+    }
+}
+
+fn parse_volume(payload: &str) -> Result<f32, String> {
+    let percent: f32 = payload.parse().map_err(|_| format!("Expected 0-100, got '{}'", payload))?;
+    Ok((percent / 100.0).clamp(0.0, 1.0))
+}
+
+/// Runs a command published to `<prefix>/<topic>`. State topics are ignored.
+fn handle_command(app: &AppHandle, outputs: &OutputIds, topic: &str, payload: &str) -> Result<(), String> {
+    let Some(topic) = topic.strip_suffix("/set") else {
+        return Ok(());
+    };
+    let state = || app.state::<AppState>();
+    let levels: Vec<&str> = topic.split('/').collect();
+    match levels.as_slice() {
+        ["capture"] => {
+            if parse_switch(payload)? {
+                crate::start_capture(state())
+            } else {
+                crate::stop_capture(state())
+            }
+        },
+        ["master", "volume"] => crate::set_master_volume(app.clone(), state(), parse_volume(payload)?),
+        ["master", "mute"] => crate::set_master_mute(app.clone(), state(), parse_switch(payload)?),
+        ["output", id, setting] => {
+            let name = outputs
+                .lock()
+                .ok()
+                .and_then(|o| o.get(*id).cloned())
+                .ok_or_else(|| format!("Unknown output: {}", id))?;
+            match *setting {
+                "volume" => crate::set_device_volume(state(), name, parse_volume(payload)?),
+                "mute" => crate::set_device_mute(state(), name, parse_switch(payload)?),
+                _ => Err(format!("Unknown setting: {}", setting)),
+            }
+        },
+        _ => Err(format!("Unknown topic: {}", topic)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Speakers (Realtek(R) Audio)"), "speakers_realtek_r_audio");
+        assert_eq!(slug("airplay://Living Room"), "airplay_living_room");
+    }
+
+    #[test]
+    fn test_output_discovery_payload() {
+        let entities = output_entities("audio_merge", "DESKTOP-1", "speakers", "Speakers");
+        let (component, id, volume) = &entities[0];
+        assert_eq!((*component, id.as_str()), ("number", "speakers_volume"));
+        assert_eq!(volume["state_topic"], "audio_merge/output/speakers/volume");
+        assert_eq!(volume["command_topic"], "audio_merge/output/speakers/volume/set");
+        assert_eq!(volume["unique_id"], "audio_merge_desktop_1_speakers_volume");
+        assert_eq!(volume["max"], 100);
+        assert_eq!(entities[1].2["command_topic"], "audio_merge/output/speakers/mute/set");
+    }
+}
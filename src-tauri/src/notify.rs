@@ -0,0 +1,62 @@
+// Native notifications for problems that need the user's attention: a lost
+// output, a stream error or a failed recording. The same message is shown at
+// most once per `REPEAT_INTERVAL`, since failing streams tend to report the
+// same error over and over.
+
+use crate::audio::AudioEvent;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+const REPEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+pub struct Notifier {
+    enabled: Mutex<bool>,
+    shown: Mutex<HashMap<String, Instant>>,
+}
+
+impl Notifier {
+    pub fn set_enabled(&self, enabled: bool) {
+        if let Ok(mut e) = self.enabled.lock() {
+            *e = enabled;
+        }
+    }
+
+    pub fn on_event(&self, app: &AppHandle, event: &AudioEvent) {
+        if !self.enabled.lock().is_ok_and(|e| *e) {
+            return;
+        }
+        let Some((title, body)) = message(event) else {
+            return;
+        };
+        if let Ok(mut shown) = self.shown.lock() {
+            let key = format!("{}\n{}", title, body);
+            if shown.get(&key).is_some_and(|t| t.elapsed() < REPEAT_INTERVAL) {
+                return;
+            }
+            shown.retain(|_, t| t.elapsed() < REPEAT_INTERVAL);
+            shown.insert(key, Instant::now());
+        }
+        if let Err(e) = app.notification().builder().title(title).body(body).show() {
+            eprintln!("Failed to show notification: {}", e);
+        }
+    }
+}
+
+/// Title and text for events worth a notification.
+fn message(event: &AudioEvent) -> Option<(&'static str, String)> {
+    match event {
+        AudioEvent::OutputDisconnected { device, .. } => Some(("Output disconnected", device.clone())),
+        AudioEvent::StreamError { stream, error } => Some(("Audio error", format!("{}: {}", stream, error))),
+        AudioEvent::RecordingFailed { error } => Some(("Recording failed", error.clone())),
+        AudioEvent::EnginePanicked { reason, restarted: true, .. } => Some(("Audio engine restarted", reason.clone())),
+        AudioEvent::EnginePanicked { reason, restarted: false, .. } => Some(("Audio engine stopped", reason.clone())),
+        AudioEvent::CaptureExclusionsRejected { error } => Some(("Capture exclusions not applied", error.to_string())),
+        AudioEvent::StreamStalled { stream } => Some(("Stream stopped responding", stream.clone())),
+        AudioEvent::StreamRecoveryFailed { stream, error } => Some(("Stream stopped", format!("{}: {}", stream, error))),
+        _ => None,
+    }
+}
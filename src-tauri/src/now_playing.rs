@@ -0,0 +1,25 @@
+// What the OS reports as currently playing, used to tag recordings.
+
+/// "Artist - Title" of the current media session, via the Windows system media
+/// transport controls.
+#[cfg(windows)]
+pub fn current() -> Option<String> {
+    use windows::Media::Control::GlobalSystemMediaTransportControlsSessionManager;
+
+    let manager = GlobalSystemMediaTransportControlsSessionManager::RequestAsync().ok()?.get().ok()?;
+    let session = manager.GetCurrentSession().ok()?;
+    let properties = session.TryGetMediaPropertiesAsync().ok()?.get().ok()?;
+    let title = properties.Title().ok()?.to_string();
+    let artist = properties.Artist().map(|a| a.to_string()).unwrap_or_default();
+
+    match (artist.is_empty(), title.is_empty()) {
+        (_, true) => None,
+        (true, false) => Some(title),
+        (false, false) => Some(format!("{} - {}", artist, title)),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn current() -> Option<String> {
+    None
+}
@@ -0,0 +1,202 @@
+// OSC control surface for TouchOSC, lighting consoles and the like. Messages
+// arriving over UDP are mapped onto the Tauri commands:
+//
+//   /output/{name}/volume  f 0.0-1.0     /input/volume   f 0.0-1.0
+//   /output/{name}/mute    i|f|T|F       /input/mute     i|f|T|F
+//   /output/{name}/solo    i|f|T|F       /master/volume  f 0.0-1.0
+//   /output/{name}/active  i|f|T|F       /master/mute    i|f|T|F
+//   /capture/start, /capture/stop
+//
+// `{name}` is the output name, its topic-style slug (OSC addresses cannot
+// contain spaces) or its 1-based position in the output list. `active` adds
+// the output to the mix or removes it.
+
+use crate::AppState;
+use rosc::{OscMessage, OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+const MAX_PACKET_SIZE: usize = 65536;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct OscSettings {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for OscSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: 9000 }
+    }
+}
+
+pub struct OscServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OscServer {
+    pub fn start(settings: &OscSettings, app: AppHandle) -> Result<Self, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", settings.port)).map_err(|e| format!("Port {}: {}", settings.port, e))?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT)).map_err(|e| e.to_string())?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        println!("Listening for OSC on port {}", settings.port);
+
+        let thread = thread::spawn(move || {
+            let mut buf = vec![0u8; MAX_PACKET_SIZE];
+            while !stop_flag.load(Ordering::Relaxed) {
+                let len = match socket.recv_from(&mut buf) {
+                    Ok((len, _from)) => len,
+                    Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                    Err(e) => {
+                        eprintln!("OSC receive error: {}", e);
+                        continue;
+                    }
+                };
+                match rosc::decoder::decode_udp(&buf[..len]) {
+                    Ok((_, packet)) => handle_packet(&app, packet),
+                    Err(e) => eprintln!("Bad OSC packet: {:?}", e),
+                }
+            }
+        });
+
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Running server, managed as app state so settings changes can restart it.
+#[derive(Default)]
+pub struct OscService {
+    server: Mutex<Option<OscServer>>,
+}
+
+impl OscService {
+    /// Stops the current server and starts a new one if `settings` enable it.
+    pub fn apply(&self, app: &AppHandle, settings: &OscSettings) -> Result<(), String> {
+        let mut server = self.server.lock().map_err(|e| e.to_string())?;
+        *server = None;
+        if settings.enabled {
+            *server = Some(OscServer::start(settings, app.clone())?);
+        }
+        Ok(())
+    }
+}
+
+fn handle_packet(app: &AppHandle, packet: OscPacket) {
+    match packet {
+        OscPacket::Message(message) => {
+            if let Err(e) = handle_message(app, &message) {
+                eprintln!("OSC {} failed: {}", message.addr, e);
+            }
+        },
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(app, packet);
+            }
+        },
+    }
+}
+
+fn handle_message(app: &AppHandle, message: &OscMessage) -> Result<(), String> {
+    let state = || app.state::<AppState>();
+    let levels: Vec<&str> = message.addr.trim_start_matches('/').split('/').collect();
+    match levels.as_slice() {
+        ["capture", "start"] => crate::start_capture(state()),
+        ["capture", "stop"] => crate::stop_capture(state()),
+        ["input", "volume"] => crate::set_input_volume(state(), volume_arg(&message.args)?),
+        ["input", "mute"] => crate::set_input_mute(state(), switch_arg(&message.args)?),
+        ["master", "volume"] => crate::set_master_volume(app.clone(), state(), volume_arg(&message.args)?),
+        ["master", "mute"] => crate::set_master_mute(app.clone(), state(), switch_arg(&message.args)?),
+        ["output", name, setting] => {
+            let outputs = crate::get_audio_state(state())?.outputs;
+            match *setting {
+                "active" => {
+                    let active = switch_arg(&message.args)?;
+                    match (active, resolve_output(&outputs, name)) {
+                        // Outputs not in the mix can only be named exactly
+                        (true, None) => crate::add_device_to_mix(app.clone(), state(), name.to_string()).map_err(|e| e.to_string()),
+                        (false, Some(output)) => crate::remove_device_from_mix(state(), output),
+                        _ => Ok(()),
+                    }
+                },
+                _ => {
+                    let output = resolve_output(&outputs, name).ok_or_else(|| format!("No output named {}", name))?;
+                    match *setting {
+                        "volume" => crate::set_device_volume(state(), output, volume_arg(&message.args)?),
+                        "mute" => crate::set_device_mute(state(), output, switch_arg(&message.args)?),
+                        "solo" => crate::set_device_solo(state(), output, switch_arg(&message.args)?),
+                        _ => Err(format!("Unknown setting: {}", setting)),
+                    }
+                },
+            }
+        },
+        _ => Err("Unknown address".to_string()),
+    }
+}
+
+/// Finds the active output an address refers to.
+fn resolve_output(outputs: &[String], name: &str) -> Option<String> {
+    outputs
+        .iter()
+        .find(|o| o.as_str() == name)
+        .or_else(|| outputs.iter().find(|o| crate::mqtt::slug(o) == name))
+        .or_else(|| name.parse::<usize>().ok().and_then(|i| outputs.get(i.checked_sub(1)?)))
+        .cloned()
+}
+
+fn number(arg: &OscType) -> Option<f32> {
+    match *arg {
+        OscType::Float(v) => Some(v),
+        OscType::Double(v) => Some(v as f32),
+        OscType::Int(v) => Some(v as f32),
+        OscType::Long(v) => Some(v as f32),
+        OscType::Bool(v) => Some(if v { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+fn volume_arg(args: &[OscType]) -> Result<f32, String> {
+    args.first()
+        .and_then(number)
+        .map(|v| v.clamp(0.0, 1.0))
+        .ok_or_else(|| "Expected a volume between 0 and 1".to_string())
+}
+
+fn switch_arg(args: &[OscType]) -> Result<bool, String> {
+    args.first()
+        .and_then(number)
+        .map(|v| v >= 0.5)
+        .ok_or_else(|| "Expected 0 or 1".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_output() {
+        let outputs = vec!["Headphones".to_string(), "Speakers (Realtek Audio)".to_string()];
+        assert_eq!(resolve_output(&outputs, "Headphones").as_deref(), Some("Headphones"));
+        assert_eq!(resolve_output(&outputs, "speakers_realtek_audio").as_deref(), Some("Speakers (Realtek Audio)"));
+        assert_eq!(resolve_output(&outputs, "2").as_deref(), Some("Speakers (Realtek Audio)"));
+        assert_eq!(resolve_output(&outputs, "0"), None);
+        assert_eq!(resolve_output(&outputs, "Monitor"), None);
+    }
+}
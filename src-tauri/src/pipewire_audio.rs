@@ -0,0 +1,293 @@
+// Native PipeWire streams on Linux (the `pipewire` feature). ALSA loopback of
+// the default output doesn't capture anything there, so system capture
+// records a sink's monitor through a PipeWire capture stream instead, and
+// sinks appear in the output list as `pipewire://node.name`, each played
+// through its own playback stream. Every stream runs its own main loop thread.
+
+use crate::priority;
+use pipewire as pw;
+use pw::spa;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+
+pub const URL_PREFIX: &str = "pipewire://";
+
+const APP_NAME: &str = "Audio Merge";
+
+pub fn url(node_name: &str) -> String {
+    format!("{}{}", URL_PREFIX, node_name)
+}
+
+/// Node name from a `pipewire://node.name` output name.
+pub fn parse_url(name: &str) -> Option<&str> {
+    name.strip_prefix(URL_PREFIX).filter(|n| !n.is_empty())
+}
+
+pub fn is_pipewire_url(name: &str) -> bool {
+    name.starts_with(URL_PREFIX)
+}
+
+/// A sink, which can be played to or recorded through its monitor.
+#[derive(Serialize, Clone, Debug)]
+pub struct PwSink {
+    /// `node.name`, the stable identity used as a stream target.
+    pub name: String,
+    /// `node.description`, the name shown by the desktop.
+    pub description: String,
+}
+
+/// The audio sinks in the graph. Each has a monitor source with the same
+/// target name.
+pub fn sinks() -> Result<Vec<PwSink>, String> {
+    pw::init();
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|e| e.to_string())?;
+    let context = pw::context::Context::new(&mainloop).map_err(|e| e.to_string())?;
+    let core = context.connect(None).map_err(|e| e.to_string())?;
+    let registry = core.get_registry().map_err(|e| e.to_string())?;
+
+    let sinks = Rc::new(RefCell::new(Vec::new()));
+    let found = sinks.clone();
+    let _registry_listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            let Some(props) = global.props else {
+                return;
+            };
+            if let Some(sink) = sink_from_props(|key| props.get(key)) {
+                found.borrow_mut().push(sink);
+            }
+        })
+        .register();
+
+    // The registry has announced every object once the core answers a sync
+    let pending = core.sync(0).map_err(|e| e.to_string())?;
+    let done_loop = mainloop.clone();
+    let _core_listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id == pw::core::PW_ID_CORE && seq == pending {
+                done_loop.quit();
+            }
+        })
+        .register();
+    mainloop.run();
+
+    let sinks = sinks.borrow().clone();
+    Ok(sinks)
+}
+
+/// The sink a registry object describes, if it is an audio sink with a
+/// name. Sinks without a description are shown by their node name.
+fn sink_from_props<'a>(get: impl Fn(&str) -> Option<&'a str>) -> Option<PwSink> {
+    if get("media.class") != Some("Audio/Sink") {
+        return None;
+    }
+    let name = get("node.name")?;
+    let description = get("node.description").unwrap_or(name);
+    Some(PwSink { name: name.to_string(), description: description.to_string() })
+}
+
+/// Which way a stream moves audio.
+enum Role {
+    /// Record a sink's monitor.
+    Capture(Box<dyn FnMut(&[f32]) + Send>),
+    /// Play into a sink.
+    Playback(Box<dyn FnMut(&mut [f32]) + Send>),
+}
+
+/// A running PipeWire stream. Dropping it stops the stream and its thread.
+pub struct PwStream {
+    quit: pw::channel::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PwStream {
+    /// Records `sink`'s monitor (the default sink for None), handing
+    /// interleaved f32 frames to `on_data`.
+    pub fn capture<F>(sink: Option<String>, channels: usize, sample_rate: u32, on_data: F) -> Result<Self, String>
+    where
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        Self::start(sink, channels, sample_rate, Role::Capture(Box::new(on_data)))
+    }
+
+    /// Plays what `render` produces into `sink`.
+    pub fn playback<F>(sink: String, channels: usize, sample_rate: u32, render: F) -> Result<Self, String>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        Self::start(Some(sink), channels, sample_rate, Role::Playback(Box::new(render)))
+    }
+
+    fn start(target: Option<String>, channels: usize, sample_rate: u32, role: Role) -> Result<Self, String> {
+        let (quit, quit_rx) = pw::channel::channel::<()>();
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded(1);
+        let thread = thread::spawn(move || {
+            let _priority = priority::promote_current_thread("PipeWire", 0, sample_rate);
+            let result = run(target, channels, sample_rate, role, quit_rx, &ready_tx);
+            if let Err(e) = result {
+                eprintln!("PipeWire stream error: {}", e);
+                let _ = ready_tx.send(Err(e));
+            }
+        });
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { quit, thread: Some(thread) }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("PipeWire stream thread exited".to_string()),
+        }
+    }
+}
+
+impl Drop for PwStream {
+    fn drop(&mut self) {
+        let _ = self.quit.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Connects a stream and runs its main loop until told to quit.
+fn run(
+    target: Option<String>,
+    channels: usize,
+    sample_rate: u32,
+    role: Role,
+    quit: pw::channel::Receiver<()>,
+    ready: &crossbeam_channel::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    pw::init();
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|e| e.to_string())?;
+    let context = pw::context::Context::new(&mainloop).map_err(|e| e.to_string())?;
+    let core = context.connect(None).map_err(|e| e.to_string())?;
+    let quit_loop = mainloop.clone();
+    let _quit = quit.attach(mainloop.loop_(), move |_| quit_loop.quit());
+
+    let capture = matches!(role, Role::Capture(_));
+    let mut props = pw::properties::properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_CATEGORY => if capture { "Capture" } else { "Playback" },
+        *pw::keys::MEDIA_ROLE => "Music",
+        *pw::keys::APP_NAME => APP_NAME,
+    };
+    if capture {
+        props.insert(*pw::keys::STREAM_CAPTURE_SINK, "true");
+    }
+    if let Some(target) = &target {
+        props.insert(*pw::keys::TARGET_OBJECT, target.as_str());
+    }
+    let stream = pw::stream::Stream::new(&core, APP_NAME, props).map_err(|e| e.to_string())?;
+
+    let stride = channels * std::mem::size_of::<f32>();
+    let mut samples: Vec<f32> = Vec::new();
+    let _listener = stream
+        .add_local_listener_with_user_data(role)
+        .process(move |stream, role| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let datas = buffer.datas_mut();
+            let Some(data) = datas.first_mut() else {
+                return;
+            };
+            match role {
+                Role::Capture(on_data) => {
+                    let size = data.chunk().size() as usize;
+                    let Some(bytes) = data.data() else {
+                        return;
+                    };
+                    samples.clear();
+                    samples.extend(
+                        bytes[..size.min(bytes.len())]
+                            .chunks_exact(4)
+                            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    );
+                    on_data(&samples);
+                },
+                Role::Playback(render) => {
+                    let Some(bytes) = data.data() else {
+                        return;
+                    };
+                    let frames = bytes.len() / stride;
+                    samples.resize(frames * channels, 0.0);
+                    render(&mut samples);
+                    for (out, sample) in bytes.chunks_exact_mut(4).zip(&samples) {
+                        out.copy_from_slice(&sample.to_le_bytes());
+                    }
+                    let chunk = data.chunk_mut();
+                    *chunk.offset_mut() = 0;
+                    *chunk.stride_mut() = stride as i32;
+                    *chunk.size_mut() = (frames * stride) as u32;
+                },
+            }
+        })
+        .register()
+        .map_err(|e| e.to_string())?;
+
+    let mut info = spa::param::audio::AudioInfoRaw::new();
+    info.set_format(spa::param::audio::AudioFormat::F32LE);
+    info.set_rate(sample_rate);
+    info.set_channels(channels as u32);
+    let format = spa::pod::Object {
+        type_: spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+        id: spa::param::ParamType::EnumFormat.as_raw(),
+        properties: info.into(),
+    };
+    let bytes = spa::pod::serialize::PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &spa::pod::Value::Object(format))
+        .map_err(|e| format!("{:?}", e))?
+        .0
+        .into_inner();
+    let mut params = [spa::pod::Pod::from_bytes(&bytes).ok_or("Invalid stream format")?];
+
+    let direction = if capture { spa::utils::Direction::Input } else { spa::utils::Direction::Output };
+    let flags = pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS | pw::stream::StreamFlags::RT_PROCESS;
+    stream.connect(direction, None, flags, &mut params).map_err(|e| e.to_string())?;
+    println!("PipeWire stream connected to {}", target.as_deref().unwrap_or("the default sink"));
+    let _ = ready.send(Ok(()));
+
+    mainloop.run();
+    let _ = stream.disconnect();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_names_round_trip() {
+        let name = url("alsa_output.pci-0000_00_1f.3.analog-stereo");
+        assert_eq!(name, "pipewire://alsa_output.pci-0000_00_1f.3.analog-stereo");
+        assert!(is_pipewire_url(&name));
+        assert_eq!(parse_url(&name), Some("alsa_output.pci-0000_00_1f.3.analog-stereo"));
+
+        assert_eq!(parse_url("pipewire://"), None);
+        assert_eq!(parse_url("Speakers"), None);
+        assert!(!is_pipewire_url("Speakers"));
+    }
+
+    #[test]
+    fn test_only_named_audio_sinks_are_listed() {
+        let props = |pairs: &'static [(&'static str, &'static str)]| {
+            move |key: &str| pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+        };
+
+        let sink = sink_from_props(props(&[
+            ("media.class", "Audio/Sink"),
+            ("node.name", "bluez_output.headset"),
+            ("node.description", "Headset"),
+        ]))
+        .unwrap();
+        assert_eq!(sink.name, "bluez_output.headset");
+        assert_eq!(sink.description, "Headset");
+
+        let bare = sink_from_props(props(&[("media.class", "Audio/Sink"), ("node.name", "null-sink")])).unwrap();
+        assert_eq!(bare.description, "null-sink");
+
+        assert!(sink_from_props(props(&[("media.class", "Audio/Source"), ("node.name", "mic")])).is_none());
+        assert!(sink_from_props(props(&[("media.class", "Audio/Sink")])).is_none());
+        assert!(sink_from_props(props(&[])).is_none());
+    }
+}
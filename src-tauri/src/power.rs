@@ -0,0 +1,75 @@
+// System sleep handling. Audio streams often die silently across a suspend,
+// so the engine rebuilds them once the machine is back. Wake-ups are spotted
+// by a thread that checks the clocks on a short interval: while the system
+// sleeps the thread doesn't run. On Windows the monotonic clock counts the
+// sleep, so its gap alone shows it; on Linux and macOS it stops, so the sleep
+// is the wall-clock time the monotonic clock never saw. Either way a thread
+// that was merely held up, or a wall clock set back, doesn't count.
+
+use crate::audio::AudioCommand;
+use crossbeam_channel::Sender;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// A gap this much longer than the interval means the system was asleep.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(10);
+/// Time for devices to come back before streams are reopened.
+const RESUME_SETTLE: Duration = Duration::from_secs(2);
+
+/// Whether `Instant` keeps counting while the system sleeps.
+const MONOTONIC_COUNTS_SLEEP: bool = cfg!(windows);
+
+/// Watches for wake-ups for the rest of the app's life.
+pub fn spawn_resume_watcher(tx: Sender<AudioCommand>) {
+    thread::spawn(move || {
+        let mut last = (Instant::now(), SystemTime::now());
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+            let awake = last.0.elapsed();
+            let wall = SystemTime::now().duration_since(last.1).ok();
+            if resumed(awake, wall, MONOTONIC_COUNTS_SLEEP) {
+                println!("System resumed from sleep, reopening audio streams");
+                thread::sleep(RESUME_SETTLE);
+                if tx.send(AudioCommand::RebuildStreams).is_err() {
+                    return;
+                }
+            }
+            last = (Instant::now(), SystemTime::now());
+        }
+    });
+}
+
+/// Whether the gaps between two checks, on the monotonic clock and on the
+/// wall clock (`None` if it went backwards), show the system was asleep.
+fn resumed(awake: Duration, wall: Option<Duration>, monotonic_counts_sleep: bool) -> bool {
+    if monotonic_counts_sleep {
+        awake > CHECK_INTERVAL + SLEEP_THRESHOLD
+    } else {
+        wall.is_some_and(|wall| wall.saturating_sub(awake) > SLEEP_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resumed() {
+        let minute = Duration::from_secs(60);
+        for counts_sleep in [false, true] {
+            // A normal check, and a wall clock set back
+            assert!(!resumed(CHECK_INTERVAL, Some(CHECK_INTERVAL), counts_sleep));
+            assert!(!resumed(CHECK_INTERVAL, None, counts_sleep));
+        }
+
+        // Linux and macOS: only the wall clock moves during sleep
+        assert!(resumed(CHECK_INTERVAL, Some(minute), false));
+        // A thread held up under load moves both clocks alike
+        assert!(!resumed(minute, Some(minute), false));
+
+        // Windows: the monotonic clock counts the sleep, whatever the wall clock says
+        assert!(resumed(minute, None, true));
+        assert!(!resumed(CHECK_INTERVAL, Some(minute), true));
+    }
+}
@@ -0,0 +1,31 @@
+// Real-time scheduling for the threads that run the audio graph.
+//
+// cpal's own callbacks already run at audio priority: WASAPI registers its
+// thread with MMCSS, Core Audio calls from its real-time I/O thread, and on
+// Linux the ALSA thread is promoted through cpal's `audio_thread_priority`
+// feature. The threads this app spawns itself to run per-period work (WASAPI
+// exclusive and application capture, PipeWire) are promoted here.
+//
+// The actor stays at normal priority: it loads plugins, opens files and waits
+// on other threads, and with rtkit a real-time thread that runs too long
+// without blocking gets the whole process killed (RLIMIT_RTTIME).
+
+use audio_thread_priority::{promote_current_thread_to_real_time, RtPriorityHandle};
+
+/// Promotes the calling thread to real-time priority for the rest of its
+/// life. `period_frames` at `sample_rate` is how often it has work to do;
+/// 0 when the period isn't known picks a generous default.
+/// Without permission (e.g. no rtkit on Linux) the thread keeps its normal
+/// priority; the handle only needs to be held, not used.
+pub fn promote_current_thread(label: &str, period_frames: u32, sample_rate: u32) -> Option<RtPriorityHandle> {
+    match promote_current_thread_to_real_time(period_frames, sample_rate) {
+        Ok(handle) => {
+            println!("{} thread running at real-time priority", label);
+            Some(handle)
+        },
+        Err(e) => {
+            eprintln!("Could not raise {} thread priority: {}", label, e);
+            None
+        },
+    }
+}
@@ -0,0 +1,171 @@
+// Quiet hours: daily time windows during which a profile is applied or the
+// master is capped at a level (e.g. -20 dB after 22:00). Checked by a backend
+// thread, so they work with the window closed. When a window ends, the mix
+// from before it is restored; a cap is lifted without touching the faders.
+
+use crate::audio::AudioCommand;
+use crate::config::{self, Profile};
+use crate::AppState;
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuietAction {
+    /// Switch to a saved profile.
+    Profile { name: String },
+    /// Limit the master gain to this many dB.
+    VolumeCap { db: f32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuietWindow {
+    pub enabled: bool,
+    /// Local time; a window whose end is before its start runs past midnight.
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Days the window starts on; empty means every day.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub action: QuietAction,
+}
+
+impl QuietWindow {
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        let (time, today) = (now.time(), now.weekday());
+        if self.start == self.end {
+            on(today)
+        } else if self.start < self.end {
+            on(today) && self.start <= time && time < self.end
+        } else {
+            // Past midnight, the window belongs to the day it started on
+            (on(today) && time >= self.start) || (on(today.pred()) && time < self.end)
+        }
+    }
+}
+
+/// First enabled window covering `now`.
+fn active(windows: &[QuietWindow], now: NaiveDateTime) -> Option<&QuietWindow> {
+    windows.iter().find(|w| w.enabled && w.contains(now))
+}
+
+/// The configured windows, managed as app state and read by the watcher.
+#[derive(Default)]
+pub struct QuietHours {
+    windows: Mutex<Vec<QuietWindow>>,
+}
+
+impl QuietHours {
+    pub fn set(&self, windows: Vec<QuietWindow>) {
+        if let Ok(mut w) = self.windows.lock() {
+            *w = windows;
+        }
+    }
+
+    fn windows(&self) -> Vec<QuietWindow> {
+        self.windows.lock().map(|w| w.clone()).unwrap_or_default()
+    }
+}
+
+/// Applies and lifts the quiet-hours windows for the rest of the app's life.
+pub fn spawn_watcher(app: AppHandle) {
+    thread::spawn(move || {
+        let mut current: Option<QuietWindow> = None;
+        // The mix from before a profile window, put back when it ends
+        let mut restore: Option<Profile> = None;
+        loop {
+            let windows = app.state::<QuietHours>().windows();
+            let next = active(&windows, Local::now().naive_local()).cloned();
+            if next != current {
+                if let Some(window) = current.take() {
+                    let keep_mix = next.as_ref().is_some_and(|w| matches!(w.action, QuietAction::Profile { .. }));
+                    leave(&app, &window, &mut restore, keep_mix);
+                }
+                if let Some(window) = &next {
+                    enter(&app, window, &mut restore);
+                }
+                current = next;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+fn enter(app: &AppHandle, window: &QuietWindow, restore: &mut Option<Profile>) {
+    println!("Quiet hours started ({}-{})", window.start, window.end);
+    let state = app.state::<AppState>();
+    match &window.action {
+        QuietAction::VolumeCap { db } => {
+            let _ = state.tx.send(AudioCommand::SetVolumeCap(Some(*db)));
+        },
+        QuietAction::Profile { name } => {
+            let config = config::load_config(app);
+            let Some(profile) = config.profile(name).cloned() else {
+                eprintln!("Quiet hours: unknown profile {}", name);
+                return;
+            };
+            if restore.is_none() {
+                let previous = config.active_profile.clone().unwrap_or_default();
+                *restore = Some(Profile::from_config(&previous, &config));
+            }
+            if let Err(e) = crate::switch_to_profile(app, &state, &profile) {
+                eprintln!("Quiet hours: failed to apply profile {}: {}", name, e);
+            }
+        },
+    }
+}
+
+/// Undoes `window`. When another profile window follows straight away, the
+/// saved mix is kept for when that one ends.
+fn leave(app: &AppHandle, window: &QuietWindow, restore: &mut Option<Profile>, keep_mix: bool) {
+    println!("Quiet hours ended ({}-{})", window.start, window.end);
+    let state = app.state::<AppState>();
+    match &window.action {
+        QuietAction::VolumeCap { .. } => {
+            let _ = state.tx.send(AudioCommand::SetVolumeCap(None));
+        },
+        QuietAction::Profile { .. } if !keep_mix => {
+            if let Some(previous) = restore.take() {
+                if let Err(e) = crate::switch_to_profile(app, &state, &previous) {
+                    eprintln!("Quiet hours: failed to restore the mix: {}", e);
+                }
+            }
+        },
+        QuietAction::Profile { .. } => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-01-01 was a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window_past_midnight() {
+        let window = QuietWindow {
+            enabled: true,
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            days: vec![Weekday::Fri],
+            action: QuietAction::VolumeCap { db: -20.0 },
+        };
+        assert!(!window.contains(at(5, 21, 59)));
+        assert!(window.contains(at(5, 22, 0)));
+        assert!(window.contains(at(6, 6, 59)));
+        assert!(!window.contains(at(6, 7, 0)));
+        assert!(!window.contains(at(6, 23, 0)));
+        assert!(active(&[QuietWindow { enabled: false, ..window }], at(5, 23, 0)).is_none());
+    }
+}
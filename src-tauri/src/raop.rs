@@ -0,0 +1,473 @@
+// AirPlay (RAOP) output. Speakers found by discovery appear in the output list
+// as `airplay://Name` and join the output set like any device: an RTSP session
+// is set up with the receiver, then the mix is sent as uncompressed ALAC over
+// RTP at 44.1 kHz stereo. The output's volume is applied by the speaker rather
+// than in the mix. Only unencrypted sessions are supported, which rules out
+// receivers that insist on AirPlay 2 or RSA-encrypted audio.
+
+use crate::atomic_float::AtomicF32;
+use crate::discovery::NetworkDevice;
+use crate::dsp::LinearResampler;
+use crate::streaming;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const URL_PREFIX: &str = "airplay://";
+
+const SAMPLE_RATE: u32 = 44100;
+const CHANNELS: usize = 2;
+const FRAMES_PER_PACKET: usize = 352;
+
+/// Dynamic payload type announced for ALAC.
+const PAYLOAD_TYPE: u8 = 96;
+
+/// Playback delay announced in sync packets, the same 1.75 s iTunes uses.
+const LATENCY_FRAMES: u32 = 77175;
+
+/// Roughly one sync packet per second.
+const SYNC_INTERVAL_PACKETS: u32 = 125;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+const VOLUME_POLL: Duration = Duration::from_millis(250);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// RAOP volume range in dB; anything below the floor is sent as mute.
+const MIN_VOLUME_DB: f32 = -30.0;
+const MUTE_VOLUME_DB: f32 = -144.0;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Element tags from the ALAC bitstream.
+const ALAC_ID_CPE: u32 = 1;
+const ALAC_ID_END: u32 = 7;
+
+pub fn url(name: &str) -> String {
+    format!("{}{}", URL_PREFIX, name)
+}
+
+/// Speaker name from an `airplay://Name` output name.
+pub fn parse_url(name: &str) -> Option<&str> {
+    name.strip_prefix(URL_PREFIX).filter(|n| !n.is_empty())
+}
+
+pub fn is_airplay_url(name: &str) -> bool {
+    name.starts_with(URL_PREFIX)
+}
+
+/// Maps a linear output gain onto the RAOP volume scale.
+pub fn volume_db(gain: f32) -> f32 {
+    if gain <= 0.0 {
+        return MUTE_VOLUME_DB;
+    }
+    let db = 20.0 * gain.log10();
+    if db < MIN_VOLUME_DB {
+        MUTE_VOLUME_DB
+    } else {
+        db.min(0.0)
+    }
+}
+
+fn ntp_now() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let fraction = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    ((now.as_secs() + NTP_UNIX_OFFSET) << 32) | fraction
+}
+
+/// Packs bits most-significant first, as the ALAC bitstream expects.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            if self.bits % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+/// Encodes interleaved stereo as an uncompressed ("escape") ALAC frame, which
+/// every receiver's decoder accepts without needing a real encoder here.
+fn alac_frame(samples: &[f32]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.write(ALAC_ID_CPE, 3);
+    writer.write(0, 4); // element instance
+    writer.write(0, 12); // unused
+    writer.write(0, 1); // no explicit frame size
+    writer.write(0, 2); // no shift
+    writer.write(1, 1); // escape: samples follow verbatim
+    for &sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write(value as u16 as u32, 16);
+    }
+    writer.write(ALAC_ID_END, 3);
+    writer.bytes
+}
+
+fn audio_packet(first: bool, sequence: u16, timestamp: u32, ssrc: u32, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(12 + payload.len());
+    packet.push(0x80);
+    // Marker bit on the first packet of the stream
+    packet.push(if first { 0x80 | PAYLOAD_TYPE } else { PAYLOAD_TYPE });
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(&timestamp.to_be_bytes());
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Ties the RTP clock to wall time so the receiver knows when to play.
+fn sync_packet(first: bool, timestamp: u32, ntp: u64) -> [u8; 20] {
+    let mut packet = [0u8; 20];
+    packet[0] = if first { 0x90 } else { 0x80 };
+    packet[1] = 0xd4;
+    packet[3] = 0x07;
+    packet[4..8].copy_from_slice(&timestamp.wrapping_sub(LATENCY_FRAMES).to_be_bytes());
+    packet[8..16].copy_from_slice(&ntp.to_be_bytes());
+    packet[16..20].copy_from_slice(&timestamp.to_be_bytes());
+    packet
+}
+
+/// Answer to a receiver's timing request, echoing its send time.
+fn timing_reply(request: &[u8], received: u64) -> Option<[u8; 32]> {
+    if request.len() < 32 || request[1] & 0x7f != 0x52 {
+        return None;
+    }
+    let mut reply = [0u8; 32];
+    reply[0] = 0x80;
+    reply[1] = 0xd3;
+    reply[3] = 0x07;
+    reply[8..16].copy_from_slice(&request[24..32]);
+    reply[16..24].copy_from_slice(&received.to_be_bytes());
+    reply[24..32].copy_from_slice(&ntp_now().to_be_bytes());
+    Some(reply)
+}
+
+/// Value of `key=port` in an RTSP Transport header.
+fn transport_port(transport: &str, key: &str) -> Option<u16> {
+    transport
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .and_then(|(_, v)| v.trim().parse().ok())
+}
+
+fn announce_sdp(session_id: u32, local: IpAddr, remote: IpAddr) -> String {
+    let family = |ip: IpAddr| if ip.is_ipv4() { "IP4" } else { "IP6" };
+    format!(
+        "v=0\r\n\
+         o=AudioMerge {id} 0 IN {local_family} {local}\r\n\
+         s=AudioMerge\r\n\
+         c=IN {remote_family} {remote}\r\n\
+         t=0 0\r\n\
+         m=audio 0 RTP/AVP {pt}\r\n\
+         a=rtpmap:{pt} AppleLossless\r\n\
+         a=fmtp:{pt} {frames} 0 16 40 10 14 {channels} 255 0 0 {rate}\r\n",
+        id = session_id,
+        local_family = family(local),
+        local = local,
+        remote_family = family(remote),
+        remote = remote,
+        pt = PAYLOAD_TYPE,
+        frames = FRAMES_PER_PACKET,
+        channels = CHANNELS,
+        rate = SAMPLE_RATE,
+    )
+}
+
+/// Control connection to the receiver.
+struct RtspSession {
+    stream: TcpStream,
+    url: String,
+    cseq: u32,
+    client_instance: String,
+    session: Option<String>,
+}
+
+impl RtspSession {
+    /// Sends one request and returns the response headers, keyed in lower case.
+    fn request(
+        &mut self,
+        method: &str,
+        headers: &[(&str, String)],
+        body: Option<(&str, &[u8])>,
+    ) -> Result<HashMap<String, String>, String> {
+        self.cseq += 1;
+        let uri = if method == "OPTIONS" { "*" } else { self.url.as_str() };
+        let mut request = format!(
+            "{} {} RTSP/1.0\r\nCSeq: {}\r\nUser-Agent: AudioMerge/1.0\r\nClient-Instance: {}\r\n",
+            method, uri, self.cseq, self.client_instance
+        );
+        if let Some(session) = &self.session {
+            request.push_str(&format!("Session: {}\r\n", session));
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some((content_type, data)) = body {
+            request.push_str(&format!("Content-Type: {}\r\nContent-Length: {}\r\n", content_type, data.len()));
+        }
+        request.push_str("\r\n");
+
+        self.stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+        if let Some((_, data)) = body {
+            self.stream.write_all(data).map_err(|e| e.to_string())?;
+        }
+
+        let header = streaming::read_header(&mut self.stream)?;
+        let mut lines = header.lines();
+        let status_line = lines.next().unwrap_or_default().to_string();
+        let response: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        if let Some(length) = response.get("content-length").and_then(|l| l.parse::<u64>().ok()) {
+            let mut discard = (&mut self.stream).take(length);
+            let _ = std::io::copy(&mut discard, &mut std::io::sink());
+        }
+
+        match status_line.split_whitespace().nth(1) {
+            Some("200") => Ok(response),
+            _ => Err(format!("{} refused: {}", method, status_line)),
+        }
+    }
+
+    fn set_volume(&mut self, db: f32) -> Result<(), String> {
+        let body = format!("volume: {:.6}\r\n", db);
+        self.request("SET_PARAMETER", &[], Some(("text/parameters", body.as_bytes())))
+            .map(|_| ())
+    }
+}
+
+/// Where the receiver wants audio and control packets.
+struct ReceiverPorts {
+    audio: SocketAddr,
+    control: SocketAddr,
+}
+
+/// Sends whatever `render` produces to an AirPlay speaker, in real time.
+pub struct RaopSender {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl RaopSender {
+    /// `render` fills interleaved frames in the capture layout, just like an
+    /// output device callback; they are converted to 44.1 kHz stereo here.
+    /// `volume` is the output's linear gain, forwarded to the speaker.
+    pub fn start<F>(
+        device: NetworkDevice,
+        channels: usize,
+        sample_rate: u32,
+        volume: Arc<AtomicF32>,
+        mut render: F,
+    ) -> Result<Self, String>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        let props = &device.properties;
+        if props.get("et").is_some_and(|et| !et.split(',').any(|t| t.trim() == "0")) {
+            return Err(format!("{} requires an encrypted AirPlay session", device.name));
+        }
+        if props.get("cn").is_some_and(|cn| !cn.split(',').any(|c| c.trim() == "1")) {
+            return Err(format!("{} does not accept ALAC", device.name));
+        }
+
+        let addr = SocketAddr::new(device.address, device.port);
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| format!("{}: {}", device.name, e))?;
+        stream.set_read_timeout(Some(RESPONSE_TIMEOUT)).map_err(|e| e.to_string())?;
+        let local_ip = stream.local_addr().map_err(|e| e.to_string())?.ip();
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u32;
+        let session_id = seed ^ std::process::id();
+        let mut rtsp = RtspSession {
+            stream,
+            url: format!("rtsp://{}/{}", local_ip, session_id),
+            cseq: 0,
+            client_instance: format!("{:016X}", ((session_id as u64) << 16) | std::process::id() as u64),
+            session: None,
+        };
+
+        let unspecified = if local_ip.is_ipv4() {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        };
+        let audio_socket = UdpSocket::bind((unspecified, 0)).map_err(|e| e.to_string())?;
+        let control_socket = UdpSocket::bind((unspecified, 0)).map_err(|e| e.to_string())?;
+        let timing_socket = UdpSocket::bind((unspecified, 0)).map_err(|e| e.to_string())?;
+        timing_socket.set_read_timeout(Some(VOLUME_POLL)).map_err(|e| e.to_string())?;
+        let control_port = control_socket.local_addr().map_err(|e| e.to_string())?.port();
+        let timing_port = timing_socket.local_addr().map_err(|e| e.to_string())?.port();
+
+        rtsp.request("OPTIONS", &[], None)?;
+        let sdp = announce_sdp(session_id, local_ip, device.address);
+        rtsp.request("ANNOUNCE", &[], Some(("application/sdp", sdp.as_bytes())))?;
+        let transport = format!(
+            "RTP/AVP/UDP;unicast;interleaved=0-1;mode=record;control_port={};timing_port={}",
+            control_port, timing_port
+        );
+        let setup = rtsp.request("SETUP", &[("Transport", transport)], None)?;
+        let session = setup
+            .get("session")
+            .and_then(|s| s.split(';').next())
+            .ok_or_else(|| "SETUP response has no session".to_string())?;
+        rtsp.session = Some(session.to_string());
+        let reply = setup.get("transport").cloned().unwrap_or_default();
+        let ports = ReceiverPorts {
+            audio: SocketAddr::new(
+                device.address,
+                transport_port(&reply, "server_port").ok_or_else(|| "SETUP response has no server port".to_string())?,
+            ),
+            control: SocketAddr::new(device.address, transport_port(&reply, "control_port").unwrap_or(0)),
+        };
+
+        let ssrc = session_id.rotate_left(7);
+        let mut sequence: u16 = seed as u16;
+        let mut timestamp: u32 = seed.rotate_left(16);
+        rtsp.request(
+            "RECORD",
+            &[
+                ("Range", "npt=0-".to_string()),
+                ("RTP-Info", format!("seq={};rtptime={}", sequence, timestamp)),
+            ],
+            None,
+        )?;
+        let mut current_db = volume_db(volume.load());
+        rtsp.set_volume(current_db)?;
+        println!("AirPlay session with {} at {}", device.name, addr);
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let timing = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; 128];
+                while !stop.load(Ordering::Relaxed) {
+                    if let Ok((len, from)) = timing_socket.recv_from(&mut buf) {
+                        if let Some(reply) = timing_reply(&buf[..len], ntp_now()) {
+                            let _ = timing_socket.send_to(&reply, from);
+                        }
+                    }
+                }
+            })
+        };
+
+        let sender = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let packet_duration = Duration::from_secs_f64(FRAMES_PER_PACKET as f64 / SAMPLE_RATE as f64);
+                let mut resampler = LinearResampler::new(channels, sample_rate, SAMPLE_RATE);
+                let mut rendered = vec![0.0f32; FRAMES_PER_PACKET * channels];
+                let mut stereo = vec![0.0f32; FRAMES_PER_PACKET * CHANNELS];
+                let mut packets: u32 = 0;
+                let mut next = Instant::now();
+                let mut last_volume_check = Instant::now();
+                let mut last_keepalive = Instant::now();
+
+                while !stop.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if next > now {
+                        thread::sleep(next - now);
+                    }
+                    next += packet_duration;
+
+                    if packets % SYNC_INTERVAL_PACKETS == 0 && ports.control.port() != 0 {
+                        let sync = sync_packet(packets == 0, timestamp, ntp_now());
+                        let _ = control_socket.send_to(&sync, ports.control);
+                    }
+
+                    resampler.process(&mut rendered, &mut render);
+                    for (out, frame) in stereo.chunks_exact_mut(CHANNELS).zip(rendered.chunks_exact(channels)) {
+                        // Mono goes to both sides; channels beyond two are dropped
+                        out[0] = frame[0];
+                        out[1] = frame.get(1).copied().unwrap_or(frame[0]);
+                    }
+                    let packet = audio_packet(packets == 0, sequence, timestamp, ssrc, &alac_frame(&stereo));
+                    if let Err(e) = audio_socket.send_to(&packet, ports.audio) {
+                        eprintln!("AirPlay send error: {}", e);
+                    }
+                    sequence = sequence.wrapping_add(1);
+                    timestamp = timestamp.wrapping_add(FRAMES_PER_PACKET as u32);
+                    packets = packets.wrapping_add(1);
+
+                    if last_volume_check.elapsed() >= VOLUME_POLL {
+                        last_volume_check = Instant::now();
+                        let db = volume_db(volume.load());
+                        if db != current_db {
+                            match rtsp.set_volume(db) {
+                                Ok(()) => current_db = db,
+                                Err(e) => eprintln!("AirPlay volume error: {}", e),
+                            }
+                        }
+                    }
+                    if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                        last_keepalive = Instant::now();
+                        if let Err(e) = rtsp.request("OPTIONS", &[], None) {
+                            eprintln!("AirPlay keepalive failed: {}", e);
+                        }
+                    }
+                }
+                let _ = rtsp.request("TEARDOWN", &[], None);
+            })
+        };
+
+        Ok(Self { stop, threads: vec![sender, timing] })
+    }
+}
+
+impl Drop for RaopSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_mapping() {
+        assert_eq!(volume_db(1.0), 0.0);
+        assert!((volume_db(0.5) + 6.02).abs() < 0.01);
+        assert_eq!(volume_db(0.01), MUTE_VOLUME_DB);
+        assert_eq!(volume_db(0.0), MUTE_VOLUME_DB);
+        assert_eq!(volume_db(2.0), 0.0);
+    }
+
+    #[test]
+    fn test_uncompressed_alac_frame() {
+        let frame = alac_frame(&[1.0, -1.0]);
+        // 23 header bits, 32 sample bits and the 3-bit end tag
+        assert_eq!(frame.len(), 8);
+        assert_eq!(frame[0] >> 5, ALAC_ID_CPE as u8);
+        // Escape flag is the 23rd bit
+        assert_eq!(frame[2] & 0x02, 0x02);
+        // First sample starts at bit 23: 0x7fff
+        assert_eq!(frame[2] & 0x01, 0);
+        assert_eq!(frame[3], 0xff);
+
+        assert_eq!(transport_port("RTP/AVP/UDP;unicast;server_port=6000;control_port=6001", "server_port"), Some(6000));
+        assert_eq!(parse_url("airplay://Kitchen"), Some("Kitchen"));
+        assert_eq!(parse_url("airplay://"), None);
+    }
+}
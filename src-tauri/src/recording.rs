@@ -0,0 +1,648 @@
+// Recording of the processed capture mix to disk. The capture callback pushes
+// into a ring buffer and a writer thread drains it into the selected encoder,
+// so file I/O and encoding never run on the audio thread.
+
+use crate::dsp::{self, Dither, Ditherer};
+use crate::encoder::{Encoder, Mp3Encoder, OggOpusEncoder, OpusSettings};
+use crate::tap;
+use crossbeam_channel::bounded;
+use lofty::config::WriteOptions;
+use lofty::file::TaggedFileExt;
+use lofty::prelude::{Accessor, TagExt};
+use lofty::tag::{ItemKey, Tag};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Seconds of audio buffered between the capture callback and the writer.
+const RECORD_BUFFER_SECONDS: usize = 2;
+
+/// How often the writer thread drains the ring buffer.
+const WRITER_POLL_MS: u64 = 20;
+
+const MAX_FLAC_COMPRESSION: u32 = 8;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingFormat {
+    #[default]
+    Wav,
+    Flac,
+    Mp3,
+    Opus,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::Wav => "wav",
+            RecordingFormat::Flac => "flac",
+            RecordingFormat::Mp3 => "mp3",
+            RecordingFormat::Opus => "opus",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RecordingSettings {
+    pub format: RecordingFormat,
+    /// Target bitrate for the lossy formats (MP3, Opus).
+    pub bitrate_kbps: u32,
+    /// FLAC compression level, 0 (fastest) to 8 (smallest).
+    pub flac_compression: u32,
+    pub opus: OpusSettings,
+    /// Where recordings are written; defaults to the user's audio folder.
+    pub directory: Option<PathBuf>,
+    /// Also record each output's processed feed to its own file.
+    pub stems: bool,
+    pub silence_skip: SilenceSkipSettings,
+    /// Dither for the formats stored as integers (FLAC).
+    pub dither: Dither,
+}
+
+/// Drops stretches of silence from recordings. The first `hang_ms` of a quiet
+/// stretch is kept so phrases aren't clipped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct SilenceSkipSettings {
+    pub enabled: bool,
+    pub threshold_db: f32,
+    pub hang_ms: u32,
+}
+
+impl Default for SilenceSkipSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -50.0,
+            hang_ms: 2000,
+        }
+    }
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            format: RecordingFormat::default(),
+            bitrate_kbps: 192,
+            flac_compression: 5,
+            opus: OpusSettings::default(),
+            directory: None,
+            stems: false,
+            silence_skip: SilenceSkipSettings::default(),
+            dither: Dither::default(),
+        }
+    }
+}
+
+/// Rollover limits for a long recording; whichever is hit first closes the
+/// current file and continues in the next numbered part.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct SplitSettings {
+    pub max_minutes: Option<u32>,
+    pub max_megabytes: Option<u64>,
+}
+
+impl SplitSettings {
+    fn is_enabled(&self) -> bool {
+        self.max_minutes.is_some() || self.max_megabytes.is_some()
+    }
+
+    fn should_split(&self, frames: u64, sample_rate: u32, bytes: u64) -> bool {
+        let too_long = self
+            .max_minutes
+            .is_some_and(|m| frames >= m as u64 * 60 * sample_rate as u64);
+        let too_big = self.max_megabytes.is_some_and(|mb| bytes >= mb * 1024 * 1024);
+        too_long || too_big
+    }
+}
+
+/// Tagging choices sent with the start command.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct TagOptions {
+    /// Defaults to "Audio Merge <date time>".
+    pub title: Option<String>,
+    /// Record what the OS reports as playing when the recording starts.
+    pub now_playing: bool,
+}
+
+/// Tags written into every file of a recording once it is finalized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingMetadata {
+    pub title: String,
+    pub date: String,
+    pub devices: Vec<String>,
+    pub now_playing: Option<String>,
+}
+
+impl RecordingMetadata {
+    fn comment(&self) -> String {
+        let mut comment = format!("Outputs: {}", self.devices.join(", "));
+        if let Some(now_playing) = &self.now_playing {
+            comment.push_str(&format!("\nNow playing: {}", now_playing));
+        }
+        comment
+    }
+}
+
+/// Writes ID3 (MP3), Vorbis comment (FLAC, Opus) or RIFF INFO (WAV) tags.
+fn write_tags(path: &Path, metadata: &RecordingMetadata) -> Result<(), String> {
+    let mut file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    if file.primary_tag().is_none() {
+        file.insert_tag(Tag::new(file.primary_tag_type()));
+    }
+    let tag = file.primary_tag_mut().ok_or_else(|| "File has no tag".to_string())?;
+    tag.set_title(metadata.title.clone());
+    tag.set_comment(metadata.comment());
+    tag.insert_text(ItemKey::RecordingDate, metadata.date.clone());
+    tag.insert_text(ItemKey::EncoderSoftware, concat!("Audio Merge ", env!("CARGO_PKG_VERSION")).to_string());
+    tag.save_to_path(path, WriteOptions::default()).map_err(|e| e.to_string())
+}
+
+/// Sequentially numbered part of a split recording, e.g. `recording_<stamp>_002.flac`.
+pub fn part_path(base: &Path, part: u32) -> PathBuf {
+    let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let file_name = match base.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_{:03}.{}", stem, part, ext),
+        None => format!("{}_{:03}", stem, part),
+    };
+    base.with_file_name(file_name)
+}
+
+/// Longest replay buffer allowed; the whole window is kept in memory.
+pub const MAX_REPLAY_SECONDS: u32 = 300;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ReplaySettings {
+    pub enabled: bool,
+    /// How much of the mix "save replay" writes out.
+    pub seconds: u32,
+    /// Global shortcut that saves the replay, e.g. "CmdOrCtrl+Shift+R".
+    pub shortcut: Option<String>,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seconds: 30,
+            shortcut: None,
+        }
+    }
+}
+
+/// Builds a timestamped file name for a new recording in `directory`.
+pub fn recording_path(directory: &Path, format: RecordingFormat) -> PathBuf {
+    timestamped_path(directory, "recording", format)
+}
+
+/// Builds a timestamped file name for a saved replay in `directory`.
+pub fn replay_path(directory: &Path, format: RecordingFormat) -> PathBuf {
+    timestamped_path(directory, "replay", format)
+}
+
+fn timestamped_path(directory: &Path, prefix: &str, format: RecordingFormat) -> PathBuf {
+    let stamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    directory.join(format!("{}_{}.{}", prefix, stamp, format.extension()))
+}
+
+/// Path of an output's stem next to the mix recording, e.g.
+/// `recording_<stamp>_Speakers.wav`.
+pub fn stem_path(mix_path: &Path, device_name: &str) -> PathBuf {
+    let stem = mix_path.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let device: String = device_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let file_name = match mix_path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_{}.{}", stem, device, ext),
+        None => format!("{}_{}", stem, device),
+    };
+    mix_path.with_file_name(file_name)
+}
+
+fn create_encoder(
+    path: &Path,
+    settings: &RecordingSettings,
+    channels: usize,
+    sample_rate: u32,
+) -> Result<Box<dyn Encoder>, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    match settings.format {
+        RecordingFormat::Wav => Ok(Box::new(WavEncoder::new(path, channels, sample_rate)?)),
+        RecordingFormat::Flac => Ok(Box::new(FlacEncoder::new(path, channels, sample_rate, settings.flac_compression, settings.dither)?)),
+        RecordingFormat::Mp3 => Ok(Box::new(Mp3Encoder::new(create_file(path)?, channels, sample_rate, settings.bitrate_kbps)?)),
+        RecordingFormat::Opus => Ok(Box::new(OggOpusEncoder::new(
+            create_file(path)?,
+            channels,
+            sample_rate,
+            settings.bitrate_kbps,
+            &settings.opus,
+        )?)),
+    }
+}
+
+/// A running recording. Dropping it stops the writer thread and finalizes the file.
+pub struct Recorder {
+    base: PathBuf,
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<PathBuf, String>>>,
+}
+
+impl Recorder {
+    /// Opens the output file and starts the writer thread. The returned producer
+    /// is fed from the capture callback. With splitting enabled, `path` is the
+    /// base name and the parts are numbered from 001.
+    pub fn start(
+        path: PathBuf,
+        settings: &RecordingSettings,
+        split: SplitSettings,
+        metadata: RecordingMetadata,
+        channels: usize,
+        sample_rate: u32,
+    ) -> Result<(Self, Producer<f32>), String> {
+        let capacity = sample_rate as usize * channels * RECORD_BUFFER_SECONDS;
+        let (producer, consumer) = RingBuffer::<f32>::new(capacity);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let (ready_tx, ready_rx) = bounded(1);
+        let base = path;
+        let path = if split.is_enabled() { part_path(&base, 1) } else { base.clone() };
+        let first = path.clone();
+        let settings = settings.clone();
+
+        let thread = thread::spawn(move || {
+            let encoder = match create_encoder(&path, &settings, channels, sample_rate) {
+                Ok(encoder) => encoder,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.clone()));
+                    return Err(e);
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+            let writer = Writer { settings, split, metadata, channels, sample_rate, path };
+            writer.run(consumer, encoder, &stop_flag)
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok((Self { base, path: first, stop, thread: Some(thread) }, producer)),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Recording thread exited".to_string()),
+        }
+    }
+
+    /// Path of the first file of the recording.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Name the recording was started with, before any part numbering.
+    pub fn base_path(&self) -> &Path {
+        &self.base
+    }
+
+    /// Stops the writer and finalizes the file. Returns the last file written.
+    pub fn finish(mut self) -> Result<PathBuf, String> {
+        self.join()?;
+        Ok(self.path.clone())
+    }
+
+    fn join(&mut self) -> Result<(), String> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            self.path = thread.join().map_err(|_| "Recording thread panicked".to_string())??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("Recording error: {}", e);
+        }
+    }
+}
+
+/// Rolling in-memory history of the mix. A drain thread moves samples from the
+/// capture tap into the history and trims it to the configured window.
+pub struct ReplayBuffer {
+    history: Arc<Mutex<VecDeque<f32>>>,
+    channels: usize,
+    sample_rate: u32,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ReplayBuffer {
+    pub fn start(seconds: u32, channels: usize, sample_rate: u32) -> (Self, Producer<f32>) {
+        let seconds = seconds.clamp(1, MAX_REPLAY_SECONDS) as usize;
+        let capacity = seconds * sample_rate as usize * channels;
+        let (producer, mut consumer) = RingBuffer::<f32>::new(sample_rate as usize * channels * RECORD_BUFFER_SECONDS);
+
+        let history = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_history = history.clone();
+        let stop_flag = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let mut buf = Vec::new();
+            while !stop_flag.load(Ordering::Relaxed) {
+                tap::drain_frames(&mut consumer, channels, &mut buf);
+                if let Ok(mut history) = thread_history.lock() {
+                    history.extend(buf.iter());
+                    // Capacity is a whole number of frames, so trimming keeps channels aligned
+                    let excess = history.len().saturating_sub(capacity);
+                    history.drain(..excess);
+                }
+                thread::sleep(Duration::from_millis(WRITER_POLL_MS));
+            }
+        });
+
+        (Self { history, channels, sample_rate, stop, thread: Some(thread) }, producer)
+    }
+
+    /// Writes the buffered window to `path` on a background thread and calls
+    /// `on_done` with the result.
+    pub fn save<F>(&self, path: PathBuf, settings: &RecordingSettings, on_done: F)
+    where
+        F: FnOnce(Result<PathBuf, String>) + Send + 'static,
+    {
+        let samples: Vec<f32> = match self.history.lock() {
+            Ok(history) => history.iter().copied().collect(),
+            Err(_) => Vec::new(),
+        };
+        let settings = settings.clone();
+        let (channels, sample_rate) = (self.channels, self.sample_rate);
+
+        thread::spawn(move || {
+            let result = create_encoder(&path, &settings, channels, sample_rate)
+                .and_then(|mut encoder| {
+                    encoder.write(&samples)?;
+                    encoder.finish()
+                })
+                .map(|_| path);
+            on_done(result);
+        });
+    }
+}
+
+impl Drop for ReplayBuffer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Frame filter implementing `SilenceSkipSettings`.
+struct SilenceSkipper {
+    threshold: f32,
+    hang_frames: u64,
+    silent_frames: u64,
+}
+
+impl SilenceSkipper {
+    fn new(settings: SilenceSkipSettings, sample_rate: u32) -> Self {
+        Self {
+            threshold: dsp::db_to_gain(settings.threshold_db),
+            hang_frames: settings.hang_ms as u64 * sample_rate as u64 / 1000,
+            silent_frames: 0,
+        }
+    }
+
+    /// Appends the frames of `input` worth keeping to `out`.
+    fn filter(&mut self, input: &[f32], channels: usize, out: &mut Vec<f32>) {
+        for frame in input.chunks(channels) {
+            if dsp::frame_peak(frame) >= self.threshold {
+                self.silent_frames = 0;
+            } else {
+                self.silent_frames += 1;
+            }
+            if self.silent_frames <= self.hang_frames {
+                out.extend_from_slice(frame);
+            }
+        }
+    }
+}
+
+/// State of the writer thread of one recording.
+struct Writer {
+    settings: RecordingSettings,
+    split: SplitSettings,
+    metadata: RecordingMetadata,
+    channels: usize,
+    sample_rate: u32,
+    path: PathBuf,
+}
+
+impl Writer {
+    /// Drains the tap into the encoder until stopped, rolling over to a new
+    /// part when a split limit is hit. Returns the last file written.
+    fn run(
+        mut self,
+        mut consumer: Consumer<f32>,
+        mut encoder: Box<dyn Encoder>,
+        stop: &AtomicBool,
+    ) -> Result<PathBuf, String> {
+        let mut buf = Vec::new();
+        let mut kept = Vec::new();
+        let mut skipper = self
+            .settings
+            .silence_skip
+            .enabled
+            .then(|| SilenceSkipper::new(self.settings.silence_skip, self.sample_rate));
+        let mut part = 1;
+        let mut frames: u64 = 0;
+        loop {
+            // Read the stop flag first so the final drain sees everything pushed before it
+            let stopping = stop.load(Ordering::Relaxed);
+
+            tap::drain_frames(&mut consumer, self.channels, &mut buf);
+            let samples = match skipper.as_mut() {
+                Some(skipper) => {
+                    kept.clear();
+                    skipper.filter(&buf, self.channels, &mut kept);
+                    &kept
+                },
+                None => &buf,
+            };
+            if !samples.is_empty() {
+                encoder.write(samples)?;
+                frames += (samples.len() / self.channels) as u64;
+            }
+
+            if stopping {
+                encoder.finish()?;
+                self.tag_current();
+                return Ok(self.path);
+            }
+
+            let bytes = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+            if self.split.should_split(frames, self.sample_rate, bytes) {
+                encoder.finish()?;
+                self.tag_current();
+                part += 1;
+                self.path = part_path(&strip_part(&self.path), part);
+                encoder = create_encoder(&self.path, &self.settings, self.channels, self.sample_rate)?;
+                frames = 0;
+                println!("Recording continues in {}", self.path.display());
+            }
+
+            thread::sleep(Duration::from_millis(WRITER_POLL_MS));
+        }
+    }
+
+    /// Tags the file just finalized; a tagging failure leaves the audio intact.
+    fn tag_current(&self) {
+        if let Err(e) = write_tags(&self.path, &self.metadata) {
+            eprintln!("Failed to tag {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Inverse of `part_path` for the first part: `name_001.ext` -> `name.ext`.
+fn strip_part(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let base = stem.rsplit_once('_').map(|(base, _)| base).unwrap_or(stem);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => path.with_file_name(format!("{}.{}", base, ext)),
+        None => path.with_file_name(base),
+    }
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path).map(BufWriter::new).map_err(|e| e.to_string())
+}
+
+/// 32-bit float WAV, written as-is.
+struct WavEncoder {
+    writer: hound::WavWriter<BufWriter<File>>,
+}
+
+impl WavEncoder {
+    fn new(path: &Path, channels: usize, sample_rate: u32) -> Result<Self, String> {
+        let spec = hound::WavSpec {
+            channels: channels as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+        Ok(Self { writer })
+    }
+}
+
+impl Encoder for WavEncoder {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        for &sample in samples {
+            self.writer.write_sample(sample).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.writer.finalize().map_err(|e| e.to_string())
+    }
+}
+
+/// Lossless 24-bit FLAC through libFLAC.
+struct FlacEncoder {
+    encoder: flac_bound::FlacEncoder<'static>,
+    channels: usize,
+    ditherer: Ditherer,
+    dithered: Vec<f32>,
+    buf: Vec<i32>,
+}
+
+const FLAC_SCALE: f32 = 8_388_607.0; // 2^23 - 1
+
+impl FlacEncoder {
+    fn new(path: &Path, channels: usize, sample_rate: u32, compression: u32, dither: Dither) -> Result<Self, String> {
+        let encoder = flac_bound::FlacEncoder::new()
+            .ok_or_else(|| "Failed to create FLAC encoder".to_string())?
+            .channels(channels as u32)
+            .bits_per_sample(24)
+            .sample_rate(sample_rate)
+            .compression_level(compression.min(MAX_FLAC_COMPRESSION))
+            .init_file(&path)
+            .map_err(|e| format!("Failed to open FLAC file: {:?}", e))?;
+        Ok(Self {
+            encoder,
+            channels,
+            ditherer: Ditherer::new(dither, FLAC_SCALE, channels),
+            dithered: Vec::new(),
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl Encoder for FlacEncoder {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.dithered.clear();
+        self.dithered.extend(samples.iter().map(|&s| s.clamp(-1.0, 1.0)));
+        self.ditherer.process(&mut self.dithered);
+        self.buf.clear();
+        self.buf.extend(self.dithered.iter().map(|&s| (s * FLAC_SCALE).round().clamp(-FLAC_SCALE, FLAC_SCALE) as i32));
+        let frames = (samples.len() / self.channels) as u32;
+        self.encoder
+            .process_interleaved(&self.buf, frames)
+            .map_err(|_| "FLAC encoding failed".to_string())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.encoder.finish().map(|_| ()).map_err(|_| "Failed to finalize FLAC file".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_parts_are_numbered() {
+        let base = Path::new("/rec/recording_2024-01-01_10-00-00.wav");
+        let second = part_path(base, 2);
+        assert_eq!(second, Path::new("/rec/recording_2024-01-01_10-00-00_002.wav"));
+        assert_eq!(part_path(&strip_part(&second), 3), Path::new("/rec/recording_2024-01-01_10-00-00_003.wav"));
+
+        let split = SplitSettings { max_minutes: Some(1), max_megabytes: None };
+        assert!(!split.should_split(59 * 1000, 1000, 0));
+        assert!(split.should_split(60 * 1000, 1000, 0));
+    }
+
+    #[test]
+    fn test_silence_skipper_keeps_hang_then_drops() {
+        let settings = SilenceSkipSettings { enabled: true, threshold_db: -20.0, hang_ms: 2 };
+        // 1000 Hz: 2 frames of hang
+        let mut skipper = SilenceSkipper::new(settings, 1000);
+        let mut out = Vec::new();
+        skipper.filter(&[0.5, 0.0, 0.0, 0.0, 0.0, 0.5], 1, &mut out);
+        assert_eq!(out, vec![0.5, 0.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_stem_path_sits_next_to_mix() {
+        let mix = Path::new("/rec/recording_2024-01-01_10-00-00.flac");
+        assert_eq!(
+            stem_path(mix, "Speakers (Realtek Audio)"),
+            Path::new("/rec/recording_2024-01-01_10-00-00_Speakers__Realtek_Audio_.flac")
+        );
+    }
+}
@@ -0,0 +1,130 @@
+// Automatic recovery of streams that report errors. Error callbacks send a
+// `StreamFailure` to the actor, which rebuilds the stream after a backoff
+// that doubles with each failed attempt, and gives up after `MAX_ATTEMPTS`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Stream name of the capture in failures and events.
+pub const CAPTURE_STREAM: &str = "capture";
+
+/// Rebuilds tried before giving up on a stream.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+const FIRST_RETRY: Duration = Duration::from_millis(500);
+
+/// A stream that has run this long since its rebuild starts over with a
+/// fresh set of attempts.
+const HEALTHY_AFTER: Duration = Duration::from_secs(30);
+
+/// An error reported from a stream's error callback.
+#[derive(Debug, Clone)]
+pub struct StreamFailure {
+    /// Output name, or `CAPTURE_STREAM`.
+    pub stream: String,
+    pub error: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    attempts: u32,
+    retry_at: Option<Instant>,
+    rebuilt_at: Option<Instant>,
+    error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct StreamRecovery {
+    streams: HashMap<String, Entry>,
+}
+
+fn backoff(attempts: u32) -> Duration {
+    FIRST_RETRY * 2u32.pow(attempts.min(MAX_ATTEMPTS))
+}
+
+impl StreamRecovery {
+    /// Schedules a rebuild of a failed stream, unless one is already pending
+    /// or the stream has been given up on.
+    pub fn failed(&mut self, failure: StreamFailure, now: Instant) {
+        let entry = self.streams.entry(failure.stream).or_insert(Entry {
+            attempts: 0,
+            retry_at: None,
+            rebuilt_at: None,
+            error: String::new(),
+        });
+        entry.error = failure.error;
+        if entry.retry_at.is_some() || entry.attempts >= MAX_ATTEMPTS {
+            return;
+        }
+        if entry.rebuilt_at.is_some_and(|t| now.duration_since(t) >= HEALTHY_AFTER) {
+            entry.attempts = 0;
+        }
+        entry.retry_at = Some(now + backoff(entry.attempts));
+    }
+
+    /// Streams whose rebuild is due, with the attempt number.
+    pub fn due(&mut self, now: Instant) -> Vec<(String, u32)> {
+        let mut due = Vec::new();
+        for (name, entry) in &mut self.streams {
+            if entry.retry_at.is_some_and(|t| t <= now) {
+                entry.retry_at = None;
+                entry.attempts += 1;
+                due.push((name.clone(), entry.attempts));
+            }
+        }
+        due
+    }
+
+    /// Records the outcome of a rebuild. Returns the last error once the
+    /// stream has run out of attempts.
+    pub fn rebuilt(&mut self, stream: &str, ok: bool, now: Instant) -> Option<String> {
+        let entry = self.streams.get_mut(stream)?;
+        if ok {
+            entry.rebuilt_at = Some(now);
+            return None;
+        }
+        if entry.attempts >= MAX_ATTEMPTS {
+            return Some(entry.error.clone());
+        }
+        entry.retry_at = Some(now + backoff(entry.attempts));
+        None
+    }
+
+    pub fn is_recovering(&self, stream: &str) -> bool {
+        self.streams.get(stream).is_some_and(|e| e.retry_at.is_some())
+    }
+
+    /// Stops tracking a stream, e.g. when the user closes it.
+    pub fn forget(&mut self, stream: &str) {
+        self.streams.remove(stream);
+    }
+
+    /// When the next rebuild is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.streams.values().filter_map(|e| e.retry_at).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backs_off_then_gives_up() {
+        let mut recovery = StreamRecovery::default();
+        let failure = StreamFailure { stream: "Speakers".to_string(), error: "device lost".to_string() };
+        let mut now = Instant::now();
+        recovery.failed(failure.clone(), now);
+        assert!(recovery.due(now).is_empty());
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            now = recovery.next_due().unwrap();
+            assert_eq!(recovery.due(now), [("Speakers".to_string(), attempt)]);
+            let gave_up = recovery.rebuilt("Speakers", false, now);
+            assert_eq!(gave_up.is_some(), attempt == MAX_ATTEMPTS);
+        }
+        assert_eq!(recovery.next_due(), None);
+        recovery.failed(failure, now);
+        assert_eq!(recovery.next_due(), None);
+    }
+}
@@ -0,0 +1,130 @@
+// Routing matrix: how much of each source reaches each output. Every route
+// defaults to unity, so only the routes the user changed are stored. The
+// recording and the network streams take the main mix, routed under the name
+// `MIX_OUTPUT`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Route name of the main mix (recordings and network streams).
+pub const MIX_OUTPUT: &str = "mix";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteSource {
+    /// The captured system, device or application audio.
+    Capture,
+    Mic,
+    /// Audio received from the network (VBAN).
+    Network,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Route {
+    pub source: RouteSource,
+    /// Output device name, or `MIX_OUTPUT`.
+    pub output: String,
+    /// Linear send level, 0.0 to 1.0.
+    pub gain: f32,
+}
+
+/// Send levels of the three sources into one output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RouteGains {
+    pub capture: f32,
+    pub mic: f32,
+    pub network: f32,
+}
+
+impl Default for RouteGains {
+    fn default() -> Self {
+        Self { capture: 1.0, mic: 1.0, network: 1.0 }
+    }
+}
+
+impl RouteGains {
+    fn get_mut(&mut self, source: RouteSource) -> &mut f32 {
+        match source {
+            RouteSource::Capture => &mut self.capture,
+            RouteSource::Mic => &mut self.mic,
+            RouteSource::Network => &mut self.network,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RoutingMatrix {
+    // Keyed by output so the audio callback can look up without allocating
+    outputs: HashMap<String, RouteGains>,
+}
+
+impl RoutingMatrix {
+    pub fn from_routes(routes: &[Route]) -> Self {
+        let mut matrix = Self::default();
+        for route in routes {
+            matrix.set(route.source, &route.output, route.gain);
+        }
+        matrix
+    }
+
+    pub fn set(&mut self, source: RouteSource, output: &str, gain: f32) {
+        let gains = self.outputs.entry(output.to_string()).or_default();
+        *gains.get_mut(source) = gain.clamp(0.0, 1.0);
+        if *gains == RouteGains::default() {
+            self.outputs.remove(output);
+        }
+    }
+
+    pub fn gains(&self, output: &str) -> RouteGains {
+        self.outputs.get(output).copied().unwrap_or_default()
+    }
+}
+
+/// Stores a route in a persisted list, dropping it when it is back at unity.
+pub fn upsert(routes: &mut Vec<Route>, source: RouteSource, output: &str, gain: f32) {
+    routes.retain(|r| !(r.source == source && r.output == output));
+    let gain = gain.clamp(0.0, 1.0);
+    if gain != 1.0 {
+        routes.push(Route { source, output: output.to_string(), gain });
+    }
+}
+
+/// Mixes the per-source stems into `out` at `gains`, then applies the
+/// per-frame master gain.
+pub fn mix_into(
+    gains: RouteGains,
+    stems: [&[f32]; 3],
+    master: &[f32],
+    channels: usize,
+    out: &mut Vec<f32>,
+) {
+    let [capture, mic, network] = stems;
+    out.clear();
+    out.extend(capture.iter().zip(mic).zip(network).enumerate().map(|(i, ((&c, &m), &n))| {
+        (c * gains.capture + m * gains.mic + n * gains.network) * master[i / channels]
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mic_routed_to_one_output() {
+        let mut routes = Vec::new();
+        upsert(&mut routes, RouteSource::Mic, "Speakers", 0.0);
+        upsert(&mut routes, RouteSource::Mic, "Speakers", 0.0);
+        assert_eq!(routes.len(), 1);
+        let matrix = RoutingMatrix::from_routes(&routes);
+
+        let stems: [&[f32]; 3] = [&[0.5, 0.5], &[0.25, 0.25], &[0.0, 0.0]];
+        let mut out = Vec::new();
+        mix_into(matrix.gains("Speakers"), stems, &[1.0], 2, &mut out);
+        assert_eq!(out, [0.5, 0.5]);
+        mix_into(matrix.gains(MIX_OUTPUT), stems, &[0.5], 2, &mut out);
+        assert_eq!(out, [0.375, 0.375]);
+
+        upsert(&mut routes, RouteSource::Mic, "Speakers", 1.0);
+        assert!(routes.is_empty());
+    }
+}
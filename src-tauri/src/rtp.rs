@@ -0,0 +1,249 @@
+// RTP output of the mix as uncompressed L16/L24 (RFC 3551 / RFC 3190), unicast
+// or multicast, with the SDP a receiver needs. Packet times and formats follow
+// AES67 so the stream can be picked up by AES67 and Dante (AES67 mode) gear.
+// There is no PTP clock here; the sender is paced by the system clock.
+
+use crate::streaming::stream_tap;
+use crate::tap;
+use rtrb::{Consumer, Producer};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const RTP_HEADER_SIZE: usize = 12;
+
+/// Keeps packets under a typical Ethernet MTU.
+const MAX_PAYLOAD_BYTES: usize = 1440;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum RtpFormat {
+    L16,
+    #[default]
+    L24,
+}
+
+impl RtpFormat {
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            RtpFormat::L16 => 2,
+            RtpFormat::L24 => 3,
+        }
+    }
+
+    fn encoding_name(&self) -> &'static str {
+        match self {
+            RtpFormat::L16 => "L16",
+            RtpFormat::L24 => "L24",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RtpSettings {
+    pub enabled: bool,
+    /// Unicast or multicast IPv4 address.
+    pub destination: String,
+    pub port: u16,
+    pub format: RtpFormat,
+    /// Packet time in microseconds; AES67 receivers must support 1000.
+    pub packet_time_us: u32,
+    pub payload_type: u8,
+    /// Multicast hop limit.
+    pub ttl: u32,
+    pub session_name: String,
+}
+
+impl Default for RtpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            destination: "239.69.0.1".to_string(),
+            port: 5004,
+            format: RtpFormat::default(),
+            packet_time_us: 1000,
+            payload_type: 97,
+            ttl: 15,
+            session_name: "Audio Merge".to_string(),
+        }
+    }
+}
+
+/// Session description for receivers of the stream.
+pub fn sdp(settings: &RtpSettings, source: IpAddr, session_id: u64, channels: usize, sample_rate: u32) -> String {
+    let destination = settings.destination.parse::<Ipv4Addr>().ok();
+    let connection = match destination {
+        Some(ip) if ip.is_multicast() => format!("{}/{}", ip, settings.ttl),
+        _ => settings.destination.clone(),
+    };
+    let ptime_ms = settings.packet_time_us as f64 / 1000.0;
+    format!(
+        "v=0\r\n\
+         o=- {id} {id} IN IP4 {source}\r\n\
+         s={name}\r\n\
+         c=IN IP4 {connection}\r\n\
+         t=0 0\r\n\
+         m=audio {port} RTP/AVP {pt}\r\n\
+         a=rtpmap:{pt} {encoding}/{rate}/{channels}\r\n\
+         a=ptime:{ptime}\r\n\
+         a=mediaclk:direct=0\r\n\
+         a=recvonly\r\n",
+        id = session_id,
+        source = source,
+        name = settings.session_name,
+        connection = connection,
+        port = settings.port,
+        pt = settings.payload_type,
+        encoding = settings.format.encoding_name(),
+        rate = sample_rate,
+        channels = channels,
+        ptime = ptime_ms,
+    )
+}
+
+fn write_header(packet: &mut Vec<u8>, payload_type: u8, sequence: u16, timestamp: u32, ssrc: u32) {
+    packet.push(0x80); // version 2, no padding, extension or CSRCs
+    packet.push(payload_type & 0x7f);
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(&timestamp.to_be_bytes());
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+}
+
+/// Appends samples as big-endian linear PCM.
+fn write_samples(packet: &mut Vec<u8>, samples: &[f32], format: RtpFormat) {
+    for &sample in samples {
+        let clamped = sample.clamp(-1.0, 1.0);
+        match format {
+            RtpFormat::L16 => packet.extend_from_slice(&((clamped * i16::MAX as f32) as i16).to_be_bytes()),
+            RtpFormat::L24 => {
+                let value = (clamped * 8_388_607.0) as i32;
+                packet.extend_from_slice(&value.to_be_bytes()[1..]);
+            },
+        }
+    }
+}
+
+pub struct RtpSender {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    sdp: String,
+}
+
+impl RtpSender {
+    pub fn start(settings: RtpSettings, channels: usize, sample_rate: u32) -> Result<(Self, Producer<f32>), String> {
+        let ip: Ipv4Addr = settings
+            .destination
+            .parse()
+            .map_err(|_| format!("Invalid RTP destination: {}", settings.destination))?;
+        let frames = (sample_rate as u64 * settings.packet_time_us as u64 / 1_000_000) as usize;
+        if frames == 0 {
+            return Err(format!("Packet time {} us is too short", settings.packet_time_us));
+        }
+        if frames * channels * settings.format.bytes_per_sample() > MAX_PAYLOAD_BYTES {
+            return Err("Packet time too long for the channel count".to_string());
+        }
+
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+        if ip.is_multicast() {
+            socket.set_multicast_ttl_v4(settings.ttl).map_err(|e| e.to_string())?;
+        }
+        let addr = SocketAddr::from((ip, settings.port));
+        socket.connect(addr).map_err(|e| e.to_string())?;
+        let source = socket.local_addr().map_err(|e| e.to_string())?.ip();
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let ssrc = (now.as_nanos() as u32) ^ std::process::id();
+        let sdp = sdp(&settings, source, now.as_secs(), channels, sample_rate);
+        println!("RTP {} stream to {}", settings.format.encoding_name(), addr);
+
+        let (producer, consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let thread = thread::spawn(move || {
+            send_loop(socket, consumer, &settings, channels, sample_rate, frames, ssrc, &stop_flag);
+        });
+
+        Ok((Self { stop, thread: Some(thread), sdp }, producer))
+    }
+
+    pub fn sdp(&self) -> &str {
+        &self.sdp
+    }
+}
+
+impl Drop for RtpSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_loop(
+    socket: UdpSocket,
+    mut consumer: Consumer<f32>,
+    settings: &RtpSettings,
+    channels: usize,
+    sample_rate: u32,
+    frames: usize,
+    ssrc: u32,
+    stop: &AtomicBool,
+) {
+    let packet_duration = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+    let mut buf = vec![0.0f32; frames * channels];
+    let mut packet = Vec::with_capacity(RTP_HEADER_SIZE + buf.len() * settings.format.bytes_per_sample());
+    let mut sequence: u16 = ssrc as u16;
+    let mut timestamp: u32 = ssrc.rotate_left(16);
+    let mut next = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        }
+        next += packet_duration;
+
+        // Underruns go out as silence so the receiver's clock keeps running
+        tap::pop_into(&mut consumer, &mut buf);
+        packet.clear();
+        write_header(&mut packet, settings.payload_type, sequence, timestamp, ssrc);
+        write_samples(&mut packet, &buf, settings.format);
+        if let Err(e) = socket.send(&packet) {
+            eprintln!("RTP send error: {}", e);
+        }
+        sequence = sequence.wrapping_add(1);
+        timestamp = timestamp.wrapping_add(frames as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_layout() {
+        let mut packet = Vec::new();
+        write_header(&mut packet, 97, 1, 48, 0xdeadbeef);
+        write_samples(&mut packet, &[1.0, -1.0], RtpFormat::L24);
+        assert_eq!(packet.len(), RTP_HEADER_SIZE + 6);
+        assert_eq!(packet[0], 0x80);
+        assert_eq!(packet[1], 97);
+        assert_eq!(&packet[4..8], &48u32.to_be_bytes());
+        assert_eq!(&packet[12..15], &[0x7f, 0xff, 0xff]);
+        assert_eq!(&packet[15..18], &[0x80, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn test_sdp_for_multicast() {
+        let settings = RtpSettings::default();
+        let sdp = sdp(&settings, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), 1, 2, 48000);
+        assert!(sdp.contains("c=IN IP4 239.69.0.1/15\r\n"));
+        assert!(sdp.contains("a=rtpmap:97 L24/48000/2\r\n"));
+        assert!(sdp.contains("a=ptime:1\r\n"));
+    }
+}
@@ -0,0 +1,166 @@
+// Device sample formats. The pipeline runs on f32 throughout; streams on
+// devices that only offer integer formats (many USB and HDMI outputs offer
+// just i16) are opened in that format and converted at the callback, with
+// optional dither for the formats of 16 bits or fewer.
+
+use crate::dsp::{Dither, Ditherer};
+use cpal::traits::DeviceTrait;
+use cpal::{FromSample, SampleFormat, SizedSample, SupportedStreamConfig, SupportedStreamConfigRange};
+
+/// Preference among sample formats: float first, then the widest integers.
+/// None for formats the streams here can't convert.
+fn rank(format: SampleFormat) -> Option<u8> {
+    match format {
+        SampleFormat::F32 => Some(0),
+        SampleFormat::F64 => Some(1),
+        SampleFormat::I32 => Some(2),
+        SampleFormat::U32 => Some(3),
+        SampleFormat::I16 => Some(4),
+        SampleFormat::U16 => Some(5),
+        SampleFormat::I8 => Some(6),
+        SampleFormat::U8 => Some(7),
+        _ => None,
+    }
+}
+
+/// The config running at `rate` in the best sample format offered.
+pub fn best_config(
+    configs: impl Iterator<Item = SupportedStreamConfigRange>,
+    rate: cpal::SampleRate,
+) -> Option<SupportedStreamConfig> {
+    configs
+        .filter(|c| c.min_sample_rate() <= rate && c.max_sample_rate() >= rate)
+        .filter_map(|c| rank(c.sample_format()).map(|r| (r, c)))
+        .min_by_key(|(r, _)| *r)
+        .map(|(_, c)| c.with_sample_rate(rate))
+}
+
+/// Opens an output stream in `format`; `render` always fills f32 samples.
+/// `dither` applies when the format has 16 bits or fewer.
+pub fn build_output_stream<R, E>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    format: SampleFormat,
+    dither: Dither,
+    render: R,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    R: FnMut(&mut [f32], &cpal::OutputCallbackInfo) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    let ditherer = |scale| Some(Ditherer::new(dither, scale, config.channels as usize));
+    match format {
+        SampleFormat::F32 => device.build_output_stream(config, render, on_error, None),
+        SampleFormat::F64 => output::<f64, _, _>(device, config, None, render, on_error),
+        SampleFormat::I32 => output::<i32, _, _>(device, config, None, render, on_error),
+        SampleFormat::U32 => output::<u32, _, _>(device, config, None, render, on_error),
+        SampleFormat::I16 => output::<i16, _, _>(device, config, ditherer(32768.0), render, on_error),
+        SampleFormat::U16 => output::<u16, _, _>(device, config, ditherer(32768.0), render, on_error),
+        SampleFormat::I8 => output::<i8, _, _>(device, config, ditherer(128.0), render, on_error),
+        SampleFormat::U8 => output::<u8, _, _>(device, config, ditherer(128.0), render, on_error),
+        _ => Err(cpal::BuildStreamError::StreamConfigNotSupported),
+    }
+}
+
+fn output<T, R, E>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut ditherer: Option<Ditherer>,
+    mut render: R,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+    R: FnMut(&mut [f32], &cpal::OutputCallbackInfo) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    let mut buffer: Vec<f32> = Vec::new();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            buffer.resize(data.len(), 0.0);
+            render(&mut buffer, info);
+            if let Some(d) = ditherer.as_mut() {
+                d.process(&mut buffer);
+            }
+            for (out, &sample) in data.iter_mut().zip(&buffer) {
+                *out = T::from_sample(sample);
+            }
+        },
+        on_error,
+        None,
+    )
+}
+
+/// Opens an input stream in `format`; `on_data` always receives f32 samples.
+pub fn build_input_stream<D, E>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    format: SampleFormat,
+    on_data: D,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    D: FnMut(&[f32], &cpal::InputCallbackInfo) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    match format {
+        SampleFormat::F32 => device.build_input_stream(config, on_data, on_error, None),
+        SampleFormat::F64 => input::<f64, _, _>(device, config, on_data, on_error),
+        SampleFormat::I32 => input::<i32, _, _>(device, config, on_data, on_error),
+        SampleFormat::U32 => input::<u32, _, _>(device, config, on_data, on_error),
+        SampleFormat::I16 => input::<i16, _, _>(device, config, on_data, on_error),
+        SampleFormat::U16 => input::<u16, _, _>(device, config, on_data, on_error),
+        SampleFormat::I8 => input::<i8, _, _>(device, config, on_data, on_error),
+        SampleFormat::U8 => input::<u8, _, _>(device, config, on_data, on_error),
+        _ => Err(cpal::BuildStreamError::StreamConfigNotSupported),
+    }
+}
+
+fn input<T, D, E>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_data: D,
+    on_error: E,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    D: FnMut(&[f32], &cpal::InputCallbackInfo) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    let mut buffer: Vec<f32> = Vec::new();
+    device.build_input_stream(
+        config,
+        move |data: &[T], info: &cpal::InputCallbackInfo| {
+            buffer.clear();
+            buffer.extend(data.iter().map(|&sample| f32::from_sample(sample)));
+            on_data(&buffer, info);
+        },
+        on_error,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleRate, SupportedBufferSize};
+
+    #[test]
+    fn test_prefers_float_at_the_rate() {
+        let range = |format, min, max| {
+            SupportedStreamConfigRange::new(2, SampleRate(min), SampleRate(max), SupportedBufferSize::Unknown, format)
+        };
+        let configs = vec![
+            range(SampleFormat::I16, 44100, 96000),
+            range(SampleFormat::F32, 44100, 44100),
+            range(SampleFormat::I32, 48000, 48000),
+        ];
+        let best = best_config(configs.clone().into_iter(), SampleRate(48000)).unwrap();
+        assert_eq!(best.sample_format(), SampleFormat::I32);
+        let best = best_config(configs.into_iter(), SampleRate(44100)).unwrap();
+        assert_eq!(best.sample_format(), SampleFormat::F32);
+    }
+}
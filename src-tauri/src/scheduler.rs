@@ -0,0 +1,162 @@
+// Recording scheduler: starts recordings at a set time and stops them after a
+// duration. Schedules are persisted in the config and re-armed at startup, so a
+// recording whose window is still open resumes after a restart.
+
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduledRecording {
+    pub id: u64,
+    pub start: DateTime<Local>,
+    /// Stop automatically after this long; `None` records until stopped.
+    pub duration_secs: Option<u64>,
+}
+
+impl ScheduledRecording {
+    fn end(&self) -> Option<DateTime<Local>> {
+        self.duration_secs.map(|secs| self.start + Duration::seconds(secs as i64))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerAction {
+    /// Start a recording, and if this schedule is the one that started it,
+    /// arm its stop with `set_stop_at`.
+    StartRecording { stop_at: Option<DateTime<Local>> },
+    StopRecording,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    schedules: Vec<ScheduledRecording>,
+    started: HashSet<u64>,
+    stop_at: Option<DateTime<Local>>,
+}
+
+/// Managed by Tauri; ticked once a second by a background thread.
+#[derive(Default)]
+pub struct Scheduler {
+    state: Mutex<SchedulerState>,
+}
+
+impl Scheduler {
+    pub fn load(&self, schedules: Vec<ScheduledRecording>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.schedules = schedules;
+            state.started.clear();
+        }
+    }
+
+    pub fn schedules(&self) -> Vec<ScheduledRecording> {
+        self.state.lock().map(|s| s.schedules.clone()).unwrap_or_default()
+    }
+
+    pub fn add(&self, start: DateTime<Local>, duration_secs: Option<u64>) -> Result<ScheduledRecording, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let id = state.schedules.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+        let schedule = ScheduledRecording { id, start, duration_secs };
+        state.schedules.push(schedule.clone());
+        Ok(schedule)
+    }
+
+    pub fn remove(&self, id: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.schedules.retain(|s| s.id != id);
+            state.started.remove(&id);
+        }
+    }
+
+    /// Arms (or clears) the automatic stop of the running recording.
+    pub fn set_stop_at(&self, stop_at: Option<DateTime<Local>>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stop_at = stop_at;
+        }
+    }
+
+    /// Advances the schedule to `now`. Returns the actions to perform and
+    /// whether the persisted schedule list changed.
+    pub fn tick(&self, now: DateTime<Local>) -> (Vec<SchedulerAction>, bool) {
+        let mut actions = Vec::new();
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(_) => return (actions, false),
+        };
+
+        if state.stop_at.is_some_and(|t| now >= t) {
+            state.stop_at = None;
+            actions.push(SchedulerAction::StopRecording);
+        }
+
+        let due: Vec<ScheduledRecording> = state
+            .schedules
+            .iter()
+            .filter(|s| s.start <= now && !state.started.contains(&s.id))
+            .cloned()
+            .collect();
+        for schedule in due {
+            state.started.insert(schedule.id);
+            match schedule.end() {
+                // Missed entirely, e.g. the app was closed for the whole window
+                Some(end) if end <= now => continue,
+                stop_at => actions.push(SchedulerAction::StartRecording { stop_at }),
+            }
+        }
+
+        // Keep timed schedules until they end so a restart can resume them
+        let before = state.schedules.len();
+        let SchedulerState { schedules, started, .. } = &mut *state;
+        schedules.retain(|s| match s.end() {
+            Some(end) => now < end,
+            None => !started.contains(&s.id),
+        });
+        let changed = schedules.len() != before;
+
+        (actions, changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timed_schedule_starts_and_stops() {
+        let scheduler = Scheduler::default();
+        let start = Local::now();
+        scheduler.add(start, Some(60)).unwrap();
+
+        let stop_at = Some(start + Duration::seconds(60));
+        assert_eq!(scheduler.tick(start - Duration::seconds(1)), (vec![], false));
+        assert_eq!(scheduler.tick(start), (vec![SchedulerAction::StartRecording { stop_at }], false));
+        scheduler.set_stop_at(stop_at);
+        assert_eq!(scheduler.tick(start + Duration::seconds(30)), (vec![], false));
+        assert_eq!(
+            scheduler.tick(start + Duration::seconds(60)),
+            (vec![SchedulerAction::StopRecording], true)
+        );
+        assert!(scheduler.schedules().is_empty());
+    }
+
+    #[test]
+    fn test_stop_is_left_to_whoever_started_the_recording() {
+        let scheduler = Scheduler::default();
+        let start = Local::now();
+        scheduler.add(start, Some(60)).unwrap();
+
+        // A manual recording was already running, so the stop was never armed
+        assert_eq!(scheduler.tick(start).0.len(), 1);
+        assert_eq!(scheduler.tick(start + Duration::seconds(60)), (vec![], true));
+    }
+
+    #[test]
+    fn test_missed_schedule_is_dropped() {
+        let scheduler = Scheduler::default();
+        let start = Local::now() - Duration::hours(2);
+        scheduler.add(start, Some(60)).unwrap();
+
+        assert_eq!(scheduler.tick(Local::now()), (vec![], true));
+    }
+}
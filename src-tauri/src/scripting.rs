@@ -0,0 +1,308 @@
+// User automation in Rhai. Each configured script is loaded once (its
+// top-level code runs at load) and may define any of these hooks:
+//
+//   fn on_event(event) { }          // every audio event, as a map with a `type`
+//   fn on_output_added(name) { }
+//   fn on_output_removed(name) { }
+//   fn on_level(peak) { }           // mix peak at or above `level_threshold`
+//   fn on_tick() { }                // every `tick_seconds`
+//
+// Scripts control the mixer with `command(name, args)`, which takes the same
+// commands as the control API, or the shorthands registered in `engine()`.
+
+use crate::api;
+use crate::audio::{AudioCommand, AudioEvent, MeterSnapshot};
+use crate::AppState;
+use crossbeam_channel::{Receiver, Sender};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const LEVEL_POLL: Duration = Duration::from_millis(200);
+/// Shortest time between two `on_level` calls.
+const LEVEL_HOOK_INTERVAL: Duration = Duration::from_secs(1);
+/// Guards against runaway loops holding up the script thread.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Runs a control API command by name and arguments, e.g. `api::execute`.
+type Execute = Arc<dyn Fn(&str, &Value) -> Result<Value, String>>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ScriptSettings {
+    pub enabled: bool,
+    /// `.rhai` files, loaded in order.
+    pub scripts: Vec<PathBuf>,
+    /// Interval of `on_tick`; 0 disables it.
+    pub tick_seconds: u32,
+    /// Linear mix peak that triggers `on_level`.
+    pub level_threshold: f32,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        Self { enabled: false, scripts: Vec::new(), tick_seconds: 60, level_threshold: 0.9 }
+    }
+}
+
+struct Script {
+    path: PathBuf,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+/// Thread owning the script engine, fed audio events over a channel.
+pub struct ScriptRunner {
+    events: Sender<Option<AudioEvent>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ScriptRunner {
+    pub fn start(settings: &ScriptSettings, app: AppHandle) -> Self {
+        let (events, rx) = crossbeam_channel::unbounded();
+        let settings = settings.clone();
+        let thread = thread::spawn(move || run(&app, &settings, &rx));
+        Self { events, thread: Some(thread) }
+    }
+}
+
+impl Drop for ScriptRunner {
+    fn drop(&mut self) {
+        let _ = self.events.send(None);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Running scripts, managed as app state so settings changes reload them.
+#[derive(Default)]
+pub struct ScriptService {
+    runner: Mutex<Option<ScriptRunner>>,
+}
+
+impl ScriptService {
+    /// Stops the current scripts and loads them again if `settings` enable them.
+    pub fn apply(&self, app: &AppHandle, settings: &ScriptSettings) -> Result<(), String> {
+        let mut runner = self.runner.lock().map_err(|e| e.to_string())?;
+        *runner = None;
+        if settings.enabled {
+            *runner = Some(ScriptRunner::start(settings, app.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn notify(&self, event: &AudioEvent) {
+        if let Ok(runner) = self.runner.lock() {
+            if let Some(runner) = runner.as_ref() {
+                let _ = runner.events.send(Some(event.clone()));
+            }
+        }
+    }
+}
+
+fn run(app: &AppHandle, settings: &ScriptSettings, rx: &Receiver<Option<AudioEvent>>) {
+    let handle = app.clone();
+    let engine = engine(Arc::new(move |name: &str, args: &Value| api::execute(&handle, name, args)));
+    let mut scripts: Vec<Script> = settings.scripts.iter().filter_map(|path| load(&engine, path)).collect();
+    println!("Loaded {} of {} scripts", scripts.len(), settings.scripts.len());
+
+    let tick = Duration::from_secs(settings.tick_seconds as u64);
+    let mut last_tick = Instant::now();
+    let mut last_level: Option<Instant> = None;
+    loop {
+        match rx.recv_timeout(LEVEL_POLL) {
+            Ok(Some(event)) => {
+                dispatch(&engine, &mut scripts, &event);
+                continue;
+            },
+            Ok(None) | Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return,
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {},
+        }
+
+        if !tick.is_zero() && last_tick.elapsed() >= tick {
+            last_tick = Instant::now();
+            for script in &mut scripts {
+                call(&engine, script, "on_tick", ());
+            }
+        }
+        if matches!(last_level, Some(t) if t.elapsed() < LEVEL_HOOK_INTERVAL) {
+            continue;
+        }
+        if let Some(meters) = read_meters(app) {
+            if meters.mix >= settings.level_threshold {
+                last_level = Some(Instant::now());
+                for script in &mut scripts {
+                    call(&engine, script, "on_level", (meters.mix as f64,));
+                }
+            }
+        }
+    }
+}
+
+/// Calls the hooks that take `event` in every script.
+fn dispatch(engine: &Engine, scripts: &mut [Script], event: &AudioEvent) {
+    let data = rhai::serde::to_dynamic(event).unwrap_or_default();
+    for script in scripts {
+        call(engine, script, "on_event", (data.clone(),));
+        match event {
+            AudioEvent::OutputAdded { device } => call(engine, script, "on_output_added", (device.clone(),)),
+            AudioEvent::OutputRemoved { device } => call(engine, script, "on_output_removed", (device.clone(),)),
+            _ => {},
+        }
+    }
+}
+
+fn read_meters(app: &AppHandle) -> Option<MeterSnapshot> {
+    let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+    app.state::<AppState>().tx.send(AudioCommand::GetMeters(reply_tx)).ok()?;
+    reply_rx.recv_timeout(LEVEL_POLL).ok()
+}
+
+fn load(engine: &Engine, path: &PathBuf) -> Option<Script> {
+    let ast = match engine.compile_file(path.clone()) {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("Failed to load script {}: {}", path.display(), e);
+            return None;
+        },
+    };
+    Some(prepare(engine, path, ast))
+}
+
+/// Runs the script's top-level code, keeping its scope for the hooks.
+fn prepare(engine: &Engine, path: &PathBuf, ast: AST) -> Script {
+    let mut scope = Scope::new();
+    if let Err(e) = engine.run_ast_with_scope(&mut scope, &ast) {
+        eprintln!("Script {} failed: {}", path.display(), e);
+    }
+    Script { path: path.clone(), ast, scope }
+}
+
+/// Calls `hook` if the script defines it.
+fn call(engine: &Engine, script: &mut Script, hook: &str, args: impl FuncArgs) {
+    if !script.ast.iter_functions().any(|f| f.name == hook) {
+        return;
+    }
+    let options = CallFnOptions::new().eval_ast(false);
+    if let Err(e) = engine.call_fn_with_options::<Dynamic>(options, &mut script.scope, &script.ast, hook, args) {
+        eprintln!("Script {} failed in {}: {}", script.path.display(), hook, e);
+    }
+}
+
+/// Engine with the mixer functions scripts can call, all run through `execute`.
+fn engine(execute: Execute) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| println!("[script] {}", text));
+
+    let ex = execute.clone();
+    engine.register_fn("command", move |name: &str, args: Map| run_command(&ex, name, Dynamic::from_map(args)));
+    let ex = execute.clone();
+    engine.register_fn("command", move |name: &str| run_command(&ex, name, Dynamic::from_map(Map::new())));
+
+    let ex = execute.clone();
+    engine.register_fn("state", move || run_command(&ex, "get_audio_state", Dynamic::from_map(Map::new())));
+    let ex = execute.clone();
+    engine.register_fn("start_capture", move || shorthand(&ex, "start_capture", json!({})));
+    let ex = execute.clone();
+    engine.register_fn("stop_capture", move || shorthand(&ex, "stop_capture", json!({})));
+    let ex = execute.clone();
+    engine.register_fn("add_output", move |name: &str| shorthand(&ex, "add_device_to_mix", json!({ "device_name": name })));
+    let ex = execute.clone();
+    engine.register_fn("remove_output", move |name: &str| {
+        shorthand(&ex, "remove_device_from_mix", json!({ "device_name": name }))
+    });
+    let ex = execute.clone();
+    engine.register_fn("set_volume", move |name: &str, volume: f64| {
+        shorthand(&ex, "set_device_volume", json!({ "device_name": name, "volume": volume }))
+    });
+    let ex = execute.clone();
+    engine.register_fn("set_mute", move |name: &str, muted: bool| {
+        shorthand(&ex, "set_device_mute", json!({ "device_name": name, "muted": muted }))
+    });
+    let ex = execute.clone();
+    engine.register_fn("set_master_volume", move |volume: f64| {
+        shorthand(&ex, "set_master_volume", json!({ "volume": volume }))
+    });
+    let ex = execute;
+    engine.register_fn("set_master_mute", move |muted: bool| shorthand(&ex, "set_master_mute", json!({ "muted": muted })));
+    engine
+}
+
+fn run_command(execute: &Execute, name: &str, args: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+    let args: Value = rhai::serde::from_dynamic(&args)?;
+    let result = execute(name, &args)?;
+    rhai::serde::to_dynamic(result)
+}
+
+fn shorthand(execute: &Execute, name: &str, args: Value) -> Result<(), Box<EvalAltResult>> {
+    execute(name, &args)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Engine whose commands are recorded instead of run.
+    fn recording_engine() -> (Engine, Arc<Mutex<Vec<(String, Value)>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = calls.clone();
+        let engine = engine(Arc::new(move |name: &str, args: &Value| {
+            log.lock().unwrap().push((name.to_string(), args.clone()));
+            match name {
+                "fail" => Err("no such command".to_string()),
+                _ => Ok(json!({ "ok": true })),
+            }
+        }));
+        (engine, calls)
+    }
+
+    fn script(engine: &Engine, source: &str) -> Script {
+        prepare(engine, &PathBuf::from("test.rhai"), engine.compile(source).unwrap())
+    }
+
+    #[test]
+    fn test_events_reach_their_hooks() {
+        let (engine, calls) = recording_engine();
+        let mut scripts = vec![script(
+            &engine,
+            r#"
+                fn on_event(event) { command("seen", #{ kind: event["type"] }); }
+                fn on_output_added(name) { set_volume(name, 0.5); }
+            "#,
+        )];
+
+        dispatch(&engine, &mut scripts, &AudioEvent::OutputAdded { device: "Speakers".to_string() });
+        dispatch(&engine, &mut scripts, &AudioEvent::OutputRemoved { device: "Speakers".to_string() });
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(
+            *calls,
+            vec![
+                ("seen".to_string(), json!({ "kind": "output_added" })),
+                ("set_device_volume".to_string(), json!({ "device_name": "Speakers", "volume": 0.5 })),
+                ("seen".to_string(), json!({ "kind": "output_removed" })),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_results_and_errors_reach_the_script() {
+        let (engine, calls) = recording_engine();
+        let mut scope = Scope::new();
+        let ok: bool = engine.eval_with_scope(&mut scope, r#"command("get_audio_state").ok"#).unwrap();
+        assert!(ok);
+        assert!(engine.eval::<Dynamic>(r#"command("fail")"#).is_err());
+        assert!(engine.eval::<Dynamic>(r#"start_capture()"#).is_ok());
+
+        let names: Vec<String> = calls.lock().unwrap().iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(names, ["get_audio_state", "fail", "start_capture"]);
+    }
+}
@@ -0,0 +1,232 @@
+// Pausing the mix while the session is locked, so audio doesn't keep playing
+// to other rooms while nobody is at the machine. The lock state is polled:
+// the WTS session flags on Windows, logind's LockedHint on Linux. macOS isn't
+// supported yet.
+
+use crate::audio::AudioCommand;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockAction {
+    /// Keep playing.
+    #[default]
+    None,
+    /// Mute the master until unlocked.
+    Mute,
+    /// Stop capture until unlocked.
+    Stop,
+}
+
+/// What was changed on lock, to undo on unlock.
+enum Paused {
+    Muted,
+    Stopped,
+}
+
+/// The configured action, managed as app state and read by the watcher.
+#[derive(Default)]
+pub struct SessionLock {
+    action: Mutex<LockAction>,
+}
+
+impl SessionLock {
+    pub fn set(&self, action: LockAction) {
+        if let Ok(mut current) = self.action.lock() {
+            *current = action;
+        }
+    }
+
+    fn action(&self) -> LockAction {
+        if let Ok(a) = self.action.lock() { *a } else { LockAction::None }
+    }
+}
+
+/// Lock state between polls, and what to undo on unlock.
+struct LockWatch<P> {
+    locked: bool,
+    paused: Option<P>,
+}
+
+impl<P> Default for LockWatch<P> {
+    fn default() -> Self {
+        Self { locked: false, paused: None }
+    }
+}
+
+impl<P> LockWatch<P> {
+    /// Whether the lock state needs polling: only while an action is set or
+    /// something is still paused.
+    fn needs_poll(&mut self, action: LockAction) -> bool {
+        if action == LockAction::None && self.paused.is_none() {
+            self.locked = false;
+            return false;
+        }
+        true
+    }
+
+    /// Feeds one poll. `pause` runs when the session locks and returns what
+    /// to undo; that is handed back when it unlocks.
+    fn update(&mut self, now_locked: bool, pause: impl FnOnce() -> Option<P>) -> Option<P> {
+        if now_locked == self.locked {
+            return None;
+        }
+        self.locked = now_locked;
+        if now_locked {
+            self.paused = pause();
+            None
+        } else {
+            self.paused.take()
+        }
+    }
+}
+
+/// Watches the session lock for the rest of the app's life.
+pub fn spawn_lock_watcher(app: AppHandle) {
+    thread::spawn(move || {
+        let mut watch: LockWatch<Paused> = LockWatch::default();
+        let mut warned = false;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let action = app.state::<SessionLock>().action();
+            if !watch.needs_poll(action) {
+                continue;
+            }
+            let now_locked = match is_locked() {
+                Some(l) => l,
+                None => {
+                    if !warned {
+                        eprintln!("Session lock state unavailable on this system");
+                        warned = true;
+                    }
+                    continue;
+                },
+            };
+            let undo = watch.update(now_locked, || {
+                println!("Session locked");
+                pause(&app, action)
+            });
+            if let Some(p) = undo {
+                println!("Session unlocked");
+                resume(&app, p);
+            }
+        }
+    });
+}
+
+/// Applies `action`, returning what to undo if anything changed.
+fn pause(app: &AppHandle, action: LockAction) -> Option<Paused> {
+    let state = crate::get_audio_state(app.state()).ok()?;
+    let tx = &app.state::<AppState>().tx;
+    match action {
+        LockAction::None => None,
+        // Not saved, so a crash while locked doesn't leave the mix muted
+        LockAction::Mute if !state.master_muted => {
+            tx.send(AudioCommand::SetMasterMute(true)).ok()?;
+            Some(Paused::Muted)
+        },
+        LockAction::Stop if state.capturing => {
+            tx.send(AudioCommand::StopLoopback).ok()?;
+            Some(Paused::Stopped)
+        },
+        _ => None,
+    }
+}
+
+fn resume(app: &AppHandle, paused: Paused) {
+    let command = match paused {
+        Paused::Muted => AudioCommand::SetMasterMute(false),
+        Paused::Stopped => AudioCommand::StartLoopback,
+    };
+    let _ = app.state::<AppState>().tx.send(command);
+}
+
+/// The session's own lock flag. The input desktop can't tell the lock screen
+/// from the UAC secure desktop, so it isn't used.
+#[cfg(windows)]
+fn is_locked() -> Option<bool> {
+    use windows::core::PWSTR;
+    use windows::Win32::System::RemoteDesktop::{
+        WTSFreeMemory, WTSQuerySessionInformationW, WTSSessionInfoEx, WTSINFOEXW, WTS_CURRENT_SERVER_HANDLE,
+        WTS_CURRENT_SESSION, WTS_SESSIONSTATE_LOCK,
+    };
+    let mut buffer = PWSTR::null();
+    let mut bytes = 0u32;
+    // SAFETY: on success the buffer holds a WTSINFOEXW, freed once below
+    unsafe {
+        WTSQuerySessionInformationW(WTS_CURRENT_SERVER_HANDLE, WTS_CURRENT_SESSION, WTSSessionInfoEx, &mut buffer, &mut bytes)
+            .ok()?;
+        let info = &*(buffer.0 as *const WTSINFOEXW);
+        let flags = (info.Level == 1).then(|| info.Data.WTSInfoExLevel1.SessionFlags);
+        WTSFreeMemory(buffer.0 as *mut _);
+        flags.map(|flags| flags == WTS_SESSIONSTATE_LOCK as i32)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_locked() -> Option<bool> {
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+    let output = std::process::Command::new("loginctl")
+        .args(["show-session", &session, "-p", "LockedHint", "--value"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn is_locked() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_pauses_and_unlock_undoes_it() {
+        let mut watch = LockWatch::default();
+        assert!(watch.needs_poll(LockAction::Mute));
+        assert_eq!(watch.update(false, || panic!("not locked")), None);
+
+        assert_eq!(watch.update(true, || Some("muted")), None);
+        // Still locked: nothing runs again
+        assert_eq!(watch.update(true, || panic!("already paused")), None);
+        assert_eq!(watch.update(false, || None), Some("muted"));
+        assert_eq!(watch.update(false, || None), None);
+    }
+
+    #[test]
+    fn test_unlock_is_still_seen_after_the_action_is_cleared() {
+        let mut watch = LockWatch::default();
+        watch.update(true, || Some("stopped"));
+
+        // Turning the action off while locked still resumes on unlock
+        assert!(watch.needs_poll(LockAction::None));
+        assert_eq!(watch.update(false, || None), Some("stopped"));
+        assert!(!watch.needs_poll(LockAction::None));
+    }
+
+    #[test]
+    fn test_nothing_to_undo_when_lock_changed_nothing() {
+        let mut watch: LockWatch<&str> = LockWatch::default();
+        // E.g. already muted when the session locked
+        watch.update(true, || None);
+        assert!(!watch.needs_poll(LockAction::None));
+        assert!(!watch.locked);
+        assert_eq!(watch.update(false, || None), None);
+    }
+}
@@ -0,0 +1,606 @@
+// Network streaming of the mix. Each stream runs on its own thread, fed from a
+// capture tap, and encodes with the shared encoders.
+
+use crate::encoder::{Encoder, Mp3Encoder, OggOpusEncoder, OpusSettings};
+use crate::tap;
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Seconds of audio buffered between the capture callback and a stream.
+const STREAM_BUFFER_SECONDS: usize = 2;
+
+/// How often stream threads drain their tap.
+const STREAM_POLL_MS: u64 = 20;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Largest HTTP header we accept from a server or client.
+const MAX_RESPONSE_HEADER: usize = 8192;
+
+/// Blocks queued per HTTP listener before it is considered stalled.
+const CLIENT_QUEUE_BLOCKS: usize = 100;
+
+/// Connections a listener serves at once; more are closed on accept.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Listening on every interface, so other machines can connect.
+pub const ANY_ADDRESS: &str = "0.0.0.0";
+
+/// Listening on this machine only.
+pub const LOOPBACK_ADDRESS: &str = "127.0.0.1";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamFormat {
+    #[default]
+    Mp3,
+    /// Opus in an Ogg container.
+    Opus,
+}
+
+impl StreamFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Mp3 => "audio/mpeg",
+            StreamFormat::Opus => "audio/ogg",
+        }
+    }
+
+    pub fn encoder<W: Write + 'static>(
+        &self,
+        out: W,
+        channels: usize,
+        sample_rate: u32,
+        bitrate_kbps: u32,
+        opus: &OpusSettings,
+    ) -> Result<Box<dyn Encoder>, String> {
+        match self {
+            StreamFormat::Mp3 => Ok(Box::new(Mp3Encoder::new(out, channels, sample_rate, bitrate_kbps)?)),
+            StreamFormat::Opus => Ok(Box::new(OggOpusEncoder::new(out, channels, sample_rate, bitrate_kbps, opus)?)),
+        }
+    }
+}
+
+/// Icecast mount the mix is pushed to as a source client.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct IcecastSettings {
+    pub host: String,
+    pub port: u16,
+    pub mount: String,
+    pub username: String,
+    pub password: String,
+    pub format: StreamFormat,
+    pub bitrate_kbps: u32,
+    /// Used when `format` is Opus.
+    pub opus: OpusSettings,
+    pub name: String,
+    pub description: String,
+    /// List the stream in public directories.
+    pub public: bool,
+}
+
+impl Default for IcecastSettings {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 8000,
+            mount: "/audio_merge".to_string(),
+            username: "source".to_string(),
+            password: String::new(),
+            format: StreamFormat::default(),
+            bitrate_kbps: 128,
+            opus: OpusSettings::default(),
+            name: "Audio Merge".to_string(),
+            description: String::new(),
+            public: false,
+        }
+    }
+}
+
+/// Built-in HTTP server that lets browsers on the LAN listen to the mix.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HttpStreamSettings {
+    pub enabled: bool,
+    /// Interface to listen on: loopback by default, a LAN address to reach
+    /// one network, or `0.0.0.0` for every interface.
+    pub bind_address: String,
+    pub port: u16,
+    /// Listeners served at once. Each one runs its own encoder.
+    pub max_clients: usize,
+    pub format: StreamFormat,
+    pub bitrate_kbps: u32,
+    /// Used when `format` is Opus.
+    pub opus: OpusSettings,
+}
+
+impl Default for HttpStreamSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: LOOPBACK_ADDRESS.to_string(),
+            port: 8090,
+            max_clients: 8,
+            format: StreamFormat::default(),
+            bitrate_kbps: 128,
+            opus: OpusSettings::default(),
+        }
+    }
+}
+
+/// Ring buffer sized for one stream tap.
+pub fn stream_tap(channels: usize, sample_rate: u32) -> (Producer<f32>, Consumer<f32>) {
+    RingBuffer::<f32>::new(sample_rate as usize * channels * STREAM_BUFFER_SECONDS)
+}
+
+/// A running Icecast source. Reconnects on its own until dropped.
+pub struct IcecastClient {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IcecastClient {
+    pub fn start(settings: IcecastSettings, channels: usize, sample_rate: u32) -> (Self, Producer<f32>) {
+        let (producer, mut consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+
+        let thread = thread::spawn(move || {
+            while !stop_flag.load(Ordering::Relaxed) {
+                match connect_icecast(&settings) {
+                    Ok(stream) => {
+                        println!("Streaming to icecast://{}:{}{}", settings.host, settings.port, settings.mount);
+                        let result = settings
+                            .format
+                            .encoder(stream, channels, sample_rate, settings.bitrate_kbps, &settings.opus)
+                            .and_then(|encoder| pump(&mut consumer, encoder, channels, &stop_flag));
+                        if let Err(e) = result {
+                            eprintln!("Icecast stream error: {}", e);
+                        }
+                    },
+                    Err(e) => eprintln!("Icecast connection failed: {}", e),
+                }
+                discard_for(&mut consumer, RECONNECT_DELAY, &stop_flag);
+            }
+        });
+
+        (Self { stop, thread: Some(thread) }, producer)
+    }
+}
+
+impl Drop for IcecastClient {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+type ClientList = Arc<Mutex<Vec<Sender<Arc<Vec<f32>>>>>>;
+
+/// Serves the mix at `/stream` as a chunked HTTP response. Every listener gets
+/// its own encoder so Ogg streams start with their headers.
+pub struct HttpStreamServer {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    port: u16,
+}
+
+impl HttpStreamServer {
+    pub fn start(settings: HttpStreamSettings, channels: usize, sample_rate: u32) -> Result<(Self, Producer<f32>), String> {
+        let (producer, mut consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let clients: ClientList = Arc::new(Mutex::new(Vec::new()));
+
+        // Port 0 picks a free port, reported by `port()`
+        let listener = bind_listener(&settings.bind_address, settings.port)?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let host = settings.bind_address.clone();
+        let accept = {
+            let stop_flag = stop.clone();
+            let clients = clients.clone();
+            let max_clients = settings.max_clients.max(1);
+            serve_tcp_listener(listener, max_clients, stop.clone(), move |stream| {
+                if let Err(e) = serve_client(stream, &settings, channels, sample_rate, &clients, &stop_flag) {
+                    println!("HTTP listener left: {}", e);
+                }
+            })?
+        };
+        println!("Serving the mix on http://{}:{}/stream", host, port);
+
+        let fan_out = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    tap::drain_frames(&mut consumer, channels, &mut buf);
+                    if !buf.is_empty() {
+                        let block = Arc::new(buf.clone());
+                        if let Ok(mut clients) = clients.lock() {
+                            // Drop listeners that went away or stopped reading
+                            clients.retain(|tx| tx.try_send(block.clone()).is_ok());
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(STREAM_POLL_MS));
+                }
+            })
+        };
+
+        Ok((Self { stop, threads: vec![fan_out, accept], port }, producer))
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for HttpStreamServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Accepts connections on `port` until `stop` is set, handling each one on its
+/// own thread. The returned thread joins the connection threads on the way
+/// out.
+pub fn spawn_tcp_listener<F>(port: u16, stop: Arc<AtomicBool>, handler: F) -> Result<JoinHandle<()>, String>
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    spawn_tcp_listener_on(ANY_ADDRESS, port, stop, handler)
+}
+
+/// Like `spawn_tcp_listener`, bound to one address only, e.g. loopback.
+pub fn spawn_tcp_listener_on<F>(host: &str, port: u16, stop: Arc<AtomicBool>, handler: F) -> Result<JoinHandle<()>, String>
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    serve_tcp_listener(bind_listener(host, port)?, MAX_CONNECTIONS, stop, handler)
+}
+
+fn bind_listener(host: &str, port: u16) -> Result<TcpListener, String> {
+    let listener = TcpListener::bind((host, port)).map_err(|e| format!("Port {}: {}", port, e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    Ok(listener)
+}
+
+fn serve_tcp_listener<F>(
+    listener: TcpListener,
+    max_connections: usize,
+    stop: Arc<AtomicBool>,
+    handler: F,
+) -> Result<JoinHandle<()>, String>
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let handler = Arc::new(handler);
+
+    Ok(thread::spawn(move || {
+        let mut connections: Vec<JoinHandle<()>> = Vec::new();
+        while !stop.load(Ordering::Relaxed) {
+            connections.retain(|connection| !connection.is_finished());
+            match listener.accept() {
+                Ok((_stream, addr)) if connections.len() >= max_connections => {
+                    println!("Port {} is serving {} connections, refused {}", port, max_connections, addr);
+                },
+                Ok((stream, _addr)) => {
+                    let handler = handler.clone();
+                    connections.push(thread::spawn(move || {
+                        if stream.set_nonblocking(false).is_ok() {
+                            handler(stream);
+                        }
+                    }));
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(STREAM_POLL_MS * 5));
+                },
+                Err(e) => eprintln!("Accept error on port {}: {}", port, e),
+            }
+        }
+        // Handlers watch the same stop flag or finish on their socket timeouts
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }))
+}
+
+fn serve_client(
+    mut stream: TcpStream,
+    settings: &HttpStreamSettings,
+    channels: usize,
+    sample_rate: u32,
+    clients: &ClientList,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let request = read_request(&mut stream)?;
+    if request.method != "GET" || !(request.path == "/" || request.path == "/stream") {
+        return write_response(&mut stream, "404 Not Found", "text/plain", b"");
+    }
+
+    let header = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: {}\r\n\
+         Transfer-Encoding: chunked\r\n\
+         Cache-Control: no-cache, no-store\r\n\
+         Connection: close\r\n\r\n",
+        settings.format.content_type()
+    );
+    stream.write_all(header.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut encoder = settings
+        .format
+        .encoder(ChunkedWriter(stream), channels, sample_rate, settings.bitrate_kbps, &settings.opus)?;
+    let (tx, rx) = bounded(CLIENT_QUEUE_BLOCKS);
+    if let Ok(mut clients) = clients.lock() {
+        clients.push(tx);
+    }
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(Duration::from_millis(STREAM_POLL_MS * 5)) {
+            Ok(block) => encoder.write(&block)?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    encoder.finish()
+}
+
+/// HTTP/1.1 chunked transfer encoding over a socket.
+struct ChunkedWriter<W: Write>(W);
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.0, "{:x}\r\n", buf.len())?;
+        self.0.write_all(buf)?;
+        self.0.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Largest request body we accept, e.g. an SDP offer.
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+
+pub struct HttpRequest {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    /// Query string without the `?`.
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads an HTTP request, including a `Content-Length` body if present.
+pub fn read_request<R: Read>(stream: &mut R) -> Result<HttpRequest, String> {
+    let text = read_header(stream)?;
+    let mut lines = text.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err("Malformed request".to_string()),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = HttpRequest { method, path, query, headers, body: Vec::new() };
+
+    let length = request
+        .header("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_REQUEST_BODY {
+        return Err("Request body too large".to_string());
+    }
+    request.body = vec![0; length];
+    stream.read_exact(&mut request.body).map_err(|e| e.to_string())?;
+
+    Ok(request)
+}
+
+/// Writes a complete response with a fixed-length body.
+pub fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<(), String> {
+    let header = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).map_err(|e| e.to_string())?;
+    stream.write_all(body).map_err(|e| e.to_string())
+}
+
+/// Opens a source connection with the Icecast 2.4+ HTTP PUT handshake.
+fn connect_icecast(settings: &IcecastSettings) -> Result<TcpStream, String> {
+    let addr = (settings.host.as_str(), settings.port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", settings.host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mount = if settings.mount.starts_with('/') { settings.mount.clone() } else { format!("/{}", settings.mount) };
+    let auth = BASE64.encode(format!("{}:{}", settings.username, settings.password));
+    let request = format!(
+        "PUT {} HTTP/1.1\r\n\
+         Host: {}:{}\r\n\
+         Authorization: Basic {}\r\n\
+         User-Agent: audio_merge/{}\r\n\
+         Content-Type: {}\r\n\
+         Ice-Name: {}\r\n\
+         Ice-Description: {}\r\n\
+         Ice-Public: {}\r\n\
+         Ice-Audio-Info: bitrate={}\r\n\
+         Expect: 100-continue\r\n\r\n",
+        mount,
+        settings.host,
+        settings.port,
+        auth,
+        env!("CARGO_PKG_VERSION"),
+        settings.format.content_type(),
+        settings.name,
+        settings.description,
+        settings.public as u8,
+        settings.bitrate_kbps,
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    icecast_status(read_response_status(&mut stream)?, &mount)?;
+    Ok(stream)
+}
+
+/// Maps the answer to the source handshake to an error worth showing.
+fn icecast_status(status: u16, mount: &str) -> Result<(), String> {
+    match status {
+        100 | 200 => Ok(()),
+        401 => Err("Icecast rejected the source credentials".to_string()),
+        403 => Err(format!("Icecast refused mount {} (in use or not allowed)", mount)),
+        code => Err(format!("Icecast answered HTTP {}", code)),
+    }
+}
+
+/// Reads an HTTP response header and returns its status code.
+pub fn read_response_status<R: Read>(stream: &mut R) -> Result<u16, String> {
+    let text = read_header(stream)?;
+    text.lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("Malformed response: {}", text.lines().next().unwrap_or_default()))
+}
+
+/// Reads bytes up to the blank line that ends an HTTP (or RTSP) header.
+pub fn read_header<R: Read>(stream: &mut R) -> Result<String, String> {
+    let mut header = Vec::new();
+    let mut byte = [0u8; 1];
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_RESPONSE_HEADER {
+            return Err("Response header too large".to_string());
+        }
+        match stream.read(&mut byte) {
+            Ok(0) => return Err("Connection closed during handshake".to_string()),
+            Ok(_) => header.push(byte[0]),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(String::from_utf8_lossy(&header).into_owned())
+}
+
+/// Encodes the tap into `encoder` until stopped or the connection fails.
+pub fn pump(
+    consumer: &mut Consumer<f32>,
+    mut encoder: Box<dyn Encoder>,
+    channels: usize,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    let mut buf = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        tap::drain_frames(consumer, channels, &mut buf);
+        if !buf.is_empty() {
+            encoder.write(&buf)?;
+        }
+        thread::sleep(Duration::from_millis(STREAM_POLL_MS));
+    }
+    encoder.finish()
+}
+
+/// Keeps the tap empty while a stream is down so it resumes with live audio.
+pub fn discard_for(consumer: &mut Consumer<f32>, duration: Duration, stop: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline && !stop.load(Ordering::Relaxed) {
+        if let Ok(chunk) = consumer.read_chunk(consumer.slots()) {
+            chunk.commit_all();
+        }
+        thread::sleep(Duration::from_millis(STREAM_POLL_MS * 5));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_splits_path_query_and_body() {
+        let raw = b"POST /api/volume?token=abc HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\n{\"a\"trailing";
+        let request = read_request(&mut &raw[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/volume");
+        assert_eq!(request.query, "token=abc");
+        assert_eq!(request.header("content-length"), Some("4"));
+        assert_eq!(request.body, b"{\"a\"");
+
+        assert!(read_request(&mut &b"GARBAGE\r\n\r\n"[..]).is_err());
+        assert!(read_request(&mut &b"GET / HTTP/1.1\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_chunked_writer_frames_each_write() {
+        let mut writer = ChunkedWriter(Vec::new());
+        writer.write_all(&[7; 26]).unwrap();
+        assert_eq!(writer.write(&[]).unwrap(), 0);
+        writer.write_all(b"ok").unwrap();
+
+        let mut expected = b"1a\r\n".to_vec();
+        expected.extend_from_slice(&[7; 26]);
+        expected.extend_from_slice(b"\r\n2\r\nok\r\n");
+        assert_eq!(writer.0, expected);
+    }
+
+    #[test]
+    fn test_icecast_handshake_status() {
+        let mut continued = &b"HTTP/1.1 100 Continue\r\n\r\n"[..];
+        assert_eq!(read_response_status(&mut continued).unwrap(), 100);
+        let mut refused = &b"HTTP/1.0 403 Forbidden\r\nServer: Icecast 2.4.4\r\n\r\n"[..];
+        assert_eq!(read_response_status(&mut refused).unwrap(), 403);
+        assert!(read_response_status(&mut &b"ICY\r\n\r\n"[..]).is_err());
+
+        assert!(icecast_status(100, "/live").is_ok());
+        assert!(icecast_status(200, "/live").is_ok());
+        assert!(icecast_status(401, "/live").unwrap_err().contains("credentials"));
+        assert!(icecast_status(403, "/live").unwrap_err().contains("/live"));
+        assert!(icecast_status(500, "/live").is_err());
+    }
+}
@@ -0,0 +1,552 @@
+// Synchronized multi-room playback. The server stamps each block of the mix
+// with its own clock and sends it to every client over TCP; clients keep an
+// estimate of the server clock (NTP-style request/reply) and play each block a
+// fixed buffer after its stamp, so every room hears the same sample at the same
+// moment. Playback drift is corrected by dropping or repeating single frames.
+//
+// Wire format: `[kind u8][length u32 LE][payload]`.
+
+use crate::streaming::{self, stream_tap};
+use crate::tap;
+use crate::host;
+use crate::sample_format;
+use crate::dsp::Dither;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use rtrb::{Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 1705;
+
+const MSG_HELLO: u8 = 0;
+const MSG_AUDIO: u8 = 1;
+const MSG_TIME: u8 = 2;
+
+/// Largest message either side accepts.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const POLL_MS: u64 = 20;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// Blocks queued per client before it is considered stalled.
+const CLIENT_QUEUE_BLOCKS: usize = 250;
+
+/// Clock offset samples kept for the median.
+const OFFSET_SAMPLES: usize = 15;
+
+/// Drift tolerated before a frame is dropped or repeated.
+const MAX_DRIFT_US: i64 = 2000;
+
+/// Stamps follow the sample count; they are re-anchored to the clock only
+/// when capture stalls or jumps by more than this.
+const RESYNC_US: i64 = 50_000;
+
+/// Silence from the server for this long drops the connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SyncServerSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Delay between capture and playback on every client. Must cover network
+    /// jitter and the slowest client's output latency.
+    pub buffer_ms: u32,
+}
+
+impl Default for SyncServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            buffer_ms: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SyncClientSettings {
+    pub enabled: bool,
+    pub server: String,
+    pub port: u16,
+    /// Local output device the synced audio plays on.
+    pub device: String,
+}
+
+impl Default for SyncClientSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: String::new(),
+            port: DEFAULT_PORT,
+            device: String::new(),
+        }
+    }
+}
+
+/// Microseconds on this machine's monotonic clock, shared by server and
+/// client code.
+fn now_us() -> i64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as i64
+}
+
+fn write_message(stream: &mut impl Write, kind: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.push(kind);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+fn read_message(stream: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_MESSAGE {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "message too large"));
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+fn read_i64(bytes: &[u8], at: usize) -> Option<i64> {
+    bytes.get(at..at + 8).map(|b| i64::from_le_bytes(b.try_into().unwrap_or([0; 8])))
+}
+
+fn encode_audio(timestamp_us: i64, samples: &[f32]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(8 + samples.len() * 2);
+    payload.extend_from_slice(&timestamp_us.to_le_bytes());
+    for &sample in samples {
+        payload.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    payload
+}
+
+fn decode_audio(payload: &[u8]) -> Option<(i64, Vec<f32>)> {
+    let timestamp = read_i64(payload, 0)?;
+    let samples = payload[8..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+        .collect();
+    Some((timestamp, samples))
+}
+
+type ClientList = Arc<Mutex<Vec<Sender<(i64, Arc<Vec<f32>>)>>>>;
+
+/// Distributes the mix to sync clients.
+pub struct SyncServer {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl SyncServer {
+    pub fn start(settings: SyncServerSettings, channels: usize, sample_rate: u32) -> Result<(Self, Producer<f32>), String> {
+        let (producer, mut consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let clients: ClientList = Arc::new(Mutex::new(Vec::new()));
+
+        let hello = {
+            let mut payload = Vec::new();
+            payload.extend_from_slice(&sample_rate.to_le_bytes());
+            payload.extend_from_slice(&(channels as u16).to_le_bytes());
+            payload.extend_from_slice(&settings.buffer_ms.to_le_bytes());
+            payload
+        };
+
+        let accept = {
+            let stop_flag = stop.clone();
+            let clients = clients.clone();
+            streaming::spawn_tcp_listener(settings.port, stop.clone(), move |stream| {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                println!("Sync client connected: {}", peer);
+                if let Err(e) = serve_client(stream, &hello, &clients, &stop_flag) {
+                    println!("Sync client {} left: {}", peer, e);
+                }
+            })?
+        };
+        println!("Sync server on port {}", settings.port);
+
+        let fan_out = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                let mut anchor: Option<(i64, i64)> = None; // (timestamp, frames since)
+                while !stop.load(Ordering::Relaxed) {
+                    tap::drain_frames(&mut consumer, channels, &mut buf);
+                    if !buf.is_empty() {
+                        // Stamp with the capture time of the block's first frame,
+                        // counted in samples so drain jitter doesn't show
+                        let frames = (buf.len() / channels) as i64;
+                        let estimate = now_us() - frames * 1_000_000 / sample_rate as i64;
+                        let counted = anchor.map(|(t, n)| t + n * 1_000_000 / sample_rate as i64);
+                        let timestamp = match counted {
+                            Some(t) if (t - estimate).abs() < RESYNC_US => t,
+                            _ => {
+                                anchor = Some((estimate, 0));
+                                estimate
+                            }
+                        };
+                        if let Some((_, n)) = anchor.as_mut() {
+                            *n += frames;
+                        }
+                        let block = Arc::new(buf.clone());
+                        if let Ok(mut clients) = clients.lock() {
+                            clients.retain(|tx| tx.try_send((timestamp, block.clone())).is_ok());
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(POLL_MS));
+                }
+            })
+        };
+
+        Ok((Self { stop, threads: vec![fan_out, accept] }, producer))
+    }
+}
+
+impl Drop for SyncServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve_client(stream: TcpStream, hello: &[u8], clients: &ClientList, stop: &Arc<AtomicBool>) -> Result<(), String> {
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+    let writer = Arc::new(Mutex::new(stream));
+
+    if let Ok(mut w) = writer.lock() {
+        write_message(&mut *w, MSG_HELLO, hello).map_err(|e| e.to_string())?;
+    }
+
+    // Time requests are answered as soon as they arrive
+    let closed = Arc::new(AtomicBool::new(false));
+    let time_thread = {
+        let writer = writer.clone();
+        let closed = closed.clone();
+        thread::spawn(move || {
+            while let Ok((kind, payload)) = read_message(&mut reader) {
+                if kind != MSG_TIME {
+                    continue;
+                }
+                let mut reply = payload.get(..8).map(|b| b.to_vec()).unwrap_or_default();
+                reply.extend_from_slice(&now_us().to_le_bytes());
+                let sent = writer.lock().map(|mut w| write_message(&mut *w, MSG_TIME, &reply).is_ok());
+                if !matches!(sent, Ok(true)) {
+                    break;
+                }
+            }
+            closed.store(true, Ordering::Relaxed);
+        })
+    };
+
+    let (tx, rx) = bounded(CLIENT_QUEUE_BLOCKS);
+    if let Ok(mut clients) = clients.lock() {
+        clients.push(tx);
+    }
+    let mut result = Ok(());
+    while !stop.load(Ordering::Relaxed) && !closed.load(Ordering::Relaxed) {
+        match rx.recv_timeout(Duration::from_millis(POLL_MS * 5)) {
+            Ok((timestamp, block)) => {
+                let payload = encode_audio(timestamp, &block);
+                let sent = writer.lock().map_err(|e| e.to_string()).and_then(|mut w| {
+                    write_message(&mut *w, MSG_AUDIO, &payload).map_err(|e| e.to_string())
+                });
+                if let Err(e) = sent {
+                    result = Err(e);
+                    break;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    if let Ok(w) = writer.lock() {
+        let _ = w.shutdown(std::net::Shutdown::Both);
+    }
+    let _ = time_thread.join();
+    result
+}
+
+/// Server clock estimate: median of recent `server - client` offsets.
+#[derive(Default)]
+struct ClockOffset {
+    samples: VecDeque<i64>,
+}
+
+impl ClockOffset {
+    fn add(&mut self, client_sent: i64, server_time: i64, client_received: i64) -> i64 {
+        let offset = server_time - (client_sent + client_received) / 2;
+        self.samples.push_back(offset);
+        while self.samples.len() > OFFSET_SAMPLES {
+            self.samples.pop_front();
+        }
+        let mut sorted: Vec<i64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted[sorted.len() / 2]
+    }
+}
+
+/// Shared between the network thread and the output callback.
+struct PlaybackClock {
+    /// Server time of the first frame in the ring, or `i64::MIN` until known.
+    first_timestamp: AtomicI64,
+    /// Server minus client clock, in microseconds.
+    offset: AtomicI64,
+    buffer_us: AtomicI64,
+}
+
+/// Plays a sync server's stream on a local output device. Reconnects until
+/// dropped; the output stream is rebuilt with each connection.
+pub struct SyncClient {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SyncClient {
+    pub fn start(settings: SyncClientSettings) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let thread = thread::spawn(move || {
+            while !stop_flag.load(Ordering::Relaxed) {
+                if let Err(e) = run_client(&settings, &stop_flag) {
+                    eprintln!("Sync client error: {}", e);
+                }
+                let deadline = Instant::now() + RECONNECT_DELAY;
+                while Instant::now() < deadline && !stop_flag.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(POLL_MS * 5));
+                }
+            }
+        });
+        Self { stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for SyncClient {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_client(settings: &SyncClientSettings, stop: &AtomicBool) -> Result<(), String> {
+    let addr = (settings.server.as_str(), settings.port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", settings.server))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let (kind, hello) = read_message(&mut stream).map_err(|e| e.to_string())?;
+    if kind != MSG_HELLO || hello.len() < 10 {
+        return Err("Unexpected greeting from sync server".to_string());
+    }
+    let sample_rate = u32::from_le_bytes([hello[0], hello[1], hello[2], hello[3]]);
+    let channels = u16::from_le_bytes([hello[4], hello[5]]) as usize;
+    let buffer_ms = u32::from_le_bytes([hello[6], hello[7], hello[8], hello[9]]);
+    println!("Synced to {} ({} Hz, {} ch, {} ms buffer)", addr, sample_rate, channels, buffer_ms);
+
+    let clock = Arc::new(PlaybackClock {
+        first_timestamp: AtomicI64::new(i64::MIN),
+        offset: AtomicI64::new(0),
+        buffer_us: AtomicI64::new(buffer_ms as i64 * 1000),
+    });
+    let ring_size = (sample_rate as usize * channels * (buffer_ms as usize + 2000)) / 1000;
+    let (mut producer, consumer) = RingBuffer::<f32>::new(ring_size.max(16384));
+    let _output = open_output(&settings.device, channels, sample_rate, consumer, clock.clone())?;
+
+    let mut offsets = ClockOffset::default();
+    let mut synced = false;
+    let mut next_time_request = Instant::now();
+    let mut requests_sent = 0u32;
+    // Server time the next received frame should have, to detect gaps
+    let mut expected_timestamp: Option<i64> = None;
+
+    while !stop.load(Ordering::Relaxed) {
+        if Instant::now() >= next_time_request {
+            write_message(&mut stream, MSG_TIME, &now_us().to_le_bytes()).map_err(|e| e.to_string())?;
+            requests_sent += 1;
+            // Settle quickly, then keep tracking slowly
+            let interval = if requests_sent < OFFSET_SAMPLES as u32 { 100 } else { 1000 };
+            next_time_request = Instant::now() + Duration::from_millis(interval);
+        }
+
+        // Audio arrives every few milliseconds, so a timeout means the
+        // server is gone rather than idle
+        let (kind, payload) = read_message(&mut stream).map_err(|e| e.to_string())?;
+        match kind {
+            MSG_TIME => {
+                if let (Some(sent), Some(server)) = (read_i64(&payload, 0), read_i64(&payload, 8)) {
+                    let offset = offsets.add(sent, server, now_us());
+                    clock.offset.store(offset, Ordering::Relaxed);
+                    synced = true;
+                }
+            },
+            MSG_AUDIO if synced => {
+                let (timestamp, samples) = match decode_audio(&payload) {
+                    Some(block) => block,
+                    None => continue,
+                };
+                // After a gap the ring no longer lines up with the stamps, so
+                // start over from this block
+                let drift = expected_timestamp.map(|t| (timestamp - t).abs()).unwrap_or(i64::MAX);
+                if drift > MAX_DRIFT_US * 10 {
+                    clock.first_timestamp.store(i64::MIN, Ordering::Relaxed);
+                    expected_timestamp = None;
+                }
+                if producer.slots() < samples.len() {
+                    continue;
+                }
+                if clock.first_timestamp.load(Ordering::Relaxed) == i64::MIN && producer.is_empty() {
+                    clock.first_timestamp.store(timestamp, Ordering::Relaxed);
+                }
+                tap::push_frames(&mut producer, &samples, channels);
+                let frames = (samples.len() / channels.max(1)) as i64;
+                expected_timestamp = Some(timestamp + frames * 1_000_000 / sample_rate as i64);
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+fn open_output(
+    device_name: &str,
+    channels: usize,
+    sample_rate: u32,
+    mut consumer: rtrb::Consumer<f32>,
+    clock: Arc<PlaybackClock>,
+) -> Result<cpal::Stream, String> {
+    let host = host::current();
+    let device = host
+        .output_devices()
+        .map_err(|e| e.to_string())?
+        .find(|d| d.name().unwrap_or_default() == device_name)
+        .ok_or_else(|| format!("Device not found: {}", device_name))?;
+    let config = cpal::StreamConfig {
+        channels: channels as u16,
+        sample_rate: cpal::SampleRate(sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+
+    let frame_us = 1_000_000.0 / sample_rate as f64;
+    let mut played_frames: i64 = 0;
+    let mut playing = false;
+    let mut frame = vec![0.0f32; channels];
+
+    // Integer-only devices get the samples converted
+    let format = device.default_output_config().map(|c| c.sample_format()).unwrap_or(cpal::SampleFormat::F32);
+    let stream = sample_format::build_output_stream(
+        &device,
+        &config,
+        format,
+        Dither::Off,
+        move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+            let first = clock.first_timestamp.load(Ordering::Relaxed);
+            if first == i64::MIN {
+                playing = false;
+                played_frames = 0;
+                data.fill(0.0);
+                return;
+            }
+            let offset = clock.offset.load(Ordering::Relaxed);
+            let buffer = clock.buffer_us.load(Ordering::Relaxed);
+            let latency = info
+                .timestamp()
+                .playback
+                .duration_since(&info.timestamp().callback)
+                .map(|d| d.as_micros() as i64)
+                .unwrap_or(0);
+            // Client time at which this buffer's first frame is heard
+            let heard_at = now_us() + latency;
+
+            for (i, out) in data.chunks_mut(channels).enumerate() {
+                let due = first - offset + buffer + (played_frames as f64 * frame_us) as i64;
+                let error = heard_at + (i as f64 * frame_us) as i64 - due;
+                if !playing {
+                    if error < 0 || consumer.slots() < channels {
+                        out.fill(0.0);
+                        continue;
+                    }
+                    playing = true;
+                }
+
+                if error > MAX_DRIFT_US && consumer.slots() >= channels * 2 {
+                    // Behind: skip a frame
+                    for _ in 0..channels {
+                        let _ = consumer.pop();
+                    }
+                    played_frames += 1;
+                }
+                if error < -MAX_DRIFT_US {
+                    // Ahead: repeat the last frame without consuming
+                    out.copy_from_slice(&frame[..out.len()]);
+                    continue;
+                }
+                if consumer.slots() >= channels {
+                    for (c, sample) in frame.iter_mut().enumerate() {
+                        *sample = consumer.pop().unwrap_or(0.0);
+                        if let Some(o) = out.get_mut(c) {
+                            *o = *sample;
+                        }
+                    }
+                } else {
+                    out.fill(0.0);
+                }
+                played_frames += 1;
+            }
+        },
+        move |err| eprintln!("Sync output error: {}", err),
+    )
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_message_roundtrip() {
+        let payload = encode_audio(123_456, &[0.0, 0.5, -0.5]);
+        let (timestamp, samples) = decode_audio(&payload).unwrap();
+        assert_eq!(timestamp, 123_456);
+        assert_eq!(samples.len(), 3);
+        assert!((samples[1] - 0.5).abs() < 1e-3);
+
+        let mut wire = Vec::new();
+        write_message(&mut wire, MSG_AUDIO, &payload).unwrap();
+        let (kind, read) = read_message(&mut wire.as_slice()).unwrap();
+        assert_eq!(kind, MSG_AUDIO);
+        assert_eq!(read, payload);
+    }
+
+    #[test]
+    fn test_clock_offset_ignores_outliers() {
+        let mut offsets = ClockOffset::default();
+        offsets.add(0, 5_000, 0);
+        offsets.add(1_000, 6_000, 1_000);
+        // One slow round trip skews its own sample but not the median
+        assert_eq!(offsets.add(2_000, 7_000, 50_000), 5_000);
+    }
+}
@@ -0,0 +1,79 @@
+// Taps on the audio graph: the real-time side pushes whole blocks into a ring
+// buffer and a worker thread drains them in whole frames.
+
+use rtrb::{Consumer, Producer};
+use std::sync::Mutex;
+
+/// Same as `push_block`, for a tap the actor swaps under a lock. The block is
+/// dropped rather than wait while the actor holds it.
+pub fn push_to_tap(tap: &Mutex<Option<Producer<f32>>>, samples: &[f32]) {
+    if let Ok(mut tap) = tap.try_lock() {
+        if let Some(producer) = tap.as_mut() {
+            push_block(producer, samples);
+        }
+    }
+}
+
+/// Pushes a whole block into a tap, or drops it if the reader has fallen
+/// behind, so interleaved channels never get out of step.
+pub fn push_block(producer: &mut Producer<f32>, samples: &[f32]) {
+    if let Ok(chunk) = producer.write_chunk_uninit(samples.len()) {
+        chunk.fill_from_iter(samples.iter().copied());
+    }
+}
+
+/// Writes as many whole frames of `samples` as fit in one copy, dropping the
+/// rest. Returns the number of samples dropped.
+pub fn push_frames(producer: &mut Producer<f32>, samples: &[f32], channels: usize) -> usize {
+    let fits = producer.slots().min(samples.len());
+    let fits = fits - fits % channels.max(1);
+    if let Ok(chunk) = producer.write_chunk_uninit(fits) {
+        chunk.fill_from_iter(samples[..fits].iter().copied());
+    }
+    samples.len() - fits
+}
+
+/// Fills `out` from `consumer` in one copy, padding with silence when fewer
+/// samples are buffered. Returns true if it had to pad.
+pub fn pop_into(consumer: &mut Consumer<f32>, out: &mut [f32]) -> bool {
+    let available = consumer.slots().min(out.len());
+    if let Ok(chunk) = consumer.read_chunk(available) {
+        let (first, second) = chunk.as_slices();
+        out[..first.len()].copy_from_slice(first);
+        out[first.len()..available].copy_from_slice(second);
+        chunk.commit_all();
+    }
+    out[available..].fill(0.0);
+    available < out.len()
+}
+
+/// Replaces `buf` with every whole frame currently buffered in `consumer`.
+pub fn drain_frames(consumer: &mut Consumer<f32>, channels: usize, buf: &mut Vec<f32>) {
+    buf.clear();
+    let available = consumer.slots() - consumer.slots() % channels;
+    if let Ok(chunk) = consumer.read_chunk(available) {
+        let (first, second) = chunk.as_slices();
+        buf.extend_from_slice(first);
+        buf.extend_from_slice(second);
+        chunk.commit_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtrb::RingBuffer;
+
+    #[test]
+    fn test_whole_frames_across_the_wrap() {
+        let (mut producer, mut consumer) = RingBuffer::new(5);
+        assert_eq!(push_frames(&mut producer, &[0.1, 0.2, 0.3, 0.4], 2), 0);
+        let mut out = [0.0; 2];
+        assert!(!pop_into(&mut consumer, &mut out));
+        // Three slots free: only one stereo frame fits
+        assert_eq!(push_frames(&mut producer, &[0.5, 0.6, 0.7, 0.8], 2), 2);
+        let mut out = [1.0; 6];
+        assert!(pop_into(&mut consumer, &mut out));
+        assert_eq!(out, [0.3, 0.4, 0.5, 0.6, 0.0, 0.0]);
+    }
+}
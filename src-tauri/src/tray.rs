@@ -0,0 +1,305 @@
+// Tray icon. Its icon and tooltip follow the engine state (capturing,
+// stopped, master muted, or an output error), updated from audio events.
+// The state icons are tinted copies of the app icon. The menu has capture
+// control, master volume steps, a mute toggle per active output and the
+// saved profiles, and is rebuilt whenever those change. Tauri's tray doesn't report
+// scroll-wheel events, so volume steps are menu entries rather than wheel
+// gestures; the tooltip shows the resulting level either way.
+
+use crate::api;
+use crate::audio::{AudioEvent, AudioStateSnapshot};
+use crate::config;
+use serde_json::json;
+use std::sync::Mutex;
+use tauri::image::Image;
+use tauri::menu::{CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager};
+
+const TRAY_ID: &str = "main";
+/// Menu id prefixes of the per-output and profile entries.
+const MUTE_PREFIX: &str = "mute:";
+const PROFILE_PREFIX: &str = "profile:";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TrayStatus {
+    Capturing,
+    Stopped,
+    Muted,
+    Error,
+}
+
+#[derive(Default)]
+struct Status {
+    capturing: bool,
+    master_muted: bool,
+    master_volume: f32,
+    /// Last output or recording failure, cleared when capture restarts.
+    error: Option<String>,
+}
+
+impl Status {
+    fn current(&self) -> TrayStatus {
+        if self.error.is_some() {
+            TrayStatus::Error
+        } else if self.master_muted {
+            TrayStatus::Muted
+        } else if self.capturing {
+            TrayStatus::Capturing
+        } else {
+            TrayStatus::Stopped
+        }
+    }
+
+    fn tooltip(&self) -> String {
+        let state = match self.current() {
+            TrayStatus::Capturing => "Capturing".to_string(),
+            TrayStatus::Stopped => "Stopped".to_string(),
+            TrayStatus::Muted => "Muted".to_string(),
+            TrayStatus::Error => self.error.clone().unwrap_or_default(),
+        };
+        format!("Audio Merge - {} ({:.0}%)", state, self.master_volume * 100.0)
+    }
+
+    /// Updates from an audio event; true if the tray needs redrawing.
+    fn apply(&mut self, event: &AudioEvent) -> bool {
+        match event {
+            AudioEvent::CaptureStarted => {
+                self.capturing = true;
+                self.error = None;
+            },
+            AudioEvent::CaptureStopped => self.capturing = false,
+            AudioEvent::MasterMuteChanged { muted } => self.master_muted = *muted,
+            AudioEvent::OutputDisconnected { device, .. } => self.error = Some(format!("{} disconnected", device)),
+            AudioEvent::RecordingFailed { error } => self.error = Some(format!("Recording failed: {}", error)),
+            AudioEvent::StreamError { stream, error } => self.error = Some(format!("{}: {}", stream, error)),
+            AudioEvent::EnginePanicked { reason, .. } => {
+                self.capturing = false;
+                self.error = Some(format!("Audio engine crashed: {}", reason));
+            },
+            AudioEvent::CaptureExclusionsRejected { error } => self.error = Some(error.to_string()),
+            AudioEvent::StreamStalled { stream } => self.error = Some(format!("{} stopped responding", stream)),
+            AudioEvent::StreamRecoveryFailed { stream, error } => self.error = Some(format!("{} stopped: {}", stream, error)),
+            AudioEvent::OutputAdded { .. } => self.error = None,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Engine state shown by the tray, managed as app state.
+#[derive(Default)]
+pub struct TrayState {
+    status: Mutex<Status>,
+}
+
+/// Creates the tray icon; `master_muted` is the restored master state.
+pub fn build(app: &AppHandle, master_muted: bool) -> tauri::Result<()> {
+    let state = crate::get_audio_state(app.state()).ok();
+    let menu = build_menu(app, state.as_ref())?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID).menu(&menu);
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, .. } = event {
+                crate::show_main_window(tray.app_handle());
+            }
+        })
+        .build(app)?;
+
+    if let Ok(mut status) = app.state::<TrayState>().status.lock() {
+        status.master_muted = master_muted;
+        status.master_volume = state.map_or(1.0, |s| s.master_volume);
+    }
+    refresh(app);
+    Ok(())
+}
+
+pub fn on_event(app: &AppHandle, event: &AudioEvent) {
+    let changed = app.state::<TrayState>().status.lock().is_ok_and(|mut s| s.apply(event));
+    if changed {
+        refresh(app);
+    }
+    if matches!(
+        event,
+        AudioEvent::CaptureStarted
+            | AudioEvent::CaptureStopped
+            | AudioEvent::OutputAdded { .. }
+            | AudioEvent::OutputRemoved { .. }
+            | AudioEvent::OutputMuteChanged { .. }
+    ) {
+        refresh_menu(app);
+    }
+}
+
+/// Rebuilds the menu, e.g. after the saved profiles changed.
+pub fn refresh_menu(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let state = crate::get_audio_state(app.state()).ok();
+    if let Some(volume) = state.as_ref().map(|s| s.master_volume) {
+        set_master_volume(app, volume);
+    }
+    match build_menu(app, state.as_ref()) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        },
+        Err(e) => eprintln!("Failed to update the tray menu: {}", e),
+    }
+}
+
+fn build_menu(app: &AppHandle, state: Option<&AudioStateSnapshot>) -> tauri::Result<Menu<tauri::Wry>> {
+    let capturing = state.is_some_and(|s| s.capturing);
+    let capture_text = if capturing { "Stop Capture" } else { "Start Capture" };
+    let capture_i = MenuItem::with_id(app, "capture", capture_text, state.is_some(), None::<&str>)?;
+    let volume_up_i = MenuItem::with_id(app, "volume-up", "Master Volume Up", true, None::<&str>)?;
+    let volume_down_i = MenuItem::with_id(app, "volume-down", "Master Volume Down", true, None::<&str>)?;
+
+    let mut outputs = Vec::new();
+    for name in state.map(|s| s.outputs.as_slice()).unwrap_or_default() {
+        let muted = state.is_some_and(|s| s.muted_outputs.contains(name));
+        let label = format!("Mute {}", name);
+        outputs.push(CheckMenuItem::with_id(app, format!("{}{}", MUTE_PREFIX, name), label, true, muted, None::<&str>)?);
+    }
+
+    let config = config::load_config(app);
+    let mut profile_items = Vec::new();
+    for profile in &config.profiles {
+        let active = config.active_profile.as_ref() == Some(&profile.name);
+        let id = format!("{}{}", PROFILE_PREFIX, profile.name);
+        profile_items.push(CheckMenuItem::with_id(app, id, &profile.name, true, active, None::<&str>)?);
+    }
+    let no_profiles = MenuItem::with_id(app, "no-profiles", "No profiles", false, None::<&str>)?;
+    let mut profile_refs: Vec<&dyn IsMenuItem<tauri::Wry>> =
+        profile_items.iter().map(|i| i as &dyn IsMenuItem<tauri::Wry>).collect();
+    if profile_refs.is_empty() {
+        profile_refs.push(&no_profiles);
+    }
+    let profiles_menu = Submenu::with_id_and_items(app, "profiles", "Profiles", true, &profile_refs)?;
+
+    let show_i = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+    let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let separator2 = PredefinedMenuItem::separator(app)?;
+
+    let mut items: Vec<&dyn IsMenuItem<tauri::Wry>> = vec![&capture_i, &volume_up_i, &volume_down_i];
+    if !outputs.is_empty() {
+        items.push(&separator);
+        items.extend(outputs.iter().map(|i| i as &dyn IsMenuItem<tauri::Wry>));
+    }
+    items.extend([&separator2 as &dyn IsMenuItem<tauri::Wry>, &profiles_menu, &show_i, &quit_i]);
+    Menu::with_items(app, &items)
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id.as_ref();
+    let result = match id {
+        "quit" => {
+            app.exit(0);
+            Ok(())
+        },
+        "show" => {
+            crate::show_main_window(app);
+            Ok(())
+        },
+        "capture" => api::execute(app, "toggle_capture", &json!({})).map(|_| ()),
+        "volume-up" => nudge_master_volume(app, 1.0),
+        "volume-down" => nudge_master_volume(app, -1.0),
+        _ => {
+            if let Some(name) = id.strip_prefix(MUTE_PREFIX) {
+                api::execute(app, "toggle_device_mute", &json!({ "device_name": name })).map(|_| ())
+            } else if let Some(name) = id.strip_prefix(PROFILE_PREFIX) {
+                let result = api::execute(app, "apply_profile", &json!({ "name": name })).map(|_| ());
+                if result.is_err() {
+                    // The click already toggled the check mark
+                    refresh_menu(app);
+                }
+                result
+            } else {
+                Ok(())
+            }
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("Tray action {} failed: {}", id, e);
+    }
+}
+
+fn refresh(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let (status, tooltip) = match app.state::<TrayState>().status.lock() {
+        Ok(s) => (s.current(), s.tooltip()),
+        Err(_) => return,
+    };
+    if let Some(icon) = app.default_window_icon().map(|base| tinted(base, status)) {
+        let _ = tray.set_icon(Some(icon));
+    }
+    let _ = tray.set_tooltip(Some(tooltip));
+}
+
+/// Moves the master fader by the configured step in `direction`.
+fn nudge_master_volume(app: &AppHandle, direction: f32) -> Result<(), String> {
+    let step = config::load_config(app).tray_volume_step * direction;
+    let result = api::execute(app, "nudge_master_volume", &json!({ "step": step }))?;
+    if let Some(volume) = result["volume"].as_f64() {
+        set_master_volume(app, volume as f32);
+    }
+    Ok(())
+}
+
+fn set_master_volume(app: &AppHandle, volume: f32) {
+    let changed = app.state::<TrayState>().status.lock().is_ok_and(|mut s| {
+        let changed = s.master_volume != volume;
+        s.master_volume = volume;
+        changed
+    });
+    if changed {
+        refresh(app);
+    }
+}
+
+/// The app icon recolored for a status: as is while capturing, grey when
+/// stopped, faded when muted and red on errors.
+fn tinted(base: &Image<'_>, status: TrayStatus) -> Image<'static> {
+    let mut rgba = base.rgba().to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        let grey = ((r as u32 * 30 + g as u32 * 59 + b as u32 * 11) / 100) as u8;
+        let recolored = match status {
+            TrayStatus::Capturing => [r, g, b, a],
+            TrayStatus::Stopped => [grey, grey, grey, a],
+            TrayStatus::Muted => [grey, grey, grey, a / 2],
+            TrayStatus::Error => [grey.saturating_add(96), grey / 3, grey / 3, a],
+        };
+        pixel.copy_from_slice(&recolored);
+    }
+    Image::new_owned(rgba, base.width(), base.height())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_follows_events() {
+        let mut status = Status { master_volume: 0.5, ..Status::default() };
+        assert_eq!(status.current(), TrayStatus::Stopped);
+        assert!(status.apply(&AudioEvent::CaptureStarted));
+        assert_eq!(status.current(), TrayStatus::Capturing);
+        status.apply(&AudioEvent::MasterMuteChanged { muted: true });
+        assert_eq!(status.current(), TrayStatus::Muted);
+        status.apply(&AudioEvent::OutputDisconnected { device: "Headphones".to_string(), error: String::new() });
+        assert_eq!(status.current(), TrayStatus::Error);
+        assert_eq!(status.tooltip(), "Audio Merge - Headphones disconnected (50%)");
+        assert!(!status.apply(&AudioEvent::RecordingPaused));
+        status.apply(&AudioEvent::CaptureStarted);
+        assert_eq!(status.current(), TrayStatus::Muted);
+    }
+}
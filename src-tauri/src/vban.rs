@@ -0,0 +1,398 @@
+// VBAN (VB-Audio network protocol) output and input. A VBAN output behaves
+// like any other entry in the output set; it is named by URL, e.g.
+// `vban://192.168.1.20:6980/Stream1`, and paced by its own clock instead of a
+// device callback. The receiver feeds an incoming stream into the mix the same
+// way the mic is.
+
+use crate::bus::{Converter, Format};
+use crate::mic::MicSource;
+use rtrb::RingBuffer;
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const URL_PREFIX: &str = "vban://";
+
+pub const DEFAULT_PORT: u16 = 6980;
+
+const HEADER_SIZE: usize = 28;
+
+/// Stream names are fixed 16-byte, zero-padded fields.
+const STREAM_NAME_SIZE: usize = 16;
+
+/// Protocol limits on one packet's payload.
+const MAX_DATA_BYTES: usize = 1436;
+const MAX_FRAMES_PER_PACKET: usize = 256;
+
+/// Sub-protocol 0 (audio), 16-bit signed PCM.
+const FORMAT_INT16: u8 = 0x01;
+
+/// Largest datagram a sender may emit.
+const MAX_PACKET_SIZE: usize = HEADER_SIZE + MAX_DATA_BYTES;
+
+/// Capacity of the ring between the receiver and the capture callback.
+const RECEIVE_BUFFER_SIZE: usize = 16384;
+
+/// How often the receiver checks whether it should stop.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Sample rates in protocol index order.
+const SAMPLE_RATES: [u32; 21] = [
+    6000, 12000, 24000, 48000, 96000, 192000, 384000, 8000, 16000, 32000, 64000, 128000, 256000, 512000, 11025,
+    22050, 44100, 88200, 176400, 352800, 705600,
+];
+
+/// Where a VBAN output sends its packets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VbanTarget {
+    pub host: String,
+    pub port: u16,
+    pub stream_name: String,
+}
+
+impl VbanTarget {
+    /// Output name for this target, the inverse of `parse_url`.
+    pub fn url(&self) -> String {
+        format!("{}{}:{}/{}", URL_PREFIX, self.host, self.port, self.stream_name)
+    }
+}
+
+/// Parses an output name of the form `vban://host[:port]/StreamName`.
+pub fn parse_url(name: &str) -> Option<VbanTarget> {
+    let rest = name.strip_prefix(URL_PREFIX)?;
+    let (authority, stream_name) = rest.split_once('/')?;
+    if authority.is_empty() || stream_name.is_empty() || stream_name.len() > STREAM_NAME_SIZE {
+        return None;
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, DEFAULT_PORT),
+    };
+    Some(VbanTarget {
+        host: host.to_string(),
+        port,
+        stream_name: stream_name.to_string(),
+    })
+}
+
+pub fn is_vban_url(name: &str) -> bool {
+    name.starts_with(URL_PREFIX)
+}
+
+fn sample_rate_index(sample_rate: u32) -> Option<u8> {
+    SAMPLE_RATES.iter().position(|&r| r == sample_rate).map(|i| i as u8)
+}
+
+fn frames_per_packet(channels: usize) -> usize {
+    (MAX_DATA_BYTES / (2 * channels)).min(MAX_FRAMES_PER_PACKET)
+}
+
+fn write_header(packet: &mut Vec<u8>, rate_index: u8, frames: usize, channels: usize, stream_name: &str, counter: u32) {
+    packet.extend_from_slice(b"VBAN");
+    packet.push(rate_index);
+    packet.push((frames - 1) as u8);
+    packet.push((channels - 1) as u8);
+    packet.push(FORMAT_INT16);
+    let mut name = [0u8; STREAM_NAME_SIZE];
+    let bytes = stream_name.as_bytes();
+    let len = bytes.len().min(STREAM_NAME_SIZE);
+    name[..len].copy_from_slice(&bytes[..len]);
+    packet.extend_from_slice(&name);
+    packet.extend_from_slice(&counter.to_le_bytes());
+}
+
+/// Incoming VBAN stream mixed into the bus alongside the loopback.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct VbanReceiverSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub stream_name: String,
+    /// Only accept packets from this host, if set.
+    pub source_ip: Option<String>,
+    /// Linear gain applied to the received audio.
+    pub volume: f32,
+}
+
+impl Default for VbanReceiverSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            stream_name: "Stream1".to_string(),
+            source_ip: None,
+            volume: 1.0,
+        }
+    }
+}
+
+/// Audio carried by one packet, as interleaved f32.
+#[derive(Debug, PartialEq)]
+struct AudioPacket<'a> {
+    sample_rate: u32,
+    channels: usize,
+    stream_name: &'a str,
+    samples: Vec<f32>,
+}
+
+/// Parses an audio packet. Returns `None` for other sub-protocols, codecs
+/// other than PCM, and malformed packets.
+fn parse_packet(packet: &[u8]) -> Option<AudioPacket<'_>> {
+    if packet.len() < HEADER_SIZE || &packet[..4] != b"VBAN" {
+        return None;
+    }
+    // Sub-protocol in the top bits; 0 is audio
+    if packet[4] & 0xe0 != 0 || packet[7] & 0xf0 != 0 {
+        return None;
+    }
+    let sample_rate = *SAMPLE_RATES.get((packet[4] & 0x1f) as usize)?;
+    let frames = packet[5] as usize + 1;
+    let channels = packet[6] as usize + 1;
+    let name = &packet[8..8 + STREAM_NAME_SIZE];
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(STREAM_NAME_SIZE);
+    let stream_name = std::str::from_utf8(&name[..name_len]).ok()?;
+
+    let data = &packet[HEADER_SIZE..];
+    let count = frames * channels;
+    let samples: Vec<f32> = match packet[7] & 0x07 {
+        0 => data.get(..count)?.iter().map(|&b| (b as f32 - 128.0) / 128.0).collect(),
+        1 => data
+            .get(..count * 2)?
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        2 => data
+            .get(..count * 3)?
+            .chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0)
+            .collect(),
+        3 => data
+            .get(..count * 4)?
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0)
+            .collect(),
+        4 => data
+            .get(..count * 4)?
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        5 => data
+            .get(..count * 8)?
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap_or([0; 8])) as f32)
+            .collect(),
+        _ => return None,
+    };
+
+    Some(AudioPacket { sample_rate, channels, stream_name, samples })
+}
+
+/// Listens for one VBAN stream and hands it to the capture callback.
+pub struct VbanReceiver {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VbanReceiver {
+    /// Received audio is converted into the bus format, whatever layout and
+    /// rate the sender uses.
+    pub fn start(settings: VbanReceiverSettings, channels: usize, sample_rate: u32) -> Result<(Self, MicSource), String> {
+        let socket = UdpSocket::bind(("0.0.0.0", settings.port)).map_err(|e| format!("Port {}: {}", settings.port, e))?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT)).map_err(|e| e.to_string())?;
+        let source_ip = match &settings.source_ip {
+            Some(host) => Some(
+                (host.as_str(), 0)
+                    .to_socket_addrs()
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .ok_or_else(|| format!("Could not resolve {}", host))?
+                    .ip(),
+            ),
+            None => None,
+        };
+
+        let (mut producer, consumer) = RingBuffer::<f32>::new(RECEIVE_BUFFER_SIZE);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        println!("Listening for VBAN stream '{}' on port {}", settings.stream_name, settings.port);
+
+        let thread = thread::spawn(move || {
+            let mut buf = [0u8; MAX_PACKET_SIZE];
+            let bus = Format::new(channels, sample_rate);
+            // Rebuilt whenever the sender changes its format
+            let mut converter: Option<(Format, Converter)> = None;
+            let mut converted = Vec::new();
+            while !stop_flag.load(Ordering::Relaxed) {
+                let (len, from) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                    Err(e) => {
+                        eprintln!("VBAN receive error: {}", e);
+                        continue;
+                    }
+                };
+                if source_ip.is_some_and(|ip| ip != from.ip()) {
+                    continue;
+                }
+                let packet = match parse_packet(&buf[..len]) {
+                    Some(p) if p.stream_name == settings.stream_name => p,
+                    _ => continue,
+                };
+                let format = Format::new(packet.channels, packet.sample_rate);
+                if !matches!(&converter, Some((current, _)) if *current == format) {
+                    println!("VBAN stream is {} channels at {} Hz", packet.channels, packet.sample_rate);
+                    converter = Some((format, Converter::into_bus(format, bus)));
+                }
+                let Some((_, into_bus)) = converter.as_mut() else {
+                    continue;
+                };
+                converted.clear();
+                into_bus.push(&packet.samples, &mut converted);
+
+                // Drop whole packets so channels stay aligned
+                if producer.slots() < converted.len() {
+                    continue;
+                }
+                for &sample in &converted {
+                    let _ = producer.push(sample * settings.volume);
+                }
+            }
+        });
+
+        Ok((Self { stop, thread: Some(thread) }, MicSource::from_consumer(consumer, channels, sample_rate)))
+    }
+}
+
+impl Drop for VbanReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sends whatever `render` produces as VBAN packets, in real time.
+pub struct VbanSender {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VbanSender {
+    /// `render` fills one packet's worth of interleaved frames, just like an
+    /// output device callback.
+    pub fn start<F>(target: VbanTarget, channels: usize, sample_rate: u32, mut render: F) -> Result<Self, String>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
+        let rate_index = sample_rate_index(sample_rate)
+            .ok_or_else(|| format!("VBAN does not support {} Hz", sample_rate))?;
+        if channels == 0 || channels > 256 {
+            return Err(format!("VBAN does not support {} channels", channels));
+        }
+        let addr: SocketAddr = (target.host.as_str(), target.port)
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("Could not resolve {}", target.host))?;
+        let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind).map_err(|e| e.to_string())?;
+        socket.set_broadcast(true).map_err(|e| e.to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let thread = thread::spawn(move || {
+            let frames = frames_per_packet(channels);
+            let packet_duration = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+            let mut buf = vec![0.0f32; frames * channels];
+            let mut packet = Vec::with_capacity(HEADER_SIZE + buf.len() * 2);
+            let mut counter: u32 = 0;
+            let mut next = Instant::now();
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                }
+                next += packet_duration;
+
+                render(&mut buf);
+                packet.clear();
+                write_header(&mut packet, rate_index, frames, channels, &target.stream_name, counter);
+                for &sample in &buf {
+                    let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    packet.extend_from_slice(&value.to_le_bytes());
+                }
+                if let Err(e) = socket.send_to(&packet, addr) {
+                    eprintln!("VBAN send error: {}", e);
+                }
+                counter = counter.wrapping_add(1);
+            }
+        });
+
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for VbanSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("vban://192.168.1.20/Stream1"),
+            Some(VbanTarget { host: "192.168.1.20".into(), port: DEFAULT_PORT, stream_name: "Stream1".into() })
+        );
+        assert_eq!(parse_url("vban://studio.local:7000/Mix").map(|t| t.port), Some(7000));
+        assert_eq!(parse_url("vban://192.168.1.20"), None);
+        assert_eq!(parse_url("Speakers (Realtek)"), None);
+
+        let target = parse_url("vban://10.0.0.5:6981/Mix").unwrap();
+        assert_eq!(parse_url(&target.url()), Some(target));
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let mut packet = Vec::new();
+        write_header(&mut packet, sample_rate_index(48000).unwrap(), 256, 2, "Stream1", 7);
+        assert_eq!(packet.len(), HEADER_SIZE);
+        assert_eq!(&packet[..4], b"VBAN");
+        assert_eq!(packet[4], 3);
+        assert_eq!(packet[5], 255);
+        assert_eq!(packet[6], 1);
+        assert_eq!(&packet[8..15], b"Stream1");
+        assert_eq!(&packet[24..], &7u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_parse_packet_roundtrip() {
+        let mut packet = Vec::new();
+        write_header(&mut packet, sample_rate_index(44100).unwrap(), 2, 2, "Desk", 0);
+        for value in [0i16, 16384, -16384, i16::MAX] {
+            packet.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let parsed = parse_packet(&packet).unwrap();
+        assert_eq!(parsed.sample_rate, 44100);
+        assert_eq!(parsed.channels, 2);
+        assert_eq!(parsed.stream_name, "Desk");
+        assert_eq!(parsed.samples.len(), 4);
+        assert!((parsed.samples[1] - 0.5).abs() < 1e-4);
+
+        // Truncated payload
+        assert!(parse_packet(&packet[..packet.len() - 1]).is_none());
+    }
+}
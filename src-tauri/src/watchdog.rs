@@ -0,0 +1,72 @@
+// Watchdog for streams whose callback stopped firing without an error, e.g. a
+// device that went away silently. Each callback already bumps its stream's
+// callback counter; the actor compares the counters about once a second.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A stream whose callback count hasn't moved for this long is stalled. Long
+/// enough for slow devices (Bluetooth) to deliver their first callback.
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the counters are compared.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Heartbeat {
+    callbacks: u64,
+    changed: Instant,
+}
+
+pub struct Watchdog {
+    streams: HashMap<String, Heartbeat>,
+    next_check: Instant,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self { streams: HashMap::new(), next_check: Instant::now() }
+    }
+
+    /// Compares the callback counts of the watched streams with the previous
+    /// check, returning the streams that have stalled. Streams missing from
+    /// `streams` stop being watched.
+    pub fn check(&mut self, streams: Vec<(String, u64)>, now: Instant) -> Vec<String> {
+        if now < self.next_check {
+            return Vec::new();
+        }
+        self.next_check = now + CHECK_INTERVAL;
+        self.streams.retain(|name, _| streams.iter().any(|(n, _)| n == name));
+        let mut stalled = Vec::new();
+        for (name, callbacks) in streams {
+            let beat = self.streams.entry(name.clone()).or_insert(Heartbeat { callbacks, changed: now });
+            if beat.callbacks != callbacks {
+                beat.callbacks = callbacks;
+                beat.changed = now;
+            } else if now.duration_since(beat.changed) >= STALL_TIMEOUT {
+                stalled.push(name);
+            }
+        }
+        for name in &stalled {
+            self.streams.remove(name);
+        }
+        stalled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_a_silent_stream_once() {
+        let mut watchdog = Watchdog::new();
+        let start = Instant::now();
+        let mut stalled = Vec::new();
+        for second in 0..=STALL_TIMEOUT.as_secs() {
+            let now = start + Duration::from_secs(second);
+            let streams = vec![("Speakers".to_string(), second), ("Headphones".to_string(), 7)];
+            stalled.extend(watchdog.check(streams, now));
+        }
+        assert_eq!(stalled, ["Headphones"]);
+    }
+}
@@ -0,0 +1,130 @@
+// Webhooks fired on audio events, for notifications in Discord, Slack, Home
+// Assistant and the like. Each hook can be limited to some event types and
+// shaped for the receiving service; deliveries run on their own threads so a
+// slow endpoint never holds up the app.
+
+use crate::audio::AudioEvent;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Webhook {
+    pub url: String,
+    /// Event types to send, as in the `type` field of audio events, e.g.
+    /// "capture_started". Every event is sent when empty.
+    pub events: Vec<String>,
+    pub format: WebhookFormat,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self { url: String::new(), events: Vec::new(), format: WebhookFormat::Json }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The event as JSON, plus a readable `message`.
+    Json,
+    /// A Discord webhook message.
+    Discord,
+    /// A Slack incoming webhook message.
+    Slack,
+}
+
+/// Configured hooks, managed as app state and fed every audio event.
+#[derive(Default)]
+pub struct WebhookService {
+    hooks: Mutex<Vec<Webhook>>,
+}
+
+impl WebhookService {
+    pub fn set(&self, hooks: Vec<Webhook>) {
+        if let Ok(mut current) = self.hooks.lock() {
+            *current = hooks;
+        }
+    }
+
+    pub fn fire(&self, event: &AudioEvent) {
+        let hooks = self.hooks.lock().map(|h| h.clone()).unwrap_or_default();
+        let data = json!(event);
+        let kind = data["type"].as_str().unwrap_or_default().to_string();
+        for hook in hooks {
+            if !hook.events.is_empty() && !hook.events.contains(&kind) {
+                continue;
+            }
+            let body = payload(hook.format, &data, &describe(event));
+            thread::spawn(move || {
+                let result = ureq::post(&hook.url)
+                    .timeout(DELIVERY_TIMEOUT)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body.to_string());
+                if let Err(e) = result {
+                    eprintln!("Webhook {} failed: {}", hook.url, e);
+                }
+            });
+        }
+    }
+}
+
+fn payload(format: WebhookFormat, event: &Value, message: &str) -> Value {
+    match format {
+        WebhookFormat::Json => json!({ "event": event, "message": message }),
+        WebhookFormat::Discord => json!({ "username": "Audio Merge", "content": message }),
+        WebhookFormat::Slack => json!({ "text": message }),
+    }
+}
+
+/// One-line description of an event for chat messages.
+fn describe(event: &AudioEvent) -> String {
+    match event {
+        AudioEvent::VolumeChanged { device, volume } => format!("{} volume set to {:.0}%", device, volume * 100.0),
+        AudioEvent::RecordingStarted { path } => format!("Recording started: {}", path),
+        AudioEvent::RecordingStopped { path } => format!("Recording finished: {}", path),
+        AudioEvent::RecordingFailed { error } => format!("Recording failed: {}", error),
+        AudioEvent::ReplaySaved { path } => format!("Replay saved: {}", path),
+        AudioEvent::RecordingPaused => "Recording paused".to_string(),
+        AudioEvent::RecordingResumed => "Recording resumed".to_string(),
+        AudioEvent::CaptureStarted => "Capture started".to_string(),
+        AudioEvent::CaptureStopped => "Capture stopped".to_string(),
+        AudioEvent::OutputAdded { device } => format!("Output added: {}", device),
+        AudioEvent::OutputRemoved { device } => format!("Output removed: {}", device),
+        AudioEvent::MasterMuteChanged { muted: true } => "Master muted".to_string(),
+        AudioEvent::MasterMuteChanged { muted: false } => "Master unmuted".to_string(),
+        AudioEvent::OutputMuteChanged { device, muted: true } => format!("{} muted", device),
+        AudioEvent::OutputMuteChanged { device, muted: false } => format!("{} unmuted", device),
+        AudioEvent::StreamError { stream, error } => format!("Audio error on {}: {}", stream, error),
+        AudioEvent::OutputDisconnected { device, .. } => format!("Output disconnected: {}", device),
+        AudioEvent::EnginePanicked { reason, restarted: true, .. } => format!("Audio engine restarted after a crash: {}", reason),
+        AudioEvent::EnginePanicked { reason, restarted: false, .. } => format!("Audio engine stopped after repeated crashes: {}", reason),
+        AudioEvent::CaptureExclusionsRejected { error } => error.to_string(),
+        AudioEvent::StreamStalled { stream } => format!("{} stopped responding", stream),
+        AudioEvent::StreamRecoveryFailed { stream, error } => format!("Could not recover {}: {}", stream, error),
+        AudioEvent::ClippingDetected { peak } => {
+            format!("Clipping detected on the mix (peak {:+.1} dBFS)", 20.0 * peak.log10())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_formats() {
+        let event = AudioEvent::OutputDisconnected { device: "Headphones".to_string(), error: "gone".to_string() };
+        let data = json!(event);
+        let message = describe(&event);
+        assert_eq!(message, "Output disconnected: Headphones");
+        assert_eq!(payload(WebhookFormat::Json, &data, &message)["event"]["type"], "output_disconnected");
+        assert_eq!(payload(WebhookFormat::Discord, &data, &message)["content"], message);
+        assert_eq!(payload(WebhookFormat::Slack, &data, &message)["text"], message);
+    }
+}
@@ -0,0 +1,341 @@
+// WebRTC monitoring of the mix. A small HTTP endpoint serves a listener page
+// and answers its SDP offer; every peer shares one Opus track, so the audio is
+// encoded once however many browsers are connected.
+
+use crate::encoder::{OpusApplication, OpusPacketEncoder, OpusSettings, OPUS_SAMPLE_RATE};
+use crate::streaming::{self, read_request, stream_tap, write_response, HttpRequest};
+use crate::tap;
+use rtrb::Producer;
+use serde::{Deserialize, Serialize};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::async_runtime::block_on;
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_OPUS};
+use webrtc::api::{APIBuilder, API};
+use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+const PACKET_POLL_MS: u64 = 5;
+
+/// Page served at `/` that connects and plays the mix.
+const LISTENER_PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width">
+<title>Audio Merge</title></head>
+<body style="font-family:sans-serif;text-align:center;padding:2em">
+<button id="play" style="font-size:1.5em">Listen</button>
+<audio id="out" autoplay></audio>
+<script>
+document.getElementById('play').onclick = async () => {
+  const pc = new RTCPeerConnection();
+  pc.addTransceiver('audio', { direction: 'recvonly' });
+  pc.ontrack = e => { document.getElementById('out').srcObject = e.streams[0]; };
+  await pc.setLocalDescription(await pc.createOffer());
+  await new Promise(r => {
+    if (pc.iceGatheringState === 'complete') return r();
+    pc.onicegatheringstatechange = () => pc.iceGatheringState === 'complete' && r();
+  });
+  const res = await fetch('/offer', { method: 'POST', body: JSON.stringify(pc.localDescription) });
+  await pc.setRemoteDescription(await res.json());
+};
+</script></body></html>"#;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WebRtcSettings {
+    pub enabled: bool,
+    /// Port of the signaling endpoint and listener page.
+    pub port: u16,
+    pub bitrate_kbps: u32,
+    pub opus: OpusSettings,
+}
+
+impl Default for WebRtcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8092,
+            bitrate_kbps: 96,
+            // Browsers decode Opus FEC, which covers the odd lost packet on Wi-Fi
+            opus: OpusSettings {
+                fec: true,
+                expected_loss_percent: 5,
+                ..OpusSettings::default()
+            },
+        }
+    }
+}
+
+type PeerList = Arc<Mutex<Vec<Arc<RTCPeerConnection>>>>;
+
+pub struct WebRtcServer {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    peers: PeerList,
+}
+
+impl WebRtcServer {
+    pub fn start(settings: WebRtcSettings, channels: usize, sample_rate: u32) -> Result<(Self, Producer<f32>), String> {
+        let mut encoder = OpusPacketEncoder::new(
+            channels,
+            sample_rate,
+            settings.bitrate_kbps,
+            &settings.opus,
+            OpusApplication::LowDelay,
+        )?;
+
+        let api = Arc::new(build_api()?);
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                clock_rate: OPUS_SAMPLE_RATE,
+                channels: channels as u16,
+                ..Default::default()
+            },
+            "audio".to_owned(),
+            "audio_merge".to_owned(),
+        ));
+
+        let (producer, mut consumer) = stream_tap(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+        let peers: PeerList = Arc::new(Mutex::new(Vec::new()));
+
+        let accept = {
+            let track = track.clone();
+            let peers = peers.clone();
+            streaming::spawn_tcp_listener(settings.port, stop.clone(), move |stream| {
+                if let Err(e) = serve_request(stream, &api, &track, &peers) {
+                    println!("WebRTC signaling failed: {}", e);
+                }
+            })?
+        };
+        println!("WebRTC monitor on http://0.0.0.0:{}/", settings.port);
+
+        let sender = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let frame_size = encoder.frame_size();
+                let mut buf = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    tap::drain_frames(&mut consumer, channels, &mut buf);
+                    let result = encoder.encode(&buf, |packet| {
+                        block_on(track.write_sample(&packet_sample(packet, frame_size))).map_err(|e| e.to_string())
+                    });
+                    if let Err(e) = result {
+                        eprintln!("WebRTC send error: {}", e);
+                    }
+                    thread::sleep(Duration::from_millis(PACKET_POLL_MS));
+                }
+            })
+        };
+
+        Ok((Self { stop, threads: vec![sender, accept], peers }, producer))
+    }
+}
+
+impl Drop for WebRtcServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        let peers = self.peers.lock().map(|mut p| std::mem::take(&mut *p)).unwrap_or_default();
+        for peer in peers {
+            let _ = block_on(peer.close());
+        }
+    }
+}
+
+fn build_api() -> Result<API, String> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().map_err(|e| e.to_string())?;
+    let registry = register_default_interceptors(Registry::new(), &mut media_engine).map_err(|e| e.to_string())?;
+    Ok(APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .build())
+}
+
+/// One Opus packet as a track sample; the track turns samples into RTP
+/// packets, advancing the timestamp by `duration` at the 48 kHz clock.
+fn packet_sample(packet: &[u8], frame_size: usize) -> Sample {
+    Sample {
+        data: bytes::Bytes::copy_from_slice(packet),
+        duration: Duration::from_secs_f64(frame_size as f64 / OPUS_SAMPLE_RATE as f64),
+        ..Default::default()
+    }
+}
+
+/// What a signaling request asks for.
+enum Signal {
+    Page,
+    Offer(RTCSessionDescription),
+    NotFound,
+}
+
+fn parse_signal(request: &HttpRequest) -> Result<Signal, String> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => Ok(Signal::Page),
+        ("POST", "/offer") => serde_json::from_slice(&request.body)
+            .map(Signal::Offer)
+            .map_err(|e| format!("Bad offer: {}", e)),
+        _ => Ok(Signal::NotFound),
+    }
+}
+
+fn serve_request(
+    mut stream: TcpStream,
+    api: &API,
+    track: &Arc<TrackLocalStaticSample>,
+    peers: &PeerList,
+) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+    let request = read_request(&mut stream)?;
+
+    match parse_signal(&request)? {
+        Signal::Page => write_response(&mut stream, "200 OK", "text/html; charset=utf-8", LISTENER_PAGE.as_bytes()),
+        Signal::Offer(offer) => {
+            match block_on(answer_offer(api, track, peers, offer)) {
+                Ok(answer) => {
+                    let body = serde_json::to_vec(&answer).map_err(|e| e.to_string())?;
+                    write_response(&mut stream, "200 OK", "application/json", &body)
+                },
+                Err(e) => {
+                    write_response(&mut stream, "500 Internal Server Error", "text/plain", e.as_bytes())?;
+                    Err(e)
+                },
+            }
+        },
+        Signal::NotFound => write_response(&mut stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+/// Creates a peer for the browser's offer and returns the complete answer.
+/// ICE candidates are gathered up front, so no trickle signaling is needed.
+async fn answer_offer(
+    api: &API,
+    track: &Arc<TrackLocalStaticSample>,
+    peers: &PeerList,
+    offer: RTCSessionDescription,
+) -> Result<RTCSessionDescription, String> {
+    let peer = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await.map_err(|e| e.to_string())?);
+    peer.add_track(track.clone() as Arc<dyn TrackLocal + Send + Sync>)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Forget peers once the browser goes away
+    let weak = Arc::downgrade(&peer);
+    let list = peers.clone();
+    peer.on_peer_connection_state_change(Box::new(move |state| {
+        if matches!(state, RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed) {
+            if let Ok(mut peers) = list.lock() {
+                peers.retain(|p| !std::ptr::eq(Arc::as_ptr(p), weak.as_ptr()));
+            }
+        }
+        Box::pin(async {})
+    }));
+
+    peer.set_remote_description(offer).await.map_err(|e| e.to_string())?;
+    let answer = peer.create_answer(None).await.map_err(|e| e.to_string())?;
+    let mut gathered = peer.gathering_complete_promise().await;
+    peer.set_local_description(answer).await.map_err(|e| e.to_string())?;
+    let _ = gathered.recv().await;
+    let local = peer
+        .local_description()
+        .await
+        .ok_or_else(|| "No local description".to_string())?;
+
+    if let Ok(mut list) = peers.lock() {
+        list.push(peer);
+    }
+    Ok(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+    use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+    use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+
+    fn request(method: &str, path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: String::new(),
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_signaling_requests_are_routed() {
+        assert!(matches!(parse_signal(&request("GET", "/", "")), Ok(Signal::Page)));
+        assert!(matches!(parse_signal(&request("GET", "/offer", "")), Ok(Signal::NotFound)));
+        assert!(parse_signal(&request("POST", "/offer", "not json")).is_err());
+
+        let offer = r#"{"type":"offer","sdp":"v=0\r\n"}"#;
+        match parse_signal(&request("POST", "/offer", offer)) {
+            Ok(Signal::Offer(offer)) => assert_eq!(offer.sdp, "v=0\r\n"),
+            _ => panic!("offer not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_samples_advance_by_the_opus_frame() {
+        let sample = packet_sample(&[1, 2, 3], 960);
+        assert_eq!(&sample.data[..], &[1, 2, 3]);
+        assert_eq!(sample.duration, Duration::from_millis(20));
+        assert_eq!(packet_sample(&[], 480).duration, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_answer_sends_the_opus_track() {
+        let api = build_api().unwrap();
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                clock_rate: OPUS_SAMPLE_RATE,
+                channels: 2,
+                ..Default::default()
+            },
+            "audio".to_owned(),
+            "audio_merge".to_owned(),
+        ));
+        let peers: PeerList = Arc::new(Mutex::new(Vec::new()));
+
+        // What the listener page sends: a receive-only audio offer
+        let browser = block_on(api.new_peer_connection(RTCConfiguration::default())).unwrap();
+        block_on(browser.add_transceiver_from_kind(
+            RTPCodecType::Audio,
+            Some(RTCRtpTransceiverInit {
+                direction: RTCRtpTransceiverDirection::Recvonly,
+                send_encodings: Vec::new(),
+            }),
+        ))
+        .unwrap();
+        let offer = block_on(browser.create_offer(None)).unwrap();
+
+        let answer = block_on(answer_offer(&api, &track, &peers, offer)).unwrap();
+        assert!(answer.sdp.contains("opus/48000/2"));
+        assert!(answer.sdp.contains("a=sendonly"));
+        assert_eq!(peers.lock().unwrap().len(), 1);
+
+        let _ = block_on(browser.close());
+        // Taken out first: closing calls back into the list
+        let open = std::mem::take(&mut *peers.lock().unwrap());
+        for peer in open {
+            let _ = block_on(peer.close());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Main window geometry, kept in the config so the window comes back where it
+// was. Moves and resizes are tracked in memory and written when the window
+// is hidden or the app exits, not on every event. Also opens the mini mixer,
+// a small always-on-top window with just the faders.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WebviewUrl, WebviewWindowBuilder, Window, WindowEvent};
+
+pub const MAIN_WINDOW: &str = "main";
+/// Label the frontend checks to render the mini mixer.
+const MINI_WINDOW: &str = "mini";
+
+/// Outer position and inner size in physical pixels.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+}
+
+/// Latest geometry of the main window, managed as app state.
+#[derive(Default)]
+pub struct WindowTracker {
+    geometry: Mutex<Option<WindowGeometry>>,
+}
+
+impl WindowTracker {
+    fn set(&self, geometry: WindowGeometry) {
+        if let Ok(mut g) = self.geometry.lock() {
+            *g = Some(geometry);
+        }
+    }
+
+    fn get(&self) -> Option<WindowGeometry> {
+        self.geometry.lock().ok().and_then(|g| *g)
+    }
+}
+
+/// Places the main window as saved, skipping positions no monitor shows
+/// anymore (e.g. an unplugged screen).
+pub fn restore(app: &AppHandle, geometry: &WindowGeometry) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    if geometry.width > 0 && geometry.height > 0 {
+        let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+    }
+    let visible = window.available_monitors().unwrap_or_default().iter().any(|m| {
+        let (pos, size) = (m.position(), m.size());
+        (pos.x..pos.x + size.width as i32).contains(&geometry.x) && (pos.y..pos.y + size.height as i32).contains(&geometry.y)
+    });
+    if visible {
+        let _ = window.set_position(PhysicalPosition::new(geometry.x, geometry.y));
+    }
+    if geometry.maximized {
+        let _ = window.maximize();
+    }
+    app.state::<WindowTracker>().set(*geometry);
+}
+
+/// Tracks the main window's moves and resizes, and saves them when it is
+/// closed to the tray.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if window.label() != MAIN_WINDOW {
+        return;
+    }
+    let app = window.app_handle();
+    match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+            let maximized = window.is_maximized().unwrap_or(false);
+            let tracker = app.state::<WindowTracker>();
+            // Keep the restored size while maximized, so un-maximizing after a
+            // restart lands where it was
+            let mut geometry = tracker.get().unwrap_or_default();
+            if !maximized && !window.is_minimized().unwrap_or(false) {
+                if let (Ok(pos), Ok(size)) = (window.outer_position(), window.inner_size()) {
+                    geometry = WindowGeometry { x: pos.x, y: pos.y, width: size.width, height: size.height, maximized };
+                }
+            }
+            geometry.maximized = maximized;
+            tracker.set(geometry);
+        },
+        WindowEvent::CloseRequested { .. } => save(app, true),
+        _ => {},
+    }
+}
+
+/// Writes the tracked geometry and whether the window is hidden to the tray.
+pub fn save(app: &AppHandle, hidden: bool) {
+    let geometry = app.state::<WindowTracker>().get();
+    let result = config::update_config(app, |c| {
+        if geometry.is_some() {
+            c.window = geometry;
+        }
+        c.window_hidden = hidden;
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to save window state: {}", e);
+    }
+}
+
+/// Shows the mini mixer, or hides it if it is showing. Returns whether it is
+/// now visible.
+pub fn toggle_mini(app: &AppHandle) -> Result<bool, String> {
+    if let Some(window) = app.get_webview_window(MINI_WINDOW) {
+        if window.is_visible().unwrap_or(false) {
+            window.hide().map_err(|e| e.to_string())?;
+            return Ok(false);
+        }
+        window.show().map_err(|e| e.to_string())?;
+        let _ = window.set_focus();
+        return Ok(true);
+    }
+    WebviewWindowBuilder::new(app, MINI_WINDOW, WebviewUrl::App("index.html".into()))
+        .title("Audio Merge Mini")
+        .inner_size(320.0, 240.0)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(true)
+}
@@ -1,5 +1,6 @@
 import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./App.css";
 import logo from "./assets/logo.png";
 
@@ -7,6 +8,7 @@ interface Device {
   name: string;
   host_api: string;
   default?: boolean;
+  pinned?: boolean;
 }
 
 interface OutputConfig {
@@ -15,6 +17,16 @@ interface OutputConfig {
   muted: boolean;
 }
 
+type AudioEvent =
+  | { type: "volume_changed"; device: string; volume: number };
+
+interface ConfigProblem {
+  recovered: boolean;
+  reload: boolean;
+  error: string;
+  backup: string | null;
+}
+
 interface AppConfig {
   input_volume: number;
   input_muted: boolean;
@@ -30,6 +42,7 @@ function App() {
   const [capturePaused, setCapturePaused] = useState(false);
   const [sourceName, setSourceName] = useState("Loading...");
   const [status, setStatus] = useState("Ready");
+  const [configProblem, setConfigProblem] = useState<ConfigProblem | null>(null);
 
   // Prevent initial save overwriting logic
   const isLoaded = useRef(false);
@@ -42,11 +55,13 @@ function App() {
 
     // 2. Fetch Devices
     const fetchDevices = async () => {
-      const d = await invoke("get_audio_devices") as Device[];
+      const d = await invoke("get_audio_devices", { favoritesFirst: true }) as Device[];
       const currentSource = await invoke("get_default_audio_device") as string;
       setSourceName(currentSource);
 
-      const available = d.filter(device => device.name !== currentSource);
+      // Outputs that would loop back into the capture are hidden
+      const feedback = await invoke("get_feedback_devices") as string[];
+      const available = d.filter(device => !feedback.includes(device.name));
       setDevices(available);
       if (available.length > 0) setSelectedDeviceName(available[0].name);
     }
@@ -83,6 +98,37 @@ function App() {
       }
     }
     loadConfig();
+
+    // 4. Follow backend-driven changes (e.g. linked faders)
+    const unlisten = listen<AudioEvent>("audio-event", (event) => {
+      const payload = event.payload;
+      if (payload.type === "volume_changed") {
+        updateOutputState(payload.device, { volume: payload.volume });
+      }
+    });
+
+    // 5. Warn when the settings file couldn't be read
+    invoke("get_config_problem")
+      .then((p) => setConfigProblem(p as ConfigProblem | null))
+      .catch(console.error);
+    const unlistenProblem = listen<ConfigProblem>("config-load-error", (event) => setConfigProblem(event.payload));
+
+    // 6. A profile switched the mix or the config file was edited; the
+    // backend already rebuilt the mix
+    const reloadConfig = async () => {
+      const config = await invoke("load_app_config") as AppConfig;
+      setInputVolume(Math.round(config.input_volume * 100));
+      setInputMuted(config.input_muted);
+      setActiveOutputs(config.outputs);
+    };
+    const unlistenProfile = listen<string>("profile-applied", reloadConfig);
+    const unlistenReload = listen("config-reloaded", reloadConfig);
+    return () => {
+      unlisten.then(f => f());
+      unlistenProfile.then(f => f());
+      unlistenReload.then(f => f());
+      unlistenProblem.then(f => f());
+    };
   }, []);
 
   // Auto-Save Effect
@@ -136,9 +182,9 @@ function App() {
       await invoke("add_device_to_mix", { deviceName: selectedDeviceName });
       const newOutput = { name: selectedDeviceName, volume: 1.0, muted: false };
       setActiveOutputs([...activeOutputs, newOutput]);
-    } catch (e) {
+    } catch (e: any) {
       console.error(e);
-      alert("Failed to add output: " + e);
+      alert("Failed to add output: " + (e?.message ?? e));
     }
   };
 
@@ -171,6 +217,22 @@ function App() {
         </div>
       </header>
 
+      {configProblem && (
+        <div className="card config-warning">
+          <div className="tech-label">CONFIG_ERROR</div>
+          <p>
+            {configProblem.reload
+              ? "The edited settings file could not be read; the running settings were kept."
+              : configProblem.recovered
+                ? "Some settings could not be read and were reset."
+                : "Settings could not be read; defaults are in use."}
+            {" "}{configProblem.error}
+          </p>
+          {configProblem.backup && <p>The original file was kept as <strong>{configProblem.backup}</strong></p>}
+          <button onClick={() => setConfigProblem(null)}>DISMISS</button>
+        </div>
+      )}
+
       <div className="card source-card" style={{ opacity: capturePaused ? 0.7 : 1 }}>
         <div className="tech-label">INPUT_STREAM_01</div>
         <div style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'flex-start' }}>
@@ -245,6 +307,11 @@ function OutputCard({ config, idx, removeOutput, updateState }: {
   // Local state for smooth slider, but syncs to parent for persistence
   const [vol, setVol] = useState(Math.round(config.volume * 100));
 
+  // Keep the slider in sync when the backend moves this output
+  useEffect(() => {
+    setVol(Math.round(config.volume * 100));
+  }, [config.volume]);
+
   return (
     <div className="card output-card">
       <div className="tech-label" style={{ color: 'var(--neon-red)', borderColor: 'rgba(255, 82, 82, 0.3)' }}>LINK_0{idx + 1}</div>
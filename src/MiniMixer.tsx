@@ -0,0 +1,108 @@
+import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import "./App.css";
+
+interface AudioState {
+  capturing: boolean;
+  outputs: string[];
+  volumes: Record<string, number>;
+  muted_outputs: string[];
+  master_volume: number;
+  master_muted: boolean;
+}
+
+// Faders moved in the main window don't raise events, so poll as well
+const REFRESH_MS = 1000;
+
+// Compact always-on-top mixer: the master and every active output
+function MiniMixer() {
+  const [state, setState] = useState<AudioState | null>(null);
+
+  const refresh = () => {
+    invoke("get_audio_state")
+      .then((s) => setState(s as AudioState))
+      .catch(console.error);
+  };
+
+  useEffect(() => {
+    refresh();
+    const timer = setInterval(refresh, REFRESH_MS);
+    const unlisten = listen("audio-event", refresh);
+    return () => {
+      clearInterval(timer);
+      unlisten.then(f => f());
+    };
+  }, []);
+
+  if (!state) return <div className="mini">...</div>;
+
+  const setVolume = (name: string, volume: number) => {
+    setState({ ...state, volumes: { ...state.volumes, [name]: volume } });
+    invoke("set_device_volume", { deviceName: name, volume }).catch(console.error);
+  };
+
+  const toggleMute = (name: string) => {
+    const muted = !state.muted_outputs.includes(name);
+    invoke("set_device_mute", { deviceName: name, muted }).then(refresh).catch(console.error);
+  };
+
+  const setMasterVolume = (volume: number) => {
+    setState({ ...state, master_volume: volume });
+    invoke("set_master_volume", { volume }).catch(console.error);
+  };
+
+  const toggleMasterMute = () => {
+    invoke("set_master_mute", { muted: !state.master_muted }).then(refresh).catch(console.error);
+  };
+
+  return (
+    <div className="mini">
+      <MiniFader
+        label="MASTER"
+        volume={state.master_volume}
+        muted={state.master_muted}
+        onVolume={setMasterVolume}
+        onMute={toggleMasterMute}
+      />
+      {state.outputs.map(name => (
+        <MiniFader
+          key={name}
+          label={name}
+          volume={state.volumes[name] ?? 1}
+          muted={state.muted_outputs.includes(name)}
+          onVolume={v => setVolume(name, v)}
+          onMute={() => toggleMute(name)}
+        />
+      ))}
+    </div>
+  );
+}
+
+function MiniFader({ label, volume, muted, onVolume, onMute }: {
+  label: string,
+  volume: number,
+  muted: boolean,
+  onVolume: (v: number) => void,
+  onMute: () => void
+}) {
+  return (
+    <div className="mini-fader">
+      <label title={label}>{label}</label>
+      <input
+        type="range"
+        min="0"
+        max="100"
+        value={Math.round(volume * 100)}
+        onChange={(e) => onVolume(parseInt(e.target.value) / 100.0)}
+      />
+      <button
+        onClick={onMute}
+        style={{ border: '1px solid var(--neon-red)', color: muted ? 'black' : 'var(--neon-red)', background: muted ? 'var(--neon-red)' : 'transparent' }}>
+        {muted ? "MUTED" : "MUTE"}
+      </button>
+    </div>
+  );
+}
+
+export default MiniMixer;
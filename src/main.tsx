@@ -1,9 +1,14 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
+import { getCurrentWindow } from "@tauri-apps/api/window";
 import App from "./App";
+import MiniMixer from "./MiniMixer";
+
+// The mini mixer window loads the same page
+const isMini = getCurrentWindow().label === "mini";
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    {isMini ? <MiniMixer /> : <App />}
   </React.StrictMode>,
 );